@@ -0,0 +1,121 @@
+//! Stream throughput of each cipher.
+//!
+//! `jolokia bench` measures the same on the user's hardware, but only
+//! encryption, and without statistics.
+
+use std::hint::black_box;
+use std::io::{self, Read};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use secrecy::ExposeSecret;
+
+use jolokia::cipher;
+use jolokia::traits::{Cipher, GeneratedKey, KeygenOptions};
+
+const LEN: u64 = 1024 * 1024;
+
+struct Subject {
+    cipher: Box<dyn Cipher>,
+    encryption_key: Vec<u8>,
+    decryption_key: Vec<u8>,
+}
+
+/// Each cipher, with a key to encrypt with and one to decrypt with.
+fn subjects() -> Vec<Subject> {
+    let ciphers: Vec<Box<dyn Cipher>> = vec![
+        Box::new(cipher::ChaCha20Poly1305),
+        #[cfg(feature = "hpke")]
+        Box::new(cipher::Hpke),
+        #[cfg(feature = "hpke")]
+        Box::new(cipher::HpkePq),
+        #[cfg(feature = "age")]
+        Box::new(cipher::Age),
+        #[cfg(feature = "rotn")]
+        Box::new(cipher::RotN),
+        #[cfg(feature = "brainfuck")]
+        Box::new(cipher::Brainfuck),
+    ];
+    ciphers
+        .into_iter()
+        .map(|cipher| {
+            let (encryption_key, decryption_key) =
+                match cipher.generate_key(&KeygenOptions::default()).unwrap() {
+                    GeneratedKey::Symmetric(key) => {
+                        (key.expose_secret().to_vec(), key.expose_secret().to_vec())
+                    }
+                    GeneratedKey::Asymmetric { public, private } => (
+                        public.expose_secret().to_vec(),
+                        private.expose_secret().to_vec(),
+                    ),
+                    // ROT-13, Brainfuck ignores it.
+                    GeneratedKey::None => (vec![13], vec![13]),
+                };
+            Subject {
+                cipher,
+                encryption_key,
+                decryption_key,
+            }
+        })
+        .collect()
+}
+
+fn encrypt_stream(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encrypt_stream");
+    group.throughput(Throughput::Bytes(LEN));
+    group.sample_size(10);
+
+    for Subject {
+        cipher,
+        encryption_key,
+        ..
+    } in subjects()
+    {
+        group.bench_function(cipher.algorithm_id(), |b| {
+            b.iter(|| {
+                let mut plaintext = io::repeat(0xa5).take(LEN);
+                cipher
+                    .encrypt_stream(black_box(&encryption_key), &mut plaintext, &mut io::sink())
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+fn decrypt_stream(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decrypt_stream");
+    group.throughput(Throughput::Bytes(LEN));
+    group.sample_size(10);
+
+    for Subject {
+        cipher,
+        encryption_key,
+        decryption_key,
+    } in subjects()
+    {
+        let mut ciphertext = Vec::new();
+        cipher
+            .encrypt_stream(
+                &encryption_key,
+                &mut io::repeat(0xa5).take(LEN),
+                &mut ciphertext,
+            )
+            .unwrap();
+
+        group.bench_function(cipher.algorithm_id(), |b| {
+            b.iter(|| {
+                cipher
+                    .decrypt_stream(
+                        black_box(&decryption_key),
+                        &mut ciphertext.as_slice(),
+                        &mut io::sink(),
+                    )
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encrypt_stream, decrypt_stream);
+criterion_main!(benches);
@@ -0,0 +1,106 @@
+#ifndef JOLOKIA_H
+#define JOLOKIA_H
+
+/* Generated with cbindgen, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Outcome of a call.
+typedef enum JolokiaStatus {
+  JOLOKIA_STATUS_OK = 0,
+  // A required pointer is `NULL`.
+  JOLOKIA_STATUS_NULL_POINTER = 1,
+  // Unknown algorithm, or text that is not UTF-8.
+  JOLOKIA_STATUS_INVALID_ARGUMENT = 2,
+  // The key is malformed, or for another algorithm.
+  JOLOKIA_STATUS_KEY = 3,
+  JOLOKIA_STATUS_ENCRYPT = 4,
+  // Wrong key, or corrupted ciphertext.
+  JOLOKIA_STATUS_DECRYPT = 5,
+  // The ciphertext is from another algorithm.
+  JOLOKIA_STATUS_ALGORITHM = 6,
+  JOLOKIA_STATUS_OTHER = 7,
+} JolokiaStatus;
+
+// Bytes allocated by jolokia, see [`jolokia_buffer_free()`].
+typedef struct JolokiaBuffer {
+  uint8_t *data;
+  size_t len;
+} JolokiaBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Generate a key for `algorithm`.
+//
+// Asymmetric algorithms write the private key to `secret_key`, and the
+// public key to `public_key`. Symmetric algorithms only write
+// `secret_key`, `public_key` is left empty (and may be `NULL`).
+//
+// # Safety
+//
+// `algorithm` must be `NULL` or a valid NUL-terminated string.
+// `secret_key` must be a valid pointer, `public_key` must be `NULL` or
+// a valid pointer.
+enum JolokiaStatus jolokia_keygen(const char *algorithm,
+                                  struct JolokiaBuffer *secret_key,
+                                  struct JolokiaBuffer *public_key);
+
+// Encrypt `plaintext` with `key` (the public key, if asymmetric).
+//
+// # Safety
+//
+// `algorithm` must be `NULL` or a valid NUL-terminated string. `key`
+// and `plaintext` must be valid for reads of their lengths (they may
+// be `NULL` if their length is 0). `ciphertext` must be a valid
+// pointer.
+enum JolokiaStatus jolokia_encrypt(const char *algorithm,
+                                   const uint8_t *key,
+                                   size_t key_len,
+                                   const uint8_t *plaintext,
+                                   size_t plaintext_len,
+                                   struct JolokiaBuffer *ciphertext);
+
+// Decrypt `ciphertext` with `key` (the private key, if asymmetric).
+//
+// Nothing is written to `plaintext` unless the whole ciphertext is
+// authentic.
+//
+// # Safety
+//
+// `algorithm` must be `NULL` or a valid NUL-terminated string. `key`
+// and `ciphertext` must be valid for reads of their lengths (they may
+// be `NULL` if their length is 0). `plaintext` must be a valid
+// pointer.
+enum JolokiaStatus jolokia_decrypt(const char *algorithm,
+                                   const uint8_t *key,
+                                   size_t key_len,
+                                   const uint8_t *ciphertext,
+                                   size_t ciphertext_len,
+                                   struct JolokiaBuffer *plaintext);
+
+// Zero and release a buffer returned by jolokia.
+//
+// The buffer is left empty, freeing it again does nothing.
+//
+// # Safety
+//
+// `buffer` must be `NULL`, or point to a buffer returned by jolokia
+// (or an empty one).
+void jolokia_buffer_free(struct JolokiaBuffer *buffer);
+
+// Describe `status`, as a static NUL-terminated string.
+//
+// The string must not be freed.
+const char *jolokia_status_message(enum JolokiaStatus status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* JOLOKIA_H */
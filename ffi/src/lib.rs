@@ -0,0 +1,521 @@
+//! C interface to jolokia.
+//!
+//! Non-Rust applications get the same algorithms, keys, and ciphertext
+//! format as the CLI: a file encrypted by `jolokia encrypt -o` can be
+//! decrypted with [`jolokia_decrypt()`], and the other way around. The
+//! header is generated with `cbindgen` (`include/jolokia.h`).
+//!
+//! # Conventions
+//!
+//! - Algorithms are given by ID (`"chacha20poly1305"`, `"hpke"`,
+//!   `"hpke-pq"`, `"age"`), as NUL-terminated strings. `NULL` means
+//!   the default, ChaCha20-Poly1305.
+//! - Keys are text, exactly as printed by `jolokia keygen` (tagged
+//!   base64, or Bech32 for age). They are passed as pointer and length,
+//!   not NUL-terminated.
+//! - Ciphertexts are raw bytes (like `jolokia encrypt --raw`).
+//! - Every function returns a [`JolokiaStatus`], `JOLOKIA_STATUS_OK`
+//!   on success. [`jolokia_status_message()`] describes the others.
+//!
+//! # Buffer Ownership
+//!
+//! Output buffers ([`JolokiaBuffer`]) are allocated by jolokia, and
+//! belong to the caller. They must be released with
+//! [`jolokia_buffer_free()`] (never with `free()`), which also zeroes
+//! them. On error, outputs are set to empty buffers, which are safe to
+//! free too.
+//!
+//! Inputs are only borrowed for the duration of the call.
+
+use std::ffi::{CStr, c_char};
+use std::ptr;
+
+use secrecy::zeroize::Zeroize;
+
+use jolokia::cipher;
+use jolokia::key;
+use jolokia::traits::{Cipher, Error, GeneratedKey, KeygenOptions};
+
+/// Outcome of a call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JolokiaStatus {
+    Ok = 0,
+    /// A required pointer is `NULL`.
+    NullPointer = 1,
+    /// Unknown algorithm, or text that is not UTF-8.
+    InvalidArgument = 2,
+    /// The key is malformed, or for another algorithm.
+    Key = 3,
+    Encrypt = 4,
+    /// Wrong key, or corrupted ciphertext.
+    Decrypt = 5,
+    /// The ciphertext is from another algorithm.
+    Algorithm = 6,
+    Other = 7,
+}
+
+/// Bytes allocated by jolokia, see [`jolokia_buffer_free()`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct JolokiaBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl JolokiaBuffer {
+    fn empty() -> Self {
+        Self {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let bytes = bytes.into_boxed_slice();
+        let len = bytes.len();
+        Self {
+            data: Box::into_raw(bytes).cast::<u8>(),
+            len,
+        }
+    }
+}
+
+/// Generate a key for `algorithm`.
+///
+/// Asymmetric algorithms write the private key to `secret_key`, and the
+/// public key to `public_key`. Symmetric algorithms only write
+/// `secret_key`, `public_key` is left empty (and may be `NULL`).
+///
+/// # Safety
+///
+/// `algorithm` must be `NULL` or a valid NUL-terminated string.
+/// `secret_key` must be a valid pointer, `public_key` must be `NULL` or
+/// a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jolokia_keygen(
+    algorithm: *const c_char,
+    secret_key: *mut JolokiaBuffer,
+    public_key: *mut JolokiaBuffer,
+) -> JolokiaStatus {
+    // SAFETY: Caller guarantees the pointers are `NULL` or valid.
+    let (secret_key, mut public_key) = unsafe { (secret_key.as_mut(), public_key.as_mut()) };
+    let Some(secret_key) = secret_key else {
+        return JolokiaStatus::NullPointer;
+    };
+    *secret_key = JolokiaBuffer::empty();
+    if let Some(ref mut public_key) = public_key {
+        **public_key = JolokiaBuffer::empty();
+    }
+
+    // SAFETY: Caller guarantees `algorithm` is `NULL` or valid.
+    let cipher = match unsafe { cipher_from_id(algorithm) } {
+        Ok(cipher) => cipher,
+        Err(status) => return status,
+    };
+    let algorithm_id = cipher.algorithm_id();
+
+    let (secret, public) = match cipher.generate_key(&KeygenOptions::default()) {
+        Ok(GeneratedKey::Symmetric(key)) => (key, None),
+        Ok(GeneratedKey::Asymmetric { private, public }) => (private, Some(public)),
+        Ok(GeneratedKey::None) => return JolokiaStatus::InvalidArgument,
+        Err(e) => return status_from_error(&e),
+    };
+    let (secret, public) = match key::encode(algorithm_id, &secret, public.as_ref()) {
+        Ok(keys) => keys,
+        Err(e) => return status_from_error(&e),
+    };
+
+    if let Some(public) = public {
+        let Some(public_key) = public_key else {
+            return JolokiaStatus::NullPointer;
+        };
+        *public_key = JolokiaBuffer::from_vec(public.into_bytes());
+    }
+    *secret_key = JolokiaBuffer::from_vec(secret.as_bytes().to_vec());
+
+    JolokiaStatus::Ok
+}
+
+/// Encrypt `plaintext` with `key` (the public key, if asymmetric).
+///
+/// # Safety
+///
+/// `algorithm` must be `NULL` or a valid NUL-terminated string. `key`
+/// and `plaintext` must be valid for reads of their lengths (they may
+/// be `NULL` if their length is 0). `ciphertext` must be a valid
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jolokia_encrypt(
+    algorithm: *const c_char,
+    key: *const u8,
+    key_len: usize,
+    plaintext: *const u8,
+    plaintext_len: usize,
+    ciphertext: *mut JolokiaBuffer,
+) -> JolokiaStatus {
+    // SAFETY: Caller guarantees the pointers are valid.
+    unsafe {
+        cipher_call(
+            algorithm,
+            (key, key_len),
+            (plaintext, plaintext_len),
+            ciphertext,
+            |cipher, key, plaintext| cipher.encrypt(key, plaintext),
+        )
+    }
+}
+
+/// Decrypt `ciphertext` with `key` (the private key, if asymmetric).
+///
+/// Nothing is written to `plaintext` unless the whole ciphertext is
+/// authentic.
+///
+/// # Safety
+///
+/// `algorithm` must be `NULL` or a valid NUL-terminated string. `key`
+/// and `ciphertext` must be valid for reads of their lengths (they may
+/// be `NULL` if their length is 0). `plaintext` must be a valid
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jolokia_decrypt(
+    algorithm: *const c_char,
+    key: *const u8,
+    key_len: usize,
+    ciphertext: *const u8,
+    ciphertext_len: usize,
+    plaintext: *mut JolokiaBuffer,
+) -> JolokiaStatus {
+    // SAFETY: Caller guarantees the pointers are valid.
+    unsafe {
+        cipher_call(
+            algorithm,
+            (key, key_len),
+            (ciphertext, ciphertext_len),
+            plaintext,
+            |cipher, key, ciphertext| cipher.decrypt(key, ciphertext),
+        )
+    }
+}
+
+/// Zero and release a buffer returned by jolokia.
+///
+/// The buffer is left empty, freeing it again does nothing.
+///
+/// # Safety
+///
+/// `buffer` must be `NULL`, or point to a buffer returned by jolokia
+/// (or an empty one).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jolokia_buffer_free(buffer: *mut JolokiaBuffer) {
+    // SAFETY: Caller guarantees `buffer` is `NULL` or valid.
+    let Some(buffer) = (unsafe { buffer.as_mut() }) else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        // SAFETY: `data` and `len` come from `JolokiaBuffer::from_vec()`.
+        let mut bytes =
+            unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)) };
+        bytes.zeroize();
+    }
+    *buffer = JolokiaBuffer::empty();
+}
+
+/// Describe `status`, as a static NUL-terminated string.
+///
+/// The string must not be freed.
+#[unsafe(no_mangle)]
+pub extern "C" fn jolokia_status_message(status: JolokiaStatus) -> *const c_char {
+    let message: &'static CStr = match status {
+        JolokiaStatus::Ok => c"Success.",
+        JolokiaStatus::NullPointer => c"A required pointer is NULL.",
+        JolokiaStatus::InvalidArgument => c"Unknown algorithm, or text is not UTF-8.",
+        JolokiaStatus::Key => c"The key is not compatible with the algorithm.",
+        JolokiaStatus::Encrypt => c"Could not encrypt input.",
+        JolokiaStatus::Decrypt => {
+            c"Could not decrypt input. The key is wrong, or the data is corrupted."
+        }
+        JolokiaStatus::Algorithm => c"Incompatible cipher algorithm.",
+        JolokiaStatus::Other => c"Unexpected error.",
+    };
+    message.as_ptr()
+}
+
+/// Decode the key, and run `operation` on `input`, into `output`.
+///
+/// # Safety
+///
+/// See [`jolokia_encrypt()`].
+unsafe fn cipher_call(
+    algorithm: *const c_char,
+    key: (*const u8, usize),
+    input: (*const u8, usize),
+    output: *mut JolokiaBuffer,
+    operation: impl FnOnce(&dyn Cipher, &[u8], &[u8]) -> jolokia::traits::Result<Vec<u8>>,
+) -> JolokiaStatus {
+    // SAFETY: Caller guarantees the pointers are `NULL` or valid.
+    let output = unsafe { output.as_mut() };
+    let Some(output) = output else {
+        return JolokiaStatus::NullPointer;
+    };
+    *output = JolokiaBuffer::empty();
+
+    // SAFETY: Caller guarantees the pointers are valid.
+    let (key, input) = unsafe { (bytes(key.0, key.1), bytes(input.0, input.1)) };
+    let (Some(key), Some(input)) = (key, input) else {
+        return JolokiaStatus::NullPointer;
+    };
+    // SAFETY: Caller guarantees `algorithm` is `NULL` or valid.
+    let cipher = match unsafe { cipher_from_id(algorithm) } {
+        Ok(cipher) => cipher,
+        Err(status) => return status,
+    };
+    let Ok(key) = std::str::from_utf8(key) else {
+        return JolokiaStatus::InvalidArgument;
+    };
+    let key = match key::decode(cipher.algorithm_id(), key) {
+        Ok(key) => key,
+        Err(e) => return status_from_error(&e),
+    };
+
+    match operation(cipher.as_ref(), &key, input) {
+        Ok(bytes) => {
+            *output = JolokiaBuffer::from_vec(bytes);
+            JolokiaStatus::Ok
+        }
+        Err(e) => status_from_error(&e),
+    }
+}
+
+/// # Safety
+///
+/// `algorithm` must be `NULL` or a valid NUL-terminated string.
+unsafe fn cipher_from_id(algorithm: *const c_char) -> Result<Box<dyn Cipher>, JolokiaStatus> {
+    if algorithm.is_null() {
+        return Ok(Box::new(cipher::ChaCha20Poly1305));
+    }
+    // SAFETY: Caller guarantees `algorithm` is valid.
+    let algorithm = unsafe { CStr::from_ptr(algorithm) };
+    algorithm
+        .to_str()
+        .ok()
+        .and_then(cipher::from_algorithm_id)
+        .ok_or(JolokiaStatus::InvalidArgument)
+}
+
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes, or `len` must be 0.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        return Some(&[]);
+    }
+    if data.is_null() {
+        return None;
+    }
+    // SAFETY: Caller guarantees `data` is valid for `len` bytes.
+    Some(unsafe { std::slice::from_raw_parts(data, len) })
+}
+
+/// Same as the CLI: tagged base64, or Bech32 for age.
+fn status_from_error(error: &Error) -> JolokiaStatus {
+    match error {
+        Error::Key | Error::Base64Decode(_) | Error::Decode { .. } => JolokiaStatus::Key,
+        Error::Encrypt => JolokiaStatus::Encrypt,
+        Error::Decrypt => JolokiaStatus::Decrypt,
+        Error::Algorithm => JolokiaStatus::Algorithm,
+        _ => JolokiaStatus::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keygen(algorithm: &CStr) -> (JolokiaBuffer, JolokiaBuffer) {
+        let mut secret_key = JolokiaBuffer::empty();
+        let mut public_key = JolokiaBuffer::empty();
+        let status =
+            unsafe { jolokia_keygen(algorithm.as_ptr(), &raw mut secret_key, &raw mut public_key) };
+        assert_eq!(status, JolokiaStatus::Ok);
+        (secret_key, public_key)
+    }
+
+    fn as_slice(buffer: &JolokiaBuffer) -> &[u8] {
+        unsafe { bytes(buffer.data, buffer.len) }.unwrap()
+    }
+
+    fn encrypt(algorithm: &CStr, key: &[u8], plaintext: &[u8]) -> (JolokiaStatus, JolokiaBuffer) {
+        let mut ciphertext = JolokiaBuffer::empty();
+        let status = unsafe {
+            jolokia_encrypt(
+                algorithm.as_ptr(),
+                key.as_ptr(),
+                key.len(),
+                plaintext.as_ptr(),
+                plaintext.len(),
+                &raw mut ciphertext,
+            )
+        };
+        (status, ciphertext)
+    }
+
+    fn decrypt(algorithm: &CStr, key: &[u8], ciphertext: &[u8]) -> (JolokiaStatus, JolokiaBuffer) {
+        let mut plaintext = JolokiaBuffer::empty();
+        let status = unsafe {
+            jolokia_decrypt(
+                algorithm.as_ptr(),
+                key.as_ptr(),
+                key.len(),
+                ciphertext.as_ptr(),
+                ciphertext.len(),
+                &raw mut plaintext,
+            )
+        };
+        (status, plaintext)
+    }
+
+    #[test]
+    fn round_trip_all_algorithms() {
+        for algorithm in [c"chacha20poly1305", c"hpke", c"hpke-pq", c"age"] {
+            let (mut secret_key, mut public_key) = keygen(algorithm);
+            let encryption_key = if public_key.data.is_null() {
+                as_slice(&secret_key)
+            } else {
+                as_slice(&public_key)
+            };
+
+            let (status, mut ciphertext) = encrypt(algorithm, encryption_key, b"hello, world");
+            assert_eq!(status, JolokiaStatus::Ok, "{algorithm:?}");
+
+            let (status, mut plaintext) =
+                decrypt(algorithm, as_slice(&secret_key), as_slice(&ciphertext));
+            assert_eq!(status, JolokiaStatus::Ok, "{algorithm:?}");
+            assert_eq!(as_slice(&plaintext), b"hello, world");
+
+            unsafe {
+                jolokia_buffer_free(&raw mut secret_key);
+                jolokia_buffer_free(&raw mut public_key);
+                jolokia_buffer_free(&raw mut ciphertext);
+                jolokia_buffer_free(&raw mut plaintext);
+            }
+        }
+    }
+
+    #[test]
+    fn keygen_matches_cli_format() {
+        let (secret_key, public_key) = keygen(c"chacha20poly1305");
+        assert!(as_slice(&secret_key).starts_with(b"chacha20poly1305:"));
+        assert!(public_key.data.is_null());
+
+        let (secret_key, public_key) = keygen(c"age");
+        assert!(as_slice(&secret_key).starts_with(b"AGE-SECRET-KEY-1"));
+        assert!(as_slice(&public_key).starts_with(b"age1"));
+    }
+
+    #[test]
+    fn keygen_asymmetric_without_public_key_is_error() {
+        let mut secret_key = JolokiaBuffer::empty();
+        let status =
+            unsafe { jolokia_keygen(c"hpke".as_ptr(), &raw mut secret_key, ptr::null_mut()) };
+
+        assert_eq!(status, JolokiaStatus::NullPointer);
+        assert!(secret_key.data.is_null());
+    }
+
+    #[test]
+    fn default_algorithm_is_chacha() {
+        let (secret_key, _) = keygen(c"chacha20poly1305");
+        let key = as_slice(&secret_key);
+
+        let mut ciphertext = JolokiaBuffer::empty();
+        let status = unsafe {
+            jolokia_encrypt(
+                ptr::null(),
+                key.as_ptr(),
+                key.len(),
+                ptr::null(),
+                0,
+                &raw mut ciphertext,
+            )
+        };
+        assert_eq!(status, JolokiaStatus::Ok);
+
+        let (status, plaintext) = decrypt(c"chacha20poly1305", key, as_slice(&ciphertext));
+        assert_eq!(status, JolokiaStatus::Ok);
+        assert_eq!(plaintext.len, 0);
+    }
+
+    #[test]
+    fn decrypt_wrong_key_is_error() {
+        let (secret_key, _) = keygen(c"chacha20poly1305");
+        let (other_key, _) = keygen(c"chacha20poly1305");
+        let (_, ciphertext) = encrypt(c"chacha20poly1305", as_slice(&secret_key), b"hello");
+
+        let (status, plaintext) = decrypt(
+            c"chacha20poly1305",
+            as_slice(&other_key),
+            as_slice(&ciphertext),
+        );
+
+        assert_eq!(status, JolokiaStatus::Decrypt);
+        assert!(plaintext.data.is_null());
+    }
+
+    #[test]
+    fn key_for_other_algorithm_is_error() {
+        let (secret_key, _) = keygen(c"chacha20poly1305");
+
+        let (status, _) = encrypt(c"hpke", as_slice(&secret_key), b"hello");
+
+        assert_eq!(status, JolokiaStatus::Key);
+    }
+
+    #[test]
+    fn unknown_algorithm_is_error() {
+        let (status, _) = encrypt(c"rot13", b"key", b"hello");
+
+        assert_eq!(status, JolokiaStatus::InvalidArgument);
+    }
+
+    #[test]
+    fn null_pointers_are_errors() {
+        let status = unsafe {
+            jolokia_encrypt(ptr::null(), ptr::null(), 1, ptr::null(), 0, ptr::null_mut())
+        };
+        assert_eq!(status, JolokiaStatus::NullPointer);
+
+        let mut output = JolokiaBuffer::empty();
+        let status = unsafe {
+            jolokia_encrypt(ptr::null(), ptr::null(), 1, ptr::null(), 0, &raw mut output)
+        };
+        assert_eq!(status, JolokiaStatus::NullPointer);
+
+        unsafe { jolokia_buffer_free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn buffer_free_twice() {
+        let (mut secret_key, _) = keygen(c"chacha20poly1305");
+
+        unsafe {
+            jolokia_buffer_free(&raw mut secret_key);
+            jolokia_buffer_free(&raw mut secret_key);
+        }
+
+        assert!(secret_key.data.is_null());
+        assert_eq!(secret_key.len, 0);
+    }
+
+    #[test]
+    fn status_message_regular() {
+        let message = unsafe { CStr::from_ptr(jolokia_status_message(JolokiaStatus::Decrypt)) };
+
+        assert!(
+            message
+                .to_str()
+                .unwrap()
+                .starts_with("Could not decrypt input.")
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! ASCII armor, ciphertext as a block of text.
+//!
+//! Armor is base64, wrapped and between markers, like PEM:
+//!
+//! ```text
+//! -----BEGIN JOLOKIA MESSAGE-----
+//! Algorithm: chacha20poly1305
+//!
+//! Q0gyMAFfjKmVdu1VsMxxYh8ZSl2zNIIHdxM6tZrf6GYazEzq7WvXAnVRfSD8SJWO
+//! hG5WPbIJwQ
+//! -----END JOLOKIA MESSAGE-----
+//! ```
+//!
+//! Unlike a single line of base64, it survives email clients and
+//! copy-pasting (which wrap long lines), and it is easy to spot in the
+//! middle of other text.
+//!
+//! Headers (`Key: value` lines, up to the first blank line) are for
+//! humans. They are skipped on the way back, the ciphertext has its own
+//! header.
+
+use std::io::{self, BufRead, Write};
+
+use crate::traits::{self, Error};
+
+pub const BEGIN: &str = "-----BEGIN JOLOKIA MESSAGE-----";
+pub const END: &str = "-----END JOLOKIA MESSAGE-----";
+
+// Same as PEM.
+const COLUMNS: usize = 64;
+
+/// Whether `prefix` is the start of armored ciphertext.
+///
+/// The prefix must be at least as long as [`BEGIN`].
+#[must_use]
+pub fn is_armored(prefix: &[u8]) -> bool {
+    prefix.starts_with(BEGIN.as_bytes())
+}
+
+/// When written to, it wraps base64 text in armor.
+///
+/// The text is wrapped as it goes, [`ArmorSink::finish()`] writes the
+/// end marker.
+pub struct ArmorSink<W: Write> {
+    writer: W,
+    column: usize,
+}
+
+impl<W: Write> ArmorSink<W> {
+    /// Start the armor, with the begin marker and the headers.
+    ///
+    /// # Errors
+    ///
+    /// Errors if writing to `writer` fails.
+    pub fn new(mut writer: W, algorithm_id: &str) -> traits::Result<Self> {
+        writeln!(writer, "{BEGIN}\nAlgorithm: {algorithm_id}\n")
+            .map_err(|e| Error::Write(e.to_string()))?;
+        Ok(Self { writer, column: 0 })
+    }
+
+    /// End the armor, and hand back the writer.
+    ///
+    /// # Errors
+    ///
+    /// Errors if writing to the writer fails.
+    pub fn finish(mut self) -> traits::Result<W> {
+        if self.column > 0 {
+            writeln!(self.writer).map_err(|e| Error::Write(e.to_string()))?;
+        }
+        writeln!(self.writer, "{END}").map_err(|e| Error::Write(e.to_string()))?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ArmorSink<W> {
+    // All or nothing, the base64 encoder doesn't handle partial writes
+    // (it returns `Ok(0)`, and `write_all()` fails).
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let (line, after) = rest.split_at(rest.len().min(COLUMNS - self.column));
+            self.writer.write_all(line)?;
+            self.column += line.len();
+            if self.column == COLUMNS {
+                self.writer.write_all(b"\n")?;
+                self.column = 0;
+            }
+            rest = after;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// When read from, it strips the armor, and yields the base64 text.
+///
+/// Line breaks (`\n` or `\r\n`) and surrounding whitespace are removed.
+/// Anything after the end marker is ignored.
+pub struct ArmorSource<R: BufRead> {
+    reader: R,
+    line: Vec<u8>,
+    position: usize,
+    is_done: bool,
+}
+
+impl<R: BufRead> ArmorSource<R> {
+    /// Read the begin marker and the headers.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the begin marker is missing, if a header is malformed,
+    /// or if reading fails.
+    pub fn new(mut reader: R) -> traits::Result<Self> {
+        let mut line = Vec::new();
+        if !read_line(&mut reader, &mut line)? || line != BEGIN.as_bytes() {
+            return Err(malformed(&format!("expected '{BEGIN}'")));
+        }
+        loop {
+            if !read_line(&mut reader, &mut line)? {
+                return Err(malformed(&format!("expected '{END}'")));
+            }
+            if line.is_empty() {
+                break;
+            }
+            if !line.windows(2).any(|w| w == b": ") {
+                return Err(malformed("headers must be followed by a blank line"));
+            }
+        }
+
+        Ok(Self {
+            reader,
+            line: Vec::new(),
+            position: 0,
+            is_done: false,
+        })
+    }
+}
+
+impl<R: BufRead> io::Read for ArmorSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.line.len() {
+            if self.is_done {
+                return Ok(0);
+            }
+            self.position = 0;
+            if !read_line(&mut self.reader, &mut self.line).map_err(io::Error::other)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    malformed(&format!("expected '{END}'")),
+                ));
+            }
+            if self.line == END.as_bytes() {
+                self.line.clear();
+                self.is_done = true;
+            }
+        }
+
+        let n = buf.len().min(self.line.len() - self.position);
+        buf[..n].copy_from_slice(&self.line[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Read a line into `line`, trimmed. `false` at the end of the input.
+fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>) -> traits::Result<bool> {
+    line.clear();
+    let n = reader
+        .read_until(b'\n', line)
+        .map_err(|e| Error::Read(e.to_string()))?;
+    *line = line.trim_ascii().to_vec();
+    Ok(n > 0)
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::Base64Decode(format!("Malformed armor, {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    fn armor(text: &[u8]) -> String {
+        let mut sink = ArmorSink::new(Vec::new(), "chacha20poly1305").unwrap();
+        sink.write_all(text).unwrap();
+        String::from_utf8(sink.finish().unwrap()).unwrap()
+    }
+
+    fn dearmor(armored: &str) -> traits::Result<Vec<u8>> {
+        let mut source = ArmorSource::new(armored.as_bytes())?;
+        let mut text = Vec::new();
+        source
+            .read_to_end(&mut text)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        Ok(text)
+    }
+
+    #[test]
+    fn armor_wraps_lines() {
+        let text = "A".repeat(COLUMNS) + &"B".repeat(10);
+
+        assert_eq!(
+            armor(text.as_bytes()),
+            format!(
+                "{BEGIN}\nAlgorithm: chacha20poly1305\n\n{}\n{}\n{END}\n",
+                "A".repeat(COLUMNS),
+                "B".repeat(10)
+            )
+        );
+    }
+
+    #[test]
+    fn armor_exact_line_has_no_empty_line() {
+        let text = "A".repeat(COLUMNS);
+
+        assert!(armor(text.as_bytes()).ends_with(&format!("{text}\n{END}\n")));
+    }
+
+    #[test]
+    fn armor_round_trip() {
+        let text = "Q0gyMAF".repeat(100);
+
+        assert_eq!(dearmor(&armor(text.as_bytes())).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn dearmor_crlf_and_indentation() {
+        let armored = format!("  {BEGIN}\r\nAlgorithm: hpke\r\n\r\n  Q0gy\r\nMAF\r\n{END}\r\n");
+
+        assert_eq!(dearmor(&armored).unwrap(), b"Q0gyMAF");
+    }
+
+    #[test]
+    fn dearmor_ignores_trailing_text() {
+        let armored = format!("{BEGIN}\n\nQ0gy\n{END}\n-- \nAlice\n");
+
+        assert_eq!(dearmor(&armored).unwrap(), b"Q0gy");
+    }
+
+    #[test]
+    fn dearmor_missing_end_is_error() {
+        let armored = format!("{BEGIN}\n\nQ0gy\n");
+
+        assert!(dearmor(&armored).is_err());
+    }
+
+    #[test]
+    fn dearmor_missing_blank_line_is_error() {
+        let armored = format!("{BEGIN}\nQ0gy\n{END}\n");
+
+        assert!(matches!(dearmor(&armored), Err(Error::Base64Decode(_))));
+    }
+
+    #[test]
+    fn is_armored_regular() {
+        assert!(is_armored(armor(b"Q0gy").as_bytes()));
+        assert!(!is_armored(b"Q0gyMAF"));
+        assert!(!is_armored(b"-----BEGIN AGE ENCRYPTED FILE-----"));
+    }
+}
@@ -1,9 +1,119 @@
+//! Ciphers. ChaCha20-Poly1305 is always built, the others have a Cargo
+//! feature of their own (`age`, `password`, `hpke`, `rotn`,
+//! `brainfuck`).
+
+#[cfg(feature = "age")]
+pub mod age;
+#[cfg(feature = "brainfuck")]
 pub mod brainfuck;
 pub mod chacha;
+#[cfg(feature = "hpke")]
 pub mod hpke;
+#[cfg(feature = "hpke")]
+pub mod hpke_pq;
+#[cfg(feature = "password")]
+pub mod password;
+pub mod registry;
+#[cfg(feature = "rotn")]
 pub mod rotn;
 
+#[cfg(feature = "age")]
+pub use age::{Age, AgePassword};
+#[cfg(feature = "brainfuck")]
 pub use brainfuck::Brainfuck;
 pub use chacha::ChaCha20Poly1305;
+#[cfg(feature = "hpke")]
 pub use hpke::Hpke;
+#[cfg(feature = "hpke")]
+pub use hpke_pq::HpkePq;
+#[cfg(feature = "password")]
+pub use password::Password;
+pub use registry::CipherRegistry;
+#[cfg(feature = "rotn")]
 pub use rotn::RotN;
+
+use crate::traits::Cipher;
+
+/// Look up a key-based cipher by algorithm ID (e.g., `"hpke"`).
+///
+/// This is for bindings, which take the algorithm as text. Ciphers
+/// keyed by a passphrase, and toy ciphers, are not included. Ciphers
+/// in the [global registry](CipherRegistry::global()) are.
+#[must_use]
+pub fn from_algorithm_id(algorithm_id: &str) -> Option<Box<dyn Cipher>> {
+    let ciphers: Vec<Box<dyn Cipher>> = vec![
+        Box::new(ChaCha20Poly1305),
+        #[cfg(feature = "hpke")]
+        Box::new(Hpke),
+        #[cfg(feature = "hpke")]
+        Box::new(HpkePq),
+        #[cfg(feature = "age")]
+        Box::new(Age),
+    ];
+    ciphers
+        .into_iter()
+        .find(|cipher| cipher.algorithm_id() == algorithm_id)
+        .or_else(|| CipherRegistry::global().get(algorithm_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use static_assertions::assert_impl_all;
+
+    use super::*;
+
+    #[cfg(feature = "age")]
+    assert_impl_all!(Age: Cipher, Send, Sync);
+    #[cfg(feature = "age")]
+    assert_impl_all!(AgePassword: Cipher, Send, Sync);
+    #[cfg(feature = "brainfuck")]
+    assert_impl_all!(Brainfuck: Cipher, Send, Sync);
+    assert_impl_all!(ChaCha20Poly1305: Cipher, Send, Sync);
+    #[cfg(feature = "hpke")]
+    assert_impl_all!(Hpke: Cipher, Send, Sync);
+    #[cfg(feature = "hpke")]
+    assert_impl_all!(HpkePq: Cipher, Send, Sync);
+    #[cfg(feature = "password")]
+    assert_impl_all!(Password: Cipher, Send, Sync);
+    #[cfg(feature = "rotn")]
+    assert_impl_all!(RotN: Cipher, Send, Sync);
+
+    assert_impl_all!(Box<dyn Cipher>: Send, Sync);
+    assert_impl_all!(Arc<dyn Cipher>: Send, Sync);
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn from_algorithm_id_regular() {
+        let cipher = from_algorithm_id("hpke-pq").unwrap();
+
+        assert_eq!(cipher.algorithm_id(), HpkePq.algorithm_id());
+    }
+
+    #[test]
+    fn from_algorithm_id_unknown() {
+        assert!(from_algorithm_id("rot13").is_none());
+        assert!(from_algorithm_id("").is_none());
+    }
+
+    #[test]
+    fn cipher_can_be_shared_between_threads() {
+        let cipher: Arc<dyn Cipher> = Arc::new(ChaCha20Poly1305);
+        let key = [0u8; 32];
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cipher = Arc::clone(&cipher);
+                std::thread::spawn(move || {
+                    let encrypted = cipher.encrypt(&key, b"hello").unwrap();
+                    cipher.decrypt(&key, &encrypted).unwrap()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), b"hello");
+        }
+    }
+}
@@ -0,0 +1,1018 @@
+//! age file format (v1), for interoperability with `age` and `rage`.
+//!
+//! See the [specification](https://age-encryption.org/v1). Two kinds of
+//! recipients are supported: X25519 keys ([`Age`]), and passphrases
+//! ([`AgePassword`], with scrypt). Files written here can be decrypted
+//! by `age`, and files written by `age` can be decrypted here.
+//!
+//! # Message Format
+//!
+//! ```text
+//! age-encryption.org/v1
+//! -> X25519 <ephemeral share>      the stanza of the recipient
+//! <wrapped file key>
+//! --- <header MAC>
+//! [ nonce (16) ]
+//! [ chunk 1 65536-byte ciphertext + 16-byte tag ]
+//!   ⋮
+//! [ last chunk, 65536-byte (or less) ciphertext + 16-byte tag ]
+//! ```
+//!
+//! - A random 16-byte **file key** is wrapped for the recipient, in a
+//!   stanza. With X25519, the wrapping key comes from an ephemeral-
+//!   static Diffie-Hellman. With scrypt, from the passphrase (and the
+//!   stanza is `-> scrypt <salt> <work factor>`).
+//!
+//! - The **header MAC** (HMAC-SHA-256, keyed from the file key) covers
+//!   the header, up to and including the `---`.
+//!
+//! - The **payload** is ChaCha20-Poly1305, keyed from the file key and
+//!   the nonce. Chunks have no length prefix, each AEAD nonce is an
+//!   11-byte big-endian counter, and a 1-byte "last chunk" flag. Only
+//!   an empty plaintext has an empty last chunk.
+//!
+//! Armored files (`-----BEGIN AGE ENCRYPTED FILE-----`) are decrypted
+//! too, but only binary files are written.
+//!
+//! age has no associated data. Keys are Bech32-encoded (`age1...` and
+//! `AGE-SECRET-KEY-1...`), see [`encode_recipient()`], and
+//! [`decode_key()`].
+
+use std::io::{self, Read, Write};
+
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use bech32::{Bech32, Hrp};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305 as ChaCha20Poly1305_, Key, Nonce};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use secrecy::{SecretSlice, zeroize::Zeroizing};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::traits::{
+    self, Base64Decode, Base64Encode, Cipher, CountingReader, CountingWriter, Error, GeneratedKey,
+    KeygenOptions, StreamStats,
+};
+use crate::{entropy, wire};
+
+/// First line of the header, the version.
+pub(crate) const MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+pub(crate) const ARMOR_BEGIN: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+const ARMOR_END: &[u8] = b"-----END AGE ENCRYPTED FILE-----";
+
+const X25519_LABEL: &[u8] = b"age-encryption.org/v1/X25519";
+const SCRYPT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+
+const RECIPIENT_HRP: &str = "age";
+const IDENTITY_HRP: &str = "age-secret-key-";
+
+const FILE_KEY_LEN: usize = 16;
+const NONCE_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const CHUNK_LEN: usize = 64 * 1024;
+
+// Stanza bodies are wrapped at 64 columns.
+const COLUMNS: usize = 64;
+
+// Headers are a few hundred bytes, don't read gigabytes looking for the
+// end of one.
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+/// Default scrypt work factor (`log2(N)`), same as `age`.
+const DEFAULT_WORK_FACTOR: u8 = 18;
+/// Highest scrypt work factor accepted for decryption, same as `age`.
+const MAX_WORK_FACTOR: u8 = 22;
+
+type FileKey = Zeroizing<[u8; FILE_KEY_LEN]>;
+
+/// Finds the stanza meant for us, and unwraps the file key from it.
+type UnwrapFileKey<'a> = dyn Fn(&[Stanza]) -> traits::Result<FileKey> + 'a;
+
+/// X25519 recipients. Encrypt to an `age1...` public key, and decrypt
+/// with an `AGE-SECRET-KEY-1...` private key (both as raw bytes).
+pub struct Age;
+
+impl Cipher for Age {
+    fn algorithm_id(&self) -> &'static str {
+        "age"
+    }
+
+    /// Generate an X25519 keypair.
+    fn generate_key(&self, options: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        options.expect_fixed_size(256)?;
+        let private = Zeroizing::new(entropy::generate::<32>()?);
+        let public = PublicKey::from(&StaticSecret::from(*private));
+        Ok(GeneratedKey::Asymmetric {
+            public: SecretSlice::from(public.as_bytes().to_vec()),
+            private: SecretSlice::from(private.to_vec()),
+        })
+    }
+
+    fn encrypt_stream(
+        &self,
+        public_key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let recipient = <[u8; 32]>::try_from(public_key).map_err(|_| Error::Encrypt)?;
+        let recipient = PublicKey::from(recipient);
+
+        let ephemeral = StaticSecret::from(*Zeroizing::new(entropy::generate::<32>()?));
+        let share = PublicKey::from(&ephemeral);
+        let shared_secret = ephemeral.diffie_hellman(&recipient);
+        if !shared_secret.was_contributory() {
+            return Err(Error::Encrypt);
+        }
+
+        let file_key = Zeroizing::new(entropy::generate::<FILE_KEY_LEN>()?);
+        let salt = [share.as_bytes().as_slice(), recipient.as_bytes()].concat();
+        let wrapping_key = hkdf(&salt, shared_secret.as_bytes(), X25519_LABEL);
+        let stanza = Stanza {
+            kind: "X25519".to_string(),
+            args: vec![share.as_bytes().base64_encode()],
+            body: wrap(&wrapping_key, &file_key)?,
+        };
+
+        encrypt(&file_key, &stanza, reader, writer)
+    }
+
+    fn decrypt_stream(
+        &self,
+        private_key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let identity =
+            Zeroizing::new(<[u8; 32]>::try_from(private_key).map_err(|_| Error::Decrypt)?);
+        let identity = StaticSecret::from(*identity);
+        let public_key = PublicKey::from(&identity);
+
+        let unwrap_file_key = |stanzas: &[Stanza]| {
+            for stanza in stanzas.iter().filter(|stanza| stanza.kind == "X25519") {
+                let [share] = stanza.args.as_slice() else {
+                    return Err(Error::Decrypt);
+                };
+                let share = <[u8; 32]>::try_from(share.base64_decode()?.as_slice())
+                    .map_err(|_| Error::Decrypt)?;
+                let shared_secret = identity.diffie_hellman(&PublicKey::from(share));
+                if !shared_secret.was_contributory() {
+                    return Err(Error::Decrypt);
+                }
+
+                let salt = [share.as_slice(), public_key.as_bytes()].concat();
+                let wrapping_key = hkdf(&salt, shared_secret.as_bytes(), X25519_LABEL);
+                // Not for us, maybe the next one is.
+                if let Ok(file_key) = unwrap(&wrapping_key, &stanza.body) {
+                    return Ok(file_key);
+                }
+            }
+            if stanzas.iter().any(|stanza| stanza.kind == "scrypt") {
+                return Err(Error::Algorithm);
+            }
+            Err(Error::Decrypt)
+        };
+
+        decrypt(&unwrap_file_key, reader, writer)
+    }
+}
+
+/// Passphrase recipients. The "key" given to this cipher is the
+/// passphrase itself.
+pub struct AgePassword {
+    work_factor: u8,
+}
+
+impl Default for AgePassword {
+    fn default() -> Self {
+        Self {
+            work_factor: DEFAULT_WORK_FACTOR,
+        }
+    }
+}
+
+impl AgePassword {
+    /// Use a custom scrypt work factor (`log2(N)`) for encryption.
+    ///
+    /// Decryption always uses the work factor stored in the header.
+    #[must_use]
+    pub fn with_work_factor(work_factor: u8) -> Self {
+        Self { work_factor }
+    }
+}
+
+impl Cipher for AgePassword {
+    fn algorithm_id(&self) -> &'static str {
+        "age"
+    }
+
+    /// Passphrases are chosen, not generated.
+    fn generate_key(&self, _: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        Ok(GeneratedKey::None)
+    }
+
+    fn encrypt_stream(
+        &self,
+        passphrase: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let salt = entropy::generate::<16>()?;
+        let wrapping_key = scrypt(passphrase, &salt, self.work_factor)?;
+
+        let file_key = Zeroizing::new(entropy::generate::<FILE_KEY_LEN>()?);
+        let stanza = Stanza {
+            kind: "scrypt".to_string(),
+            args: vec![(&salt).base64_encode(), self.work_factor.to_string()],
+            body: wrap(&wrapping_key, &file_key)?,
+        };
+
+        encrypt(&file_key, &stanza, reader, writer)
+    }
+
+    fn decrypt_stream(
+        &self,
+        passphrase: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let unwrap_file_key = |stanzas: &[Stanza]| {
+            // A passphrase is the only recipient, or it's not age.
+            let [stanza] = stanzas else {
+                return Err(if stanzas.iter().any(|stanza| stanza.kind == "scrypt") {
+                    Error::Decrypt
+                } else {
+                    Error::Algorithm
+                });
+            };
+            if stanza.kind != "scrypt" {
+                return Err(Error::Algorithm);
+            }
+            let [salt, work_factor] = stanza.args.as_slice() else {
+                return Err(Error::Decrypt);
+            };
+            let salt = <[u8; 16]>::try_from(salt.base64_decode()?.as_slice())
+                .map_err(|_| Error::Decrypt)?;
+            let work_factor = parse_work_factor(work_factor)?;
+
+            let wrapping_key = scrypt(passphrase, &salt, work_factor)?;
+            unwrap(&wrapping_key, &stanza.body)
+        };
+
+        decrypt(&unwrap_file_key, reader, writer)
+    }
+}
+
+/// Encode a public key (raw bytes) as an `age1...` recipient.
+///
+/// # Errors
+///
+/// Errors if the key is not 32 bytes.
+pub fn encode_recipient(public_key: &[u8]) -> traits::Result<String> {
+    if public_key.len() != 32 {
+        return Err(Error::Key);
+    }
+    bech32::encode_lower::<Bech32>(Hrp::parse_unchecked(RECIPIENT_HRP), public_key)
+        .map_err(|_| Error::Key)
+}
+
+/// Encode a private key (raw bytes) as an `AGE-SECRET-KEY-1...`
+/// identity.
+///
+/// # Errors
+///
+/// Errors if the key is not 32 bytes.
+pub fn encode_identity(private_key: &[u8]) -> traits::Result<Zeroizing<String>> {
+    if private_key.len() != 32 {
+        return Err(Error::Key);
+    }
+    bech32::encode_upper::<Bech32>(Hrp::parse_unchecked(IDENTITY_HRP), private_key)
+        .map(Zeroizing::new)
+        .map_err(|_| Error::Key)
+}
+
+/// Whether `key` looks like an age recipient or identity.
+///
+/// Identity files written by `age-keygen` start with comments, they
+/// are skipped (see [`decode_key()`]).
+#[must_use]
+pub fn is_age_key(key: &str) -> bool {
+    key_line(key).is_some_and(|key| {
+        key.starts_with("age1") || key.to_lowercase().starts_with("age-secret-key-1")
+    })
+}
+
+/// Decode an age recipient, or identity, into a key (raw bytes).
+///
+/// Empty lines and `#` comments around the key are skipped, like in
+/// identity files written by `age-keygen`.
+///
+/// # Errors
+///
+/// Errors if the key is not a valid recipient or identity.
+pub fn decode_key(key: &str) -> traits::Result<Zeroizing<Vec<u8>>> {
+    let mut lines = key
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let (Some(key), None) = (lines.next(), lines.next()) else {
+        return Err(Error::Key);
+    };
+    let (hrp, key) = bech32::decode(key).map_err(|_| Error::Key)?;
+    let key = Zeroizing::new(key);
+    let hrp = hrp.to_lowercase();
+    if (hrp != RECIPIENT_HRP && hrp != IDENTITY_HRP) || key.len() != 32 {
+        return Err(Error::Key);
+    }
+    Ok(key)
+}
+
+/// First line that isn't empty or a comment.
+fn key_line(key: &str) -> Option<&str> {
+    key.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+struct Stanza {
+    kind: String,
+    args: Vec<String>,
+    body: Vec<u8>,
+}
+
+impl Stanza {
+    fn write_to(&self, header: &mut Vec<u8>) {
+        header.extend_from_slice(b"-> ");
+        header.extend_from_slice(self.kind.as_bytes());
+        for arg in &self.args {
+            header.push(b' ');
+            header.extend_from_slice(arg.as_bytes());
+        }
+        header.push(b'\n');
+
+        let body = self.body.base64_encode();
+        for line in body.as_bytes().chunks(COLUMNS) {
+            header.extend_from_slice(line);
+            header.push(b'\n');
+        }
+        // The last line is always short, even if it has to be empty.
+        if body.len().is_multiple_of(COLUMNS) {
+            header.push(b'\n');
+        }
+    }
+}
+
+fn encrypt(
+    file_key: &[u8; FILE_KEY_LEN],
+    stanza: &Stanza,
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> traits::Result<StreamStats> {
+    let mut reader = CountingReader::new(reader);
+    let mut writer = CountingWriter::new(writer);
+
+    let mut header = MAGIC.to_vec();
+    stanza.write_to(&mut header);
+    header.extend_from_slice(b"---");
+    let mac = header_mac(file_key, &header).finalize().into_bytes();
+    header.push(b' ');
+    header.extend_from_slice(mac.as_slice().base64_encode().as_bytes());
+    header.push(b'\n');
+
+    let nonce = entropy::generate::<NONCE_LEN>()?;
+    writer
+        .write_all(&header)
+        .and_then(|()| writer.write_all(&nonce))
+        .map_err(|e| Error::Write(e.to_string()))?;
+
+    let payload_key = hkdf(&nonce, file_key, b"payload");
+    let cipher = ChaCha20Poly1305_::new(<&Key>::from(&*payload_key));
+
+    // Plaintext, wiped on drop (i.e., on success and on error).
+    let mut buffer = Zeroizing::new(vec![0u8; CHUNK_LEN]);
+    let mut next = Zeroizing::new([0u8; 1]);
+    let mut filled = wire::read_up_to(&mut reader, &mut buffer)?;
+    let mut chunks = 0;
+    loop {
+        // A full chunk is the last one only if nothing comes after it.
+        let last = filled < CHUNK_LEN || wire::read_up_to(&mut reader, next.as_mut_slice())? == 0;
+        let chunk = cipher
+            .encrypt(&chunk_nonce(chunks, last), &buffer[..filled])
+            .map_err(|_| Error::Encrypt)?;
+        writer
+            .write_all(&chunk)
+            .map_err(|e| Error::Write(e.to_string()))?;
+        chunks += 1;
+        if last {
+            break;
+        }
+        buffer[0] = next[0];
+        filled = 1 + wire::read_up_to(&mut reader, &mut buffer[1..])?;
+    }
+
+    Ok(StreamStats {
+        bytes_in: reader.count,
+        bytes_out: writer.count,
+        chunks,
+    })
+}
+
+fn decrypt(
+    unwrap_file_key: &UnwrapFileKey<'_>,
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> traits::Result<StreamStats> {
+    let mut reader = CountingReader::new(reader);
+    let mut writer = CountingWriter::new(writer);
+
+    // Binary files start with `a`, armored files with `-`.
+    let mut first = [0u8; 1];
+    let n = wire::read_up_to(&mut reader, &mut first)?;
+    let chunks = if first[..n] == ARMOR_BEGIN[..1] {
+        let mut armored = first[..n].to_vec();
+        reader
+            .read_to_end(&mut armored)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        let binary = dearmor(&armored)?;
+        decrypt_binary(unwrap_file_key, &mut binary.as_slice(), &mut writer)?
+    } else {
+        let mut reader = (&first[..n]).chain(&mut reader);
+        decrypt_binary(unwrap_file_key, &mut reader, &mut writer)?
+    };
+
+    Ok(StreamStats {
+        bytes_in: reader.count,
+        bytes_out: writer.count,
+        chunks,
+    })
+}
+
+/// Returns the number of chunks.
+fn decrypt_binary(
+    unwrap_file_key: &UnwrapFileKey<'_>,
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> traits::Result<u64> {
+    let mut header = vec![0u8; MAGIC.len()];
+    if wire::read_up_to(reader, &mut header)? < MAGIC.len() || header != MAGIC {
+        return Err(Error::Algorithm);
+    }
+
+    let mut stanzas = Vec::new();
+    let mac = loop {
+        let line = read_line(reader, &mut header)?;
+        if let Some(mac) = line.strip_prefix(b"--- ") {
+            break mac.to_vec();
+        }
+        let Some(args) = line.strip_prefix(b"-> ") else {
+            return Err(Error::Decrypt);
+        };
+        let args = String::from_utf8(args.to_vec()).map_err(|_| Error::Decrypt)?;
+        let mut args = args.split(' ').map(str::to_string);
+        let kind = args.next().ok_or(Error::Decrypt)?;
+
+        let mut body = Vec::new();
+        loop {
+            let line = read_line(reader, &mut header)?;
+            if line.len() > COLUMNS {
+                return Err(Error::Decrypt);
+            }
+            body.extend_from_slice(&line);
+            if line.len() < COLUMNS {
+                break;
+            }
+        }
+        stanzas.push(Stanza {
+            kind,
+            args: args.collect(),
+            body: body.as_slice().base64_decode()?,
+        });
+    };
+    // The MAC covers the header up to `---`, not the space after it.
+    header.truncate(header.len() - b" \n".len() - mac.len());
+
+    let file_key = unwrap_file_key(&stanzas)?;
+    header_mac(file_key.as_slice(), &header)
+        .verify_slice(&mac.as_slice().base64_decode()?)
+        .map_err(|_| Error::Decrypt)?;
+
+    let mut nonce = [0u8; NONCE_LEN];
+    reader
+        .read_exact(&mut nonce)
+        .map_err(|e| Error::Read(e.to_string()))?;
+    let payload_key = hkdf(&nonce, file_key.as_slice(), b"payload");
+    let cipher = ChaCha20Poly1305_::new(<&Key>::from(&*payload_key));
+
+    let mut buffer = vec![0u8; CHUNK_LEN + TAG_LEN];
+    let mut next = [0u8; 1];
+    let mut filled = wire::read_up_to(reader, &mut buffer)?;
+    let mut chunks = 0;
+    loop {
+        let last = filled < buffer.len() || wire::read_up_to(reader, &mut next)? == 0;
+        // Plaintext, wiped on drop.
+        let chunk = Zeroizing::new(
+            cipher
+                .decrypt(&chunk_nonce(chunks, last), &buffer[..filled])
+                .map_err(|_| Error::Decrypt)?,
+        );
+        // Only an empty plaintext ends with an empty chunk.
+        if last && chunk.is_empty() && chunks > 0 {
+            return Err(Error::Decrypt);
+        }
+        writer
+            .write_all(&chunk)
+            .map_err(|e| Error::Write(e.to_string()))?;
+        chunks += 1;
+        if last {
+            break;
+        }
+        buffer[0] = next[0];
+        filled = 1 + wire::read_up_to(reader, &mut buffer[1..])?;
+    }
+
+    Ok(chunks)
+}
+
+/// Read a header line (returned without the `\n`), and append it to
+/// `header`, as-is.
+///
+/// The payload comes right after the header, so this reads byte by
+/// byte, to not consume any of it.
+fn read_line(reader: &mut dyn Read, header: &mut Vec<u8>) -> traits::Result<Vec<u8>> {
+    let start = header.len();
+    let mut byte = [0u8; 1];
+    loop {
+        if wire::read_up_to(reader, &mut byte)? == 0 {
+            return Err(Error::Read(
+                io::Error::from(io::ErrorKind::UnexpectedEof).to_string(),
+            ));
+        }
+        header.push(byte[0]);
+        if byte[0] == b'\n' {
+            return Ok(header[start..header.len() - 1].to_vec());
+        }
+        if header.len() > MAX_HEADER_LEN {
+            return Err(Error::Decrypt);
+        }
+    }
+}
+
+/// Armor is strict PEM: canonical padded base64, wrapped at 64 columns.
+///
+/// Like age, anything looser is rejected (lines of other lengths, stray
+/// spaces, padding before the end). Whitespace is only allowed around
+/// the armor, and lines may end with CRLF.
+fn dearmor(armored: &[u8]) -> traits::Result<Vec<u8>> {
+    const COLUMNS: usize = 64;
+
+    let Some(body) = armored
+        .trim_ascii()
+        .strip_prefix(ARMOR_BEGIN)
+        .and_then(|armored| armored.strip_suffix(ARMOR_END))
+    else {
+        return Err(Error::Algorithm);
+    };
+
+    // The markers are on lines of their own, so the body starts and
+    // ends with an empty "line".
+    let lines: Vec<&[u8]> = body
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect();
+    let [b"", lines @ .., b""] = lines.as_slice() else {
+        return Err(malformed_armor("the markers must be on their own lines"));
+    };
+    let Some((last, full)) = lines.split_last() else {
+        return Err(malformed_armor("there is no data"));
+    };
+    if full.iter().any(|line| line.len() != COLUMNS) || !(1..=COLUMNS).contains(&last.len()) {
+        return Err(malformed_armor("lines must be 64 columns, but the last"));
+    }
+
+    // The engine rejects padding in the middle, whitespace, and
+    // non-canonical encodings (e.g., unused bits that aren't zero).
+    BASE64_STANDARD
+        .decode(lines.concat())
+        .map_err(|e| malformed_armor(&e.to_string()))
+}
+
+fn malformed_armor(reason: &str) -> Error {
+    Error::Base64Decode(format!("Malformed age armor, {reason}"))
+}
+
+fn chunk_nonce(counter: u64, last: bool) -> Nonce {
+    // 11-byte big-endian counter, then the "last chunk" flag.
+    let mut nonce = [0u8; 12];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = u8::from(last);
+    Nonce::from(nonce)
+}
+
+fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, key.as_mut_slice())
+        .expect("32 bytes is a valid HKDF-SHA-256 output length");
+    key
+}
+
+fn header_mac(file_key: &[u8], header: &[u8]) -> Hmac<Sha256> {
+    let key = hkdf(&[], file_key, b"header");
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_slice()).expect("HMAC takes keys of any length");
+    mac.update(header);
+    mac
+}
+
+fn scrypt(
+    passphrase: &[u8],
+    salt: &[u8; 16],
+    work_factor: u8,
+) -> traits::Result<Zeroizing<[u8; 32]>> {
+    let params = scrypt::Params::new(work_factor, 8, 1)
+        .map_err(|_| Error::Parameter(format!("Invalid scrypt work factor: {work_factor}")))?;
+    let salt = [SCRYPT_LABEL, salt].concat();
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(passphrase, &salt, &params, key.as_mut_slice())
+        .expect("32 bytes is a valid scrypt output length");
+    Ok(key)
+}
+
+/// Decimal, without leading zeros.
+fn parse_work_factor(work_factor: &str) -> traits::Result<u8> {
+    if work_factor.starts_with('0') || !work_factor.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::Decrypt);
+    }
+    let work_factor = work_factor.parse::<u8>().map_err(|_| Error::Decrypt)?;
+    if work_factor > MAX_WORK_FACTOR {
+        return Err(Error::Parameter(format!(
+            "scrypt work factor {work_factor} is too high (at most {MAX_WORK_FACTOR})"
+        )));
+    }
+    Ok(work_factor)
+}
+
+/// Zero nonce, wrapping keys are used once.
+fn wrap(wrapping_key: &[u8; 32], file_key: &[u8; FILE_KEY_LEN]) -> traits::Result<Vec<u8>> {
+    ChaCha20Poly1305_::new(<&Key>::from(wrapping_key))
+        .encrypt(&Nonce::default(), file_key.as_slice())
+        .map_err(|_| Error::Encrypt)
+}
+
+fn unwrap(wrapping_key: &[u8; 32], body: &[u8]) -> traits::Result<FileKey> {
+    if body.len() != FILE_KEY_LEN + TAG_LEN {
+        return Err(Error::Decrypt);
+    }
+    let file_key = Zeroizing::new(
+        ChaCha20Poly1305_::new(<&Key>::from(wrapping_key))
+            .decrypt(&Nonce::default(), body)
+            .map_err(|_| Error::Decrypt)?,
+    );
+    <[u8; FILE_KEY_LEN]>::try_from(file_key.as_slice())
+        .map(Zeroizing::new)
+        .map_err(|_| Error::Decrypt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors, written by `rage` 0.12 (which adds "grease" stanzas,
+    // that must be ignored).
+    const IDENTITY: &str = "\
+# created: 2026-10-18T01:15:55Z
+# public key: age1hxrsgh77k8jzk60gdfccf3n094ffzfdvuldlptkarpprq4079g9q9lslkq
+AGE-SECRET-KEY-1QVEPHMCSAGFMDKG87F2CC2QGT8ENGNWLCPWQV7P8DKEKHC53GQJSCE0W5G
+";
+    const RECIPIENT: &str = "age1hxrsgh77k8jzk60gdfccf3n094ffzfdvuldlptkarpprq4079g9q9lslkq";
+    // `Hello, World!`, in base64 (the file is binary).
+    const ENCRYPTED: &str = "YWdlLWVuY3J5cHRpb24ub3JnL3YxCi0+IFgyNTUxOSA1VStKUVFFWnVsSyt0R2IyQ3BQaTEzSm1tZ3ovdHhvdDBtcUNxakt3YTFvCjJHaEFkUngyc0JGU2RTYlFGc2NOZ3hHU3pibW9sTWFQbi9oOGVFWWlHbU0KLT4gOkJ7aCUzOFYtZ3JlYXNlIFV0T2tmUCBBMSNfIF5qfDoKcVpiZAotLS0gOFE3aG82S3pyZDJLZnJmYWpvKzRXZnBBS1FveXlyckh5cy9GWkRqTjllUQrjPxX3idBTFGGaM+CWpIzrZyrkUjwcHmzi24XNwpxQ7zHvBFoXFkDkopYWdeE";
+    // Same message, with `rage -a`.
+    const ARMORED: &str = "\
+-----BEGIN AGE ENCRYPTED FILE-----
+YWdlLWVuY3J5cHRpb24ub3JnL3YxCi0+IFgyNTUxOSBYNGllY24vdnlDUThPRzVZ
+cCsvb0xQS2M5RkpHTDd3cHlKZWN5K2xIWDFVCmdFOEhINy9UY3E4S1RpY3dhYzJL
+N3ZVcXJ6S1BLT05kTnhseGRwSHVVUzgKLT4gdiVHdz8hQy1ncmVhc2UgISYsTGVZ
+SWsgdWp7IFUgQDwoYG5NCkRidkczb1hpZ3IreVdHRWVHM0dybkxrM3pvUkluMWlP
+LzAzM3BLUlM5R0lJZkJrdDFaZjFsYmRjaEVDQkJ6MXgKQy9BakQzd3UKLS0tIHZ0
+K0JIaTR2c0o3Sjh5VGlEYWNYZGQ4akxBczhkNVRBNDF6NEpNeGZ3cTQKBQRSCdkB
+Yg7MX/ljzmBEB2TRenFWB0yU7Dik4DbYds69XutSTbBTb3b9068z
+-----END AGE ENCRYPTED FILE-----
+";
+    // Empty message.
+    const ENCRYPTED_EMPTY: &str = "YWdlLWVuY3J5cHRpb24ub3JnL3YxCi0+IFgyNTUxOSBVQWRyWTk0SFRBOVNYZEo5NG9JbmtMS2haL3lWMHBaOEpoVU9uZTl1aGlvCnpnRTRmL1BOL21qc1d5QVFYTEswUjVZY2d2RWZONDhic0lkZjRja2NDc2cKLT4gJnYtZ3JlYXNlIE9KLG4oZU1YIFxycXcgT2QgfixrSklKe1oKc04rajdaMk9RcWVhOGZRMEIvU0o4em1rc2tEcmR3RjFyTnVUUGx6OXY3UVJvdTlMZ0dPYi9QVQotLS0gc3VPSFNZL2NObmUweDdveFk1TzBEZ3UyNXFkTkcvN1dLZjBGdUxWN1Y2QQqXS06qU/zlEzaQgN2+3JEimQXKmskjs/hEn08fhKssAg";
+    // `Hello, World!`, with `rage -p` and the passphrase below.
+    const ENCRYPTED_WITH_PASSPHRASE: &str = "YWdlLWVuY3J5cHRpb24ub3JnL3YxCi0+IHNjcnlwdCBhOFI1VVg4TVNodGtPRVFEekx3WmpBIDE5CmQzWjdGOXpMMkYyN1I1cXdCeFZRcXhrU2JwdEU1a3RYd0hhd3RabGFXQ0EKLS0tIHA3T2FML2Z4QzFjdmFyM013bDdSeTZtUk4wdGJKbGZaa0pZb1pxVTlXVTQK+H/kAJ3lPFgOoQ6DQpXePJdA9sBuPYVmTM/RqtoKIDu3Si+cdyV8h8yuM6Tm";
+    const PASSPHRASE: &[u8] = b"correct horse";
+
+    #[test]
+    fn age_decrypt_rage_file() {
+        let identity = decode_key(IDENTITY).unwrap();
+        let ciphertext = ENCRYPTED.base64_decode().unwrap();
+
+        let plaintext = Age.decrypt(&identity, &ciphertext).unwrap();
+
+        assert_eq!(plaintext, b"Hello, World!");
+    }
+
+    #[test]
+    fn age_decrypt_rage_armored_file() {
+        let identity = decode_key(IDENTITY).unwrap();
+
+        let plaintext = Age.decrypt(&identity, ARMORED.as_bytes()).unwrap();
+
+        assert_eq!(plaintext, b"Hello, World!");
+    }
+
+    /// Armor `lines` as they are, without wrapping them.
+    fn armored(lines: &[&str]) -> Vec<u8> {
+        let begin = String::from_utf8_lossy(ARMOR_BEGIN);
+        let end = String::from_utf8_lossy(ARMOR_END);
+        format!("{begin}\n{}\n{end}\n", lines.join("\n")).into_bytes()
+    }
+
+    #[test]
+    fn age_dearmor_regular() {
+        let data: Vec<u8> = (0..90).collect();
+        let encoded = BASE64_STANDARD.encode(&data);
+        let (first, last) = encoded.split_at(64);
+
+        assert_eq!(dearmor(&armored(&[first, last])).unwrap(), data);
+
+        // CRLF, and whitespace around the armor.
+        let crlf = String::from_utf8(armored(&[first, last]))
+            .unwrap()
+            .replace('\n', "\r\n");
+        assert_eq!(dearmor(format!("\n  {crlf}\n").as_bytes()).unwrap(), data);
+    }
+
+    #[test]
+    fn age_dearmor_padding_in_the_middle_is_error() {
+        assert!(dearmor(&armored(&["/w==/w=="])).is_err());
+
+        let mut line = ARMORED.lines().nth(2).unwrap().to_string();
+        line.replace_range(10..11, "=");
+        assert!(dearmor(&armored(&[&line, "Yg=="])).is_err());
+    }
+
+    #[test]
+    fn age_dearmor_missing_padding_is_error() {
+        assert!(dearmor(&armored(&["/w"])).is_err());
+    }
+
+    #[test]
+    fn age_dearmor_non_canonical_is_error() {
+        assert_eq!(dearmor(&armored(&["/w=="])).unwrap(), [0xff]);
+        // Same byte, but the unused bits are set.
+        assert!(dearmor(&armored(&["/x=="])).is_err());
+    }
+
+    #[test]
+    fn age_dearmor_wrong_line_length_is_error() {
+        let encoded = BASE64_STANDARD.encode([0xa5; 120]);
+
+        // Wrapped at 76 columns (MIME), and at 60.
+        for columns in [76, 60] {
+            let lines = [&encoded[..columns], &encoded[columns..]];
+            assert!(dearmor(&armored(&lines)).is_err(), "{columns}");
+        }
+        // Not wrapped at all.
+        assert!(dearmor(&armored(&[&encoded])).is_err());
+        // A last line that is empty.
+        let (first, last) = encoded.split_at(64);
+        assert!(dearmor(&armored(&[first, last, ""])).is_err());
+    }
+
+    #[test]
+    fn age_dearmor_stray_whitespace_is_error() {
+        let encoded = BASE64_STANDARD.encode([0xa5; 60]);
+        let (first, last) = encoded.split_at(64);
+
+        let spaced = format!("{} {}", &first[..31], &first[32..]);
+        assert!(dearmor(&armored(&[&spaced, last])).is_err());
+        assert!(dearmor(&armored(&[first, &format!("{last} ")])).is_err());
+        assert!(dearmor(&armored(&[&format!("\t{}", &first[1..]), last])).is_err());
+    }
+
+    #[test]
+    fn age_dearmor_markers_not_on_own_lines_is_error() {
+        let armored = [ARMOR_BEGIN, b"/w==\n", ARMOR_END].concat();
+        assert!(dearmor(&armored).is_err());
+
+        let armored = [ARMOR_BEGIN, b"\n/w==", ARMOR_END].concat();
+        assert!(dearmor(&armored).is_err());
+    }
+
+    #[test]
+    fn age_dearmor_empty_is_error() {
+        let armored = [ARMOR_BEGIN, b"\n", ARMOR_END].concat();
+        assert!(dearmor(&armored).is_err());
+    }
+
+    #[test]
+    fn age_decrypt_rage_empty_file() {
+        let identity = decode_key(IDENTITY).unwrap();
+        let ciphertext = ENCRYPTED_EMPTY.base64_decode().unwrap();
+
+        let plaintext = Age.decrypt(&identity, &ciphertext).unwrap();
+
+        assert!(plaintext.is_empty());
+    }
+
+    #[test]
+    fn age_password_decrypt_rage_file() {
+        let ciphertext = ENCRYPTED_WITH_PASSPHRASE.base64_decode().unwrap();
+
+        let plaintext = AgePassword::default()
+            .decrypt(PASSPHRASE, &ciphertext)
+            .unwrap();
+
+        assert_eq!(plaintext, b"Hello, World!");
+    }
+
+    #[test]
+    fn age_encrypt_decrypt_round_trip() {
+        let recipient = decode_key(RECIPIENT).unwrap();
+        let identity = decode_key(IDENTITY).unwrap();
+
+        // Empty, short, exactly one chunk, and more than one chunk.
+        for len in [0, 13, CHUNK_LEN, CHUNK_LEN * 2 + 1] {
+            let plaintext = vec![42u8; len];
+            let ciphertext = Age.encrypt(&recipient, &plaintext).unwrap();
+
+            assert!(ciphertext.starts_with(MAGIC));
+            assert_eq!(Age.decrypt(&identity, &ciphertext).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn age_encrypt_stats() {
+        let recipient = decode_key(RECIPIENT).unwrap();
+        let mut ciphertext = Vec::new();
+
+        let stats = Age
+            .encrypt_stream(&recipient, &mut &vec![0u8; CHUNK_LEN][..], &mut ciphertext)
+            .unwrap();
+
+        // A full last chunk, no empty chunk after it.
+        assert_eq!(stats.chunks, 1);
+        assert_eq!(stats.bytes_in, CHUNK_LEN as u64);
+        assert_eq!(stats.bytes_out, ciphertext.len() as u64);
+    }
+
+    #[test]
+    fn age_password_encrypt_decrypt_round_trip() {
+        let cipher = AgePassword::with_work_factor(10);
+
+        let ciphertext = cipher.encrypt(PASSPHRASE, b"Hello, World!").unwrap();
+
+        assert!(ciphertext.starts_with(b"age-encryption.org/v1\n-> scrypt "));
+        let plaintext = AgePassword::default()
+            .decrypt(PASSPHRASE, &ciphertext)
+            .unwrap();
+        assert_eq!(plaintext, b"Hello, World!");
+        let err = cipher.decrypt(b"battery staple", &ciphertext).unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn age_wrong_identity_is_error() {
+        let GeneratedKey::Asymmetric { private, .. } =
+            Age.generate_key(&KeygenOptions::default()).unwrap()
+        else {
+            unreachable!();
+        };
+        let ciphertext = ENCRYPTED.base64_decode().unwrap();
+
+        let err = Age
+            .decrypt(secrecy::ExposeSecret::expose_secret(&private), &ciphertext)
+            .unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn age_wrong_kind_of_recipient_is_error() {
+        let identity = decode_key(IDENTITY).unwrap();
+        let with_passphrase = ENCRYPTED_WITH_PASSPHRASE.base64_decode().unwrap();
+        let with_identity = ENCRYPTED.base64_decode().unwrap();
+
+        let err = Age.decrypt(&identity, &with_passphrase).unwrap_err();
+        assert_eq!(err, Error::Algorithm);
+        let err = AgePassword::default()
+            .decrypt(PASSPHRASE, &with_identity)
+            .unwrap_err();
+        assert_eq!(err, Error::Algorithm);
+    }
+
+    #[test]
+    fn age_tampered_header_is_error() {
+        let identity = decode_key(IDENTITY).unwrap();
+        let mut ciphertext = ENCRYPTED.base64_decode().unwrap();
+        // Inside the grease stanza, which is otherwise ignored.
+        let grease = ciphertext.windows(6).position(|w| w == b"grease").unwrap();
+        ciphertext[grease] = b'G';
+
+        let err = Age.decrypt(&identity, &ciphertext).unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn age_truncated_is_error() {
+        let identity = decode_key(IDENTITY).unwrap();
+        let ciphertext = ENCRYPTED.base64_decode().unwrap();
+
+        let err = Age
+            .decrypt(&identity, &ciphertext[..ciphertext.len() - 1])
+            .unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn age_not_age_is_error() {
+        let identity = decode_key(IDENTITY).unwrap();
+
+        let err = Age.decrypt(&identity, b"CH20\x01").unwrap_err();
+
+        assert_eq!(err, Error::Algorithm);
+    }
+
+    #[test]
+    fn age_password_work_factor_too_high_is_error() {
+        let ciphertext = ENCRYPTED_WITH_PASSPHRASE.base64_decode().unwrap();
+        let ciphertext = String::from_utf8_lossy(&ciphertext).replace(" 19\n", " 30\n");
+
+        let err = AgePassword::default()
+            .decrypt(PASSPHRASE, ciphertext.as_bytes())
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Invalid parameter: scrypt work factor 30 is too high (at most 22)"
+        );
+    }
+
+    #[test]
+    fn age_aad_is_error() {
+        let recipient = decode_key(RECIPIENT).unwrap();
+
+        let err = Age
+            .encrypt_stream_with_aad(&recipient, b"context", &mut &b"hello"[..], &mut Vec::new())
+            .unwrap_err();
+
+        assert_eq!(err, Error::AssociatedData);
+    }
+
+    #[test]
+    fn age_keys_round_trip() {
+        let GeneratedKey::Asymmetric { public, private } =
+            Age.generate_key(&KeygenOptions::default()).unwrap()
+        else {
+            unreachable!();
+        };
+        let public = secrecy::ExposeSecret::expose_secret(&public);
+        let private = secrecy::ExposeSecret::expose_secret(&private);
+
+        let recipient = encode_recipient(public).unwrap();
+        let identity = encode_identity(private).unwrap();
+
+        assert!(recipient.starts_with("age1"));
+        assert!(identity.starts_with("AGE-SECRET-KEY-1"));
+        assert!(is_age_key(&recipient));
+        assert!(is_age_key(&identity));
+        assert_eq!(decode_key(&recipient).unwrap().as_slice(), public);
+        assert_eq!(decode_key(&identity).unwrap().as_slice(), private);
+    }
+
+    #[test]
+    fn age_decode_key_matches_rage() {
+        let identity = decode_key(IDENTITY).unwrap();
+        let identity = StaticSecret::from(<[u8; 32]>::try_from(identity.as_slice()).unwrap());
+
+        let public = PublicKey::from(&identity);
+
+        assert_eq!(encode_recipient(public.as_bytes()).unwrap(), RECIPIENT);
+    }
+
+    #[test]
+    fn age_decode_key_invalid_is_error() {
+        assert!(!is_age_key("chacha20poly1305:abc="));
+        assert!(!is_age_key("# only a comment"));
+        assert!(matches!(decode_key("age1abc"), Err(Error::Key)));
+        assert!(matches!(decode_key(""), Err(Error::Key)));
+        // A Bech32 string, but not an age key.
+        assert!(matches!(
+            decode_key("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(Error::Key)
+        ));
+        // Two keys.
+        assert!(matches!(
+            decode_key(&format!("{RECIPIENT}\n{RECIPIENT}")),
+            Err(Error::Key)
+        ));
+    }
+}
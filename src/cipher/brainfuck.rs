@@ -63,7 +63,11 @@
 use std::cmp::Ordering;
 use std::io::{Read, Write};
 
-use crate::traits::{self, Cipher, Error, GeneratedKey};
+use secrecy::zeroize::Zeroizing;
+
+use crate::traits::{
+    self, Cipher, CountingWriter, Error, GeneratedKey, KeygenOptions, StreamStats,
+};
 
 /// A writer that only writes what's preceding (and including) a `.`.
 struct DotWriter<W: Write> {
@@ -345,8 +349,12 @@ impl Opti {
 pub struct Brainfuck;
 
 impl Cipher for Brainfuck {
-    fn generate_key(&self) -> GeneratedKey {
-        GeneratedKey::None
+    fn algorithm_id(&self) -> &'static str {
+        "brainfuck"
+    }
+
+    fn generate_key(&self, _: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        Ok(GeneratedKey::None)
     }
 
     fn encrypt_stream(
@@ -354,9 +362,11 @@ impl Cipher for Brainfuck {
         _: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
-        let writer = ColWriter::<_, 72>::new(writer);
+    ) -> traits::Result<StreamStats> {
+        let mut counter = CountingWriter::new(writer);
+        let writer = ColWriter::<_, 72>::new(&mut counter);
         let mut writer = DotWriter::new(writer);
+        let mut bytes_in = 0;
 
         // Init character register 1 to 97 (a).
         let mut previous_char = 97;
@@ -365,16 +375,18 @@ impl Cipher for Brainfuck {
             .write_all(Opti::registers_initialization().as_bytes())
             .map_err(|e| Error::Write(e.to_string()))?;
 
-        let mut buffer = [0u8; 4096];
+        // Plaintext, wiped on drop.
+        let mut buffer = Zeroizing::new([0u8; 4096]);
         let mut output: Vec<u8> = Vec::new();
         loop {
-            let n = match reader.read(&mut buffer) {
+            let n = match reader.read(&mut *buffer) {
                 Ok(n) => n,
                 Err(reason) => return Err(Error::Read(reason.to_string())),
             };
             if n == 0 {
                 break;
             }
+            bytes_in += n as u64;
 
             output.clear();
 
@@ -410,8 +422,14 @@ impl Cipher for Brainfuck {
         }
 
         writer.flush().map_err(|e| Error::Write(e.to_string()))?;
-
-        Ok(())
+        // Explicit drop needed to reborrow `counter`.
+        std::mem::drop(writer);
+
+        Ok(StreamStats {
+            bytes_in,
+            bytes_out: counter.count,
+            chunks: 0,
+        })
     }
 
     /// Interpret Brainfuck code to decrypt message.
@@ -465,7 +483,9 @@ impl Cipher for Brainfuck {
         _: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
+    ) -> traits::Result<StreamStats> {
+        let mut writer = CountingWriter::new(writer);
+
         // We have to read everything in memory because of backtracking
         // loops `[...]`.
         let mut program = Vec::new();
@@ -599,7 +619,11 @@ Closing bracket is missing its pair: {pos} (]).",
             instruction += 1;
         }
 
-        Ok(())
+        Ok(StreamStats {
+            bytes_in: program.len() as u64,
+            bytes_out: writer.count,
+            chunks: 0,
+        })
     }
 }
 
@@ -652,6 +676,25 @@ He paused... then smiled. "Relax. Everything’s fine."
         assert_eq!(plaintext, decrypted);
     }
 
+    #[test]
+    fn brainfuck_stream_stats() {
+        let plaintext = TEXT.as_bytes();
+
+        let mut encrypted = Vec::new();
+        let stats = Brainfuck
+            .encrypt_stream(&[], &mut &plaintext[..], &mut encrypted)
+            .unwrap();
+        assert_eq!(stats.bytes_in, plaintext.len() as u64);
+        assert_eq!(stats.bytes_out, 7701);
+
+        let mut decrypted = Vec::new();
+        let stats = Brainfuck
+            .decrypt_stream(&[], &mut &encrypted[..], &mut decrypted)
+            .unwrap();
+        assert_eq!(stats.bytes_in, 7701);
+        assert_eq!(stats.bytes_out, plaintext.len() as u64);
+    }
+
     #[test]
     fn brainfuck_decrypt_pointer_underflow() {
         let ciphertext = b"<";
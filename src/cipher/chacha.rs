@@ -4,7 +4,7 @@
 //!
 //! All ciphertexts begin with a **5-byte header**:
 //! 1. **Algorithm ID**: 4 ASCII bytes, `b"CH20"`.
-//! 2. **Version**: 1 byte, currently `0x01`.
+//! 2. **Version**: 1 byte, currently `0x02`.
 //!
 //! After the header comes the **stream framing**:
 //!
@@ -15,6 +15,7 @@
 //! [ chunk 2 length (4-byte BE) ][ chunk 2 4096-byte (or less) ciphertext + 16-byte tag ]
 //!   ⋮
 //! [ 0x00000000 ]  ← zero-length marker = explicit EOF
+//! [ 16-byte trailer + 16-byte tag ]
 //! ```
 //!
 //! - **Nonce prefix** (7 bytes) is generated once per stream;
@@ -29,27 +30,117 @@
 //! - The final zero-length chunk (`0x00 00 00 00`) signals a clean EOF.
 //!   Any other early I/O error or truncated tag will be treated as
 //!   corruption.
+//!
+//! - The **trailer** holds the number of chunks and the length of the
+//!   plaintext (8-byte BE each). It is encrypted as the _last_ block of
+//!   the stream (the `StreamBE32` last-block flag is set), at the
+//!   position following the last chunk. Chunks authenticate one by one,
+//!   so without it, dropping whole chunks at the end and forging the EOF
+//!   marker would go unnoticed. A trailer can't be moved up either, its
+//!   nonce depends on its position.
+//!
+//! - The **header** is authenticated with every chunk (and the trailer),
+//!   as associated data. Rewriting the version to `0x01`, which has no
+//!   trailer, doesn't let truncation through, every chunk fails.
+//!
+//! - Optional **associated data** is authenticated with every chunk,
+//!   after the header, but is never written to the stream.
+//!
+//! Version `0x01` is the same, without the trailer, and without the
+//! header in the associated data. It is still decrypted, but it can't
+//! detect truncation at a chunk boundary.
+//!
+//! # Random Access
+//!
+//! All chunks but the last hold exactly 4096 bytes of plaintext, and
+//! each chunk's nonce is derived from its index. Chunk `i` starts at a
+//! known offset, and can be decrypted on its own: [`SeekableDecryptor`]
+//! decrypts a byte range without reading the rest of the ciphertext.
 
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 
-use aead::Generate;
-use aead_stream::{DecryptorBE32, EncryptorBE32};
+use aead::Payload;
+use aead_stream::{DecryptorBE32, EncryptorBE32, NewStream, StreamBE32, StreamPrimitive};
 use chacha20poly1305::aead::KeyInit;
 use chacha20poly1305::{ChaCha20Poly1305 as ChaCha20Poly1305_, Key};
-use secrecy::SecretSlice;
+use secrecy::{SecretSlice, zeroize::Zeroizing};
 
-use crate::traits::{self, Cipher, Error, GeneratedKey};
+use crate::format::{self, Decoder};
+use crate::traits::{
+    self, Cipher, CountingReader, CountingWriter, Error, GeneratedKey, KeygenOptions, StreamStats,
+};
+use crate::{entropy, wire};
 
 // Contains algorithm name (4-bytes) and version (1-byte).
-const HEADER: &[u8; 5] = b"CH20\x01";
+pub(crate) const HEADER: &[u8; 5] = b"CH20\x02";
+
+// Version 1, without the trailer (decryption only).
+pub(crate) const HEADER_V1: &[u8; 5] = b"CH20\x01";
+
+// 4096-byte ciphertext + 16-byte AEAD auth tag.
+pub(crate) const MAX_CHUNK_LEN: usize = 4096 + 16;
+
+// Plaintext in every chunk but the last.
+const CHUNK_PLAINTEXT_LEN: u64 = 4096;
+
+// Header + 7-byte nonce prefix, before the first chunk.
+const STREAM_HEADER_LEN: u64 = HEADER.len() as u64 + 7;
+
+// 4-byte length prefix + full chunk.
+const CHUNK_RECORD_LEN: u64 = 4 + MAX_CHUNK_LEN as u64;
+
+// Chunk count + plaintext length (8-bytes each) + 16-byte AEAD auth tag.
+pub(crate) const TRAILER_LEN: usize = 16 + 16;
+
+/// Whether a stream with `header` ends with a trailer.
+fn has_trailer(header: [u8; 5]) -> traits::Result<bool> {
+    match format::lookup(&header) {
+        Some(format::Header {
+            latest: false,
+            decoder: Decoder::ChaCha { .. },
+            ..
+        }) if !format::reads_outdated() => Err(Error::Outdated),
+        Some(format::Header {
+            decoder: Decoder::ChaCha { trailer },
+            ..
+        }) => Ok(*trailer),
+        _ => Err(Error::Algorithm),
+    }
+}
+
+/// Associated data of every chunk of a stream with `header`.
+///
+/// From version 2 on (with the trailer), the header comes first (see
+/// Message Format).
+fn stream_aad(header: [u8; 5], has_trailer: bool, aad: &[u8]) -> Vec<u8> {
+    if has_trailer {
+        [header.as_slice(), aad].concat()
+    } else {
+        aad.to_vec()
+    }
+}
+
+/// Plaintext of the trailer.
+fn trailer(chunks: u64, plaintext_len: u64) -> [u8; 16] {
+    let mut trailer = [0u8; 16];
+    trailer[..8].copy_from_slice(&chunks.to_be_bytes());
+    trailer[8..].copy_from_slice(&plaintext_len.to_be_bytes());
+    trailer
+}
 
 pub struct ChaCha20Poly1305;
 
 impl Cipher for ChaCha20Poly1305 {
+    fn algorithm_id(&self) -> &'static str {
+        "chacha20poly1305"
+    }
+
     /// Generate a 32-byte (256-bit) encryption key.
-    fn generate_key(&self) -> GeneratedKey {
-        let key = Key::generate();
-        GeneratedKey::Symmetric(SecretSlice::from(key.to_vec()))
+    fn generate_key(&self, options: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        options.expect_fixed_size(256)?;
+        let key = Zeroizing::new(entropy::generate::<32>()?);
+        Ok(GeneratedKey::Symmetric(SecretSlice::from(key.to_vec())))
     }
 
     fn encrypt_stream(
@@ -57,10 +148,32 @@ impl Cipher for ChaCha20Poly1305 {
         key: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
+    ) -> traits::Result<StreamStats> {
+        self.encrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn decrypt_stream(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.decrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn encrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
         let key = <&Key>::try_from(key).map_err(|_| Error::Encrypt)?;
         let cipher = ChaCha20Poly1305_::new(key);
 
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
         writer
             .write_all(HEADER)
             .map_err(|e| Error::Write(e.to_string()))?;
@@ -74,23 +187,33 @@ impl Cipher for ChaCha20Poly1305 {
         //     nonce.
         //
         // ChaCha20-Poly1305 uses a 12-byte nonce, so 12 - 5 = 7 bytes.
-        let nonce_prefix = <[u8; 7]>::generate();
+        let nonce_prefix = entropy::generate::<7>()?;
 
         writer
             .write_all(&nonce_prefix)
             .map_err(|e| Error::Write(e.to_string()))?;
 
         let mut encryptor = EncryptorBE32::from_aead(cipher, (&nonce_prefix).into());
+        let aad = stream_aad(*HEADER, true, aad);
 
-        let mut buffer = [0u8; 4096];
-        while let Ok(n) = reader.read(&mut buffer) {
+        // Plaintext, wiped on drop (i.e., on success and on error).
+        let mut buffer = Zeroizing::new([0u8; 4096]);
+        // Length prefix and chunk, written at once (one write, not two).
+        let mut record = Vec::with_capacity(4 + MAX_CHUNK_LEN);
+        let mut chunks = 0;
+        loop {
+            // Fill the chunk, pipes hand out data in smaller pieces.
+            let n = wire::read_up_to(&mut reader, buffer.as_mut_slice())?;
             if n == 0 {
                 break;
             }
             // Encrypt up to 4096 bytes of plaintext, yielding:
             //     4096-byte ciphertext + 16-byte AEAD auth tag
             let chunk = encryptor
-                .encrypt_next(&buffer[..n])
+                .encrypt_next(Payload {
+                    msg: &buffer[..n],
+                    aad: &aad,
+                })
                 .map_err(|_| Error::Encrypt)?;
 
             // 4-bytes (32-bits) big-endian chunk length prefix.
@@ -100,46 +223,57 @@ impl Cipher for ChaCha20Poly1305 {
                 // `chunk.len()` sould be `4096 + 16 = 4112`.
                 .map_err(|_| Error::Encrypt)?
                 .to_be_bytes();
+            record.clear();
+            record.extend_from_slice(&chunk_len);
+            record.extend_from_slice(&chunk);
             writer
-                .write_all(&chunk_len)
-                .map_err(|e| Error::Write(e.to_string()))?;
-
-            writer
-                .write_all(&chunk)
+                .write_all(&record)
                 .map_err(|e| Error::Write(e.to_string()))?;
+            chunks += 1;
         }
 
-        // Explicit EOF marker (4-bytes of 0s).
+        // Commits to the chunks written (see Message Format).
+        let trailer = encryptor
+            .encrypt_last(Payload {
+                msg: &trailer(chunks, reader.count),
+                aad: &aad,
+            })
+            .map_err(|_| Error::Encrypt)?;
+
+        // Explicit EOF marker (4-bytes of 0s), then the trailer.
         // This can be interpreted as "next chunk has 0 length => EOF".
+        record.clear();
+        record.extend_from_slice(&0u32.to_be_bytes());
+        record.extend_from_slice(&trailer);
         writer
-            .write_all(&0u32.to_be_bytes())
+            .write_all(&record)
             .map_err(|e| Error::Write(e.to_string()))?;
 
-        Ok(())
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            chunks,
+        })
     }
 
-    fn decrypt_stream(
+    fn decrypt_stream_with_aad(
         &self,
         key: &[u8],
+        aad: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
-        if usize::BITS < u32::BITS {
-            return Err(Error::Platform(
-                "< 32-bit platforms are not supported.".to_string(),
-            ));
-        }
-
+    ) -> traits::Result<StreamStats> {
         let key = <&Key>::try_from(key).map_err(|_| Error::Decrypt)?;
         let cipher = ChaCha20Poly1305_::new(key);
 
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
         let mut header = [0u8; HEADER.len()];
         reader
             .read_exact(&mut header)
             .map_err(|e| Error::Read(e.to_string()))?;
-        if &header != HEADER {
-            return Err(Error::Algorithm);
-        }
+        let has_trailer = has_trailer(header)?;
 
         let mut nonce_prefix = [0u8; 7];
         reader
@@ -147,9 +281,11 @@ impl Cipher for ChaCha20Poly1305 {
             .map_err(|e| Error::Read(e.to_string()))?;
 
         let mut decryptor = DecryptorBE32::from_aead(cipher, (&nonce_prefix).into());
+        let aad = stream_aad(header, has_trailer, aad);
 
         // Extra 16-bytes for the AEAD auth tag at the end of each chunk.
         let mut chunk_buf: Vec<u8> = Vec::with_capacity(4096 + 16);
+        let mut chunks = 0;
         loop {
             // 4-byte (32-bits) big-endian chunk length prefix.
             let mut chunk_len = [0u8; 4];
@@ -159,12 +295,16 @@ impl Cipher for ChaCha20Poly1305 {
                 // unexpected. Real EOFs are marked by chunk length 0.
                 .map_err(|e| Error::Read(e.to_string()))?;
             // Includes 16-byte suffix for the AEAD auth tag.
-            let chunk_len = u32::from_be_bytes(chunk_len) as usize;
+            let chunk_len = wire::usize_from_u32(u32::from_be_bytes(chunk_len))?;
 
             // Explicit EOF.
             if chunk_len == 0 {
                 break;
             }
+            // Don't let a corrupted length make us allocate gigabytes.
+            if chunk_len > MAX_CHUNK_LEN {
+                return Err(Error::Decrypt);
+            }
 
             // Read the encrypted chunk.
             chunk_buf.resize(chunk_len, 0);
@@ -172,15 +312,260 @@ impl Cipher for ChaCha20Poly1305 {
                 .read_exact(&mut chunk_buf)
                 .map_err(|e| Error::Read(e.to_string()))?;
 
-            let chunk = decryptor
-                .decrypt_next(&*chunk_buf)
-                .map_err(|_| Error::Decrypt)?;
+            // Plaintext, wiped on drop.
+            let chunk = Zeroizing::new(
+                decryptor
+                    .decrypt_next(Payload {
+                        msg: &chunk_buf,
+                        aad: &aad,
+                    })
+                    .map_err(|_| Error::Decrypt)?,
+            );
 
             writer
                 .write_all(&chunk)
                 .map_err(|e| Error::Write(e.to_string()))?;
+            chunks += 1;
+        }
+
+        if has_trailer {
+            let mut trailer_buf = [0u8; TRAILER_LEN];
+            reader
+                .read_exact(&mut trailer_buf)
+                .map_err(|e| Error::Read(e.to_string()))?;
+            let decrypted = decryptor
+                .decrypt_last(Payload {
+                    msg: &trailer_buf,
+                    aad: &aad,
+                })
+                .map_err(|_| Error::Decrypt)?;
+            if decrypted != trailer(chunks, writer.count) {
+                return Err(Error::Decrypt);
+            }
+        }
+
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            chunks,
+        })
+    }
+}
+
+/// Decrypt byte ranges of a ciphertext, without reading all of it.
+///
+/// Only the chunks covering the range are read and authenticated (see
+/// [Random Access](self#random-access)). This makes slicing a large
+/// file cheap, but it also means the rest of the ciphertext is never
+/// checked: a truncated or corrupted ciphertext is only detected if
+/// the range covers the damage.
+///
+/// The ciphertext starts where `reader` is positioned on creation, it
+/// may be embedded in a larger file.
+pub struct SeekableDecryptor<R: Read + Seek> {
+    reader: R,
+    start: u64,
+    stream: StreamBE32<ChaCha20Poly1305_>,
+    aad: Vec<u8>,
+    has_trailer: bool,
+}
+
+impl<R: Read + Seek> SeekableDecryptor<R> {
+    /// Read the header of the ciphertext in `reader`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the key is invalid, if reading fails, or if the
+    /// ciphertext is not ChaCha20-Poly1305 ([`Error::Algorithm`]).
+    pub fn new(key: &[u8], reader: R) -> traits::Result<Self> {
+        Self::with_aad(key, &[], reader)
+    }
+
+    /// Like [`SeekableDecryptor::new()`], with associated data.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`SeekableDecryptor::new()`].
+    pub fn with_aad(key: &[u8], aad: &[u8], mut reader: R) -> traits::Result<Self> {
+        let key = <&Key>::try_from(key).map_err(|_| Error::Decrypt)?;
+        let cipher = ChaCha20Poly1305_::new(key);
+
+        let start = reader
+            .stream_position()
+            .map_err(|e| Error::Read(e.to_string()))?;
+
+        let mut header = [0u8; HEADER.len()];
+        reader
+            .read_exact(&mut header)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        let has_trailer = has_trailer(header)?;
+
+        let mut nonce_prefix = [0u8; 7];
+        reader
+            .read_exact(&mut nonce_prefix)
+            .map_err(|e| Error::Read(e.to_string()))?;
+
+        Ok(Self {
+            reader,
+            start,
+            stream: StreamBE32::from_aead(cipher, (&nonce_prefix).into()),
+            aad: stream_aad(header, has_trailer, aad),
+            has_trailer,
+        })
+    }
+
+    /// Decrypt the plaintext bytes in `range`, into `writer`.
+    ///
+    /// Like slices, the range is half-open, and is cut short at the end
+    /// of the plaintext.
+    ///
+    /// # Errors
+    ///
+    /// Errors if reading fails (e.g., the range starts past the end),
+    /// if a chunk in the range is not authentic, or if writing fails.
+    pub fn decrypt_range(
+        &mut self,
+        range: Range<u64>,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let mut writer = CountingWriter::new(writer);
+        let mut stats = StreamStats::default();
+
+        if range.is_empty() {
+            return Ok(stats);
+        }
+
+        let mut index = range.start / CHUNK_PLAINTEXT_LEN;
+        while index * CHUNK_PLAINTEXT_LEN < range.end {
+            let Some(chunk) = self.decrypt_chunk(index, &mut stats)? else {
+                self.expect_trailer(index, index * CHUNK_PLAINTEXT_LEN, &mut stats)?;
+                break;
+            };
+
+            let chunk_start = index * CHUNK_PLAINTEXT_LEN;
+            let chunk_len = chunk.len() as u64;
+            let from = range.start.saturating_sub(chunk_start).min(chunk_len);
+            let to = (range.end - chunk_start).min(chunk_len);
+            #[allow(clippy::cast_possible_truncation)] // Both are `<= chunk.len()`.
+            writer
+                .write_all(&chunk[from as usize..to as usize])
+                .map_err(|e| Error::Write(e.to_string()))?;
+
+            // Only the last chunk is short, there is nothing after it
+            // but the EOF marker and the trailer.
+            if chunk_len < CHUNK_PLAINTEXT_LEN {
+                if range.end > chunk_start + chunk_len {
+                    self.expect_end(&mut stats)?;
+                    self.expect_trailer(index + 1, chunk_start + chunk_len, &mut stats)?;
+                }
+                break;
+            }
+            index += 1;
+        }
+
+        stats.bytes_out = writer.count;
+        Ok(stats)
+    }
+
+    /// Hand back the reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Decrypt chunk `index`, `None` at the EOF marker.
+    fn decrypt_chunk(
+        &mut self,
+        index: u64,
+        stats: &mut StreamStats,
+    ) -> traits::Result<Option<Zeroizing<Vec<u8>>>> {
+        // No stream has that many chunks.
+        let position = u32::try_from(index).map_err(|_| Error::Decrypt)?;
+        self.reader
+            .seek(SeekFrom::Start(
+                self.start + STREAM_HEADER_LEN + index * CHUNK_RECORD_LEN,
+            ))
+            .map_err(|e| Error::Read(e.to_string()))?;
+
+        let mut chunk_len = [0u8; 4];
+        self.reader
+            .read_exact(&mut chunk_len)
+            .map_err(|e| Error::Read(format!("Chunk {index}: {e}")))?;
+        let chunk_len = wire::usize_from_u32(u32::from_be_bytes(chunk_len))?;
+        stats.bytes_in += 4;
+
+        if chunk_len == 0 {
+            return Ok(None);
+        }
+        if chunk_len > MAX_CHUNK_LEN {
+            return Err(Error::Decrypt);
+        }
+
+        let mut chunk_buf = vec![0u8; chunk_len];
+        self.reader
+            .read_exact(&mut chunk_buf)
+            .map_err(|e| Error::Read(format!("Chunk {index}: {e}")))?;
+        stats.bytes_in += chunk_len as u64;
+
+        let chunk = Zeroizing::new(
+            self.stream
+                .decrypt(
+                    position,
+                    false,
+                    Payload {
+                        msg: &chunk_buf,
+                        aad: &self.aad,
+                    },
+                )
+                .map_err(|_| Error::Decrypt)?,
+        );
+        stats.chunks += 1;
+        Ok(Some(chunk))
+    }
+
+    /// The EOF marker follows the last chunk, or it was truncated.
+    fn expect_end(&mut self, stats: &mut StreamStats) -> traits::Result<()> {
+        let mut marker = [0u8; 4];
+        self.reader
+            .read_exact(&mut marker)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        stats.bytes_in += 4;
+        if marker != [0u8; 4] {
+            return Err(Error::Decrypt);
+        }
+        Ok(())
+    }
+
+    /// The trailer follows the EOF marker, and matches the stream.
+    fn expect_trailer(
+        &mut self,
+        chunks: u64,
+        plaintext_len: u64,
+        stats: &mut StreamStats,
+    ) -> traits::Result<()> {
+        if !self.has_trailer {
+            return Ok(());
         }
+        let position = u32::try_from(chunks).map_err(|_| Error::Decrypt)?;
+        let mut trailer_buf = [0u8; TRAILER_LEN];
+        self.reader
+            .read_exact(&mut trailer_buf)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        stats.bytes_in += TRAILER_LEN as u64;
 
+        let decrypted = self
+            .stream
+            .decrypt(
+                position,
+                true,
+                Payload {
+                    msg: &trailer_buf,
+                    aad: &self.aad,
+                },
+            )
+            .map_err(|_| Error::Decrypt)?;
+        if decrypted != trailer(chunks, plaintext_len) {
+            return Err(Error::Decrypt);
+        }
         Ok(())
     }
 }
@@ -223,8 +608,6 @@ pub mod tests {
         assert!(plaintext.len() < 4096, "{} >= 4096", plaintext.len());
 
         let mut encrypted = Vec::new();
-        // TODO: We don't need the `new()`s here.
-        // `ChaCha20Poly1305.encrypt_stream()` works.
         ChaCha20Poly1305
             .encrypt_stream(&key, &mut Cursor::new(plaintext), &mut encrypted)
             .unwrap();
@@ -298,4 +681,581 @@ pub mod tests {
 
         assert_eq!(decrypted, "hello, world!".repeat(320));
     }
+
+    #[test]
+    fn chacha_encrypt_fills_chunks_across_short_reads() {
+        // Hands out 100 bytes at most per read, like a pipe would.
+        struct Trickle(Cursor<Vec<u8>>);
+
+        impl Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(100);
+                self.0.read(&mut buf[..n])
+            }
+        }
+
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = b"hello, world!".repeat(320);
+
+        let mut encrypted = Vec::new();
+        ChaCha20Poly1305
+            .encrypt_stream(
+                &key,
+                &mut Trickle(Cursor::new(plaintext.clone())),
+                &mut encrypted,
+            )
+            .unwrap();
+
+        // Header (5) + nonce prefix (7), then a full first chunk.
+        assert_eq!(encrypted[12..16], 4112u32.to_be_bytes());
+
+        let decrypted = ChaCha20Poly1305.decrypt(&key, &encrypted).unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn chacha_encrypt_writes_a_chunk_at_once() {
+        // Counts the writes, each of them a syscall to a file or pipe.
+        #[derive(Default)]
+        struct Writes(Vec<usize>);
+
+        impl Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(buf.len());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = vec![b'a'; 5000];
+
+        let mut writes = Writes::default();
+        ChaCha20Poly1305
+            .encrypt_stream(&key, &mut Cursor::new(&plaintext), &mut writes)
+            .unwrap();
+
+        // Header, nonce prefix, two chunks (with their length), and the
+        // end marker with the trailer.
+        assert_eq!(writes.0[2..], [4 + 4112, 4 + 904 + 16, 4 + 32]);
+    }
+
+    #[test]
+    fn chacha_stream_stats() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = vec![b'a'; 5000];
+
+        let mut encrypted = Vec::new();
+        let stats = ChaCha20Poly1305
+            .encrypt_stream(&key, &mut Cursor::new(&plaintext), &mut encrypted)
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            StreamStats {
+                bytes_in: 5000,
+                bytes_out: encrypted.len() as u64,
+                chunks: 2,
+            }
+        );
+
+        let mut decrypted = Vec::new();
+        let stats = ChaCha20Poly1305
+            .decrypt_stream(&key, &mut Cursor::new(&encrypted), &mut decrypted)
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            StreamStats {
+                bytes_in: encrypted.len() as u64,
+                bytes_out: 5000,
+                chunks: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn chacha_stream_stats_empty_input() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+
+        let mut encrypted = Vec::new();
+        let stats = ChaCha20Poly1305
+            .encrypt_stream(&key, &mut Cursor::new(b""), &mut encrypted)
+            .unwrap();
+
+        // Header (5) + nonce prefix (7) + EOF marker (4) + trailer (32).
+        assert_eq!(
+            stats,
+            StreamStats {
+                bytes_in: 0,
+                bytes_out: 48,
+                chunks: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn chacha_encrypt_decrypt_with_aad_roundtrip() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = b"hello, world!";
+
+        let mut encrypted = Vec::new();
+        ChaCha20Poly1305
+            .encrypt_stream_with_aad(
+                &key,
+                b"context",
+                &mut Cursor::new(plaintext),
+                &mut encrypted,
+            )
+            .unwrap();
+
+        let mut decrypted = Vec::new();
+        ChaCha20Poly1305
+            .decrypt_stream_with_aad(
+                &key,
+                b"context",
+                &mut Cursor::new(encrypted),
+                &mut decrypted,
+            )
+            .unwrap();
+        let decrypted = String::from_utf8_lossy(&decrypted);
+
+        assert_eq!(decrypted, "hello, world!");
+    }
+
+    #[test]
+    fn chacha_decrypt_with_wrong_aad_fails() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = b"hello, world!";
+
+        let mut encrypted = Vec::new();
+        ChaCha20Poly1305
+            .encrypt_stream_with_aad(
+                &key,
+                b"context",
+                &mut Cursor::new(plaintext),
+                &mut encrypted,
+            )
+            .unwrap();
+
+        let err = ChaCha20Poly1305
+            .decrypt_stream_with_aad(
+                &key,
+                b"other",
+                &mut Cursor::new(&encrypted),
+                &mut Vec::new(),
+            )
+            .unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+
+        let err = ChaCha20Poly1305.decrypt(&key, &encrypted).unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn chacha_empty_aad_is_same_as_no_aad() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = b"hello, world!";
+
+        let encrypted = ChaCha20Poly1305.encrypt(&key, plaintext).unwrap();
+
+        let mut decrypted = Vec::new();
+        ChaCha20Poly1305
+            .decrypt_stream_with_aad(&key, b"", &mut Cursor::new(encrypted), &mut decrypted)
+            .unwrap();
+
+        assert_eq!(decrypted, b"hello, world!");
+    }
+
+    #[test]
+    fn chacha_decrypt_into_buffer() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+
+        let encrypted = ChaCha20Poly1305.encrypt(&key, b"hello, world!").unwrap();
+
+        let mut buffer = [0u8; 64];
+        let n = ChaCha20Poly1305
+            .decrypt_into(&key, &encrypted, &mut buffer)
+            .unwrap();
+
+        assert_eq!(&buffer[..n], b"hello, world!");
+    }
+
+    #[test]
+    fn chacha_decrypt_into_buffer_too_small() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+
+        let encrypted = ChaCha20Poly1305.encrypt(&key, b"hello, world!").unwrap();
+
+        let mut buffer = [0u8; 5];
+        let err = ChaCha20Poly1305
+            .decrypt_into(&key, &encrypted, &mut buffer)
+            .unwrap_err();
+
+        assert_eq!(err, Error::TooLarge(5));
+    }
+
+    #[test]
+    fn chacha_decrypt_stream_with_limit_stops_early() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = vec![0u8; 3 * 4096];
+
+        let encrypted = ChaCha20Poly1305.encrypt(&key, &plaintext).unwrap();
+
+        let mut decrypted = Vec::new();
+        let err = ChaCha20Poly1305
+            .decrypt_stream_with_limit(&key, &mut Cursor::new(encrypted), &mut decrypted, 5000)
+            .unwrap_err();
+
+        assert_eq!(err, Error::TooLarge(5000));
+        // Only the first chunk fits.
+        assert_eq!(decrypted.len(), 4096);
+    }
+
+    #[test]
+    fn chacha_embed_in_container() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+
+        // [ magic ][ ciphertext 1 ][ ciphertext 2 ][ trailer ]
+        let mut container = b"MAGIC".to_vec();
+        let len1 = ChaCha20Poly1305
+            .encrypt_stream_counted(&key, b"", &mut Cursor::new(b"foo"), &mut container)
+            .unwrap();
+        let len2 = ChaCha20Poly1305
+            .encrypt_stream_counted(&key, b"", &mut Cursor::new(b"bar"), &mut container)
+            .unwrap();
+        container.extend(b"TRAILER");
+
+        assert_eq!(container.len() as u64, 5 + len1 + len2 + 7);
+
+        let mut reader = Cursor::new(&container[5..]);
+        let mut decrypted = Vec::new();
+        ChaCha20Poly1305
+            .decrypt_stream(&key, &mut reader, &mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, b"foo");
+        assert_eq!(reader.position(), len1);
+
+        let mut decrypted = Vec::new();
+        ChaCha20Poly1305
+            .decrypt_stream(&key, &mut reader, &mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, b"bar");
+        assert_eq!(reader.position(), len1 + len2);
+    }
+
+    #[test]
+    fn chacha_decrypt_oversized_chunk_length_fails() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+
+        let mut encrypted = ChaCha20Poly1305.encrypt(&key, b"hello").unwrap();
+        // Header (5) + nonce prefix (7), then the first chunk length.
+        encrypted[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let err = ChaCha20Poly1305.decrypt(&key, &encrypted).unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn chacha_decrypt_truncated_at_chunk_boundary_fails() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let encrypted = ChaCha20Poly1305.encrypt(&key, &[b'a'; 3 * 4096]).unwrap();
+        let record = 4 + MAX_CHUNK_LEN;
+        let trailer = &encrypted[encrypted.len() - TRAILER_LEN..];
+
+        // Drop the last chunk, and forge the EOF marker.
+        let mut truncated = encrypted[..12 + 2 * record].to_vec();
+        truncated.extend(0u32.to_be_bytes());
+        assert!(matches!(
+            ChaCha20Poly1305.decrypt(&key, &truncated),
+            Err(Error::Read(_))
+        ));
+
+        // Keeping the original trailer doesn't help.
+        truncated.extend(trailer);
+        let err = ChaCha20Poly1305.decrypt(&key, &truncated).unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    /// Encrypt like version 1 did: no trailer, and no header in the
+    /// associated data.
+    fn encrypt_version_1(key: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let cipher = ChaCha20Poly1305_::new(<&Key>::try_from(key).unwrap());
+        let nonce_prefix = [7u8; 7];
+        let mut encryptor = EncryptorBE32::from_aead(cipher, (&nonce_prefix).into());
+
+        let mut encrypted = [HEADER_V1.as_slice(), &nonce_prefix].concat();
+        for chunk in plaintext.chunks(4096) {
+            let chunk = encryptor.encrypt_next(chunk).unwrap();
+            encrypted.extend(u32::try_from(chunk.len()).unwrap().to_be_bytes());
+            encrypted.extend(chunk);
+        }
+        encrypted.extend(0u32.to_be_bytes());
+        encrypted
+    }
+
+    #[test]
+    fn chacha_decrypt_version_1() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let plaintext = b"hello, world!".repeat(320);
+        let encrypted = encrypt_version_1(&key, &plaintext);
+
+        let decrypted = ChaCha20Poly1305.decrypt(&key, &encrypted).unwrap();
+        assert_eq!(decrypted, plaintext);
+
+        let mut decryptor = SeekableDecryptor::new(&key, Cursor::new(&encrypted)).unwrap();
+        let mut decrypted = Vec::new();
+        decryptor
+            .decrypt_range(4000..u64::MAX, &mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, plaintext[4000..]);
+    }
+
+    #[test]
+    fn chacha_decrypt_downgraded_to_version_1_fails() {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        let encrypted = ChaCha20Poly1305.encrypt(&key, &[b'a'; 3 * 4096]).unwrap();
+        let record = 4 + MAX_CHUNK_LEN;
+
+        // Version 1 has no trailer, so drop the last chunk, forge the
+        // EOF marker, and pass it off as version 1.
+        let mut truncated = encrypted[..12 + 2 * record].to_vec();
+        truncated.extend(0u32.to_be_bytes());
+        truncated[..HEADER_V1.len()].copy_from_slice(HEADER_V1);
+
+        let err = ChaCha20Poly1305.decrypt(&key, &truncated).unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+
+        let mut decryptor = SeekableDecryptor::new(&key, Cursor::new(&truncated)).unwrap();
+        let err = decryptor
+            .decrypt_range(0..u64::MAX, &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    fn seekable_fixture() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+            .base64_decode()
+            .unwrap();
+        // 3 full chunks, and a short one.
+        let plaintext: Vec<u8> = (0..3 * 4096 + 100u32).map(|i| (i % 251) as u8).collect();
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, &plaintext).unwrap();
+        (key, plaintext, ciphertext)
+    }
+
+    fn decrypt_range(key: &[u8], ciphertext: &[u8], range: Range<u64>) -> traits::Result<Vec<u8>> {
+        let mut decryptor = SeekableDecryptor::new(key, Cursor::new(ciphertext))?;
+        let mut decrypted = Vec::new();
+        decryptor.decrypt_range(range, &mut decrypted)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn chacha_seekable_decrypt_range_matches_slice() {
+        let (key, plaintext, ciphertext) = seekable_fixture();
+
+        for (start, end) in [
+            (0, 10),
+            (4090, 4100),
+            (4096, 8192),
+            (100, 3 * 4096 + 50),
+            (3 * 4096 + 10, 3 * 4096 + 100),
+            (0, plaintext.len()),
+        ] {
+            let decrypted = decrypt_range(&key, &ciphertext, start as u64..end as u64).unwrap();
+
+            assert_eq!(decrypted, plaintext[start..end], "{start}..{end}");
+        }
+    }
+
+    #[test]
+    fn chacha_seekable_decrypt_range_cut_short_at_end() {
+        let (key, plaintext, ciphertext) = seekable_fixture();
+
+        let decrypted = decrypt_range(&key, &ciphertext, 12_000..u64::MAX).unwrap();
+
+        assert_eq!(decrypted, plaintext[12_000..]);
+    }
+
+    #[test]
+    fn chacha_seekable_decrypt_range_full_chunks_only() {
+        let (key, _, _) = seekable_fixture();
+        let plaintext = vec![7u8; 2 * 4096];
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, &plaintext).unwrap();
+
+        assert_eq!(
+            decrypt_range(&key, &ciphertext, 4000..u64::MAX).unwrap(),
+            plaintext[4000..]
+        );
+        assert!(
+            decrypt_range(&key, &ciphertext, 8192..9000)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn chacha_seekable_decrypt_range_empty() {
+        let (key, _, ciphertext) = seekable_fixture();
+
+        assert!(decrypt_range(&key, &ciphertext, 50..50).unwrap().is_empty());
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 50..10;
+        assert!(
+            decrypt_range(&key, &ciphertext, reversed)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn chacha_seekable_reads_only_covering_chunks() {
+        let (key, plaintext, mut ciphertext) = seekable_fixture();
+        // Corrupt the first chunk, which is not in the range.
+        ciphertext[20] ^= 1;
+
+        let mut decryptor = SeekableDecryptor::new(&key, Cursor::new(&ciphertext)).unwrap();
+        let mut decrypted = Vec::new();
+        let stats = decryptor.decrypt_range(5000..5010, &mut decrypted).unwrap();
+
+        assert_eq!(decrypted, plaintext[5000..5010]);
+        assert_eq!(stats.chunks, 1);
+        assert_eq!(stats.bytes_out, 10);
+        assert!(matches!(
+            decrypt_range(&key, &ciphertext, 0..10),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn chacha_seekable_detects_swapped_chunks() {
+        let (key, _, mut ciphertext) = seekable_fixture();
+        let record = 4 + MAX_CHUNK_LEN;
+        let (first, second) = (12, 12 + record);
+        let chunk = ciphertext[first..second].to_vec();
+        ciphertext.copy_within(second..second + record, first);
+        ciphertext[second..second + record].copy_from_slice(&chunk);
+
+        assert!(matches!(
+            decrypt_range(&key, &ciphertext, 0..10),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn chacha_seekable_past_the_end_is_error() {
+        let (key, _, ciphertext) = seekable_fixture();
+
+        assert!(matches!(
+            decrypt_range(&key, &ciphertext, 100_000..100_010),
+            Err(Error::Read(_))
+        ));
+    }
+
+    #[test]
+    fn chacha_seekable_truncated_is_error() {
+        let (key, _, ciphertext) = seekable_fixture();
+        let truncated = &ciphertext[..ciphertext.len() - 4];
+
+        assert!(decrypt_range(&key, truncated, 0..10).is_ok());
+        assert!(matches!(
+            decrypt_range(&key, truncated, 12_000..u64::MAX),
+            Err(Error::Read(_))
+        ));
+    }
+
+    #[test]
+    fn chacha_seekable_checks_trailer_at_the_end() {
+        let (key, plaintext, mut ciphertext) = seekable_fixture();
+        let len = ciphertext.len();
+        ciphertext[len - 1] ^= 1;
+
+        assert_eq!(
+            decrypt_range(&key, &ciphertext, 0..10).unwrap(),
+            plaintext[..10]
+        );
+        assert!(matches!(
+            decrypt_range(&key, &ciphertext, 12_000..u64::MAX),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn chacha_seekable_with_aad() {
+        let (key, _, _) = seekable_fixture();
+        let mut ciphertext = Vec::new();
+        ChaCha20Poly1305
+            .encrypt_stream_with_aad(&key, b"aad", &mut &b"Hello, World!"[..], &mut ciphertext)
+            .unwrap();
+
+        let mut decryptor =
+            SeekableDecryptor::with_aad(&key, b"aad", Cursor::new(&ciphertext)).unwrap();
+        let mut decrypted = Vec::new();
+        decryptor.decrypt_range(7..12, &mut decrypted).unwrap();
+        assert_eq!(decrypted, b"World");
+
+        assert!(matches!(
+            decrypt_range(&key, &ciphertext, 7..12),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn chacha_seekable_embedded_in_container() {
+        let (key, plaintext, ciphertext) = seekable_fixture();
+        let mut container = b"MAGIC".to_vec();
+        container.extend(&ciphertext);
+
+        let mut reader = Cursor::new(&container);
+        reader.set_position(5);
+        let mut decryptor = SeekableDecryptor::new(&key, reader).unwrap();
+        let mut decrypted = Vec::new();
+        decryptor.decrypt_range(8000..8200, &mut decrypted).unwrap();
+
+        assert_eq!(decrypted, plaintext[8000..8200]);
+    }
+
+    #[test]
+    fn chacha_seekable_other_algorithm_is_error() {
+        let (key, _, _) = seekable_fixture();
+
+        let err = SeekableDecryptor::new(&key, Cursor::new(b"HPKE\x01...")).err();
+
+        assert_eq!(err, Some(Error::Algorithm));
+    }
 }
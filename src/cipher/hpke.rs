@@ -43,6 +43,9 @@
 //!
 //! - Any header or encapsulated key mismatch results in immediate
 //!   failure.
+//!
+//! - Optional **associated data** is passed through to the payload
+//!   encryption, and is never written to the stream.
 
 use std::io::{Read, Write};
 
@@ -53,10 +56,13 @@ use hpke::{Deserializable, OpModeR, OpModeS, Serializable};
 use secrecy::{SecretSlice, zeroize::Zeroizing};
 
 use crate::cipher::ChaCha20Poly1305;
-use crate::traits::{self, Cipher, Error, GeneratedKey};
+use crate::entropy;
+use crate::traits::{
+    self, Cipher, CountingReader, CountingWriter, Error, GeneratedKey, KeygenOptions, StreamStats,
+};
 
 // Contains algorithm name (4-bytes) and version (1-byte).
-const HEADER: &[u8; 5] = b"HPKE\x01";
+pub(crate) const HEADER: &[u8; 5] = b"HPKE\x01";
 
 // Used to bind the derived keys to a specific application context or
 // protocol version. It's the same idea as `HEADER` but used by HPKE
@@ -67,13 +73,14 @@ const EXPORT_LABEL: &[u8] = b"stream";
 pub struct Hpke;
 
 impl Cipher for Hpke {
+    fn algorithm_id(&self) -> &'static str {
+        "hpke"
+    }
+
     /// Generate an X25519 32-byte (256-bit) keypair.
-    fn generate_key(&self) -> GeneratedKey {
-        let (sk, pk) = <X25519HkdfSha256 as Kem>::gen_keypair();
-        GeneratedKey::Asymmetric {
-            public: SecretSlice::from(pk.to_bytes().to_vec()),
-            private: SecretSlice::from(sk.to_bytes().to_vec()),
-        }
+    fn generate_key(&self, options: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        options.expect_fixed_size(256)?;
+        generate_keypair::<X25519HkdfSha256>()
     }
 
     fn encrypt_stream(
@@ -81,69 +88,8 @@ impl Cipher for Hpke {
         public_key: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
-        // Recipient's public key.
-        let public_key = <X25519HkdfSha256 as Kem>::PublicKey::from_bytes(public_key)
-            .map_err(|_| Error::Encrypt)?;
-
-        // Generate an ephemeral keypair.
-        //
-        // Note: The private key is internal (`encryption_context`) and
-        // will be used to derive a symmetric key on our (the encryptor)
-        // side. The public key will be sent along and be used by the
-        // recipient (the decryptor) to derive the same symmetric key
-        // on his side.
-        //
-        // Why ephemeral? Forward secrecy. If the recipient's private
-        // key ever gets compromised, passed and future messages will
-        // still be safe because the derived symmetric key will be
-        // _unique_ to that session/message. If not for the ephemeral
-        // keypair, we would always use the _same_ symmetric key,
-        // breaking forward secrecy.
-        let (encapsulated_public_key, encryption_context) =
-            hpke::setup_sender::<ChaCha20Poly1305_, HkdfSha256, X25519HkdfSha256>(
-                &OpModeS::Base,
-                &public_key,
-                INFO,
-            )
-            .map_err(|_| Error::Encrypt)?;
-
-        // Derive a 32-byte symmetric key. Will encrypt the _message_.
-        //
-        // Note: Contrary to "traditional" hybrid encryption, the
-        // symmetric key is not encrypted and sent along, instead, it
-        // is _derived_. During encryption, it is derived by combining
-        // the recipient's public key, and the ephemeral private key
-        // (dropped after use). During decryption, it is derived by
-        // combining the recipient's private key, and the public key
-        // (`encapsulated_public_key`) that we send along.
-        let mut symmetric_key = Zeroizing::new([0u8; 32]);
-        encryption_context
-            .export(EXPORT_LABEL, symmetric_key.as_mut_slice())
-            .map_err(|_| Error::Encrypt)?;
-
-        writer
-            .write_all(HEADER)
-            .map_err(|e| Error::Write(e.to_string()))?;
-
-        // 2-bytes (16-bits) big-endian encapsulated public key length.
-        // length prefix for encapsulated_key
-        let encapsulated_public_key = encapsulated_public_key.to_bytes();
-        let encapsulated_public_key_len = u16::try_from(encapsulated_public_key.len())
-            .map_err(|_| Error::Encrypt)?
-            .to_be_bytes();
-        writer
-            .write_all(&encapsulated_public_key_len)
-            .map_err(|e| Error::Write(e.to_string()))?;
-        writer
-            .write_all(&encapsulated_public_key)
-            .map_err(|e| Error::Write(e.to_string()))?;
-
-        // We've written the header and the encapsulated public key,
-        // the only thing left to do is to append the encrypted payload.
-        ChaCha20Poly1305.encrypt_stream(symmetric_key.as_ref(), reader, writer)?;
-
-        Ok(())
+    ) -> traits::Result<StreamStats> {
+        self.encrypt_stream_with_aad(public_key, &[], reader, writer)
     }
 
     fn decrypt_stream(
@@ -151,65 +97,199 @@ impl Cipher for Hpke {
         private_key: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
-        if usize::BITS < u16::BITS {
-            return Err(Error::Platform(
-                "< 16-bit platforms are not supported.".to_string(),
-            ));
-        }
-
-        // Recipient's private key.
-        let private_key = <X25519HkdfSha256 as Kem>::PrivateKey::from_bytes(private_key)
-            .map_err(|_| Error::Decrypt)?;
-
-        let mut header = [0u8; HEADER.len()];
-        reader
-            .read_exact(&mut header)
-            .map_err(|e| Error::Read(e.to_string()))?;
-        if &header != HEADER {
-            return Err(Error::Algorithm);
-        }
-
-        // 2-bytes (16-bits) big-endian encapsulated public key length.
-        let mut encapsulated_public_key_len = [0u8; 2];
-        reader
-            .read_exact(&mut encapsulated_public_key_len)
-            .map_err(|e| Error::Read(e.to_string()))?;
-        let encapsulated_public_key_len = u16::from_be_bytes(encapsulated_public_key_len) as usize;
-        if encapsulated_public_key_len != 32 {
-            return Err(Error::Decrypt);
-        }
-
-        let mut encapsulated_public_key = vec![0u8; encapsulated_public_key_len];
-        reader
-            .read_exact(&mut encapsulated_public_key)
-            .map_err(|e| Error::Read(e.to_string()))?;
-        let encapsulated_public_key =
-            <X25519HkdfSha256 as Kem>::EncappedKey::from_bytes(&encapsulated_public_key)
-                .map_err(|_| Error::Decrypt)?;
-
-        // Combine the encapsulated public key and the recipient's
-        // secret key to derive the shared symmetric key.
-        let decryption_context = hpke::setup_receiver::<
-            ChaCha20Poly1305_,
-            HkdfSha256,
-            X25519HkdfSha256,
-        >(
-            &OpModeR::Base, &private_key, &encapsulated_public_key, INFO
-        )
-        .map_err(|_| Error::Decrypt)?;
+    ) -> traits::Result<StreamStats> {
+        self.decrypt_stream_with_aad(private_key, &[], reader, writer)
+    }
 
-        // Derive the 32-byte shared symmetric key.
-        let mut symmetric_key = Zeroizing::new([0u8; 32]);
-        decryption_context
-            .export(EXPORT_LABEL, symmetric_key.as_mut_slice())
-            .map_err(|_| Error::Decrypt)?;
+    fn encrypt_stream_with_aad(
+        &self,
+        public_key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        encrypt::<X25519HkdfSha256>(*HEADER, INFO, public_key, aad, reader, writer)
+    }
 
-        // We've got the symmetric key, decrypt the payload.
-        ChaCha20Poly1305.decrypt_stream(symmetric_key.as_ref(), reader, writer)?;
+    fn decrypt_stream_with_aad(
+        &self,
+        private_key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        decrypt::<X25519HkdfSha256>(*HEADER, INFO, private_key, aad, reader, writer)
+    }
+}
+
+// Random bytes drawn for a KEM operation. This is enough for all the
+// KEMs we use (X-Wing encapsulation needs the most, 64 bytes).
+const KEM_RANDOMNESS_LEN: usize = 64;
+
+/// Generate a keypair for the KEM `K`.
+pub(crate) fn generate_keypair<K: Kem>() -> traits::Result<GeneratedKey> {
+    let (sk, pk) = K::gen_keypair_with_rng(&mut entropy::Drawn::<KEM_RANDOMNESS_LEN>::new()?);
+    let sk = Zeroizing::new(sk.to_bytes());
+    Ok(GeneratedKey::Asymmetric {
+        public: SecretSlice::from(pk.to_bytes().to_vec()),
+        private: SecretSlice::from(sk.as_slice().to_vec()),
+    })
+}
 
-        Ok(())
+/// Encrypt with the KEM `K`. `header` and `info` identify the cipher.
+pub(crate) fn encrypt<K: Kem>(
+    header: [u8; 5],
+    info: &[u8],
+    public_key: &[u8],
+    aad: &[u8],
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> traits::Result<StreamStats> {
+    // Recipient's public key.
+    let public_key = K::PublicKey::from_bytes(public_key).map_err(|_| Error::Encrypt)?;
+
+    let mut reader = CountingReader::new(reader);
+    let mut writer = CountingWriter::new(writer);
+
+    // Generate an ephemeral keypair.
+    //
+    // Note: The private key is internal (`encryption_context`) and
+    // will be used to derive a symmetric key on our (the encryptor)
+    // side. The public key will be sent along and be used by the
+    // recipient (the decryptor) to derive the same symmetric key
+    // on his side.
+    //
+    // Why ephemeral? Forward secrecy. If the recipient's private
+    // key ever gets compromised, passed and future messages will
+    // still be safe because the derived symmetric key will be
+    // _unique_ to that session/message. If not for the ephemeral
+    // keypair, we would always use the _same_ symmetric key,
+    // breaking forward secrecy.
+    let (encapsulated_public_key, encryption_context) =
+        hpke::setup_sender_with_rng::<ChaCha20Poly1305_, HkdfSha256, K>(
+            &OpModeS::Base,
+            &public_key,
+            info,
+            &mut entropy::Drawn::<KEM_RANDOMNESS_LEN>::new()?,
+        )
+        .map_err(|_| Error::Encrypt)?;
+
+    // Derive a 32-byte symmetric key. Will encrypt the _message_.
+    //
+    // Note: Contrary to "traditional" hybrid encryption, the
+    // symmetric key is not encrypted and sent along, instead, it
+    // is _derived_. During encryption, it is derived by combining
+    // the recipient's public key, and the ephemeral private key
+    // (dropped after use). During decryption, it is derived by
+    // combining the recipient's private key, and the public key
+    // (`encapsulated_public_key`) that we send along.
+    let mut symmetric_key = Zeroizing::new([0u8; 32]);
+    encryption_context
+        .export(EXPORT_LABEL, symmetric_key.as_mut_slice())
+        .map_err(|_| Error::Encrypt)?;
+
+    writer
+        .write_all(&header)
+        .map_err(|e| Error::Write(e.to_string()))?;
+
+    // 2-bytes (16-bits) big-endian encapsulated public key length.
+    // length prefix for encapsulated_key
+    let encapsulated_public_key = encapsulated_public_key.to_bytes();
+    let encapsulated_public_key_len = u16::try_from(encapsulated_public_key.len())
+        .map_err(|_| Error::Encrypt)?
+        .to_be_bytes();
+    writer
+        .write_all(&encapsulated_public_key_len)
+        .map_err(|e| Error::Write(e.to_string()))?;
+    writer
+        .write_all(&encapsulated_public_key)
+        .map_err(|e| Error::Write(e.to_string()))?;
+
+    // We've written the header and the encapsulated public key,
+    // the only thing left to do is to append the encrypted payload.
+    let stats = ChaCha20Poly1305.encrypt_stream_with_aad(
+        symmetric_key.as_ref(),
+        aad,
+        &mut reader,
+        &mut writer,
+    )?;
+
+    Ok(StreamStats {
+        bytes_in: reader.count,
+        bytes_out: writer.count,
+        ..stats
+    })
+}
+
+/// Decrypt with the KEM `K`. `header` and `info` identify the cipher.
+pub(crate) fn decrypt<K: Kem>(
+    header: [u8; 5],
+    info: &[u8],
+    private_key: &[u8],
+    aad: &[u8],
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> traits::Result<StreamStats> {
+    // Recipient's private key.
+    let private_key = K::PrivateKey::from_bytes(private_key).map_err(|_| Error::Decrypt)?;
+
+    let mut reader = CountingReader::new(reader);
+    let mut writer = CountingWriter::new(writer);
+
+    let mut found = [0u8; 5];
+    reader
+        .read_exact(&mut found)
+        .map_err(|e| Error::Read(e.to_string()))?;
+    if found != header {
+        return Err(Error::Algorithm);
     }
+
+    // 2-bytes (16-bits) big-endian encapsulated public key length.
+    let mut encapsulated_public_key_len = [0u8; 2];
+    reader
+        .read_exact(&mut encapsulated_public_key_len)
+        .map_err(|e| Error::Read(e.to_string()))?;
+    let encapsulated_public_key_len = usize::from(u16::from_be_bytes(encapsulated_public_key_len));
+    if encapsulated_public_key_len != K::EncappedKey::size() {
+        return Err(Error::Decrypt);
+    }
+
+    let mut encapsulated_public_key = vec![0u8; encapsulated_public_key_len];
+    reader
+        .read_exact(&mut encapsulated_public_key)
+        .map_err(|e| Error::Read(e.to_string()))?;
+    let encapsulated_public_key =
+        K::EncappedKey::from_bytes(&encapsulated_public_key).map_err(|_| Error::Decrypt)?;
+
+    // Combine the encapsulated public key and the recipient's
+    // secret key to derive the shared symmetric key.
+    let decryption_context = hpke::setup_receiver::<ChaCha20Poly1305_, HkdfSha256, K>(
+        &OpModeR::Base,
+        &private_key,
+        &encapsulated_public_key,
+        info,
+    )
+    .map_err(|_| Error::Decrypt)?;
+
+    // Derive the 32-byte shared symmetric key.
+    let mut symmetric_key = Zeroizing::new([0u8; 32]);
+    decryption_context
+        .export(EXPORT_LABEL, symmetric_key.as_mut_slice())
+        .map_err(|_| Error::Decrypt)?;
+
+    // We've got the symmetric key, decrypt the payload.
+    let stats = ChaCha20Poly1305.decrypt_stream_with_aad(
+        symmetric_key.as_ref(),
+        aad,
+        &mut reader,
+        &mut writer,
+    )?;
+
+    Ok(StreamStats {
+        bytes_in: reader.count,
+        bytes_out: writer.count,
+        ..stats
+    })
 }
 
 #[cfg(test)]
@@ -242,6 +322,44 @@ pub mod tests {
         assert_eq!(decrypted, "hello, world!");
     }
 
+    #[test]
+    fn hpke_stream_stats_include_headers() {
+        let public_key = "lNLRjAfH2i8QfgEBmkwb9DyigB6mFae94FYCx46qij0"
+            .base64_decode()
+            .unwrap();
+        let private_key = "caEdcM9zySxJCc+HBD7QzzpJwBVWm2BcGyBMoGETi+g"
+            .base64_decode()
+            .unwrap();
+
+        let mut encrypted = Vec::new();
+        let stats = Hpke
+            .encrypt_stream(&public_key, &mut Cursor::new(b"hello"), &mut encrypted)
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            StreamStats {
+                bytes_in: 5,
+                bytes_out: encrypted.len() as u64,
+                chunks: 1,
+            }
+        );
+
+        let mut decrypted = Vec::new();
+        let stats = Hpke
+            .decrypt_stream(&private_key, &mut Cursor::new(&encrypted), &mut decrypted)
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            StreamStats {
+                bytes_in: encrypted.len() as u64,
+                bytes_out: 5,
+                chunks: 1,
+            }
+        );
+    }
+
     #[test]
     fn hpke_encrypt_decrypt_streaming_roundtrip_shorter_than_a_chunk() {
         let public_key = "lNLRjAfH2i8QfgEBmkwb9DyigB6mFae94FYCx46qij0"
@@ -329,4 +447,44 @@ pub mod tests {
 
         assert_eq!(decrypted, "hello, world!".repeat(320));
     }
+
+    #[test]
+    fn hpke_decrypt_with_wrong_aad_fails() {
+        let public_key = "lNLRjAfH2i8QfgEBmkwb9DyigB6mFae94FYCx46qij0"
+            .base64_decode()
+            .unwrap();
+        let private_key = "caEdcM9zySxJCc+HBD7QzzpJwBVWm2BcGyBMoGETi+g"
+            .base64_decode()
+            .unwrap();
+        let plaintext = b"hello, world!";
+
+        let mut encrypted = Vec::new();
+        Hpke.encrypt_stream_with_aad(
+            &public_key,
+            b"context",
+            &mut Cursor::new(plaintext),
+            &mut encrypted,
+        )
+        .unwrap();
+
+        let mut decrypted = Vec::new();
+        Hpke.decrypt_stream_with_aad(
+            &private_key,
+            b"context",
+            &mut Cursor::new(&encrypted),
+            &mut decrypted,
+        )
+        .unwrap();
+        assert_eq!(decrypted, b"hello, world!");
+
+        let err = Hpke
+            .decrypt_stream_with_aad(
+                &private_key,
+                b"other",
+                &mut Cursor::new(&encrypted),
+                &mut Vec::new(),
+            )
+            .unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+    }
 }
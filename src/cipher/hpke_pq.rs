@@ -0,0 +1,178 @@
+//! Post-quantum hybrid HPKE.
+//!
+//! Ciphersuite: HPKE-Base-X-Wing-HKDF-SHA256-ChaCha20Poly1305.
+//!
+//! Same construction as [`Hpke`](super::Hpke), with the X25519 KEM
+//! swapped for **X-Wing**, a hybrid of X25519 and ML-KEM-768 (Kyber).
+//! The shared secret stays safe as long as _either_ of the two holds,
+//! which protects today's ciphertexts against a future quantum computer
+//! ("harvest now, decrypt later"), without betting everything on a
+//! younger algorithm.
+//!
+//! # Message Format
+//!
+//! ```text
+//! [ header (5) ]       b"HPQ1\x01", algorithm ID and version
+//! [ encapsulated public key length (2-byte BE) ]
+//! [ encapsulated public key (1120) ]
+//! [ chacha encrypted payload ]
+//! ```
+//!
+//! Keys are much larger than X25519 keys: the public key is 1216 bytes,
+//! the private key is a 32-byte seed.
+
+use std::io::{Read, Write};
+
+use ::hpke::kem::XWing;
+
+use crate::traits::{self, Cipher, GeneratedKey, KeygenOptions, StreamStats};
+
+use super::hpke;
+
+// Contains algorithm name (4-bytes) and version (1-byte).
+pub(crate) const HEADER: &[u8; 5] = b"HPQ1\x01";
+
+// Different from plain HPKE, keys derived for one can't be mistaken for
+// the other.
+const INFO: &[u8] = b"jolokia-hpke-pq-stream-v1";
+
+pub struct HpkePq;
+
+impl Cipher for HpkePq {
+    fn algorithm_id(&self) -> &'static str {
+        "hpke-pq"
+    }
+
+    /// Generate an X-Wing keypair (256-bit security level).
+    fn generate_key(&self, options: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        options.expect_fixed_size(256)?;
+        hpke::generate_keypair::<XWing>()
+    }
+
+    fn encrypt_stream(
+        &self,
+        public_key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.encrypt_stream_with_aad(public_key, &[], reader, writer)
+    }
+
+    fn decrypt_stream(
+        &self,
+        private_key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.decrypt_stream_with_aad(private_key, &[], reader, writer)
+    }
+
+    fn encrypt_stream_with_aad(
+        &self,
+        public_key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        hpke::encrypt::<XWing>(*HEADER, INFO, public_key, aad, reader, writer)
+    }
+
+    fn decrypt_stream_with_aad(
+        &self,
+        private_key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        hpke::decrypt::<XWing>(*HEADER, INFO, private_key, aad, reader, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+
+    use crate::cipher::Hpke;
+    use crate::traits::Error;
+
+    fn keypair() -> (Vec<u8>, Vec<u8>) {
+        let key = HpkePq.generate_key(&KeygenOptions::default()).unwrap();
+        (
+            key.get_asymmetric_public().expose_secret().to_vec(),
+            key.get_asymmetric_private().expose_secret().to_vec(),
+        )
+    }
+
+    #[test]
+    fn hpke_pq_key_sizes() {
+        let (public, private) = keypair();
+
+        assert_eq!(public.len(), 1216);
+        assert_eq!(private.len(), 32);
+    }
+
+    #[test]
+    fn hpke_pq_encrypt_decrypt_roundtrip() {
+        let (public, private) = keypair();
+        let plaintext = b"hello, world!".repeat(1000);
+
+        let encrypted = HpkePq.encrypt(&public, &plaintext).unwrap();
+        let decrypted = HpkePq.decrypt(&private, &encrypted).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn hpke_pq_message_format() {
+        let (public, _) = keypair();
+
+        let encrypted = HpkePq.encrypt(&public, b"hello").unwrap();
+
+        assert_eq!(&encrypted[..5], HEADER);
+        assert_eq!(u16::from_be_bytes([encrypted[5], encrypted[6]]), 1120);
+    }
+
+    #[test]
+    fn hpke_pq_decrypt_with_wrong_key_fails() {
+        let (public, _) = keypair();
+        let (_, other_private) = keypair();
+
+        let encrypted = HpkePq.encrypt(&public, b"hello").unwrap();
+        let err = HpkePq.decrypt(&other_private, &encrypted).unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn hpke_pq_decrypt_with_wrong_aad_fails() {
+        let (public, private) = keypair();
+
+        let mut encrypted = Vec::new();
+        HpkePq
+            .encrypt_stream_with_aad(&public, b"context", &mut &b"hello"[..], &mut encrypted)
+            .unwrap();
+        let err = HpkePq
+            .decrypt_stream_with_aad(
+                &private,
+                b"other",
+                &mut encrypted.as_slice(),
+                &mut Vec::new(),
+            )
+            .unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn hpke_pq_and_hpke_do_not_mix() {
+        let (public, _) = keypair();
+        let hpke_private = [0u8; 32];
+
+        let encrypted = HpkePq.encrypt(&public, b"hello").unwrap();
+        let err = Hpke.decrypt(&hpke_private, &encrypted).unwrap_err();
+
+        assert_eq!(err, Error::Algorithm);
+    }
+}
@@ -0,0 +1,278 @@
+//! Password-based encryption.
+//!
+//! The key is derived from a password with Argon2id (see [`kdf`]), and
+//! the data is encrypted with [`ChaCha20Poly1305`].
+//!
+//! # Message Format
+//!
+//! ```text
+//! [ header (5) ]         b"PASS\x01", algorithm ID and version
+//! [ salt (16) ]          random, unique per message
+//! [ KDF params (12) ]    memory (KiB), iterations, parallelism (4-byte BE each)
+//! [ ChaCha20-Poly1305 ciphertext, with its own header ]
+//! ```
+//!
+//! The salt and parameters are not authenticated as such, but they are
+//! bound to the ciphertext all the same: changing any of them changes
+//! the derived key, and decryption fails.
+
+use std::io::{Read, Write};
+
+use crate::cipher::ChaCha20Poly1305;
+use crate::entropy;
+use crate::kdf::{self, Params};
+use crate::traits::{
+    self, Cipher, CountingReader, CountingWriter, Error, GeneratedKey, KeygenOptions, StreamStats,
+};
+
+// Contains algorithm name (4-bytes) and version (1-byte).
+pub(crate) const HEADER: &[u8; 5] = b"PASS\x01";
+
+/// The "key" given to this cipher is the password itself.
+#[derive(Default)]
+pub struct Password {
+    params: Params,
+}
+
+impl Password {
+    /// Use custom KDF parameters for encryption.
+    ///
+    /// Decryption always uses the parameters stored in the ciphertext.
+    #[must_use]
+    pub fn with_params(params: Params) -> Self {
+        Self { params }
+    }
+}
+
+impl Cipher for Password {
+    fn algorithm_id(&self) -> &'static str {
+        "password"
+    }
+
+    /// Passwords are chosen, not generated.
+    fn generate_key(&self, _: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        Ok(GeneratedKey::None)
+    }
+
+    fn encrypt_stream(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.encrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn decrypt_stream(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.decrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn encrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
+        let salt = entropy::generate::<{ kdf::SALT_LEN }>()?;
+        let derived_key = kdf::derive_key(key, &salt, self.params)?;
+
+        writer
+            .write_all(HEADER)
+            .and_then(|()| writer.write_all(&salt))
+            .and_then(|()| writer.write_all(&self.params.to_bytes()))
+            .map_err(|e| Error::Write(e.to_string()))?;
+
+        let stats = ChaCha20Poly1305.encrypt_stream_with_aad(
+            derived_key.as_slice(),
+            aad,
+            &mut reader,
+            &mut writer,
+        )?;
+
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            ..stats
+        })
+    }
+
+    fn decrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
+        let mut header = [0u8; HEADER.len()];
+        reader
+            .read_exact(&mut header)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        if &header != HEADER {
+            return Err(Error::Algorithm);
+        }
+
+        let mut salt = [0u8; kdf::SALT_LEN];
+        let mut params = [0u8; Params::LEN];
+        reader
+            .read_exact(&mut salt)
+            .and_then(|()| reader.read_exact(&mut params))
+            .map_err(|e| Error::Read(e.to_string()))?;
+        let params = Params::from_bytes(&params)?;
+
+        let derived_key = kdf::derive_key(key, &salt, params)?;
+
+        let stats = ChaCha20Poly1305.decrypt_stream_with_aad(
+            derived_key.as_slice(),
+            aad,
+            &mut reader,
+            &mut writer,
+        )?;
+
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            ..stats
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::kdf::TEST_PARAMS;
+
+    #[test]
+    fn password_encrypt_decrypt_roundtrip() {
+        let cipher = Password::with_params(TEST_PARAMS);
+        let plaintext = b"hello, world!";
+
+        let encrypted = cipher.encrypt(b"hunter2", plaintext).unwrap();
+        let decrypted = cipher.decrypt(b"hunter2", &encrypted).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn password_encrypt_is_salted() {
+        let cipher = Password::with_params(TEST_PARAMS);
+
+        let a = cipher.encrypt(b"hunter2", b"hello").unwrap();
+        let b = cipher.encrypt(b"hunter2", b"hello").unwrap();
+
+        assert_ne!(
+            a[..HEADER.len() + kdf::SALT_LEN],
+            b[..HEADER.len() + kdf::SALT_LEN]
+        );
+    }
+
+    #[test]
+    fn password_header_stores_params() {
+        let cipher = Password::with_params(TEST_PARAMS);
+
+        let encrypted = cipher.encrypt(b"hunter2", b"hello").unwrap();
+
+        let params = &encrypted[HEADER.len() + kdf::SALT_LEN..][..Params::LEN];
+        assert_eq!(params, TEST_PARAMS.to_bytes());
+    }
+
+    #[test]
+    fn password_decrypt_uses_stored_params() {
+        let encrypted = Password::with_params(TEST_PARAMS)
+            .encrypt(b"hunter2", b"hello")
+            .unwrap();
+
+        // Default parameters, but the ciphertext's win.
+        let decrypted = Password::default().decrypt(b"hunter2", &encrypted).unwrap();
+
+        assert_eq!(decrypted, b"hello");
+    }
+
+    #[test]
+    fn password_wrong_password_is_error() {
+        let cipher = Password::with_params(TEST_PARAMS);
+
+        let encrypted = cipher.encrypt(b"hunter2", b"hello").unwrap();
+        let err = cipher.decrypt(b"hunter3", &encrypted).unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn password_tampered_salt_is_error() {
+        let cipher = Password::with_params(TEST_PARAMS);
+
+        let mut encrypted = cipher.encrypt(b"hunter2", b"hello").unwrap();
+        encrypted[HEADER.len()] ^= 1;
+        let err = cipher.decrypt(b"hunter2", &encrypted).unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn password_excessive_params_is_error() {
+        let cipher = Password::with_params(TEST_PARAMS);
+
+        let mut encrypted = cipher.encrypt(b"hunter2", b"hello").unwrap();
+        let start = HEADER.len() + kdf::SALT_LEN;
+        encrypted[start..start + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = cipher.decrypt(b"hunter2", &encrypted).unwrap_err();
+
+        assert!(matches!(err, Error::Parameter(_)));
+    }
+
+    #[test]
+    fn password_empty_password_is_error() {
+        let err = Password::with_params(TEST_PARAMS)
+            .encrypt(b"", b"hello")
+            .unwrap_err();
+        assert_eq!(err, Error::Key);
+    }
+
+    #[test]
+    fn password_other_algorithm_is_error() {
+        let encrypted = ChaCha20Poly1305.encrypt(&[0u8; 32], b"hello").unwrap();
+        let err = Password::with_params(TEST_PARAMS)
+            .decrypt(b"hunter2", &encrypted)
+            .unwrap_err();
+        assert_eq!(err, Error::Algorithm);
+    }
+
+    #[test]
+    fn password_with_aad_roundtrip() {
+        let cipher = Password::with_params(TEST_PARAMS);
+
+        let mut encrypted = Vec::new();
+        cipher
+            .encrypt_stream_with_aad(b"hunter2", b"v1", &mut &b"hello"[..], &mut encrypted)
+            .unwrap();
+
+        let mut decrypted = Vec::new();
+        cipher
+            .decrypt_stream_with_aad(b"hunter2", b"v1", &mut encrypted.as_slice(), &mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, b"hello");
+
+        let err = cipher
+            .decrypt_stream_with_aad(
+                b"hunter2",
+                b"v2",
+                &mut encrypted.as_slice(),
+                &mut Vec::new(),
+            )
+            .unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+    }
+}
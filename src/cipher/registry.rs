@@ -0,0 +1,315 @@
+//! Registry of external ciphers.
+//!
+//! Downstream crates plug their own [`Cipher`] in without forking
+//! jolokia: registered ciphers are looked up by algorithm ID like the
+//! built-in ones (see [`super::from_algorithm_id()`]). The registry is
+//! for the library only, the CLI can't load external code.
+//!
+//! A cipher is registered with the 4-byte ID its ciphertexts start
+//! with, the first half of its header (see [`crate::format`]). With it,
+//! raw ciphertexts are told apart from base64 (see
+//! [`crate::inspect::is_raw()`]). Writing the header is up to the
+//! cipher, the registry only reads it.
+//!
+//! IDs are first come, first served, and jolokia's own always win.
+
+use std::sync::{PoisonError, RwLock};
+
+#[cfg(feature = "brainfuck")]
+use super::Brainfuck;
+use super::ChaCha20Poly1305;
+#[cfg(feature = "password")]
+use super::Password;
+#[cfg(feature = "rotn")]
+use super::RotN;
+#[cfg(feature = "age")]
+use super::{Age, AgePassword, age};
+#[cfg(feature = "hpke")]
+use super::{Hpke, HpkePq};
+use crate::armor;
+use crate::format::HEADERS;
+use crate::traits::{Cipher, Error, Result};
+
+/// Makes an instance of a registered cipher.
+pub type CipherFactory = fn() -> Box<dyn Cipher>;
+
+struct Entry {
+    header_id: [u8; 4],
+    algorithm_id: &'static str,
+    factory: CipherFactory,
+}
+
+/// External ciphers, by algorithm ID and header ID.
+pub struct CipherRegistry {
+    entries: RwLock<Vec<Entry>>,
+}
+
+static GLOBAL: CipherRegistry = CipherRegistry::new();
+
+impl CipherRegistry {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// The registry jolokia consults (e.g., for `-a`).
+    #[must_use]
+    pub fn global() -> &'static Self {
+        &GLOBAL
+    }
+
+    /// Register the cipher `factory` makes, under its algorithm ID.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the algorithm ID or the header ID is already taken,
+    /// by jolokia or by another registered cipher.
+    pub fn register(&self, header_id: [u8; 4], factory: CipherFactory) -> Result<()> {
+        let algorithm_id = factory().algorithm_id();
+        if algorithm_id.is_empty() {
+            return Err(Error::Other("The algorithm ID is empty".to_string()));
+        }
+
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+
+        if is_builtin_algorithm(algorithm_id)
+            || entries
+                .iter()
+                .any(|entry| entry.algorithm_id == algorithm_id)
+        {
+            return Err(Error::Other(format!(
+                "The algorithm ID '{algorithm_id}' is already taken"
+            )));
+        }
+        if is_builtin_header(header_id) || entries.iter().any(|entry| entry.header_id == header_id)
+        {
+            return Err(Error::Other(format!(
+                "The header ID '{}' is already taken",
+                header_id.escape_ascii()
+            )));
+        }
+
+        entries.push(Entry {
+            header_id,
+            algorithm_id,
+            factory,
+        });
+        drop(entries);
+        Ok(())
+    }
+
+    /// The cipher registered as `algorithm_id`.
+    #[must_use]
+    pub fn get(&self, algorithm_id: &str) -> Option<Box<dyn Cipher>> {
+        self.find(|entry| entry.algorithm_id == algorithm_id)
+    }
+
+    /// The cipher whose ciphertexts start like `prefix` does.
+    #[must_use]
+    pub fn detect(&self, prefix: &[u8]) -> Option<Box<dyn Cipher>> {
+        self.find(|entry| prefix.starts_with(&entry.header_id))
+    }
+
+    /// Algorithm IDs of the registered ciphers, in order of
+    /// registration.
+    #[must_use]
+    pub fn algorithm_ids(&self) -> Vec<&'static str> {
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|entry| entry.algorithm_id)
+            .collect()
+    }
+
+    fn find(&self, predicate: impl Fn(&Entry) -> bool) -> Option<Box<dyn Cipher>> {
+        let factory = self
+            .entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|entry| predicate(entry))
+            .map(|entry| entry.factory)?;
+        // Outside the lock, the factory may well use the registry.
+        Some(factory())
+    }
+}
+
+impl Default for CipherRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_builtin_algorithm(algorithm_id: &str) -> bool {
+    // Only those compiled in, the others' IDs are free.
+    let builtins: Vec<Box<dyn Cipher>> = vec![
+        #[cfg(feature = "age")]
+        Box::new(Age),
+        #[cfg(feature = "age")]
+        Box::new(AgePassword::default()),
+        #[cfg(feature = "brainfuck")]
+        Box::new(Brainfuck),
+        Box::new(ChaCha20Poly1305),
+        #[cfg(feature = "hpke")]
+        Box::new(Hpke),
+        #[cfg(feature = "hpke")]
+        Box::new(HpkePq),
+        #[cfg(feature = "password")]
+        Box::new(Password::default()),
+        #[cfg(feature = "rotn")]
+        Box::new(RotN),
+    ];
+    builtins
+        .iter()
+        .any(|cipher| cipher.algorithm_id() == algorithm_id)
+}
+
+/// Headers jolokia reads, and the start of age files and armor.
+fn is_builtin_header(header_id: [u8; 4]) -> bool {
+    HEADERS
+        .iter()
+        .map(|header| &header.bytes[..4])
+        .chain([
+            #[cfg(feature = "age")]
+            &age::MAGIC[..4],
+            #[cfg(feature = "age")]
+            &age::ARMOR_BEGIN[..4],
+            &armor::BEGIN.as_bytes()[..4],
+        ])
+        .any(|id| id == header_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use crate::traits::{GeneratedKey, KeygenOptions, StreamStats};
+
+    /// ChaCha20-Poly1305, under another name.
+    struct Corporate;
+
+    impl Cipher for Corporate {
+        fn algorithm_id(&self) -> &'static str {
+            "corporate"
+        }
+
+        fn generate_key(&self, options: &KeygenOptions) -> Result<GeneratedKey> {
+            ChaCha20Poly1305.generate_key(options)
+        }
+
+        fn encrypt_stream(
+            &self,
+            key: &[u8],
+            reader: &mut dyn Read,
+            writer: &mut dyn Write,
+        ) -> Result<StreamStats> {
+            ChaCha20Poly1305.encrypt_stream(key, reader, writer)
+        }
+
+        fn decrypt_stream(
+            &self,
+            key: &[u8],
+            reader: &mut dyn Read,
+            writer: &mut dyn Write,
+        ) -> Result<StreamStats> {
+            ChaCha20Poly1305.decrypt_stream(key, reader, writer)
+        }
+    }
+
+    fn corporate() -> Box<dyn Cipher> {
+        Box::new(Corporate)
+    }
+
+    #[test]
+    fn register_regular() {
+        let registry = CipherRegistry::new();
+
+        registry.register(*b"CORP", corporate).unwrap();
+
+        assert_eq!(
+            registry.get("corporate").unwrap().algorithm_id(),
+            "corporate"
+        );
+        assert!(registry.get("chacha20poly1305").is_none());
+        assert_eq!(registry.algorithm_ids(), ["corporate"]);
+    }
+
+    #[test]
+    fn register_detect_by_header_id() {
+        let registry = CipherRegistry::new();
+        registry.register(*b"CORP", corporate).unwrap();
+
+        let cipher = registry.detect(b"CORP\x01 and more").unwrap();
+
+        assert_eq!(cipher.algorithm_id(), "corporate");
+        assert!(registry.detect(b"CORE\x01").is_none());
+        assert!(registry.detect(b"COR").is_none());
+    }
+
+    #[test]
+    fn register_taken_algorithm_id_is_error() {
+        let registry = CipherRegistry::new();
+        registry.register(*b"CORP", corporate).unwrap();
+
+        let err = registry.register(*b"CRP2", corporate).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The algorithm ID 'corporate' is already taken"
+        );
+    }
+
+    #[test]
+    fn register_builtin_algorithm_id_is_error() {
+        let registry = CipherRegistry::new();
+
+        let err = registry
+            .register(*b"CORP", || Box::new(ChaCha20Poly1305))
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The algorithm ID 'chacha20poly1305' is already taken"
+        );
+    }
+
+    #[test]
+    fn register_builtin_header_id_is_error() {
+        let registry = CipherRegistry::new();
+
+        for header_id in [
+            *b"CH20",
+            #[cfg(feature = "hpke")]
+            *b"HPKE",
+            #[cfg(feature = "age")]
+            *b"age-",
+            #[cfg(feature = "age")]
+            *b"----",
+        ] {
+            let err = registry.register(header_id, corporate).unwrap_err();
+            assert!(err.to_string().starts_with("The header ID"), "{err}");
+        }
+        assert!(registry.algorithm_ids().is_empty());
+    }
+
+    #[test]
+    fn register_works_through_shared_reference() {
+        let registry = Arc::new(CipherRegistry::new());
+
+        let handle = {
+            let registry = Arc::clone(&registry);
+            thread::spawn(move || registry.register(*b"CORP", corporate))
+        };
+        handle.join().unwrap().unwrap();
+
+        let cipher = registry.get("corporate").unwrap();
+        let encrypted = cipher.encrypt(&[0u8; 32], b"hello").unwrap();
+        assert_eq!(cipher.decrypt(&[0u8; 32], &encrypted).unwrap(), b"hello");
+    }
+}
@@ -2,13 +2,19 @@
 
 use std::io::{Read, Write};
 
-use crate::traits::{self, Cipher, Error, GeneratedKey};
+use secrecy::zeroize::Zeroizing;
+
+use crate::traits::{self, Cipher, Error, GeneratedKey, KeygenOptions, StreamStats};
 
 pub struct RotN;
 
 impl Cipher for RotN {
-    fn generate_key(&self) -> GeneratedKey {
-        GeneratedKey::None
+    fn algorithm_id(&self) -> &'static str {
+        "rotn"
+    }
+
+    fn generate_key(&self, _: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        Ok(GeneratedKey::None)
     }
 
     fn encrypt_stream(
@@ -16,15 +22,22 @@ impl Cipher for RotN {
         key: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
+    ) -> traits::Result<StreamStats> {
         let key = extract_n_from_key_or_fail(key)?;
 
-        let mut buffer = [0u8; 4096];
-        // TODO: Handle errors like in chacha decrypt(), same for chacha encrypt().
-        while let Ok(n) = reader.read(&mut buffer) {
+        let mut stats = StreamStats::default();
+        // Plaintext, wiped on drop.
+        let mut buffer = Zeroizing::new([0u8; 4096]);
+        loop {
+            let n = match reader.read(&mut *buffer) {
+                Ok(n) => n,
+                Err(reason) => return Err(Error::Read(reason.to_string())),
+            };
             if n == 0 {
                 break;
             }
+            stats.bytes_in += n as u64;
+            stats.bytes_out += n as u64;
             for c in &mut buffer[..n] {
                 *c = rotate(*c, key);
             }
@@ -33,7 +46,7 @@ impl Cipher for RotN {
                 .map_err(|e| Error::Write(e.to_string()))?;
         }
 
-        Ok(())
+        Ok(stats)
     }
 
     fn decrypt_stream(
@@ -41,15 +54,22 @@ impl Cipher for RotN {
         key: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> traits::Result<()> {
+    ) -> traits::Result<StreamStats> {
         let key = extract_n_from_key_or_fail(key)?;
 
-        let mut buffer = [0u8; 4096];
-        // TODO: Handle errors like in chacha decrypt(), same for chacha encrypt().
-        while let Ok(n) = reader.read(&mut buffer) {
+        let mut stats = StreamStats::default();
+        // Plaintext, wiped on drop.
+        let mut buffer = Zeroizing::new([0u8; 4096]);
+        loop {
+            let n = match reader.read(&mut *buffer) {
+                Ok(n) => n,
+                Err(reason) => return Err(Error::Read(reason.to_string())),
+            };
             if n == 0 {
                 break;
             }
+            stats.bytes_in += n as u64;
+            stats.bytes_out += n as u64;
             for c in &mut buffer[..n] {
                 *c = rotate(*c, -key);
             }
@@ -58,7 +78,7 @@ impl Cipher for RotN {
                 .map_err(|e| Error::Write(e.to_string()))?;
         }
 
-        Ok(())
+        Ok(stats)
     }
 }
 
@@ -149,6 +169,23 @@ pub mod tests {
         assert_eq!(&decrypted, b"attack at dawn");
     }
 
+    #[test]
+    fn rot_stream_stats() {
+        let mut encrypted = Vec::new();
+        let stats = RotN
+            .encrypt_stream(&[5], &mut &b"attack at dawn"[..], &mut encrypted)
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            StreamStats {
+                bytes_in: 14,
+                bytes_out: 14,
+                chunks: 0,
+            }
+        );
+    }
+
     #[test]
     fn rot_encrypt_does_not_break_multibyte_chars() {
         let plaintext = "hello ü, ñ, ü, 漢 world".as_bytes();
@@ -215,4 +252,12 @@ pub mod tests {
             );
         }
     }
+
+    #[test]
+    fn rot_does_not_support_aad() {
+        let err = RotN
+            .encrypt_stream_with_aad(&[13], b"context", &mut &b"hello"[..], &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err, Error::AssociatedData);
+    }
 }
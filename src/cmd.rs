@@ -1,94 +1,1396 @@
+pub mod bench;
 pub mod cli;
+pub mod doctor;
+pub mod environment;
+pub mod error;
+pub mod filter;
+pub mod help;
+pub mod in_place;
+pub mod keyring;
+pub mod metadata;
+pub mod mmap;
+pub mod operation;
+pub mod plan;
+pub mod plugin;
+pub mod qr;
+pub mod rekey;
+pub mod shred;
+pub mod signal;
 pub mod ui;
+pub mod warning;
 
-use std::io::{Read, Write};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
 
-use secrecy::{ExposeSecret, zeroize::Zeroizing};
+use secrecy::{ExposeSecret, SecretSlice, zeroize::Zeroizing};
 
-use jolokia::base64::{Base64Sink, Base64Source};
-use jolokia::traits::{Base64Decode, Base64Encode, Cipher, GeneratedKey};
+use jolokia::armor::{self, ArmorSink, ArmorSource};
+use jolokia::cipher::{self, age, chacha::SeekableDecryptor};
+use jolokia::encoding::{self, Encoding, base64};
+use jolokia::traits::{
+    Base64Decode, Base64Encode, Cipher, Error, GeneratedKey, KeygenOptions, Signer, StreamStats,
+};
+use jolokia::{frame, inspect, key};
 
-#[allow(clippy::unnecessary_wraps)] // Keep return type consistent.
-pub fn keygen(cipher: &dyn Cipher, add_newline: bool) -> Result<(), String> {
-    match cipher.generate_key() {
-        GeneratedKey::Symmetric(key) => {
-            let key = Zeroizing::new(key.expose_secret().base64_encode());
-            print!("{}", key.as_str());
+pub use error::CliError;
+
+/// Where generated keys go, instead of the terminal (`keygen -o`).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KeyFiles<'a> {
+    /// The key, or the private key if asymmetric. The file is only
+    /// readable by its owner.
+    pub secret: Option<&'a Path>,
+    /// The public key, if asymmetric.
+    pub public: Option<&'a Path>,
+}
+
+/// Generate and print a key.
+///
+/// With a `passphrase`, the secret key (the private key, if asymmetric)
+/// is protected by it (see [`key::protect()`]). With `qr`, keys are
+/// shown as QR codes. Keys are written in `encoding`, except age keys
+/// and protected keys, which have their own.
+///
+/// Returns the number of lines printed.
+pub fn keygen(
+    cipher: &dyn Cipher,
+    options: &KeygenOptions,
+    passphrase: Option<&[u8]>,
+    files: KeyFiles,
+    qr: bool,
+    encoding: &dyn Encoding,
+    add_newline: bool,
+) -> Result<usize, CliError> {
+    print_key(
+        cipher.algorithm_id(),
+        cipher.generate_key(options),
+        passphrase,
+        files,
+        qr,
+        encoding,
+        add_newline,
+    )
+}
+
+/// Generate and print a signing keypair.
+///
+/// Returns the number of lines printed.
+pub fn keygen_signer(
+    signer: &dyn Signer,
+    options: &KeygenOptions,
+    files: KeyFiles,
+    add_newline: bool,
+) -> Result<usize, CliError> {
+    print_key(
+        signer.algorithm_id(),
+        signer.generate_key(options),
+        None,
+        files,
+        false,
+        &encoding::base64::Base64,
+        add_newline,
+    )
+}
+
+/// Print the key, or write it to `files`.
+///
+/// The public key of a pair is printed if it has no file, and the
+/// private key goes to a file: only public keys end up on `stdout`.
+fn print_key(
+    algorithm_id: &str,
+    key: jolokia::traits::Result<GeneratedKey>,
+    passphrase: Option<&[u8]>,
+    files: KeyFiles,
+    qr: bool,
+    encoding: &dyn Encoding,
+    add_newline: bool,
+) -> Result<usize, CliError> {
+    // age keys are printed the way `age-keygen` does, so age can use them.
+    let is_age = algorithm_id == cipher::Age.algorithm_id();
+    let secret_key = |key: &SecretSlice<u8>| match passphrase {
+        Some(passphrase) => {
+            key::protect(algorithm_id, key.expose_secret(), passphrase).map(Zeroizing::new)
         }
+        None if is_age => age::encode_identity(key.expose_secret()),
+        None => Ok(Zeroizing::new(tag_key(algorithm_id, key, encoding))),
+    };
+    let (public, secret) = match key? {
+        GeneratedKey::Symmetric(key) => (None, secret_key(&key)?),
         GeneratedKey::Asymmetric { private, public } => {
-            let public = Zeroizing::new(public.expose_secret().base64_encode());
-            let private = Zeroizing::new(private.expose_secret().base64_encode());
-            eprintln!("Public:");
-            println!("{}", public.as_str());
-            eprintln!("Private:");
-            print!("{}", private.as_str());
+            let public = if is_age {
+                Zeroizing::new(age::encode_recipient(public.expose_secret())?)
+            } else {
+                Zeroizing::new(tag_key(algorithm_id, &public, encoding))
+            };
+            (Some(public), secret_key(&private)?)
         }
         GeneratedKey::None => {
-            return Err("The selected algorithm does not generate keys.".to_string());
+            return Err(CliError::Usage(
+                "The selected algorithm does not generate keys.".to_string(),
+            ));
+        }
+    };
+
+    let mut nb_lines = 0;
+    if let Some(public) = public {
+        match files.public {
+            Some(file) => write_key_file(file, &key_file_content(&public, file, qr)?, false)?,
+            // With the private key in a file, there's nothing to tell apart.
+            None if files.secret.is_some() => nb_lines += show_key(&public, qr, true)?,
+            None => {
+                eprintln!("Public:");
+                nb_lines += 1 + show_key(&public, qr, true)?;
+                eprintln!("Private:");
+                nb_lines += 1;
+            }
         }
     }
+    match files.secret {
+        Some(file) => write_key_file(file, &key_file_content(&secret, file, qr)?, true)?,
+        None => nb_lines += show_key(&secret, qr, add_newline)?,
+    }
+    Ok(nb_lines)
+}
+
+/// Print `key`, returns the number of lines printed.
+fn show_key(key: &str, qr: bool, add_newline: bool) -> Result<usize, CliError> {
+    if qr {
+        let qr = Zeroizing::new(qr::render(key.as_bytes(), qr::Image::Text)?);
+        let qr = String::from_utf8_lossy(&qr);
+        print!("{qr}");
+        return Ok(qr.lines().count());
+    }
+    print!("{key}");
     if add_newline {
         println!();
     }
-    Ok(())
+    Ok(1)
+}
+
+/// What goes into a key file: the key, or its QR code (see
+/// [`qr::Image::for_file()`]).
+fn key_file_content(key: &str, file: &Path, qr: bool) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    if qr {
+        return qr::render(key.as_bytes(), qr::Image::for_file(file)).map(Zeroizing::new);
+    }
+    Ok(Zeroizing::new(format!("{key}\n").into_bytes()))
+}
+
+/// Write `content` to `file`.
+///
+/// A secret key file is only readable by its owner (`0600`), even if
+/// it already existed. The permissions are set before the key is
+/// written, it is never readable by others, not even briefly.
+fn write_key_file(file: &Path, content: &[u8], is_secret: bool) -> Result<(), CliError> {
+    let error = |e: io::Error| {
+        CliError::Io(format!(
+            "Could not open file for writing '{}': {e}",
+            file.display()
+        ))
+    };
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if is_secret {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut f = options.open(file).map_err(error)?;
+    // `mode()` only applies to new files.
+    #[cfg(unix)]
+    if is_secret {
+        use std::os::unix::fs::PermissionsExt;
+        f.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(error)?;
+    }
+    f.write_all(content)
+        .and_then(|()| f.sync_all())
+        .map_err(|e| Error::Write(e.to_string()).into())
+}
+
+fn tag_key(algorithm_id: &str, key: &SecretSlice<u8>, encoding: &dyn Encoding) -> String {
+    let key = Zeroizing::new(encoding.encode(key.expose_secret()));
+    key::tag(algorithm_id, &key)
+}
+
+/// How ciphertext is encoded, once decided.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Format {
+    Raw,
+    Base64,
+    /// Base64, wrapped in [`armor`].
+    Armored,
+    /// Base64, as a QR code (`--qr`).
+    Qr(qr::Image),
+    Hex,
+    Base32,
+}
+
+impl Format {
+    /// The text encoding, `None` if raw.
+    fn encoding(self) -> Option<&'static dyn Encoding> {
+        match self {
+            Self::Raw => None,
+            Self::Base64 | Self::Armored | Self::Qr(_) => Some(&encoding::base64::Base64),
+            Self::Hex => Some(&encoding::hex::Hex),
+            Self::Base32 => Some(&encoding::base32::Base32),
+        }
+    }
+
+    /// A reader of the bytes of `ciphertext`, decoded if it's text.
+    fn source<'a>(self, ciphertext: &'a mut dyn Read) -> Box<dyn Read + 'a> {
+        match self.encoding() {
+            Some(encoding) => encoding.source(ciphertext),
+            None => Box::new(ciphertext),
+        }
+    }
 }
 
 pub fn encrypt<R: Read, W: Write>(
     cipher: &dyn Cipher,
     key: &[u8],
-    mut plaintext: R,
+    aad: &[u8],
+    plaintext: R,
     mut output: W,
-    from_raw_bytes: bool,
+    format: Format,
     add_newline: bool,
-) -> Result<(), String> {
-    let key = Zeroizing::new(decode_base64_key(key)?);
+) -> Result<(), CliError> {
+    let key = decode_key(cipher.algorithm_id(), key)?;
 
-    let mut sink: Box<dyn Write> = if from_raw_bytes {
-        Box::new(&mut output)
-    } else {
-        Box::new(Base64Sink::new(&mut output))
-    };
+    if let Format::Qr(image) = format {
+        // Anything longer can't fit, don't read it all into memory.
+        let plaintext = plaintext.take(qr::MAX_LEN as u64 + 1);
+        let mut encoded = Vec::new();
+        encrypt_into(cipher, &key, aad, plaintext, &mut encoded, Format::Base64)?;
+        let qr = qr::render(&encoded, image)?;
+        // Text ends with a newline, images must not get one.
+        return output
+            .write_all(&qr)
+            .map_err(|e| Error::Write(e.to_string()).into());
+    }
 
-    cipher
-        .encrypt_stream(&key, &mut plaintext, &mut sink)
-        .map_err(|e| e.to_string())?;
+    if format == Format::Armored {
+        let mut armor = ArmorSink::new(&mut output, cipher.algorithm_id())?;
+        encrypt_into(cipher, &key, aad, plaintext, &mut armor, Format::Base64)?;
+        armor.finish()?;
+        // The armor ends with a newline.
+        return Ok(());
+    }
 
-    sink.flush().map_err(|e| e.to_string())?;
+    encrypt_into(cipher, &key, aad, plaintext, &mut output, format)?;
 
     if add_newline {
-        // Explicit drop needed to reborrow `&mut output`.
-        std::mem::drop(sink);
         _ = writeln!(output);
     }
 
     Ok(())
 }
 
+fn encrypt_into<R: Read, W: Write>(
+    cipher: &dyn Cipher,
+    key: &[u8],
+    aad: &[u8],
+    mut plaintext: R,
+    mut output: W,
+    format: Format,
+) -> Result<(), CliError> {
+    let mut sink: Box<dyn Write> = match format.encoding() {
+        Some(encoding) => encoding.sink(&mut output),
+        None => Box::new(&mut output),
+    };
+
+    let stats = cipher.encrypt_stream_with_aad(key, aad, &mut plaintext, &mut sink)?;
+    log_stats(&stats);
+
+    // The end of the text is written when the sink is dropped.
+    sink.flush().map_err(|e| Error::Write(e.to_string()).into())
+}
+
 pub fn decrypt<R: Read, W: Write>(
     cipher: &dyn Cipher,
     key: &[u8],
+    aad: &[u8],
     mut ciphertext: R,
     mut output: W,
+    format: Option<Format>,
+) -> Result<(), CliError> {
+    let key = decode_key(cipher.algorithm_id(), key)?;
+
+    let (format, mut ciphertext) = detect_format(&mut ciphertext, format)?;
+    let mut source = format.source(&mut ciphertext);
+
+    let stats = cipher.decrypt_stream_with_aad(&key, aad, &mut source, &mut output)?;
+    log_stats(&stats);
+
+    // Buffered output that fails to write must not fail silently.
+    output
+        .flush()
+        .map_err(|e| Error::Write(e.to_string()).into())
+}
+
+/// Byte counts are those of the cipher, before base64 (if any).
+fn log_stats(stats: &StreamStats) {
+    ui::log::info(format_args!(
+        "{} bytes in, {} bytes out ({} chunks)",
+        stats.bytes_in, stats.bytes_out, stats.chunks
+    ));
+}
+
+/// Encrypt each line of `plaintext` as an independent message.
+///
+/// Each ciphertext is written as one frame: a line of base64, or a
+/// length-prefixed [`frame`] in raw mode. Output is flushed after each
+/// frame, so a consumer on the other end of a pipe gets messages as
+/// soon as they are available.
+pub fn encrypt_framed<R: Read, W: Write>(
+    cipher: &dyn Cipher,
+    key: &[u8],
+    aad: &[u8],
+    plaintext: R,
+    mut output: W,
     to_raw_bytes: bool,
-) -> Result<(), String> {
-    let key = Zeroizing::new(decode_base64_key(key)?);
+) -> Result<(), CliError> {
+    let key = decode_key(cipher.algorithm_id(), key)?;
+
+    let mut plaintext = io::BufReader::new(plaintext);
+    let mut line = Zeroizing::new(Vec::new());
+    loop {
+        line.clear();
+        let n = plaintext
+            .read_until(b'\n', &mut line)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        if n == 0 {
+            break;
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+
+        let mut encrypted = Vec::new();
+        cipher.encrypt_stream_with_aad(&key, aad, &mut line.as_slice(), &mut encrypted)?;
+
+        if to_raw_bytes {
+            frame::write_frame(&mut output, &encrypted)?;
+        } else {
+            writeln!(output, "{}", encrypted.base64_encode())
+                .map_err(|e| Error::Write(e.to_string()))?;
+        }
+        output.flush().map_err(|e| Error::Write(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Tell raw ciphertext from base64 and armor, unless `format` says
+/// which it is.
+///
+/// Detection reads the first bytes (see [`inspect::is_raw()`] and
+/// [`armor::is_armored()`]), the returned reader puts them back. Armor
+/// is stripped, the reader of armored ciphertext returns base64.
+///
+/// Armor is always detected, even if `format` says otherwise. ROT-n and
+/// Brainfuck default to raw, and nothing raw starts with the marker.
+fn detect_format<'a>(
+    mut ciphertext: impl Read + 'a,
+    format: Option<Format>,
+) -> Result<(Format, Box<dyn Read + 'a>), CliError> {
+    let mut prefix = Vec::new();
+    (&mut ciphertext)
+        .take(armor::BEGIN.len() as u64)
+        .read_to_end(&mut prefix)
+        .map_err(|e| Error::Read(e.to_string()))?;
+    let is_armored = armor::is_armored(&prefix);
+    let is_raw = inspect::is_raw(&prefix);
+    let ciphertext = io::Cursor::new(prefix).chain(ciphertext);
+
+    if is_armored {
+        let source = ArmorSource::new(io::BufReader::new(ciphertext))?;
+        return Ok((Format::Armored, Box::new(source)));
+    }
+    let format = format.unwrap_or(if is_raw { Format::Raw } else { Format::Base64 });
+    Ok((format, Box::new(ciphertext)))
+}
 
-    let mut source: Box<dyn Read> = if to_raw_bytes {
-        Box::new(&mut ciphertext)
-    } else {
-        Box::new(Base64Source::new(&mut ciphertext))
+/// Outcome of [`decrypt_framed()`].
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct FramedReport {
+    pub nb_frames: usize,
+    /// Frames that could not be decrypted (only with
+    /// `continue_on_error`, otherwise the first failure is an error).
+    pub nb_skipped: usize,
+}
+
+/// Decrypt frames written by [`encrypt_framed()`], one line each.
+///
+/// With `continue_on_error`, a frame that does not decrypt is reported
+/// and skipped, instead of ending the stream. Broken framing (a bad
+/// length prefix in raw mode, or a read error) is always an error, as
+/// there is no telling where the next frame starts.
+pub fn decrypt_framed<R: Read, W: Write>(
+    cipher: &dyn Cipher,
+    key: &[u8],
+    aad: &[u8],
+    ciphertext: R,
+    mut output: W,
+    from_raw_bytes: bool,
+    continue_on_error: bool,
+) -> Result<FramedReport, CliError> {
+    let key = decode_key(cipher.algorithm_id(), key)?;
+
+    let mut report = FramedReport::default();
+    let mut ciphertext = io::BufReader::new(ciphertext);
+    let mut line = String::new();
+    loop {
+        let encrypted = if from_raw_bytes {
+            match frame::read_frame(&mut ciphertext)? {
+                Some(encrypted) => Ok(encrypted),
+                None => break,
+            }
+        } else {
+            line.clear();
+            let n = ciphertext
+                .read_line(&mut line)
+                .map_err(|e| Error::Read(e.to_string()))?;
+            if n == 0 {
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            line.trim().base64_decode().map_err(CliError::from)
+        };
+        report.nb_frames += 1;
+
+        let decrypted = encrypted.and_then(|encrypted| {
+            let mut decrypted = Zeroizing::new(Vec::new());
+            cipher.decrypt_stream_with_aad(
+                &key,
+                aad,
+                &mut encrypted.as_slice(),
+                &mut *decrypted,
+            )?;
+            Ok(decrypted)
+        });
+        let decrypted = match decrypted {
+            Ok(decrypted) => decrypted,
+            Err(reason) if continue_on_error => {
+                report.nb_skipped += 1;
+                eprintln!(
+                    "{error}: Skipping frame {}: {reason}",
+                    report.nb_frames,
+                    error = ui::Color::error("error"),
+                );
+                continue;
+            }
+            Err(reason) => return Err(reason),
+        };
+
+        output
+            .write_all(&decrypted)
+            .and_then(|()| output.write_all(b"\n"))
+            .and_then(|()| output.flush())
+            .map_err(|e| Error::Write(e.to_string()))?;
+    }
+
+    Ok(report)
+}
+
+/// Decrypt only the first `n` bytes of `ciphertext`, and print them.
+///
+/// Decryption stops as soon as `n` bytes are available (see
+/// [`PeekWriter`]). Ciphers only write plaintext once it has been
+/// authenticated, so what gets printed has been verified, even though
+/// the rest of the ciphertext was never read.
+///
+/// Text is printed as-is, anything else is printed as a hex dump.
+pub fn peek<R: Read, W: Write>(
+    cipher: &dyn Cipher,
+    key: &[u8],
+    aad: &[u8],
+    mut ciphertext: R,
+    mut output: W,
+    format: Option<Format>,
+    n: usize,
+) -> Result<(), CliError> {
+    let key = decode_key(cipher.algorithm_id(), key)?;
+
+    let (format, mut ciphertext) = detect_format(&mut ciphertext, format)?;
+    let mut source = format.source(&mut ciphertext);
+
+    let mut peek = PeekWriter::new(n);
+    let res = cipher.decrypt_stream_with_aad(&key, aad, &mut source, &mut peek);
+    // Filling up the writer is how we stop early, not an error.
+    if !peek.is_full() {
+        res?;
+    }
+
+    let preview = Zeroizing::new(match std::str::from_utf8(&peek.buffer) {
+        Ok(text) if !text.contains(|c: char| c.is_control() && !c.is_whitespace()) => {
+            text.to_string()
+        }
+        _ => hex_dump(&peek.buffer),
+    });
+
+    writeln!(output, "{}", preview.as_str()).map_err(|e| Error::Write(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Decrypt the bytes of the plaintext in `range`.
+///
+/// The ciphertext is decrypted as a stream: bytes before the range are
+/// dropped, and decryption stops at the end of the range (see
+/// [`RangeWriter`]). Prefer [`decrypt_range_seekable()`] when it
+/// applies, it skips straight to the range.
+pub fn decrypt_range<R: Read, W: Write>(
+    cipher: &dyn Cipher,
+    key: &[u8],
+    aad: &[u8],
+    mut ciphertext: R,
+    output: W,
+    format: Option<Format>,
+    range: Range<u64>,
+) -> Result<(), CliError> {
+    let key = decode_key(cipher.algorithm_id(), key)?;
+
+    let (format, mut ciphertext) = detect_format(&mut ciphertext, format)?;
+    let mut source = format.source(&mut ciphertext);
+
+    let mut output = RangeWriter::new(output, range);
+    let res = cipher.decrypt_stream_with_aad(&key, aad, &mut source, &mut output);
+    // Reaching the end of the range is how we stop early, not an error.
+    if !output.is_done() {
+        res?;
+    }
+
+    output
+        .flush()
+        .map_err(|e| Error::Write(e.to_string()).into())
+}
+
+/// Decrypt the bytes of the plaintext in `range`, seeking to them.
+///
+/// Only the chunks covering the range are read (see
+/// [`SeekableDecryptor`]), which requires a raw ChaCha20-Poly1305
+/// ciphertext. For anything else, nothing is written and `false` is
+/// returned, it must go through [`decrypt_range()`].
+pub fn decrypt_range_seekable<R: Read + Seek, W: Write>(
+    key: &[u8],
+    aad: &[u8],
+    ciphertext: R,
+    mut output: W,
+    range: Range<u64>,
+) -> Result<bool, CliError> {
+    let key = decode_key(cipher::ChaCha20Poly1305.algorithm_id(), key)?;
+
+    let mut decryptor = match SeekableDecryptor::with_aad(&key, aad, ciphertext) {
+        Ok(decryptor) => decryptor,
+        Err(Error::Algorithm) => return Ok(false),
+        Err(e) => return Err(e.into()),
     };
+    decryptor.decrypt_range(range, &mut output)?;
 
-    cipher
-        .decrypt_stream(&key, &mut source, &mut output)
-        .map_err(|e| e.to_string())?;
+    output.flush().map_err(|e| Error::Write(e.to_string()))?;
+    Ok(true)
+}
+
+/// Sign `message`, and print the detached signature (base64).
+pub fn sign<R: Read, W: Write>(
+    signer: &dyn Signer,
+    private_key: &[u8],
+    mut message: R,
+    mut output: W,
+    add_newline: bool,
+) -> Result<(), CliError> {
+    let private_key = decode_key(signer.algorithm_id(), private_key)?;
+
+    let signature = signer.sign_stream(&private_key, &mut message)?;
 
+    write!(output, "{}", signature.base64_encode())
+        .and_then(|()| {
+            if add_newline {
+                writeln!(output)
+            } else {
+                Ok(())
+            }
+        })
+        .and_then(|()| output.flush())
+        .map_err(|e| Error::Write(e.to_string()).into())
+}
+
+/// Check the detached `signature` (base64) of `message`.
+pub fn verify<R: Read>(
+    signer: &dyn Signer,
+    public_key: &[u8],
+    mut message: R,
+    signature: &str,
+) -> Result<(), CliError> {
+    let public_key = decode_key(signer.algorithm_id(), public_key)?;
+    let signature = signature.trim().base64_decode()?;
+
+    signer.verify_stream(&public_key, &mut message, &signature)?;
     Ok(())
 }
 
-fn decode_base64_key(key: &[u8]) -> Result<Vec<u8>, String> {
-    match key.base64_decode() {
-        Ok(key) => Ok(key),
-        Err(reason) => Err(reason.to_string()),
+/// Print what can be read from `ciphertext` without the key.
+pub fn inspect<R: Read, W: Write>(
+    mut ciphertext: R,
+    mut output: W,
+    format: Option<Format>,
+    now: SystemTime,
+) -> Result<(), CliError> {
+    let (format, mut ciphertext) = detect_format(&mut ciphertext, format)?;
+    let mut source = format.source(&mut ciphertext);
+
+    let metadata = inspect::inspect(&mut source).map_err(|e| match e {
+        Error::Algorithm => {
+            CliError::Other("Not a ciphertext, or from an algorithm without metadata".to_string())
+        }
+        e => e.into(),
+    })?;
+
+    write!(output, "{}", format_metadata(&metadata, now))
+        .map_err(|e| Error::Write(e.to_string()).into())
+}
+
+fn format_metadata(metadata: &inspect::Metadata, now: SystemTime) -> String {
+    let mut lines = Vec::new();
+
+    let algorithm = match (metadata.password, metadata.encapsulated_key_len) {
+        (Some(_), _) => "ChaCha20-Poly1305 (password)",
+        (None, Some(_)) if metadata.post_quantum => "HPKE-PQ (X-Wing)",
+        (None, Some(_)) => "HPKE",
+        (None, None) => "ChaCha20-Poly1305",
+    };
+    lines.push(format!("algorithm:    {algorithm}"));
+    if metadata.outdated {
+        lines.push("format:       outdated (see 'migrate')".to_string());
+    }
+
+    if let Some(expires_at) = metadata.expires_at {
+        let expires = match expires_at.duration_since(now) {
+            Ok(remaining) => format!("in {}", ui::format::duration(remaining)),
+            Err(elapsed) => format!("{} ago (expired)", ui::format::duration(elapsed.duration())),
+        };
+        lines.push(format!("expires:      {expires}"));
+    }
+    if let Some(ref key_hint) = metadata.key_hint {
+        lines.push(format!("key hint:     {key_hint}"));
+    }
+    if let Some(params) = metadata.password {
+        lines.push(format!(
+            "kdf:          Argon2id ({}, {} iterations, parallelism {})",
+            ui::format::bytes(u64::from(params.memory_kib) * 1024),
+            params.iterations,
+            params.parallelism,
+        ));
+    }
+    if let Some(len) = metadata.encapsulated_key_len {
+        lines.push(format!(
+            "hpke key:     {} (encapsulated)",
+            ui::format::bytes(len as u64)
+        ));
+    }
+
+    let nonce_prefix = metadata
+        .nonce_prefix
+        .iter()
+        .fold(String::new(), |mut hex, b| {
+            _ = write!(hex, "{b:02x}");
+            hex
+        });
+    lines.push(format!("nonce prefix: {nonce_prefix}"));
+    lines.push(format!(
+        "chunks:       {} (max. {})",
+        metadata.nb_chunks,
+        ui::format::bytes(metadata.max_chunk_len as u64),
+    ));
+    lines.push(format!(
+        "plaintext:    {}",
+        ui::format::bytes(metadata.plaintext_len())
+    ));
+    lines.push(format!(
+        "end:          {}",
+        if metadata.complete {
+            "ok"
+        } else {
+            "missing (truncated)"
+        }
+    ));
+
+    lines.join("\n") + "\n"
+}
+
+/// Format bytes like `xxd` does (offset, hex, ASCII).
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, line)| {
+            let hex: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = line
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}: {:<47}  {ascii}", i * 16, hex.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Decode base64 key, after checking its algorithm tag (if any).
+///
+/// Decoding is constant-time (see [`base64::decode_key()`]).
+fn decode_key(algorithm_id: &str, key: &[u8]) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let key = match std::str::from_utf8(key) {
+        Ok(key) => match key::split_tag(key) {
+            (Some(tag), _) if tag != algorithm_id => {
+                return Err(CliError::Key(format!(
+                    "This key is for '{tag}', not '{algorithm_id}'"
+                )));
+            }
+            (_, key) => key.as_bytes(),
+        },
+        // Not valid base64 either, let decoding report the error.
+        Err(_) => key,
+    };
+    // A malformed key is not corrupted data, it fails whatever the input.
+    base64::decode_key(key).map_err(|reason| CliError::Key(reason.to_string()))
+}
+
+/// A reader that fails if the input exceeds a maximum size.
+///
+/// Contrary to [`Read::take()`], exceeding the limit is an error, not
+/// an EOF. We don't want to silently encrypt a truncated message.
+pub struct SizeLimitedReader<R: Read> {
+    inner: R,
+    remaining: u64,
+    limit: u64,
+}
+
+impl<R: Read> SizeLimitedReader<R> {
+    pub fn new(reader: R, limit: u64) -> Self {
+        Self {
+            inner: reader,
+            remaining: limit,
+            limit,
+        }
+    }
+}
+
+impl<R: Read> Read for SizeLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        // `n` is bound by `buf.len()`, it always fits in a `u64`.
+        let n_ = n as u64;
+        if n_ > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Message exceeds the maximum stdin size ({})",
+                    ui::format::bytes(self.limit)
+                ),
+            ));
+        }
+        self.remaining -= n_;
+        Ok(n)
+    }
+}
+
+/// A writer that keeps the first `limit` bytes, and then fails.
+///
+/// Ciphers stop at the first write error, this is what makes them exit
+/// early without processing the rest of the input.
+pub struct PeekWriter {
+    buffer: Zeroizing<Vec<u8>>,
+    limit: usize,
+}
+
+impl PeekWriter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            buffer: Zeroizing::new(Vec::with_capacity(limit)),
+            limit,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.buffer.len() >= self.limit
+    }
+}
+
+impl Write for PeekWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.limit - self.buffer.len());
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Peek is full"));
+        }
+        self.buffer.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that only passes on the bytes in `range`, and then fails.
+///
+/// Like [`PeekWriter`], failing is what makes ciphers stop early,
+/// instead of decrypting the rest of the input for nothing.
+pub struct RangeWriter<W: Write> {
+    inner: W,
+    position: u64,
+    range: Range<u64>,
+}
+
+impl<W: Write> RangeWriter<W> {
+    pub fn new(inner: W, range: Range<u64>) -> Self {
+        Self {
+            inner,
+            position: 0,
+            range,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.position >= self.range.end
+    }
+}
+
+impl<W: Write> Write for RangeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.is_done() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "Range is complete",
+            ));
+        }
+        // `buf.len()` always fits in a `u64`.
+        let end = self.position + buf.len() as u64;
+        let from = self.range.start.clamp(self.position, end) - self.position;
+        let to = self.range.end.clamp(self.position, end) - self.position;
+        if from < to {
+            // Both are `<= buf.len()`.
+            #[allow(clippy::cast_possible_truncation)]
+            self.inner.write_all(&buf[from as usize..to as usize])?;
+        }
+        self.position = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Set when the reader on the other end of the output went away.
+#[derive(Clone, Debug, Default)]
+pub struct OutputClosed(Arc<AtomicBool>);
+
+impl OutputClosed {
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A writer that tells a closed output apart from other errors.
+///
+/// Ciphers report failed writes like any other error. But when the
+/// consumer of a pipe exits early (e.g., `| head`), the error is a
+/// consequence, not a failure of the encryption or the decryption, and
+/// it should not be reported as one.
+pub struct OutputWatch<W: Write> {
+    inner: W,
+    closed: OutputClosed,
+}
+
+impl<W: Write> OutputWatch<W> {
+    pub fn new(writer: W) -> (Self, OutputClosed) {
+        let closed = OutputClosed::default();
+        let watch = Self {
+            inner: writer,
+            closed: closed.clone(),
+        };
+        (watch, closed)
+    }
+
+    fn watch<T>(&self, res: io::Result<T>) -> io::Result<T> {
+        if let Err(ref reason) = res
+            && reason.kind() == io::ErrorKind::BrokenPipe
+        {
+            self.closed.0.store(true, Ordering::Relaxed);
+        }
+        res
+    }
+}
+
+impl<W: Write> Write for OutputWatch<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let res = self.inner.write(buf);
+        self.watch(res)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let res = self.inner.flush();
+        self.watch(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use jolokia::cipher::ChaCha20Poly1305;
+
+    const KEY: &[u8] = b"9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    #[test]
+    fn size_limited_reader_within_limit() {
+        let mut reader = SizeLimitedReader::new(io::Cursor::new(b"hello"), 5);
+
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).unwrap();
+
+        assert_eq!(buffer, b"hello");
+    }
+
+    #[test]
+    fn size_limited_reader_exceeds_limit() {
+        let mut reader = SizeLimitedReader::new(io::Cursor::new(b"hello, world"), 5);
+
+        let mut buffer = Vec::new();
+        let err = reader.read_to_end(&mut buffer).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("(5 bytes)"));
+    }
+
+    #[test]
+    fn peek_writer_stops_at_limit() {
+        let mut writer = PeekWriter::new(5);
+
+        let err = writer.write_all(b"hello, world").unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        assert!(writer.is_full());
+        assert_eq!(writer.buffer.as_slice(), b"hello");
+    }
+
+    #[test]
+    fn range_writer_passes_on_range() {
+        let mut output = Vec::new();
+        let mut writer = RangeWriter::new(&mut output, 3..8);
+
+        writer.write_all(b"abcd").unwrap();
+        writer.write_all(b"efgh").unwrap();
+        let err = writer.write_all(b"ijkl").unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        assert!(writer.is_done());
+        assert_eq!(output, b"defgh");
+    }
+
+    #[test]
+    fn range_writer_range_within_one_write() {
+        let mut output = Vec::new();
+        let mut writer = RangeWriter::new(&mut output, 2..4);
+
+        writer.write_all(b"abcdef").unwrap();
+
+        assert!(writer.is_done());
+        assert_eq!(output, b"cd");
+    }
+
+    struct FailingWriter(io::ErrorKind);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(self.0))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn output_watch_regular() {
+        let (mut output, closed) = OutputWatch::new(Vec::new());
+
+        output.write_all(b"hello").unwrap();
+
+        assert_eq!(output.inner, b"hello");
+        assert!(!closed.is_set());
+    }
+
+    #[test]
+    fn output_watch_broken_pipe_is_closed() {
+        let (mut output, closed) = OutputWatch::new(FailingWriter(io::ErrorKind::BrokenPipe));
+
+        assert!(output.write_all(b"hello").is_err());
+        assert!(closed.is_set());
+    }
+
+    #[test]
+    fn output_watch_other_error_is_not_closed() {
+        let (mut output, closed) = OutputWatch::new(FailingWriter(io::ErrorKind::StorageFull));
+
+        assert!(output.write_all(b"hello").is_err());
+        assert!(!closed.is_set());
+    }
+
+    #[test]
+    fn output_watch_closed_through_cipher() {
+        let (output, closed) = OutputWatch::new(FailingWriter(io::ErrorKind::BrokenPipe));
+
+        let err = encrypt(
+            &ChaCha20Poly1305,
+            KEY,
+            &[],
+            &b"hello"[..],
+            output,
+            Format::Raw,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().starts_with("Could not write to output"));
+        assert!(closed.is_set());
+    }
+
+    #[test]
+    fn decrypt_detects_raw_and_base64() {
+        let key = KEY.base64_decode().unwrap();
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, b"hello").unwrap();
+
+        for input in [ciphertext.clone(), ciphertext.base64_encode().into_bytes()] {
+            let mut output = Vec::new();
+            decrypt(
+                &ChaCha20Poly1305,
+                KEY,
+                &[],
+                input.as_slice(),
+                &mut output,
+                None,
+            )
+            .unwrap();
+            assert_eq!(output, b"hello");
+        }
+    }
+
+    #[test]
+    fn detect_format_puts_bytes_back() {
+        let (format, mut reader) = detect_format(&b"CH20\x01 and more"[..], None).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+
+        assert_eq!(format, Format::Raw);
+        assert_eq!(read, b"CH20\x01 and more");
+    }
+
+    #[test]
+    fn decrypt_detects_armor() {
+        let mut armored = Vec::new();
+        encrypt(
+            &ChaCha20Poly1305,
+            KEY,
+            &[],
+            &b"hello"[..],
+            &mut armored,
+            Format::Armored,
+            true,
+        )
+        .unwrap();
+        assert!(
+            armored
+                .starts_with(b"-----BEGIN JOLOKIA MESSAGE-----\nAlgorithm: chacha20poly1305\n\n")
+        );
+        assert!(armored.ends_with(b"\n-----END JOLOKIA MESSAGE-----\n"));
+
+        // Even if told otherwise.
+        for format in [None, Some(Format::Base64), Some(Format::Raw)] {
+            let mut output = Vec::new();
+            decrypt(
+                &ChaCha20Poly1305,
+                KEY,
+                &[],
+                armored.as_slice(),
+                &mut output,
+                format,
+            )
+            .unwrap();
+            assert_eq!(output, b"hello");
+        }
+    }
+
+    #[test]
+    fn peek_text() {
+        let key = KEY.base64_decode().unwrap();
+        let plaintext = "abc".repeat(10_000);
+        let ciphertext = ChaCha20Poly1305
+            .encrypt(&key, plaintext.as_bytes())
+            .unwrap();
+
+        let mut output = Vec::new();
+        peek(
+            &ChaCha20Poly1305,
+            KEY,
+            &[],
+            ciphertext.as_slice(),
+            &mut output,
+            Some(Format::Raw),
+            7,
+        )
+        .unwrap();
+
+        assert_eq!(output, b"abcabca\n");
+    }
+
+    #[test]
+    fn peek_shorter_than_n() {
+        let key = KEY.base64_decode().unwrap();
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, b"abc").unwrap();
+
+        let mut output = Vec::new();
+        peek(
+            &ChaCha20Poly1305,
+            KEY,
+            &[],
+            ciphertext.as_slice(),
+            &mut output,
+            Some(Format::Raw),
+            100,
+        )
+        .unwrap();
+
+        assert_eq!(output, b"abc\n");
+    }
+
+    #[test]
+    fn peek_binary_is_hex_dump() {
+        let key = KEY.base64_decode().unwrap();
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, b"GIF89a\x00\x01").unwrap();
+
+        let mut output = Vec::new();
+        peek(
+            &ChaCha20Poly1305,
+            KEY,
+            &[],
+            ciphertext.as_slice(),
+            &mut output,
+            Some(Format::Raw),
+            8,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "00000000: 47 49 46 38 39 61 00 01                          GIF89a..\n"
+        );
+    }
+
+    #[test]
+    fn peek_wrong_key_fails() {
+        let key = KEY.base64_decode().unwrap();
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, b"abc").unwrap();
+
+        let res = peek(
+            &ChaCha20Poly1305,
+            b"hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w",
+            &[],
+            ciphertext.as_slice(),
+            &mut Vec::new(),
+            Some(Format::Raw),
+            2,
+        );
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn decrypt_range_regular() {
+        let key = KEY.base64_decode().unwrap();
+        let plaintext = "0123456789".repeat(1_000);
+        let ciphertext = ChaCha20Poly1305
+            .encrypt(&key, plaintext.as_bytes())
+            .unwrap()
+            .base64_encode();
+
+        let mut output = Vec::new();
+        decrypt_range(
+            &ChaCha20Poly1305,
+            KEY,
+            &[],
+            ciphertext.as_bytes(),
+            &mut output,
+            None,
+            4095..4105,
+        )
+        .unwrap();
+
+        assert_eq!(output, &plaintext.as_bytes()[4095..4105]);
+    }
+
+    #[test]
+    fn decrypt_range_past_the_end_is_empty() {
+        let key = KEY.base64_decode().unwrap();
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, b"abc").unwrap();
+
+        let mut output = Vec::new();
+        decrypt_range(
+            &ChaCha20Poly1305,
+            KEY,
+            &[],
+            ciphertext.as_slice(),
+            &mut output,
+            Some(Format::Raw),
+            10..20,
+        )
+        .unwrap();
+
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn decrypt_range_wrong_key_fails() {
+        let key = KEY.base64_decode().unwrap();
+        let ciphertext = ChaCha20Poly1305.encrypt(&key, b"abc").unwrap();
+
+        let res = decrypt_range(
+            &ChaCha20Poly1305,
+            b"hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w",
+            &[],
+            ciphertext.as_slice(),
+            &mut Vec::new(),
+            Some(Format::Raw),
+            0..2,
+        );
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn decrypt_range_seekable_regular() {
+        let key = KEY.base64_decode().unwrap();
+        let plaintext = "0123456789".repeat(1_000);
+        let ciphertext = ChaCha20Poly1305
+            .encrypt(&key, plaintext.as_bytes())
+            .unwrap();
+
+        let mut output = Vec::new();
+        let is_seekable = decrypt_range_seekable(
+            KEY,
+            &[],
+            io::Cursor::new(ciphertext),
+            &mut output,
+            9000..9005,
+        )
+        .unwrap();
+
+        assert!(is_seekable);
+        assert_eq!(output, b"01234");
+    }
+
+    #[test]
+    fn decrypt_range_seekable_base64_is_not_seekable() {
+        let key = KEY.base64_decode().unwrap();
+        let ciphertext = ChaCha20Poly1305
+            .encrypt(&key, b"abc")
+            .unwrap()
+            .base64_encode();
+
+        let mut output = Vec::new();
+        let is_seekable =
+            decrypt_range_seekable(KEY, &[], io::Cursor::new(ciphertext), &mut output, 0..2)
+                .unwrap();
+
+        assert!(!is_seekable);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn format_metadata_regular() {
+        let metadata = inspect::Metadata {
+            expires_at: Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_hours(2)),
+            nonce_prefix: [0, 1, 2, 3, 0xab, 0xcd, 0xef],
+            nb_chunks: 2,
+            max_chunk_len: 4112,
+            chunks_len: 4112 + 916,
+            complete: true,
+            ..Default::default()
+        };
+        let now = SystemTime::UNIX_EPOCH + std::time::Duration::from_hours(1);
+
+        assert_eq!(
+            format_metadata(&metadata, now),
+            "\
+algorithm:    ChaCha20-Poly1305
+expires:      in 1 h 00 min
+nonce prefix: 00010203abcdef
+chunks:       2 (max. 4.0 KiB)
+plaintext:    4.9 KiB
+end:          ok
+"
+        );
+    }
+
+    #[test]
+    fn format_metadata_expired_truncated() {
+        let metadata = inspect::Metadata {
+            expires_at: Some(SystemTime::UNIX_EPOCH),
+            ..Default::default()
+        };
+        let now = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(90);
+
+        let formatted = format_metadata(&metadata, now);
+
+        assert!(formatted.contains("expires:      1 min 30 s ago (expired)\n"));
+        assert!(formatted.contains("end:          missing (truncated)\n"));
+    }
+
+    #[test]
+    fn format_metadata_outdated() {
+        let metadata = inspect::Metadata {
+            outdated: true,
+            ..Default::default()
+        };
+
+        let formatted = format_metadata(&metadata, SystemTime::UNIX_EPOCH);
+
+        assert!(formatted.contains("format:       outdated (see 'migrate')\n"));
+    }
+
+    #[test]
+    fn inspect_not_a_ciphertext_is_error() {
+        let err = inspect(
+            &b"hello, world"[..],
+            Vec::new(),
+            Some(Format::Raw),
+            SystemTime::now(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Not a ciphertext, or from an algorithm without metadata"
+        );
     }
 }
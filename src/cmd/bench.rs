@@ -0,0 +1,162 @@
+//! Cipher throughput, `jolokia bench`.
+//!
+//! Each algorithm encrypts the same synthetic stream, with a fresh key,
+//! and the output is thrown away. What is measured is the cipher, not
+//! the disk nor the terminal.
+//!
+//! For numbers with statistics (and decryption), see `cargo bench`.
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use secrecy::{ExposeSecret, zeroize::Zeroizing};
+
+use jolokia::encoding::base64;
+use jolokia::traits::{Cipher, GeneratedKey, KeygenOptions};
+
+use super::CliError;
+use super::cli::Algorithm;
+
+/// Bytes encrypted per algorithm, by default (16 MiB).
+pub const DEFAULT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Encrypt `size` bytes with each of `algorithms` (all of them if
+/// empty), and report the throughput to `output`.
+pub fn bench(algorithms: &[Algorithm], size: u64, mut output: impl Write) -> Result<(), CliError> {
+    let algorithms = if algorithms.is_empty() {
+        all_algorithms()
+    } else {
+        algorithms.to_vec()
+    };
+
+    for algorithm in algorithms {
+        let elapsed = measure(algorithm, size)?;
+        writeln!(
+            output,
+            "{:<20} {:>10.1} MB/s",
+            algorithm.to_string(),
+            mb_per_s(size, elapsed)
+        )
+        .map_err(|e| CliError::Io(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Time it takes `algorithm` to encrypt `size` bytes.
+fn measure(algorithm: Algorithm, size: u64) -> Result<Duration, CliError> {
+    let cipher: Box<dyn Cipher> = algorithm.into();
+    let key = encryption_key(algorithm, cipher.as_ref())?;
+    let mut plaintext = io::repeat(0xa5).take(size);
+
+    let start = Instant::now();
+    cipher.encrypt_stream(&key, &mut plaintext, &mut io::sink())?;
+    Ok(start.elapsed())
+}
+
+/// A fresh key (public, if asymmetric), or the fixed key of ciphers
+/// that don't generate any (ROT-n, Brainfuck).
+fn encryption_key(
+    algorithm: Algorithm,
+    cipher: &dyn Cipher,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let key = match cipher.generate_key(&KeygenOptions::default())? {
+        GeneratedKey::Symmetric(key) | GeneratedKey::Asymmetric { public: key, .. } => key,
+        GeneratedKey::None => match algorithm.default_key() {
+            GeneratedKey::Symmetric(key) => {
+                return Ok(base64::decode_key(key.expose_secret())?);
+            }
+            _ => {
+                return Err(CliError::Other(format!(
+                    "{algorithm} has no key to benchmark"
+                )));
+            }
+        },
+    };
+    Ok(Zeroizing::new(key.expose_secret().to_vec()))
+}
+
+fn mb_per_s(size: u64, elapsed: Duration) -> f64 {
+    #[allow(clippy::cast_precision_loss)] // Display only.
+    let size = size as f64 / 1_000_000.0;
+    size / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+/// Every algorithm built in.
+fn all_algorithms() -> Vec<Algorithm> {
+    vec![
+        Algorithm::ChaCha20Poly1305,
+        #[cfg(feature = "hpke")]
+        Algorithm::Hpke,
+        #[cfg(feature = "hpke")]
+        Algorithm::HpkePq,
+        Algorithm::Age,
+        #[cfg(feature = "rotn")]
+        Algorithm::RotN,
+        #[cfg(feature = "brainfuck")]
+        Algorithm::Brainfuck,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench_regular() {
+        let mut output = Vec::new();
+
+        bench(&[Algorithm::ChaCha20Poly1305], 64 * 1024, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("ChaCha20-Poly1305 "), "{output}");
+        assert!(output.trim_end().ends_with(" MB/s"), "{output}");
+        assert_eq!(output.lines().count(), 1);
+    }
+
+    #[test]
+    fn bench_all_algorithms() {
+        let mut output = Vec::new();
+
+        bench(&[], 1024, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        for algorithm in [
+            "ChaCha20-Poly1305",
+            "HPKE",
+            "HPKE-PQ",
+            "age",
+            "ROT-n",
+            "Brainfuck",
+        ] {
+            assert!(
+                output
+                    .lines()
+                    .any(|line| line.starts_with(&format!("{algorithm} "))),
+                "{output}"
+            );
+        }
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn encryption_key_of_asymmetric_is_public() {
+        let key = encryption_key(Algorithm::Hpke, &jolokia::cipher::Hpke).unwrap();
+
+        assert_eq!(key.len(), 32);
+    }
+
+    #[cfg(feature = "rotn")]
+    #[test]
+    fn encryption_key_of_rotn_is_fixed() {
+        let key = encryption_key(Algorithm::RotN, &jolokia::cipher::RotN).unwrap();
+
+        assert_eq!(*key, [13]);
+    }
+
+    #[test]
+    fn mb_per_s_regular() {
+        let mb_per_s = mb_per_s(10_000_000, Duration::from_secs(2));
+
+        assert!((mb_per_s - 5.0).abs() < f64::EPSILON);
+    }
+}
@@ -1,28 +1,65 @@
+use std::fmt;
 use std::fs;
-use std::io::{self, IsTerminal};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
-use secrecy::{ExposeSecret, SecretSlice, SecretString};
+use secrecy::{ExposeSecret, SecretSlice, SecretString, zeroize::Zeroizing};
 
-use jolokia::cipher;
-use jolokia::traits::{Base64Encode, Cipher, GeneratedKey};
+use jolokia::encoding::{self, Encoding, base64};
+#[cfg(feature = "hpke")]
+use jolokia::ssh;
+use jolokia::traits::{Base64Encode, Cipher, Error, GeneratedKey, KeygenOptions};
+use jolokia::{cipher, entropy, key};
+
+use super::CliError;
+use super::environment::{self, Environment, Stream};
+use super::keyring;
+use super::plan;
+use super::ui::log::Verbosity;
+use super::warning::Warning;
 
 pub const KEY_ENV_VAR: &str = "JOLOKIA_CIPHER_KEY";
+pub const PASSWORD_ENV_VAR: &str = "JOLOKIA_PASSWORD";
+
+/// Output buffer size, unless `--buffer-size` says otherwise (64 KiB).
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Devices `--entropy` accepts, other than `system` and `strict`.
+const RANDOM_DEVICES: [&str; 3] = ["/dev/urandom", "/dev/random", "/dev/hwrng"];
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Command {
     KeyGen,
     Encrypt,
     Decrypt,
+    Rekey,
+    /// Re-encrypt old-format ciphertexts in the latest format.
+    Migrate,
+    Doctor,
+    /// Measure the throughput of the ciphers.
+    Bench,
+    Inspect,
+    /// Check the integrity of a ciphertext, without output.
+    Check,
+    Sign,
+    Verify,
+    Man,
 }
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum Algorithm {
     #[default]
     ChaCha20Poly1305,
+    #[cfg(feature = "hpke")]
     Hpke,
+    #[cfg(feature = "hpke")]
+    HpkePq,
+    Age,
+    #[cfg(feature = "rotn")]
     RotN,
+    #[cfg(feature = "brainfuck")]
     Brainfuck,
 }
 
@@ -33,16 +70,74 @@ impl Algorithm {
             Self::ChaCha20Poly1305 => GeneratedKey::Symmetric(SecretSlice::from(
                 b"edLKPT4jYaabmMwuKzgQwklMC9HxTYmhVY7qln4yrJM".to_vec(),
             )),
+            #[cfg(feature = "hpke")]
             Self::Hpke => GeneratedKey::Asymmetric {
                 public: SecretSlice::from(b"eRR5BeA731Ug5In5EELCpc8wqIUbUSHfP9vyjG1FVAU".to_vec()),
                 private: SecretSlice::from(b"cMDcZQWSnd6AQh8lZrSvDqMRr5oAA4ooGrEsrxExQAM".to_vec()),
             },
+            // No default, new algorithms don't get an insecure fallback
+            // (see `Args::build_from_args_with_env()`).
+            Self::Age => GeneratedKey::None,
+            #[cfg(feature = "hpke")]
+            Self::HpkePq => GeneratedKey::None,
+            #[cfg(feature = "rotn")]
             Self::RotN => GeneratedKey::Symmetric(SecretSlice::from(b"DQ".to_vec())), // This is base64 for `13`.
+            #[cfg(feature = "brainfuck")]
             Self::Brainfuck => GeneratedKey::Symmetric(SecretSlice::from(b"QnJhaW5mdWNr".to_vec())), // Whatever.
         }
     }
 }
 
+impl Algorithm {
+    /// ROT-n and Brainfuck, which have no header, no authentication,
+    /// and no real key.
+    pub fn is_toy(self) -> bool {
+        match self {
+            #[cfg(feature = "rotn")]
+            Self::RotN => true,
+            #[cfg(feature = "brainfuck")]
+            Self::Brainfuck => true,
+            _ => false,
+        }
+    }
+
+    /// ROT-n, whose key is a number.
+    pub fn is_rotn(self) -> bool {
+        match self {
+            #[cfg(feature = "rotn")]
+            Self::RotN => true,
+            _ => false,
+        }
+    }
+
+    /// Whether keys have a public part.
+    pub fn is_asymmetric(self) -> bool {
+        match self {
+            #[cfg(feature = "hpke")]
+            Self::Hpke | Self::HpkePq => true,
+            Self::Age => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+            #[cfg(feature = "hpke")]
+            Self::Hpke => "HPKE",
+            #[cfg(feature = "hpke")]
+            Self::HpkePq => "HPKE-PQ",
+            Self::Age => "age",
+            #[cfg(feature = "rotn")]
+            Self::RotN => "ROT-n",
+            #[cfg(feature = "brainfuck")]
+            Self::Brainfuck => "Brainfuck",
+        })
+    }
+}
+
 impl FromStr for Algorithm {
     type Err = ();
 
@@ -52,8 +147,14 @@ impl FromStr for Algorithm {
             "chacha20poly1305" | "chacha20" | "chacha" | "cha20" | "cha" => {
                 Ok(Self::ChaCha20Poly1305)
             }
+            #[cfg(feature = "hpke")]
             "hpke" => Ok(Self::Hpke),
+            #[cfg(feature = "hpke")]
+            "hpkepq" | "xwing" => Ok(Self::HpkePq),
+            "age" => Ok(Self::Age),
+            #[cfg(feature = "rotn")]
             "rotn" | "rot" => Ok(Self::RotN),
+            #[cfg(feature = "brainfuck")]
             "brainfuck" | "bf" => Ok(Self::Brainfuck),
             _ => Err(()),
         }
@@ -64,21 +165,38 @@ impl From<Algorithm> for Box<dyn Cipher> {
     fn from(value: Algorithm) -> Self {
         match value {
             Algorithm::ChaCha20Poly1305 => Box::new(cipher::ChaCha20Poly1305),
+            #[cfg(feature = "hpke")]
             Algorithm::Hpke => Box::new(cipher::Hpke),
+            #[cfg(feature = "hpke")]
+            Algorithm::HpkePq => Box::new(cipher::HpkePq),
+            Algorithm::Age => Box::new(cipher::Age),
+            #[cfg(feature = "rotn")]
             Algorithm::RotN => Box::new(cipher::RotN),
+            #[cfg(feature = "brainfuck")]
             Algorithm::Brainfuck => Box::new(cipher::Brainfuck),
         }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Eq, PartialEq)]
 pub enum Message {
     String(String),
     File(PathBuf),
     Stdin,
 }
 
-#[derive(Debug, Default, Eq, PartialEq)]
+/// Message strings are plaintext, they never show up in debug output.
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(_) => f.debug_tuple("String").field(&"[REDACTED]").finish(),
+            Self::File(file) => f.debug_tuple("File").field(file).finish(),
+            Self::Stdin => write!(f, "Stdin"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub enum Output {
     File(PathBuf),
     #[default]
@@ -86,51 +204,328 @@ pub enum Output {
     Redirected,
 }
 
+/// Text encoding of keys and ciphertexts, `--encoding`.
+///
+/// Base64 unless told otherwise. Hex and base32 are for systems that
+/// can't take base64 (see [`jolokia::encoding`]).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum TextEncoding {
+    #[default]
+    Base64,
+    Hex,
+    Base32,
+}
+
+impl TextEncoding {
+    pub fn encoding(self) -> &'static dyn Encoding {
+        match self {
+            Self::Base64 => &encoding::base64::Base64,
+            Self::Hex => &encoding::hex::Hex,
+            Self::Base32 => &encoding::base32::Base32,
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.encoding().name())
+    }
+}
+
+impl FromStr for TextEncoding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "base64" | "b64" => Ok(Self::Base64),
+            "hex" | "base16" => Ok(Self::Hex),
+            "base32" | "b32" => Ok(Self::Base32),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How ciphertext is encoded, `--base64`, `--raw`, or `--armor`.
+///
+/// Without either, it is decided by the [`plan`](super::plan), from
+/// where the ciphertext goes to (or comes from).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Armor {
+    #[default]
+    Auto,
+    Base64,
+    Raw,
+    Armored,
+}
+
+/// Where the key comes from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum KeySource {
+    /// `--key`.
+    Argument,
+    /// The key environment variable.
+    Environment,
+    /// A file, from either `--key` or the environment variable.
+    File(PathBuf),
+    /// No key given, the default key is used.
+    #[default]
+    Default,
+    /// The algorithm has a fixed key, any key given is ignored.
+    Fixed,
+    /// `--password`, the key is derived from a passphrase.
+    Password,
+    /// `--key -`, the key is typed in on the terminal.
+    Prompt,
+    /// `--key-name`, the named key from the keyring.
+    Keyring(String),
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Argument => write!(f, "--key"),
+            Self::Environment => write!(f, "${KEY_ENV_VAR}"),
+            Self::File(file) => write!(f, "'{}'", file.display()),
+            Self::Default => write!(f, "default (not secure)"),
+            Self::Fixed => write!(f, "fixed by algorithm"),
+            Self::Password => write!(f, "passphrase (Argon2id)"),
+            Self::Prompt => write!(f, "typed in (--key -)"),
+            Self::Keyring(name) => write!(f, "'{name}' (keyring)"),
+        }
+    }
+}
+
+// Note: `Debug` is safe to derive, secrets redact themselves (`key` is
+// a `SecretString`, and `Message` has a redacting `Debug`).
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Default)]
 pub struct Args {
     pub command: Option<Command>,
     pub algorithm: Option<Algorithm>,
+    pub keygen_options: KeygenOptions,
+    pub confirm_then_clear: bool,
+    /// `keygen --protect`, encrypt the key with a passphrase.
+    pub protect: bool,
+    /// `keygen --public-output`, where the public key goes (the private
+    /// key goes to `output`).
+    pub public_output: Option<PathBuf>,
+    /// Where keys, nonces, and salts get their randomness.
+    pub entropy: Option<entropy::Source>,
+    /// `sign --keygen`, generate a signing keypair.
+    pub signing_keygen: bool,
     pub key: Option<SecretString>,
-    pub raw: bool,
+    pub key_source: KeySource,
+    /// `--key-name`, look the key up in the keyring.
+    pub key_name: Option<String>,
+    /// `rekey --new-algorithm`, defaults to the algorithm of the old key.
+    pub new_algorithm: Option<Algorithm>,
+    /// `rekey --new-key`, the key to re-encrypt with.
+    pub new_key: Option<SecretString>,
+    /// The key (or passphrase) must be typed in on the terminal.
+    pub prompt_key: bool,
+    /// The key is protected by a passphrase, it must be unlocked (see
+    /// [`Args::unlock_key()`]).
+    pub locked_key: bool,
+    pub password: bool,
+    pub armor: Armor,
+    pub framed: bool,
+    pub continue_on_error: bool,
+    /// `--mmap`, read the input file from a map of it.
+    pub mmap: bool,
+    /// `--legacy`, decrypt old versions of the format (see `migrate`).
+    pub legacy: bool,
     pub message: Option<Message>,
+    pub aad: Option<String>,
+    /// Detached signature to check (`verify`).
+    pub signature: Option<String>,
+    pub expire: Option<Duration>,
+    pub strict: bool,
+    /// Embed a hint of the key in the ciphertext.
+    pub id_hint: bool,
+    /// `keygen` and `encrypt`, show the output as a QR code.
+    pub qr: bool,
+    /// Text encoding of keys and ciphertexts, if not base64.
+    pub encoding: Option<TextEncoding>,
+    pub pre_filter: Option<String>,
+    pub post_filter: Option<String>,
+    pub max_stdin_size: Option<u64>,
+    /// `bench --size`, bytes to encrypt per algorithm.
+    pub bench_size: Option<u64>,
+    /// `--buffer-size`, bytes of output buffered before a write.
+    pub buffer_size: Option<usize>,
+    pub peek: Option<usize>,
+    /// Plaintext bytes to decrypt (`--range`), end excluded.
+    pub range: Option<Range<u64>>,
     pub output: Output,
+    /// Added to the input file name on encryption, and removed on
+    /// decryption, to name the output (`--suffix`).
+    pub suffix: Option<String>,
+    /// Overwrite and remove the input file once encrypted.
+    pub shred: bool,
+    /// Give the output the times of the input file.
+    pub preserve_times: bool,
+    pub no_warn: Vec<Warning>,
+    pub dry_run: bool,
+    pub yes: bool,
+    /// Overwrite an existing output file.
+    pub force: bool,
     pub short_help: bool,
     pub long_help: bool,
+    pub no_pager: bool,
+    /// Show a progress bar on `stderr` (encryption and decryption).
+    pub progress: bool,
+    /// `-v` or `-q`.
+    pub verbosity: Verbosity,
     pub version: bool,
 }
 
 impl Args {
-    pub fn build_from_args<I>(mut cli_args: I) -> Result<Self, String>
+    /// Build arguments, in the environment of the process.
+    ///
+    /// In tests, the environment is a blank [`environment::Fake`], so
+    /// results do not depend on how the tests are run.
+    pub fn build_from_args<I>(cli_args: I) -> Result<Self, CliError>
+    where
+        I: Iterator<Item: AsRef<str> + ToString>,
+    {
+        #[cfg(not(test))]
+        let env = environment::System;
+        #[cfg(test)]
+        let env = environment::Fake::default();
+        Self::build_from_args_with_env(cli_args, &env)
+    }
+
+    /// Bytes of output buffered before a write (see `--buffer-size`).
+    pub fn output_buffer_size(&self) -> usize {
+        self.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Where the key comes from, as far as the algorithm cares.
+    pub fn effective_key_source(&self) -> KeySource {
+        if self.algorithm.is_some_and(Algorithm::is_toy) {
+            KeySource::Fixed
+        } else {
+            self.key_source.clone()
+        }
+    }
+
+    /// Build arguments, in `env`. Errors are all bad usage.
+    pub fn build_from_args_with_env<I>(cli_args: I, env: &dyn Environment) -> Result<Self, CliError>
+    where
+        I: Iterator<Item: AsRef<str> + ToString>,
+    {
+        Self::parse(cli_args, env).map_err(CliError::Usage)
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn parse<I>(mut cli_args: I, env: &dyn Environment) -> Result<Self, String>
     where
         I: Iterator<Item: AsRef<str> + ToString>,
     {
         let mut args = Self::default();
+        // Resolved after parsing, `-i` may come before `-f`.
+        let mut in_place = false;
+        let mut no_progress = false;
+        // After `--`, everything is a message (e.g., `-- "-n"`).
+        let mut end_of_options = false;
 
         while let Some(arg) = cli_args.next() {
+            if end_of_options {
+                Self::push_positional(&mut args, arg.as_ref())?;
+                continue;
+            }
+
             let some_command = args.command.is_some();
             let some_algorithm = args.algorithm.is_some();
-            let some_key = args.key.is_some();
+            let some_key = args.key.is_some() || args.prompt_key;
+            let some_key_name = args.key_name.is_some();
+            let some_new_algorithm = args.new_algorithm.is_some();
+            let some_new_key = args.new_key.is_some();
+            let some_bits = args.keygen_options.bits.is_some();
             let some_message = args.message.is_some();
+            let some_signature = args.signature.is_some();
+            let some_entropy = args.entropy.is_some();
+            let some_expire = args.expire.is_some();
             let some_output = matches!(args.output, Output::File(_));
+            let some_max_stdin_size = args.max_stdin_size.is_some();
+            let some_bench_size = args.bench_size.is_some();
+            let some_buffer_size = args.buffer_size.is_some();
+            let some_peek = args.peek.is_some();
+            let some_range = args.range.is_some();
+            let some_pre_filter = args.pre_filter.is_some();
+            let some_post_filter = args.post_filter.is_some();
 
             let is_keygen = args
                 .command
                 .as_ref()
                 .is_some_and(|c| matches!(c, Command::KeyGen));
+            let is_encrypt = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Encrypt));
+            let is_decrypt = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Decrypt));
+            let is_rekey = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Rekey));
+            let is_migrate = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Migrate));
+            let is_check = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Check));
+            let is_bench = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Bench));
+            let is_sign = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Sign));
+            let is_verify = args
+                .command
+                .as_ref()
+                .is_some_and(|c| matches!(c, Command::Verify));
+            // Signing doesn't go through a cipher, cipher options don't apply.
+            let is_signing = is_sign || is_verify;
             let message_file = match args.message.as_ref() {
                 Some(Message::File(f)) => Some(f),
                 _ => None,
             };
 
             match arg.as_ref() {
+                "--" if some_command => end_of_options = true,
                 "-h" => args.short_help = true,
                 "--help" => args.long_help = true,
+                "--no-pager" => args.no_pager = true,
                 "-V" | "--version" => args.version = true,
+                "-v" | "--verbose" if args.verbosity != Verbosity::Quiet => {
+                    args.verbosity = Verbosity::Verbose;
+                }
+                "-q" | "--quiet" if args.verbosity != Verbosity::Verbose => {
+                    args.verbosity = Verbosity::Quiet;
+                }
+                "-v" | "--verbose" | "-q" | "--quiet" => {
+                    return Err("'--verbose' cannot be used with '--quiet'".to_string());
+                }
                 "keygen" if !some_command => args.command = Some(Command::KeyGen),
                 "encrypt" if !some_command => args.command = Some(Command::Encrypt),
                 "decrypt" if !some_command => args.command = Some(Command::Decrypt),
-                "-a" | "--algorithm" if some_command && !some_algorithm => {
+                "rekey" if !some_command => args.command = Some(Command::Rekey),
+                "migrate" if !some_command => args.command = Some(Command::Migrate),
+                "doctor" if !some_command => args.command = Some(Command::Doctor),
+                "bench" if !some_command => args.command = Some(Command::Bench),
+                "inspect" if !some_command => args.command = Some(Command::Inspect),
+                "check" if !some_command => args.command = Some(Command::Check),
+                "sign" if !some_command => args.command = Some(Command::Sign),
+                "verify" if !some_command => args.command = Some(Command::Verify),
+                "man" if !some_command => args.command = Some(Command::Man),
+                "-a" | "--algorithm" if some_command && !is_signing && !some_algorithm => {
                     let Some(algorithm) = cli_args.next() else {
                         return Err(format!("Expected algorithm after '{}'", arg.as_ref()));
                     };
@@ -139,11 +534,72 @@ impl Args {
                     };
                     args.algorithm = Some(algorithm);
                 }
+                "--new-algorithm" if is_rekey && !some_new_algorithm => {
+                    let Some(algorithm) = cli_args.next() else {
+                        return Err(format!("Expected algorithm after '{}'", arg.as_ref()));
+                    };
+                    let Ok(algorithm) = algorithm.as_ref().parse() else {
+                        return Err(format!("Unrecognized algorithm '{}'", algorithm.as_ref()));
+                    };
+                    args.new_algorithm = Some(algorithm);
+                }
+                "--bits" if is_keygen && !some_bits => {
+                    let Some(bits) = cli_args.next() else {
+                        return Err(format!("Expected key size after '{}'", arg.as_ref()));
+                    };
+                    let Ok(bits) = bits.as_ref().parse() else {
+                        return Err(format!("Invalid key size '{}'", bits.as_ref()));
+                    };
+                    args.keygen_options.bits = Some(bits);
+                }
+                "--confirm-then-clear" if is_keygen => args.confirm_then_clear = true,
+                "--protect" if is_keygen => args.protect = true,
+                "--public-output" if is_keygen && args.public_output.is_none() => {
+                    let Some(file) = cli_args.next() else {
+                        return Err(format!("Expected file name after '{}'", arg.as_ref()));
+                    };
+                    args.public_output = Some(PathBuf::from(file.as_ref()));
+                }
+                "--params" if is_keygen => {
+                    let Some(params) = cli_args.next() else {
+                        return Err(format!("Expected parameters after '{}'", arg.as_ref()));
+                    };
+                    for param in params.as_ref().split(',') {
+                        let Some((name, value)) = param.split_once('=') else {
+                            return Err(format!("Expected 'name=value', got '{param}'"));
+                        };
+                        args.keygen_options
+                            .params
+                            .push((name.trim().to_string(), value.trim().to_string()));
+                    }
+                }
+                "--entropy"
+                    if (is_keygen || is_encrypt || is_rekey || is_migrate || is_sign)
+                        && !some_entropy =>
+                {
+                    let Some(source) = cli_args.next() else {
+                        return Err(format!("Expected entropy source after '{}'", arg.as_ref()));
+                    };
+                    args.entropy = Some(Self::parse_entropy_source(source.as_ref())?);
+                }
+                "--keygen" if is_sign => args.signing_keygen = true,
+                "--signature" if is_verify && !some_signature => {
+                    let Some(signature) = cli_args.next() else {
+                        return Err(format!("Expected signature after '{}'", arg.as_ref()));
+                    };
+                    args.signature = Some(signature.to_string());
+                }
                 "-k" | "--key" if some_command && !is_keygen && !some_key => {
                     let Some(key) = cli_args.next() else {
                         return Err(format!("Expected key after '{}'", arg.as_ref()));
                     };
+                    if key.as_ref() == "-" {
+                        args.prompt_key = true;
+                        args.key_source = KeySource::Prompt;
+                        continue;
+                    }
                     args.key = Some(SecretString::from(key.as_ref()));
+                    args.key_source = KeySource::Argument;
 
                     // Note: We _don't_ zeroize `arg` here, because it
                     // would make us require a `+ Zeroize` trait bound
@@ -152,97 +608,1027 @@ impl Args {
                     // other processes, with a (safely) immutable copy
                     // in `argv`.
                 }
-                "-r" | "--raw" if some_command && !is_keygen => args.raw = true,
-                "-o" | "--output" if some_command && !some_output => {
+                "--new-key" if is_rekey && !some_new_key => {
+                    let Some(key) = cli_args.next() else {
+                        return Err(format!("Expected key after '{}'", arg.as_ref()));
+                    };
+                    args.new_key = Some(SecretString::from(key.as_ref()));
+                }
+                "--key-name"
+                    if (is_encrypt || is_decrypt || is_rekey || is_migrate || is_check)
+                        && !some_key_name =>
+                {
+                    let Some(name) = cli_args.next() else {
+                        return Err(format!("Expected key name after '{}'", arg.as_ref()));
+                    };
+                    args.key_name = Some(name.to_string());
+                }
+                "--password"
+                    if some_command && !is_keygen && !is_signing && !is_rekey && !is_migrate =>
+                {
+                    args.password = true;
+                }
+                // The last one wins, so aliases can be overridden.
+                "-r" | "--raw" if some_command && !is_keygen && !is_signing => {
+                    args.armor = Armor::Raw;
+                }
+                "--base64" if some_command && !is_keygen && !is_signing => {
+                    args.armor = Armor::Base64;
+                }
+                // Armor is detected on the way back.
+                "--armor" if is_encrypt || is_rekey || is_migrate => args.armor = Armor::Armored,
+                "--framed"
+                    if some_command && !is_keygen && !is_signing && !is_rekey && !is_migrate =>
+                {
+                    args.framed = true;
+                }
+                "--continue-on-error" if is_decrypt => args.continue_on_error = true,
+                "--mmap" if is_encrypt || is_decrypt => args.mmap = true,
+                "-o" | "--output" if some_command && !is_check && !some_output => {
                     let Some(file) = cli_args.next() else {
                         return Err(format!("Expected file name after '{}'", arg.as_ref()));
                     };
                     args.output = Output::File(PathBuf::from(file.as_ref()));
                 }
-                "-f" | "--file" if some_command && !some_message => {
+                "-f" | "--file" if some_command && message_file.is_none() => {
                     let Some(file) = cli_args.next() else {
                         return Err(format!("Expected file name after '{}'", arg.as_ref()));
                     };
+                    // With a file, the message string is associated data.
+                    if let Some(Message::String(aad)) = args.message.take() {
+                        args.aad = Some(aad);
+                    }
                     args.message = Some(Message::File(PathBuf::from(file.as_ref())));
                 }
-                "-i" | "--in-place" if message_file.is_some() && !some_output => {
-                    let message_file = message_file.expect("it is `Some`");
-                    args.output = Output::File(message_file.to_owned());
+                "--max-stdin-size" if some_command && !is_keygen && !some_max_stdin_size => {
+                    let Some(size) = cli_args.next() else {
+                        return Err(format!("Expected size after '{}'", arg.as_ref()));
+                    };
+                    let Ok(size) = size.as_ref().parse() else {
+                        return Err(format!("Invalid size '{}'", size.as_ref()));
+                    };
+                    args.max_stdin_size = Some(size);
+                }
+                "--buffer-size"
+                    if (is_encrypt || is_decrypt || is_rekey || is_migrate)
+                        && !some_buffer_size =>
+                {
+                    let Some(size) = cli_args.next() else {
+                        return Err(format!("Expected size after '{}'", arg.as_ref()));
+                    };
+                    let Some(size) = size.as_ref().parse().ok().filter(|&size| size > 0) else {
+                        return Err(format!("Invalid size '{}'", size.as_ref()));
+                    };
+                    args.buffer_size = Some(size);
+                }
+                "--size" if is_bench && !some_bench_size => {
+                    let Some(size) = cli_args.next() else {
+                        return Err(format!("Expected size after '{}'", arg.as_ref()));
+                    };
+                    let Ok(size) = size.as_ref().parse() else {
+                        return Err(format!("Invalid size '{}'", size.as_ref()));
+                    };
+                    args.bench_size = Some(size);
+                }
+                "--expire" if is_encrypt && !some_expire => {
+                    let Some(duration) = cli_args.next() else {
+                        return Err(format!("Expected duration after '{}'", arg.as_ref()));
+                    };
+                    let Some(duration) = Self::parse_duration(duration.as_ref()) else {
+                        return Err(format!("Invalid duration '{}'", duration.as_ref()));
+                    };
+                    args.expire = Some(duration);
+                }
+                "--strict" if is_decrypt => args.strict = true,
+                "--legacy" if is_decrypt || is_rekey || is_check => args.legacy = true,
+                "--id-hint" if is_encrypt => args.id_hint = true,
+                "--qr" if is_keygen || is_encrypt => args.qr = true,
+                "--encoding"
+                    if (is_keygen
+                        || is_encrypt
+                        || is_decrypt
+                        || is_check
+                        || args.command == Some(Command::Inspect))
+                        && args.encoding.is_none() =>
+                {
+                    let Some(encoding) = cli_args.next() else {
+                        return Err(format!("Expected encoding after '{}'", arg.as_ref()));
+                    };
+                    let Ok(encoding) = encoding.as_ref().parse() else {
+                        return Err(format!(
+                            "Unrecognized encoding '{}', expected base64, hex, or base32",
+                            encoding.as_ref()
+                        ));
+                    };
+                    args.encoding = Some(encoding);
+                }
+                "--dry-run"
+                    if some_command
+                        && !is_keygen
+                        && !is_signing
+                        && !is_rekey
+                        && !is_migrate
+                        && !is_check =>
+                {
+                    args.dry_run = true;
+                }
+                "-y" | "--yes" if some_command && !is_keygen && !is_signing => args.yes = true,
+                "--force" if some_command => args.force = true,
+                "--no-warn" if some_command => {
+                    let Some(warnings) = cli_args.next() else {
+                        return Err(format!("Expected warning after '{}'", arg.as_ref()));
+                    };
+                    args.no_warn.extend(Warning::parse_list(warnings.as_ref())?);
+                }
+                "--pre-filter" if is_encrypt && !some_pre_filter => {
+                    let Some(command) = cli_args.next() else {
+                        return Err(format!("Expected command after '{}'", arg.as_ref()));
+                    };
+                    args.pre_filter = Some(command.to_string());
+                }
+                "--post-filter" if is_decrypt && !some_post_filter => {
+                    let Some(command) = cli_args.next() else {
+                        return Err(format!("Expected command after '{}'", arg.as_ref()));
+                    };
+                    args.post_filter = Some(command.to_string());
+                }
+                "--peek" if is_decrypt && !some_peek => {
+                    let Some(n) = cli_args.next() else {
+                        return Err(format!("Expected size after '{}'", arg.as_ref()));
+                    };
+                    let Ok(n) = n.as_ref().parse() else {
+                        return Err(format!("Invalid size '{}'", n.as_ref()));
+                    };
+                    args.peek = Some(n);
+                }
+                "--range" if is_decrypt && !some_range => {
+                    let Some(range) = cli_args.next() else {
+                        return Err(format!("Expected range after '{}'", arg.as_ref()));
+                    };
+                    args.range = Some(Self::parse_range(range.as_ref())?);
                 }
-                message if some_command && !is_keygen && !some_message => {
-                    args.message = Some(Message::String(message.to_string()));
+                "--no-progress" if is_encrypt || is_decrypt || is_check => no_progress = true,
+                "--shred" if is_encrypt => args.shred = true,
+                "--suffix" if (is_encrypt || is_decrypt) && args.suffix.is_none() => {
+                    let Some(suffix) = cli_args.next() else {
+                        return Err(format!("Expected suffix after '{}'", arg.as_ref()));
+                    };
+                    args.suffix = Some(Self::parse_suffix(suffix.as_ref())?);
+                }
+                "--preserve-times" if is_encrypt || is_decrypt || is_rekey || is_migrate => {
+                    args.preserve_times = true;
                 }
-                unknown => {
-                    return Err(format!("Unknown argument: '{unknown}'"));
+                "-i" | "--in-place" if is_encrypt || is_decrypt || is_rekey || is_migrate => {
+                    in_place = true;
                 }
+                // Anything else that looks like an option is a typo, not
+                // a message (e.g., `--ouput`). It must come after `--`.
+                option if option.starts_with('-') && option != "-" => {
+                    if some_command && !is_keygen && !some_message {
+                        return Err(format!(
+                            "Unknown argument: '{option}' (if it is the message, put it after '--')"
+                        ));
+                    }
+                    return Err(format!("Unknown argument: '{option}'"));
+                }
+                positional => Self::push_positional(&mut args, positional)?,
             }
         }
 
-        if args.key.is_some() && args.algorithm == Some(Algorithm::Brainfuck) {
+        if in_place && args.range.is_some() {
+            return Err("'--range' cannot be used with '--in-place'".to_string());
+        }
+        if in_place && args.suffix.is_some() {
+            return Err("'--suffix' cannot be used with '--in-place'".to_string());
+        }
+        if in_place && args.shred {
+            return Err("'--shred' cannot be used with '--in-place'".to_string());
+        }
+        if in_place && args.qr {
+            return Err("'--qr' cannot be used with '--in-place'".to_string());
+        }
+
+        if in_place {
+            Self::use_in_place(&mut args)?;
+        }
+
+        // In-place, the output is the input, even if it's a directory.
+        if !in_place
+            && matches!(
+                args.command,
+                Some(Command::Encrypt | Command::Decrypt | Command::Rekey | Command::Migrate)
+            )
+        {
+            Self::resolve_output_directory(&mut args)?;
+        }
+
+        if !args.force {
+            Self::check_overwrite(&args)?;
+        }
+
+        if args.shred {
+            Self::check_shred(&args)?;
+        }
+
+        if args.key_name.is_some() {
+            Self::use_keyring(&mut args, env)?;
+        }
+
+        #[cfg(feature = "brainfuck")]
+        if (args.key.is_some() || args.prompt_key) && args.algorithm == Some(Algorithm::Brainfuck) {
             return Err("Brainfuck does not use keys".to_string());
         }
 
+        if args.post_filter.is_some() && args.peek.is_some() {
+            return Err("'--post-filter' cannot be used with '--peek'".to_string());
+        }
+
+        if args.range.is_some() {
+            for (is_set, option) in [
+                (args.peek.is_some(), "--peek"),
+                (args.post_filter.is_some(), "--post-filter"),
+                (args.framed, "--framed"),
+            ] {
+                if is_set {
+                    return Err(format!("'--range' cannot be used with '{option}'"));
+                }
+            }
+        }
+
+        // The expiry date is authenticated as associated data.
+        if args.expire.is_some()
+            && let Some(algorithm) = args.algorithm
+            && (algorithm == Algorithm::Age || algorithm.is_toy())
+        {
+            return Err(format!("'--expire' cannot be used with {algorithm}"));
+        }
+
+        // A hint of a passphrase would help guess it offline, without
+        // the KDF. Asymmetric keys differ on either end.
+        if args.id_hint && args.password {
+            return Err("'--id-hint' cannot be used with '--password'".to_string());
+        }
+        if args.id_hint
+            && let Some(algorithm) = args.algorithm
+            && algorithm != Algorithm::ChaCha20Poly1305
+        {
+            return Err(format!("'--id-hint' cannot be used with {algorithm}"));
+        }
+
+        if args.continue_on_error && !args.framed {
+            return Err("'--continue-on-error' requires '--framed'".to_string());
+        }
+        if args.armor == Armor::Armored && args.framed {
+            return Err("'--armor' cannot be used with '--framed'".to_string());
+        }
+        // The encoding replaces base64, wherever base64 would be.
+        if args.encoding.is_some() {
+            for (is_set, option) in [
+                (args.armor == Armor::Raw, "--raw"),
+                (args.armor == Armor::Base64, "--base64"),
+                (args.armor == Armor::Armored, "--armor"),
+                (args.framed, "--framed"),
+                (args.qr, "--qr"),
+                (args.protect, "--protect"),
+            ] {
+                if is_set {
+                    return Err(format!("'--encoding' cannot be used with '{option}'"));
+                }
+            }
+            // age keys are Bech32, and the rest don't have keys to
+            // encode. The ciphertexts are fine.
+            if args.command == Some(Command::KeyGen)
+                && let Some(algorithm) = args.algorithm
+                && (algorithm == Algorithm::Age || algorithm.is_toy())
+            {
+                return Err(format!("'--encoding' cannot be used with {algorithm} keys"));
+            }
+        }
+
+        // QR codes are base64, and hold a single message.
+        if args.qr {
+            for (is_set, option) in [
+                (args.armor == Armor::Raw, "--raw"),
+                (args.armor == Armor::Base64, "--base64"),
+                (args.armor == Armor::Armored, "--armor"),
+                (args.framed, "--framed"),
+            ] {
+                if is_set {
+                    return Err(format!("'--qr' cannot be used with '{option}'"));
+                }
+            }
+        }
+
+        if args.password {
+            Self::use_password(&mut args, env)?;
+        }
+
+        if args.prompt_key && !env.is_terminal(Stream::Stdin) {
+            return Err("Cannot prompt for the key, 'stdin' is not a terminal".to_string());
+        }
+
+        if args.protect {
+            Self::check_protect(&args, env)?;
+        }
+
+        if args.public_output.is_some() {
+            Self::check_public_output(&args)?;
+        }
+
         // Default to `--raw` for ROT-n and Brainfuck.
-        if matches!(args.algorithm, Some(Algorithm::RotN | Algorithm::Brainfuck)) {
-            args.raw = true;
+        if args.algorithm.is_some_and(Algorithm::is_toy) && args.armor == Armor::Auto && !args.qr {
+            args.armor = Armor::Raw;
         }
 
-        // If no key, try `env`.
-        if args.key.is_none() {
-            args.key = Self::maybe_get_key_from_env();
+        let is_signing = matches!(args.command, Some(Command::Sign | Command::Verify));
+
+        // If no key, try `env`. Signing keys are never shared with the
+        // cipher key, they must be given explicitly.
+        if args.key.is_none()
+            && !is_signing
+            && !args.password
+            && !args.prompt_key
+            && let Some(key) = env.var(KEY_ENV_VAR)
+        {
+            args.key = Some(SecretString::from(key));
+            args.key_source = KeySource::Environment;
         }
-        if let Some(ref key) = args.key {
+        if let Some(ref key) = args.key
+            && !args.password
+        {
             // If the given key is a file, use the content of the file
             // as the key.
-            if let Some(key) = Self::maybe_get_key_from_file(key) {
-                args.key = Some(key);
+            if let Some(content) = Self::maybe_get_key_from_file(key) {
+                // Named keys are shown by name, wherever they are.
+                if !matches!(args.key_source, KeySource::Keyring(_)) {
+                    args.key_source = KeySource::File(PathBuf::from(key.expose_secret()));
+                }
+                args.key = Some(content);
             }
         }
         if let Some(ref key) = args.key
-            && args.algorithm == Some(Algorithm::RotN)
+            && !args.password
+            && let Some(key) = Self::use_foreign_key(key, &mut args.algorithm)?
+        {
+            args.key = Some(key);
+        } else if let Some(ref key) = args.key
+            && let Some(encoding) = args.encoding
+            && !args.password
+            && !matches!(args.key_source, KeySource::Keyring(_))
+            && !args.algorithm.is_some_and(Algorithm::is_rotn)
+            && !key::is_protected(key.expose_secret())
+        {
+            args.key = Some(Self::normalize_key_encoding(key, encoding)?);
+        }
+
+        // After the key, SSH and age keys select the algorithm.
+        if args.command == Some(Command::Rekey) {
+            Self::check_rekey(&mut args, in_place)?;
+        }
+        if args.command == Some(Command::Migrate) {
+            Self::check_migrate(&mut args, in_place)?;
+        }
+
+        if let Some(ref key) = args.key
+            && !args.password
+            && key::is_protected(key.expose_secret())
+        {
+            if env.var(PASSWORD_ENV_VAR).is_none() && !env.is_terminal(Stream::Stdin) {
+                return Err(format!(
+                    "The key is protected, its passphrase must be in {PASSWORD_ENV_VAR}, or typed in on a terminal"
+                ));
+            }
+            args.locked_key = true;
+        }
+        if let Some(ref key) = args.key
+            && args.algorithm.is_some_and(Algorithm::is_rotn)
+            && !args.locked_key
         {
             args.key = Some(Self::normalize_rotn_key_to_base64(key)?);
         }
 
-        // If not message, try `stdin`.
-        if args.message.is_none() && Self::does_stdin_have_content() {
+        if is_signing {
+            Self::check_signing(&mut args)?;
+        }
+
+        if let Some(algorithm) = args.algorithm
+            && matches!(algorithm.default_key(), GeneratedKey::None)
+            && matches!(
+                args.command,
+                Some(Command::Encrypt | Command::Decrypt | Command::Rekey | Command::Check)
+            )
+            && args.key.is_none()
+            && !args.prompt_key
+        {
+            let cipher: Box<dyn Cipher> = algorithm.into();
+            let name = cipher.algorithm_id();
+            return Err(format!(
+                "{algorithm} has no default key, generate one with 'keygen -a {name}'"
+            ));
+        }
+
+        args.no_warn.extend(Warning::suppressed_by_env(env));
+
+        // If not message, use `stdin`. Either it is piped in, or it is
+        // interactive and the message will be typed in by the user.
+        if args.message.is_none() && env.has_stdin() {
             args.message = Some(Message::Stdin);
         }
 
         // If no explicit `--output`, check if redirected or `stdout`.
-        if !matches!(args.output, Output::File(_)) && Self::is_output_redirected() {
+        if !matches!(args.output, Output::File(_)) && !env.is_terminal(Stream::Stdout) {
             args.output = Output::Redirected;
         }
 
+        if args.preserve_times {
+            if !matches!(args.message, Some(Message::File(_))) {
+                return Err("'--preserve-times' requires '--file'".to_string());
+            }
+            if !matches!(args.output, Output::File(_)) {
+                return Err("'--preserve-times' requires '--output' or '--in-place'".to_string());
+            }
+        }
+
+        // Only for inputs that can take a while, and if someone's
+        // watching. A message typed in on a terminal ends when the user
+        // says so, there's nothing to report.
+        args.progress = !no_progress
+            && args.verbosity != Verbosity::Quiet
+            && matches!(
+                args.command,
+                Some(Command::Encrypt | Command::Decrypt | Command::Check)
+            )
+            && !args.framed
+            && !args.dry_run
+            && match args.message {
+                Some(Message::File(_)) => true,
+                Some(Message::Stdin) => !env.is_terminal(Stream::Stdin),
+                _ => false,
+            }
+            && env.is_terminal(Stream::Stderr);
+
         Ok(args)
     }
 
-    fn maybe_get_key_from_env() -> Option<SecretString> {
-        std::env::var(KEY_ENV_VAR).ok().map(SecretString::from)
+    /// `sign` and `verify` have no default key, and `verify` needs a
+    /// signature. The signature may be given as a file.
+    fn check_signing(args: &mut Self) -> Result<(), String> {
+        if args.signing_keygen {
+            return Ok(());
+        }
+        let command = if args.command == Some(Command::Sign) {
+            "sign"
+        } else {
+            "verify"
+        };
+        if args.key.is_none() && !args.prompt_key {
+            return Err(format!("'{command}' requires a key, with '--key'"));
+        }
+        if command == "verify" {
+            let Some(ref signature) = args.signature else {
+                return Err("'verify' requires a signature, with '--signature'".to_string());
+            };
+            if Path::new(signature).is_file() {
+                let content = fs::read_to_string(signature)
+                    .map_err(|e| format!("Cannot read signature '{signature}': {e}"))?;
+                args.signature = Some(content.trim().to_string());
+            }
+        }
+        Ok(())
     }
 
-    /// Try to extract non empty key from potentially existing file.
-    ///
-    /// The file _must_ exist, _must_ be readable, and _must_ be
-    /// non-empty. If these conditions are met, the content of the file
-    /// is returned (trailing whitespace gets removed).
-    fn maybe_get_key_from_file(maybe_file: &SecretString) -> Option<SecretString> {
-        let maybe_file = Path::new(maybe_file.expose_secret());
-        if maybe_file.is_file()
-            && let Ok(key) = fs::read_to_string(maybe_file)
-        {
-            let key = key.trim_end();
-            if !key.is_empty() {
-                return Some(SecretString::from(key));
+    /// Take a positional argument as the message or, with `--file`, as
+    /// associated data.
+    fn push_positional(args: &mut Self, arg: &str) -> Result<(), String> {
+        let takes_positional = args.command.is_some_and(|c| !matches!(c, Command::KeyGen));
+        let takes_aad = args
+            .command
+            .is_some_and(|c| !matches!(c, Command::KeyGen | Command::Sign | Command::Verify));
+        match args.message {
+            Some(Message::File(_)) if takes_aad && args.aad.is_none() => {
+                args.aad = Some(arg.to_string());
             }
+            None if takes_positional => args.message = Some(Message::String(arg.to_string())),
+            _ => return Err(format!("Unknown argument: '{arg}'")),
         }
-        None
+        Ok(())
     }
 
-    /// Normalize ROT-n keys to base64.
-    ///
-    /// ROT-n keys are string representations of decimal numbers
-    /// (e.g, "13"). We want to normalize them into base64 so they work
+    /// Output to the input file (`--in-place`).
+    fn use_in_place(args: &mut Self) -> Result<(), String> {
+        let Some(Message::File(ref file)) = args.message else {
+            return Err("'--in-place' requires an input file, with '--file'".to_string());
+        };
+        if matches!(args.output, Output::File(_)) {
+            return Err("'--in-place' cannot be used with '--output'".to_string());
+        }
+        args.output = Output::File(file.clone());
+        Ok(())
+    }
+
+    /// With `-o <dir>`, or with `--suffix` and no `-o`, name the output
+    /// after the input file.
+    ///
+    /// Encryption appends `.enc` (or the suffix), decryption removes it,
+    /// and rekeying keeps the name as is.
+    fn resolve_output_directory(args: &mut Self) -> Result<(), String> {
+        let dir = match args.output {
+            Output::File(ref dir) => dir.clone(),
+            // Next to the input.
+            _ if args.suffix.is_some() => {
+                let Some(Message::File(ref input)) = args.message else {
+                    return Err("'--suffix' requires an input file, with '--file'".to_string());
+                };
+                let name = Self::output_name(args, input)?;
+                args.output = Output::File(input.with_file_name(name));
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        if !dir.is_dir() {
+            let path = dir.to_string_lossy();
+            if path.ends_with('/') || path.ends_with(std::path::MAIN_SEPARATOR) {
+                return Err(format!("Output directory '{path}' does not exist"));
+            }
+            if args.suffix.is_some() {
+                return Err(
+                    "'--suffix' cannot be used with an output file, only a directory".to_string(),
+                );
+            }
+            return Ok(());
+        }
+
+        let Some(Message::File(ref input)) = args.message else {
+            return Err(format!(
+                "Cannot name the output in '{}', there is no input file",
+                dir.display()
+            ));
+        };
+        let name = Self::output_name(args, input)?;
+
+        args.output = Output::File(dir.join(name));
+        Ok(())
+    }
+
+    /// The name of the output of `input`.
+    ///
+    /// Without `--suffix`, a name that doesn't end with `.enc` is kept as
+    /// is on decryption. With it, it's an error (the output would be the
+    /// input).
+    fn output_name(args: &Self, input: &Path) -> Result<String, String> {
+        let Some(name) = input.file_name().map(|name| name.to_string_lossy()) else {
+            return Err(format!(
+                "Cannot name the output after '{}'",
+                input.display()
+            ));
+        };
+        let suffix = args.suffix.as_deref().unwrap_or(".enc");
+        Ok(match args.command {
+            Some(Command::Encrypt) => format!("{name}{suffix}"),
+            Some(Command::Decrypt) => match name.strip_suffix(suffix) {
+                Some(stem) if !stem.is_empty() => stem.to_string(),
+                _ if args.suffix.is_some() => {
+                    return Err(format!(
+                        "Cannot name the output, '{}' does not end with '{suffix}'",
+                        input.display()
+                    ));
+                }
+                _ => name.to_string(),
+            },
+            _ => name.to_string(),
+        })
+    }
+
+    /// Parse a file name suffix (e.g., `.enc`).
+    fn parse_suffix(suffix: &str) -> Result<String, String> {
+        if suffix.is_empty() || suffix.contains(['/', std::path::MAIN_SEPARATOR]) {
+            return Err(format!("Invalid suffix '{suffix}'"));
+        }
+        Ok(suffix.to_string())
+    }
+
+    /// Refuse to replace an existing file with the output, unless it's
+    /// the input (in-place).
+    ///
+    /// Only regular files are protected, writing to a device (e.g.,
+    /// `/dev/null`) or a pipe doesn't destroy anything.
+    fn check_overwrite(args: &Self) -> Result<(), String> {
+        if let Some(ref file) = args.public_output
+            && file.is_file()
+        {
+            return Err(format!(
+                "'{}' already exists, use '--force' to overwrite it",
+                file.display()
+            ));
+        }
+        let Output::File(ref file) = args.output else {
+            return Ok(());
+        };
+        if !file.is_file() {
+            return Ok(());
+        }
+        if let Some(Message::File(ref input)) = args.message
+            && plan::is_same_file(input, file)
+        {
+            return Ok(());
+        }
+        Err(format!(
+            "'{}' already exists, use '--force' to overwrite it",
+            file.display()
+        ))
+    }
+
+    /// The public key goes to its own file, the private key to
+    /// `--output`. Only asymmetric keys have a public part.
+    fn check_public_output(args: &Self) -> Result<(), String> {
+        let algorithm = args.algorithm.unwrap_or_default();
+        if !algorithm.is_asymmetric() {
+            return Err(format!("'--public-output' cannot be used with {algorithm}"));
+        }
+        if !matches!(args.output, Output::File(_)) {
+            return Err(
+                "'--public-output' requires an output file for the private key, with '--output'"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Only shred a regular file, once it's encrypted into another file.
+    fn check_shred(args: &Self) -> Result<(), String> {
+        let Some(Message::File(ref input)) = args.message else {
+            return Err("'--shred' requires an input file, with '--file'".to_string());
+        };
+        let Output::File(ref output) = args.output else {
+            return Err(
+                "'--shred' requires an output file, with '--output' or '--suffix'".to_string(),
+            );
+        };
+        // Removing a link would leave its target, plaintext, behind.
+        let is_regular = fs::symlink_metadata(input).is_ok_and(|m| m.file_type().is_file());
+        if !is_regular {
+            return Err(format!(
+                "Cannot shred '{}', it is not a regular file",
+                input.display()
+            ));
+        }
+        if plan::is_same_file(input, output) {
+            return Err("'--shred' cannot be used with '--in-place'".to_string());
+        }
+        Ok(())
+    }
+
+    /// Parse an entropy source: `system`, `strict`, or a random device.
+    fn parse_entropy_source(source: &str) -> Result<entropy::Source, String> {
+        match source {
+            "system" => return Ok(entropy::Source::System),
+            "strict" => return Ok(entropy::Source::strict()),
+            _ => (),
+        }
+        // A regular file would hand out the same "random" bytes forever,
+        // and so would `/dev/zero` or a FIFO. Only known random devices.
+        match fs::canonicalize(source) {
+            Ok(path)
+                if RANDOM_DEVICES
+                    .iter()
+                    .any(|device| path == Path::new(device)) =>
+            {
+                Ok(entropy::Source::Device(PathBuf::from(source)))
+            }
+            _ => Err(format!(
+                "Invalid entropy source '{source}', expected 'system', 'strict', or a random device ({})",
+                RANDOM_DEVICES.join(", ")
+            )),
+        }
+    }
+
+    /// Parse a byte range: `start:end`, `start:`, or `:end`.
+    fn parse_range(range: &str) -> Result<Range<u64>, String> {
+        let invalid = || format!("Invalid range '{range}', expected 'start:end'");
+        let Some((start, end)) = range.split_once(':') else {
+            return Err(invalid());
+        };
+        let start = match start {
+            "" => 0,
+            start => start.parse().map_err(|_| invalid())?,
+        };
+        let end = match end {
+            "" => u64::MAX,
+            end => end.parse().map_err(|_| invalid())?,
+        };
+        if start > end {
+            return Err(format!(
+                "Invalid range '{range}', the start is after the end"
+            ));
+        }
+        Ok(start..end)
+    }
+
+    /// Parse a duration, like `90s`, `30m`, `48h`, `7d`, or `2w`.
+    fn parse_duration(duration: &str) -> Option<Duration> {
+        let duration = duration.trim();
+        let unit = match duration.chars().last()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        let n: u64 = duration[..duration.len() - 1].parse().ok()?;
+        Some(Duration::from_secs(n.checked_mul(unit)?))
+    }
+
+    /// `rekey` needs a new key, and works on directories in-place only.
+    ///
+    /// ROT-n and Brainfuck are not worth rotating keys for, and their
+    /// keys are fixed by the CLI anyway.
+    fn check_rekey(args: &mut Self, in_place: bool) -> Result<(), String> {
+        if let Some(ref new_key) = args.new_key
+            && let Some(content) = Self::maybe_get_key_from_file(new_key)
+        {
+            args.new_key = Some(content);
+        }
+        if let Some(ref new_key) = args.new_key
+            && let Some(new_key) = Self::use_foreign_key(new_key, &mut args.new_algorithm)?
+        {
+            args.new_key = Some(new_key);
+        }
+
+        let algorithm = args.algorithm.unwrap_or_default();
+        let new_algorithm = *args.new_algorithm.get_or_insert(algorithm);
+        for algorithm in [algorithm, new_algorithm] {
+            if algorithm.is_toy() {
+                return Err(format!("'rekey' cannot be used with {algorithm}"));
+            }
+        }
+
+        if args.new_key.is_none() {
+            return Err("'rekey' requires a new key, with '--new-key'".to_string());
+        }
+
+        if let Some(Message::File(ref input)) = args.message
+            && input.is_dir()
+            && !in_place
+        {
+            return Err(format!(
+                "Rekeying the directory '{}' requires '--in-place'",
+                input.display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// `migrate` re-encrypts with the same algorithm and key, and works
+    /// on directories in-place only.
+    ///
+    /// HPKE would need the public key too, that's what `rekey` is for.
+    /// age has its own format, and ROT-n and Brainfuck have none.
+    fn check_migrate(args: &mut Self, in_place: bool) -> Result<(), String> {
+        let algorithm = args.algorithm.unwrap_or_default();
+        match algorithm {
+            Algorithm::ChaCha20Poly1305 => (),
+            #[cfg(feature = "hpke")]
+            Algorithm::Hpke | Algorithm::HpkePq => {
+                return Err(format!(
+                    "'migrate' cannot be used with {algorithm}, use 'rekey' with the public key as '--new-key'"
+                ));
+            }
+            _ => return Err(format!("'migrate' cannot be used with {algorithm}")),
+        }
+        args.new_algorithm = Some(algorithm);
+
+        if let Some(Message::File(ref input)) = args.message
+            && input.is_dir()
+            && !in_place
+        {
+            return Err(format!(
+                "Migrating the directory '{}' requires '--in-place'",
+                input.display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// `keygen --protect` needs a passphrase, from the environment, or
+    /// typed in on the terminal.
+    fn check_protect(args: &Self, env: &dyn Environment) -> Result<(), String> {
+        if let Some(algorithm) = args.algorithm
+            && algorithm.is_toy()
+        {
+            return Err(format!("'--protect' cannot be used with {algorithm}"));
+        }
+        if env.var(PASSWORD_ENV_VAR).is_none() && !env.is_terminal(Stream::Stdin) {
+            return Err(format!(
+                "'--protect' requires a passphrase, in {PASSWORD_ENV_VAR}, or typed in on a terminal"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Take the key (and its algorithm) from the keyring.
+    ///
+    /// A named key replaces `--key` and the environment, so they can't
+    /// be mixed. The algorithm of the key is used, unless another one
+    /// is asked for explicitly, which is an error.
+    fn use_keyring(args: &mut Self, env: &dyn Environment) -> Result<(), String> {
+        let Some(name) = args.key_name.clone() else {
+            return Ok(());
+        };
+        if args.key.is_some() || args.prompt_key {
+            return Err("'--key-name' cannot be used with '--key'".to_string());
+        }
+        if args.password {
+            return Err("'--key-name' cannot be used with '--password'".to_string());
+        }
+
+        let entry = keyring::lookup(&name, env)?;
+        match (args.algorithm, entry.algorithm) {
+            (Some(given), Some(expected)) if given != expected => {
+                return Err(format!("Key '{name}' is for {expected}, not {given}"));
+            }
+            (None, expected) => args.algorithm = expected,
+            _ => (),
+        }
+
+        args.key = Some(entry.key);
+        args.key_source = KeySource::Keyring(name);
+        Ok(())
+    }
+
+    /// Take the passphrase as the key.
+    ///
+    /// The passphrase is given with `--key`, or in the environment. It
+    /// is used as-is, it is never read from a file. Without either, it
+    /// is typed in on the terminal, if there is one.
+    fn use_password(args: &mut Self, env: &dyn Environment) -> Result<(), String> {
+        if args.framed {
+            // Each frame would go through the (slow, by design) KDF.
+            return Err("'--password' cannot be used with '--framed'".to_string());
+        }
+        if args
+            .algorithm
+            .is_some_and(|a| !matches!(a, Algorithm::ChaCha20Poly1305 | Algorithm::Age))
+        {
+            return Err(format!(
+                "'--password' only works with {}, and {}",
+                Algorithm::ChaCha20Poly1305,
+                Algorithm::Age
+            ));
+        }
+        if args.key.is_none()
+            && !args.prompt_key
+            && let Some(password) = env.var(PASSWORD_ENV_VAR)
+        {
+            args.key = Some(SecretString::from(password));
+        }
+        match args.key {
+            Some(ref password) => args.key = Some(Self::encode_password(password)),
+            None if args.prompt_key || env.is_terminal(Stream::Stdin) => args.prompt_key = true,
+            None => {
+                return Err(format!(
+                    "'--password' requires a passphrase, with '--key' or in {PASSWORD_ENV_VAR}"
+                ));
+            }
+        }
+        args.key_source = KeySource::Password;
+        Ok(())
+    }
+
+    /// Like all keys, the passphrase is passed on in base64 (which also
+    /// keeps colons from being mistaken for an algorithm tag).
+    fn encode_password(password: &SecretString) -> SecretString {
+        SecretString::from(password.expose_secret().as_bytes().base64_encode())
+    }
+
+    /// Set the key typed in on the terminal (see `prompt_key`).
+    ///
+    /// It goes through the same normalization as keys given on the
+    /// command line, except that it is never a file.
+    pub fn set_prompted_key(&mut self, key: &SecretString) -> Result<(), String> {
+        let key = if self.password {
+            Self::encode_password(key)
+        } else if self.algorithm.is_some_and(Algorithm::is_rotn) {
+            Self::normalize_rotn_key_to_base64(key)?
+        } else if let Some(encoding) = self.encoding
+            && !key::is_protected(key.expose_secret())
+        {
+            Self::normalize_key_encoding(key, encoding)?
+        } else {
+            key.clone()
+        };
+        self.locked_key = !self.password && key::is_protected(key.expose_secret());
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Decrypt a key protected by a passphrase (see `locked_key`).
+    pub fn unlock_key(&mut self, passphrase: &SecretString) -> Result<(), String> {
+        let Some(ref key) = self.key else {
+            return Ok(());
+        };
+        let (algorithm_id, key) =
+            key::unprotect(key.expose_secret(), passphrase.expose_secret().as_bytes()).map_err(
+                |e| match e {
+                    Error::Decrypt => {
+                        "Could not unlock the key, the passphrase is likely wrong".to_string()
+                    }
+                    e => e.to_string(),
+                },
+            )?;
+        let key = base64::encode_key(&key);
+        self.key = Some(SecretString::from(key::tag(algorithm_id, &key)));
+        self.locked_key = false;
+        Ok(())
+    }
+
+    /// Convert an SSH or age key to a tagged key, `None` if it's not one.
+    ///
+    /// These keys belong to one algorithm (SSH keys are HPKE keys). They
+    /// select it if no algorithm is given, and are an error with any
+    /// other.
+    fn use_foreign_key(
+        key: &SecretString,
+        algorithm: &mut Option<Algorithm>,
+    ) -> Result<Option<SecretString>, String> {
+        type Convert = fn(&str) -> jolokia::traits::Result<Zeroizing<Vec<u8>>>;
+        let key = key.expose_secret();
+        let (format, kind, target, convert): (_, _, _, Convert) = match key {
+            #[cfg(feature = "hpke")]
+            _ if ssh::is_ssh_key(key) => {
+                ("SSH", "'ssh-ed25519'", Algorithm::Hpke, ssh::to_hpke_key)
+            }
+            _ if cipher::age::is_age_key(key) => {
+                ("age", "age", Algorithm::Age, cipher::age::decode_key)
+            }
+            _ => return Ok(None),
+        };
+
+        let algorithm = *algorithm.get_or_insert(target);
+        if algorithm != target {
+            return Err(format!(
+                "{format} keys can only be used with {target}, not {algorithm}"
+            ));
+        }
+        let key = convert(key).map_err(|e| match e {
+            Error::Key => format!("Not a valid {kind} key"),
+            e => e.to_string(),
+        })?;
+        let key = base64::encode_key(&key);
+        let cipher: Box<dyn Cipher> = target.into();
+        Ok(Some(SecretString::from(key::tag(
+            cipher.algorithm_id(),
+            &key,
+        ))))
+    }
+
+    /// Try to extract non empty key from potentially existing file.
+    ///
+    /// The file _must_ exist, _must_ be readable, and _must_ be
+    /// non-empty. If these conditions are met, the content of the file
+    /// is returned (trailing whitespace gets removed).
+    fn maybe_get_key_from_file(maybe_file: &SecretString) -> Option<SecretString> {
+        let maybe_file = Path::new(maybe_file.expose_secret());
+        if maybe_file.is_file()
+            && let Ok(key) = fs::read_to_string(maybe_file)
+        {
+            let key = key.trim_end();
+            if !key.is_empty() {
+                return Some(SecretString::from(key));
+            }
+        }
+        None
+    }
+
+    /// Convert a key in another encoding (`--encoding`) to base64.
+    ///
+    /// The tag, if any, is kept as-is.
+    fn normalize_key_encoding(
+        key: &SecretString,
+        encoding: TextEncoding,
+    ) -> Result<SecretString, String> {
+        if encoding == TextEncoding::Base64 {
+            return Ok(key.clone());
+        }
+        let (tag, key) = key::split_tag(key.expose_secret());
+        let Ok(key) = encoding
+            .encoding()
+            .decode(key.as_bytes())
+            .map(Zeroizing::new)
+        else {
+            return Err(format!("Not a valid {encoding} key"));
+        };
+        let key = base64::encode_key(&key);
+        Ok(SecretString::from(match tag {
+            Some(tag) => key::tag(tag, &key),
+            None => key.to_string(),
+        }))
+    }
+
+    /// Normalize ROT-n keys to base64.
+    ///
+    /// ROT-n keys are string representations of decimal numbers
+    /// (e.g, "13"). We want to normalize them into base64 so they work
     /// the same as all the other keys for all the other algorithms.
     fn normalize_rotn_key_to_base64(key: &SecretString) -> Result<SecretString, String> {
         let key = key.expose_secret();
@@ -252,24 +1638,6 @@ impl Args {
         let key = (&[key] as &[u8; 1]).base64_encode();
         Ok(SecretString::from(key))
     }
-
-    fn does_stdin_have_content() -> bool {
-        #![allow(unreachable_code)]
-        #[cfg(test)]
-        return false;
-        // If the descriptor/handle refers to a terminal/tty, there is
-        // nothing in stdin to be consumed.
-        !io::stdin().is_terminal()
-    }
-
-    fn is_output_redirected() -> bool {
-        #![allow(unreachable_code)]
-        #[cfg(test)]
-        return false;
-        // If the descriptor/handle refers to a terminal/tty, the output
-        // is not redirected to a file.
-        !io::stdout().is_terminal()
-    }
 }
 
 #[cfg(test)]
@@ -278,15 +1646,26 @@ mod tests {
 
     use super::*;
 
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/");
+
     #[test]
     fn command_keygen_regular() {
         let args = Args::build_from_args(["keygen"].iter()).unwrap();
         assert!(args.command.is_some_and(|c| c == Command::KeyGen));
     }
 
+    #[test]
+    fn errors_are_usage_errors() {
+        let err = Args::build_from_args(["encrypt", "--bits", "256"].iter()).unwrap_err();
+        assert!(matches!(err, CliError::Usage(_)));
+        assert_eq!(err.exit_code(), crate::cmd::error::EXIT_USAGE);
+    }
+
     #[test]
     fn second_command_does_not_override_keygen() {
-        let err = Args::build_from_args(["keygen", "encrypt"].iter()).unwrap_err();
+        let err = Args::build_from_args(["keygen", "encrypt"].iter())
+            .unwrap_err()
+            .to_string();
         assert!(err.contains("'encrypt'"));
     }
 
@@ -315,130 +1694,2162 @@ mod tests {
     }
 
     #[test]
-    fn default_algorithm() {
-        assert_eq!(Algorithm::default(), Algorithm::ChaCha20Poly1305);
+    fn command_rekey_regular() {
+        let args = Args::build_from_args(
+            [
+                "rekey",
+                "-k",
+                "old",
+                "--new-key",
+                "new",
+                "-f",
+                "foo.enc",
+                "-i",
+            ]
+            .iter(),
+        )
+        .unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Rekey));
+        assert!(args.key.is_some_and(|k| k.expose_secret() == "old"));
+        assert!(args.new_key.is_some_and(|k| k.expose_secret() == "new"));
+        assert_eq!(args.new_algorithm, Some(Algorithm::ChaCha20Poly1305));
+        assert_eq!(args.output, Output::File(PathBuf::from("foo.enc")));
     }
 
+    #[cfg(feature = "hpke")]
     #[test]
-    fn option_algorithm_default() {
-        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
-        assert!(args.algorithm.is_none());
+    fn command_rekey_new_algorithm() {
+        let args = Args::build_from_args(
+            [
+                "rekey",
+                "-a",
+                "chacha",
+                "--new-algorithm",
+                "hpke",
+                "--new-key",
+                "new",
+            ]
+            .iter(),
+        )
+        .unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::ChaCha20Poly1305));
+        assert_eq!(args.new_algorithm, Some(Algorithm::Hpke));
     }
 
     #[test]
-    fn option_short_algorithm_regular() {
-        let args = Args::build_from_args(["encrypt", "-a", "ChaCha20-Poly1305"].iter()).unwrap();
-        assert!(matches!(args.algorithm, Some(Algorithm::ChaCha20Poly1305)));
+    fn command_rekey_new_key_file() {
+        let file = format!("{FIXTURES}lorem.txt");
+        let args = Args::build_from_args(["rekey", "--new-key", &file].iter()).unwrap();
+        assert!(
+            args.new_key
+                .is_some_and(|k| k.expose_secret().starts_with("Lorem"))
+        );
     }
 
     #[test]
-    fn option_long_algorithm_regular() {
+    fn command_rekey_key_name() {
+        let args = Args::build_from_args_with_env(
+            ["rekey", "--key-name", "work", "--new-key", "new"].iter(),
+            &keyring_env(),
+        )
+        .unwrap();
+        assert_eq!(args.key_source, KeySource::Keyring("work".to_string()));
+        assert_eq!(args.new_algorithm, Some(Algorithm::ChaCha20Poly1305));
+    }
+
+    #[test]
+    fn command_rekey_without_new_key_is_error() {
+        let err = Args::build_from_args(["rekey", "-k", "old"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'rekey' requires a new key, with '--new-key'");
+    }
+
+    #[test]
+    fn command_rekey_insecure_algorithm_is_error() {
+        let err = Args::build_from_args(["rekey", "-a", "rot", "--new-key", "5"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'rekey' cannot be used with ROT-n");
+
+        let err =
+            Args::build_from_args(["rekey", "--new-algorithm", "bf", "--new-key", "new"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "'rekey' cannot be used with Brainfuck");
+    }
+
+    #[test]
+    fn command_rekey_directory_requires_in_place() {
+        let err = Args::build_from_args(["rekey", "--new-key", "new", "-f", FIXTURES].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            format!("Rekeying the directory '{FIXTURES}' requires '--in-place'")
+        );
+    }
+
+    #[test]
+    fn command_rekey_password_is_error() {
+        let err = Args::build_from_args(["rekey", "--new-key", "new", "--password"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unknown argument: '--password' (if it is the message, put it after '--')"
+        );
+    }
+
+    #[test]
+    fn command_migrate_regular() {
+        let file = format!("{FIXTURES}lorem.txt");
         let args =
-            Args::build_from_args(["encrypt", "--algorithm", "ChaCha20-Poly1305"].iter()).unwrap();
-        assert!(matches!(args.algorithm, Some(Algorithm::ChaCha20Poly1305)));
+            Args::build_from_args(["migrate", "-k", "key", "-f", &file, "-i"].iter()).unwrap();
+
+        assert!(args.command.is_some_and(|c| c == Command::Migrate));
+        assert_eq!(args.new_algorithm, Some(Algorithm::ChaCha20Poly1305));
+        assert!(args.new_key.is_none());
+        assert_eq!(args.output, Output::File(PathBuf::from(file)));
     }
 
     #[test]
-    fn option_key_default() {
-        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
-        assert!(args.key.is_none());
+    fn command_migrate_hpke_is_error() {
+        let err = Args::build_from_args(["migrate", "-a", "hpke"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "'migrate' cannot be used with HPKE, use 'rekey' with the public key as '--new-key'"
+        );
+
+        let err = Args::build_from_args(["migrate", "-a", "rot"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'migrate' cannot be used with ROT-n");
     }
 
     #[test]
-    fn option_short_key_regular() {
-        let args = Args::build_from_args(["encrypt", "-k", "abcdef"].iter()).unwrap();
-        assert!(args.key.is_some_and(|k| k.expose_secret() == "abcdef"));
+    fn command_migrate_directory_requires_in_place() {
+        let err = Args::build_from_args(["migrate", "-f", FIXTURES].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            format!("Migrating the directory '{FIXTURES}' requires '--in-place'")
+        );
     }
 
     #[test]
-    fn option_long_key_regular() {
-        let args = Args::build_from_args(["encrypt", "--key", "abcdef"].iter()).unwrap();
-        assert!(args.key.is_some_and(|k| k.expose_secret() == "abcdef"));
+    fn command_migrate_new_key_is_error() {
+        let err = Args::build_from_args(["migrate", "--new-key", "new"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unknown argument: '--new-key' (if it is the message, put it after '--')"
+        );
     }
 
     #[test]
-    fn option_raw_default() {
-        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
-        assert!(!args.raw);
+    fn new_key_only_for_rekey() {
+        let err = Args::build_from_args(["encrypt", "--new-key", "new"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unknown argument: '--new-key' (if it is the message, put it after '--')"
+        );
     }
 
     #[test]
-    fn option_short_raw_regular() {
-        let args = Args::build_from_args(["encrypt", "-r"].iter()).unwrap();
-        assert!(args.raw);
+    fn command_doctor_regular() {
+        let args = Args::build_from_args(["doctor"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Doctor));
     }
 
     #[test]
-    fn option_long_raw_regular() {
-        let args = Args::build_from_args(["encrypt", "--raw"].iter()).unwrap();
-        assert!(args.raw);
+    fn command_inspect_regular() {
+        let args = Args::build_from_args(["inspect", "-r", "-f", "foo.enc"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Inspect));
+        assert_eq!(args.armor, Armor::Raw);
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("foo.enc"))));
     }
 
     #[test]
-    fn option_output_default() {
-        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
-        assert_eq!(args.output, Output::Stdout);
+    fn command_sign_regular() {
+        let args = Args::build_from_args(["sign", "-k", "key", "-f", "foo.txt"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Sign));
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("foo.txt"))));
     }
 
     #[test]
-    fn option_short_output_regular() {
-        let args = Args::build_from_args(["encrypt", "-o", "out.enc"].iter()).unwrap();
-        assert_eq!(args.output, Output::File(PathBuf::from("out.enc")));
+    fn command_sign_keygen() {
+        let args = Args::build_from_args(["sign", "--keygen"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Sign));
+        assert!(args.signing_keygen);
     }
 
     #[test]
-    fn option_long_output_regular() {
-        let args = Args::build_from_args(["encrypt", "--output", "out.enc"].iter()).unwrap();
-        assert_eq!(args.output, Output::File(PathBuf::from("out.enc")));
+    fn command_sign_without_key_is_error() {
+        let err = Args::build_from_args(["sign", "hello"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'sign' requires a key, with '--key'");
     }
 
     #[test]
-    fn option_message_default() {
-        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
-        assert!(args.message.is_none());
+    fn command_sign_does_not_use_key_from_env() {
+        let env = environment::Fake::default().with_var(KEY_ENV_VAR, "from-env");
+        let err = Args::build_from_args_with_env(["sign", "hello"].iter(), &env)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'sign' requires a key, with '--key'");
     }
 
     #[test]
-    fn option_short_file_regular() {
-        let args = Args::build_from_args(["encrypt", "-f", "in.txt"].iter()).unwrap();
-        assert_eq!(args.message, Some(Message::File(PathBuf::from("in.txt"))));
+    fn command_sign_cipher_option_is_error() {
+        let err = Args::build_from_args(["sign", "-k", "key", "hello", "--raw"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'--raw'"));
+        let err = Args::build_from_args(["verify", "-k", "key", "hello", "--password"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'--password'"));
     }
 
     #[test]
-    fn option_long_file_regular() {
-        let args = Args::build_from_args(["encrypt", "--file", "in.txt"].iter()).unwrap();
-        assert_eq!(args.message, Some(Message::File(PathBuf::from("in.txt"))));
+    fn command_check_regular() {
+        let args = Args::build_from_args(["check", "-k", "key", "-f", "foo.enc"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Check));
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("foo.enc"))));
     }
 
     #[test]
-    fn option_short_help_regular() {
-        let args = Args::build_from_args(["-h"].iter()).unwrap();
-        assert!(args.short_help);
-        assert!(!args.long_help);
+    fn command_check_has_no_output() {
+        let args = Args::build_from_args(["check", "-f", "foo.enc", "-o", "out.txt"].iter());
+        assert!(args.is_err());
+        let args = Args::build_from_args(["check", "-f", "foo.enc", "--dry-run"].iter());
+        assert!(args.is_err());
     }
 
     #[test]
-    fn option_long_help_regular() {
-        let args = Args::build_from_args(["--help"].iter()).unwrap();
-        assert!(!args.short_help);
-        assert!(args.long_help);
+    fn command_check_age_without_key_is_error() {
+        let err = Args::build_from_args(["check", "-a", "age", "-f", "foo.age"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no default key"), "{err}");
     }
 
     #[test]
-    fn option_short_version_regular() {
-        let args = Args::build_from_args(["-V"].iter()).unwrap();
-        assert!(args.version);
+    fn command_verify_regular() {
+        let args =
+            Args::build_from_args(["verify", "-k", "key", "--signature", "sig", "hello"].iter())
+                .unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Verify));
+        assert_eq!(args.signature.as_deref(), Some("sig"));
+        assert_eq!(args.message, Some(Message::String("hello".to_string())));
     }
 
     #[test]
-    fn option_long_version_regular() {
-        let args = Args::build_from_args(["--version"].iter()).unwrap();
-        assert!(args.version);
+    fn command_verify_signature_from_file() {
+        let file = format!("{FIXTURES}lorem.txt");
+        let args =
+            Args::build_from_args(["verify", "-k", "key", "--signature", &file].iter()).unwrap();
+        let content = fs::read_to_string(&file).unwrap();
+        assert_eq!(args.signature.as_deref(), Some(content.trim()));
+    }
+
+    #[test]
+    fn command_verify_without_signature_is_error() {
+        let err = Args::build_from_args(["verify", "-k", "key", "hello"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'verify' requires a signature, with '--signature'");
+    }
+
+    #[test]
+    fn unknown_option_is_not_the_message() {
+        let err = Args::build_from_args(["encrypt", "--ouput", "out.txt", "hello"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unknown argument: '--ouput' (if it is the message, put it after '--')"
+        );
+    }
+
+    #[test]
+    fn unknown_option_after_the_message() {
+        let err = Args::build_from_args(["encrypt", "hello", "--ouput"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Unknown argument: '--ouput'");
+    }
+
+    #[test]
+    fn message_after_end_of_options() {
+        let args = Args::build_from_args(["encrypt", "-r", "--", "-n"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Raw);
+        assert_eq!(args.message, Some(Message::String("-n".to_string())));
+    }
+
+    #[test]
+    fn options_after_end_of_options_are_positional() {
+        let args = Args::build_from_args(["encrypt", "--", "--raw"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Auto);
+        assert_eq!(args.message, Some(Message::String("--raw".to_string())));
+    }
+
+    #[test]
+    fn aad_after_end_of_options() {
+        let args =
+            Args::build_from_args(["encrypt", "-f", "foo.txt", "--", "-ctx"].iter()).unwrap();
+        assert_eq!(args.aad.as_deref(), Some("-ctx"));
+    }
+
+    #[test]
+    fn dash_is_a_message() {
+        let args = Args::build_from_args(["encrypt", "-"].iter()).unwrap();
+        assert_eq!(args.message, Some(Message::String("-".to_string())));
+    }
+
+    #[test]
+    fn command_man_regular() {
+        let args = Args::build_from_args(["man"].iter()).unwrap();
+        assert!(args.command.is_some_and(|c| c == Command::Man));
+    }
+
+    #[test]
+    fn default_algorithm() {
+        assert_eq!(Algorithm::default(), Algorithm::ChaCha20Poly1305);
+    }
+
+    #[test]
+    fn option_algorithm_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert!(args.algorithm.is_none());
+    }
+
+    #[test]
+    fn option_short_algorithm_regular() {
+        let args = Args::build_from_args(["encrypt", "-a", "ChaCha20-Poly1305"].iter()).unwrap();
+        assert!(matches!(args.algorithm, Some(Algorithm::ChaCha20Poly1305)));
+    }
+
+    #[test]
+    fn option_long_algorithm_regular() {
+        let args =
+            Args::build_from_args(["encrypt", "--algorithm", "ChaCha20-Poly1305"].iter()).unwrap();
+        assert!(matches!(args.algorithm, Some(Algorithm::ChaCha20Poly1305)));
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn option_algorithm_hpke_pq() {
+        for name in ["hpke-pq", "HPKE-PQ", "xwing"] {
+            let args = Args::build_from_args(["keygen", "-a", name].iter()).unwrap();
+            assert_eq!(args.algorithm, Some(Algorithm::HpkePq));
+        }
+    }
+
+    #[test]
+    fn option_algorithm_hpke_pq_without_key_is_error() {
+        let err = Args::build_from_args(["encrypt", "-a", "hpke-pq", "hello"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "HPKE-PQ has no default key, generate one with 'keygen -a hpke-pq'"
+        );
+    }
+
+    #[test]
+    fn option_algorithm_age() {
+        let args = Args::build_from_args(["keygen", "-a", "age"].iter()).unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::Age));
+    }
+
+    #[test]
+    fn option_algorithm_age_without_key_is_error() {
+        let err = Args::build_from_args(["encrypt", "-a", "age", "hello"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "age has no default key, generate one with 'keygen -a age'"
+        );
+    }
+
+    #[test]
+    fn option_algorithm_age_password() {
+        let args = Args::build_from_args(["encrypt", "-a", "age", "--password", "-k", "pw"].iter())
+            .unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::Age));
+        assert!(args.password);
+    }
+
+    #[test]
+    fn option_algorithm_age_expire_is_error() {
+        let err =
+            Args::build_from_args(["encrypt", "-a", "age", "-k", "abc=", "--expire", "1d"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "'--expire' cannot be used with age");
+    }
+
+    #[test]
+    fn algorithm_kinds() {
+        #[cfg(feature = "rotn")]
+        assert!(Algorithm::RotN.is_toy() && Algorithm::RotN.is_rotn());
+        #[cfg(feature = "brainfuck")]
+        assert!(Algorithm::Brainfuck.is_toy() && !Algorithm::Brainfuck.is_rotn());
+        assert!(!Algorithm::ChaCha20Poly1305.is_toy());
+
+        #[cfg(feature = "hpke")]
+        assert!(Algorithm::Hpke.is_asymmetric() && Algorithm::HpkePq.is_asymmetric());
+        assert!(Algorithm::Age.is_asymmetric());
+        assert!(!Algorithm::ChaCha20Poly1305.is_asymmetric());
+        #[cfg(feature = "rotn")]
+        assert!(!Algorithm::RotN.is_asymmetric());
+    }
+
+    #[test]
+    fn option_bits_default() {
+        let args = Args::build_from_args(["keygen"].iter()).unwrap();
+        assert!(args.keygen_options.bits.is_none());
+    }
+
+    #[test]
+    fn option_bits_regular() {
+        let args = Args::build_from_args(["keygen", "--bits", "256"].iter()).unwrap();
+        assert_eq!(args.keygen_options.bits, Some(256));
+    }
+
+    #[test]
+    fn option_bits_only_for_keygen() {
+        let err = Args::build_from_args(["encrypt", "--bits", "256"].iter());
+        assert!(err.unwrap_err().to_string().contains("'--bits'"));
+    }
+
+    #[test]
+    fn option_params_regular() {
+        let args =
+            Args::build_from_args(["keygen", "--params", "m=64, t=3", "--params", "p=1"].iter())
+                .unwrap();
+        assert_eq!(
+            args.keygen_options.params,
+            [
+                ("m".to_string(), "64".to_string()),
+                ("t".to_string(), "3".to_string()),
+                ("p".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn option_params_invalid_is_error() {
+        let err = Args::build_from_args(["keygen", "--params", "m=64,t"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'t'"));
+    }
+
+    #[test]
+    fn option_key_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert!(args.key.is_none());
+    }
+
+    #[test]
+    fn option_short_key_regular() {
+        let args = Args::build_from_args(["encrypt", "-k", "abcdef"].iter()).unwrap();
+        assert!(args.key.is_some_and(|k| k.expose_secret() == "abcdef"));
+    }
+
+    #[test]
+    fn option_long_key_regular() {
+        let args = Args::build_from_args(["encrypt", "--key", "abcdef"].iter()).unwrap();
+        assert!(args.key.is_some_and(|k| k.expose_secret() == "abcdef"));
+    }
+
+    fn keyring_env() -> environment::Fake {
+        environment::Fake::default()
+            .with_var("XDG_CONFIG_HOME", &format!("{FIXTURES}config"))
+            .with_var("HOME", FIXTURES)
+    }
+
+    #[test]
+    fn option_key_name_regular() {
+        let args = Args::build_from_args_with_env(
+            ["encrypt", "--key-name", "work"].iter(),
+            &keyring_env(),
+        )
+        .unwrap();
+        assert!(
+            args.key
+                .is_some_and(|k| k.expose_secret() == "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ")
+        );
+        assert_eq!(args.algorithm, Some(Algorithm::ChaCha20Poly1305));
+        assert_eq!(args.key_source, KeySource::Keyring("work".to_string()));
+    }
+
+    #[test]
+    fn option_key_name_over_env() {
+        let env = keyring_env().with_var(KEY_ENV_VAR, "from-env");
+        let args =
+            Args::build_from_args_with_env(["decrypt", "--key-name", "work"].iter(), &env).unwrap();
+        assert_eq!(args.key_source, KeySource::Keyring("work".to_string()));
+    }
+
+    #[test]
+    fn option_key_name_file_in_home() {
+        let args = Args::build_from_args_with_env(
+            ["encrypt", "--key-name", "home-file"].iter(),
+            &keyring_env(),
+        )
+        .unwrap();
+        assert!(
+            args.key
+                .is_some_and(|k| k.expose_secret().starts_with("Lorem"))
+        );
+        assert_eq!(args.algorithm, None);
+        assert_eq!(args.key_source, KeySource::Keyring("home-file".to_string()));
+    }
+
+    #[test]
+    fn option_key_name_with_key_is_error() {
+        let err = Args::build_from_args_with_env(
+            ["encrypt", "--key-name", "work", "-k", "abcdef"].iter(),
+            &keyring_env(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(err, "'--key-name' cannot be used with '--key'");
+    }
+
+    #[test]
+    fn option_key_name_with_password_is_error() {
+        let err = Args::build_from_args_with_env(
+            ["encrypt", "--key-name", "work", "--password"].iter(),
+            &keyring_env(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(err, "'--key-name' cannot be used with '--password'");
+    }
+
+    #[test]
+    fn option_key_name_other_algorithm_is_error() {
+        let err = Args::build_from_args_with_env(
+            ["encrypt", "--key-name", "work", "-a", "hpke"].iter(),
+            &keyring_env(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(err, "Key 'work' is for ChaCha20-Poly1305, not HPKE");
+    }
+
+    #[test]
+    fn option_key_name_unknown_is_error() {
+        let err = Args::build_from_args_with_env(
+            ["encrypt", "--key-name", "nope"].iter(),
+            &keyring_env(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.starts_with("No key named 'nope' in the keyring"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn option_key_name_without_home_is_error() {
+        let err = Args::build_from_args(["encrypt", "--key-name", "work"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Cannot locate the keyring, 'HOME' is not set");
+    }
+
+    #[test]
+    fn option_raw_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Auto);
+    }
+
+    #[test]
+    fn option_short_raw_regular() {
+        let args = Args::build_from_args(["encrypt", "-r"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Raw);
+    }
+
+    #[test]
+    fn option_long_raw_regular() {
+        let args = Args::build_from_args(["encrypt", "--raw"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Raw);
+    }
+
+    #[test]
+    fn option_base64_regular() {
+        let args = Args::build_from_args(["encrypt", "--base64"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Base64);
+    }
+
+    #[test]
+    fn option_raw_and_base64_last_wins() {
+        let args = Args::build_from_args(["encrypt", "--raw", "--base64"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Base64);
+
+        let args = Args::build_from_args(["decrypt", "--base64", "-r"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Raw);
+    }
+
+    #[test]
+    fn option_raw_default_for_rotn() {
+        let args = Args::build_from_args(["encrypt", "-a", "rot"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Raw);
+    }
+
+    #[test]
+    fn option_base64_for_rotn() {
+        let args = Args::build_from_args(["encrypt", "-a", "rot", "--base64"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Base64);
+    }
+
+    #[test]
+    fn option_armor_regular() {
+        let args = Args::build_from_args(["encrypt", "--armor"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Armored);
+
+        let args = Args::build_from_args(["encrypt", "--armor", "--raw"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Raw);
+    }
+
+    #[test]
+    fn option_armor_decrypt_is_error() {
+        let err = Args::build_from_args(["decrypt", "--armor"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'--armor'"));
+    }
+
+    #[test]
+    fn option_armor_framed_is_error() {
+        let err = Args::build_from_args(["encrypt", "--armor", "--framed"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--armor' cannot be used with '--framed'");
+    }
+
+    #[test]
+    fn option_framed_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert!(!args.framed);
+    }
+
+    #[test]
+    fn option_framed_regular() {
+        let args = Args::build_from_args(["decrypt", "--framed"].iter()).unwrap();
+        assert!(args.framed);
+    }
+
+    #[test]
+    fn option_mmap_regular() {
+        let args = Args::build_from_args(["encrypt", "-f", "foo", "--mmap"].iter()).unwrap();
+        assert!(args.mmap);
+        let args = Args::build_from_args(["decrypt", "--mmap"].iter()).unwrap();
+        assert!(args.mmap);
+    }
+
+    #[test]
+    fn option_mmap_not_for_rekey() {
+        let err = Args::build_from_args(["rekey", "--mmap"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Unknown argument: '--mmap'"), "{err}");
+    }
+
+    #[test]
+    fn option_legacy_regular() {
+        for command in [&["decrypt"][..], &["rekey", "--new-key", "abc"], &["check"]] {
+            let args = Args::build_from_args([command, &["--legacy"]].concat().iter()).unwrap();
+            assert!(args.legacy);
+        }
+        let args = Args::build_from_args(["decrypt"].iter()).unwrap();
+        assert!(!args.legacy);
+    }
+
+    #[test]
+    fn option_legacy_not_for_migrate() {
+        let err = Args::build_from_args(["migrate", "--legacy"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Unknown argument: '--legacy'"), "{err}");
+    }
+
+    #[test]
+    fn option_continue_on_error_regular() {
+        let args =
+            Args::build_from_args(["decrypt", "--framed", "--continue-on-error"].iter()).unwrap();
+        assert!(args.continue_on_error);
+    }
+
+    #[test]
+    fn option_continue_on_error_requires_framed() {
+        let err = Args::build_from_args(["decrypt", "--continue-on-error"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--continue-on-error' requires '--framed'");
+    }
+
+    #[test]
+    fn option_in_place_after_file() {
+        let args = Args::build_from_args(["encrypt", "-f", "in.txt", "-i"].iter()).unwrap();
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("in.txt"))));
+        assert_eq!(args.output, Output::File(PathBuf::from("in.txt")));
+    }
+
+    #[test]
+    fn option_in_place_before_file() {
+        let args = Args::build_from_args(["encrypt", "-i", "-f", "in.txt"].iter()).unwrap();
+        assert_eq!(args.output, Output::File(PathBuf::from("in.txt")));
+
+        let args = Args::build_from_args(["decrypt", "--in-place", "-f", "in.txt"].iter()).unwrap();
+        assert_eq!(args.output, Output::File(PathBuf::from("in.txt")));
+    }
+
+    #[test]
+    fn option_in_place_without_file_is_error() {
+        let err = Args::build_from_args(["encrypt", "-i", "hello"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--in-place' requires an input file, with '--file'");
+    }
+
+    #[test]
+    fn option_in_place_with_output_is_error() {
+        let err = Args::build_from_args(["encrypt", "-o", "out.enc", "-i", "-f", "in.txt"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--in-place' cannot be used with '--output'");
+
+        let err = Args::build_from_args(["encrypt", "-f", "in.txt", "-i", "-o", "out.enc"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--in-place' cannot be used with '--output'");
+    }
+
+    #[test]
+    fn option_in_place_keygen_is_error() {
+        let err = Args::build_from_args(["keygen", "-i"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Unknown argument: '-i'");
+    }
+
+    #[test]
+    fn option_output_directory_encrypt() {
+        let args =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "-o", FIXTURES].iter()).unwrap();
+        assert_eq!(
+            args.output,
+            Output::File(PathBuf::from(FIXTURES).join("in.txt.enc"))
+        );
+    }
+
+    #[test]
+    fn option_output_directory_decrypt() {
+        let args =
+            Args::build_from_args(["decrypt", "-f", "dir/in.txt.enc", "-o", FIXTURES].iter())
+                .unwrap();
+        assert_eq!(
+            args.output,
+            Output::File(PathBuf::from(FIXTURES).join("in.txt"))
+        );
+
+        // Nothing to remove, the name is kept.
+        let args =
+            Args::build_from_args(["decrypt", "-f", "in.bin", "-o", FIXTURES].iter()).unwrap();
+        assert_eq!(
+            args.output,
+            Output::File(PathBuf::from(FIXTURES).join("in.bin"))
+        );
+    }
+
+    #[test]
+    fn option_output_directory_without_trailing_slash() {
+        let fixtures = FIXTURES.trim_end_matches('/');
+        let args =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "-o", fixtures].iter()).unwrap();
+        assert_eq!(
+            args.output,
+            Output::File(PathBuf::from(fixtures).join("in.txt.enc"))
+        );
+    }
+
+    #[test]
+    fn option_output_missing_directory_is_error() {
+        let err =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "-o", "does/not/exist/"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "Output directory 'does/not/exist/' does not exist");
+    }
+
+    #[test]
+    fn option_output_directory_without_input_file_is_error() {
+        let err = Args::build_from_args(["encrypt", "hello", "-o", FIXTURES].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            format!("Cannot name the output in '{FIXTURES}', there is no input file")
+        );
+    }
+
+    #[test]
+    fn option_output_existing_file_is_error() {
+        let existing = format!("{FIXTURES}lorem.txt");
+        let err = Args::build_from_args(["encrypt", "-f", "in.txt", "-o", &existing].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            format!("'{existing}' already exists, use '--force' to overwrite it")
+        );
+    }
+
+    #[test]
+    fn option_output_existing_file_in_directory_is_error() {
+        let err = Args::build_from_args(["decrypt", "-f", "lorem.txt.enc", "-o", FIXTURES].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("already exists"), "{err}");
+    }
+
+    #[test]
+    fn option_output_existing_file_with_force() {
+        let existing = format!("{FIXTURES}lorem.txt");
+        let args =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "-o", &existing, "--force"].iter())
+                .unwrap();
+        assert!(args.force);
+        assert_eq!(args.output, Output::File(PathBuf::from(existing)));
+    }
+
+    #[test]
+    fn option_output_existing_file_is_input() {
+        let existing = format!("{FIXTURES}lorem.txt");
+        let args =
+            Args::build_from_args(["encrypt", "-f", &existing, "-o", &existing].iter()).unwrap();
+        assert!(!args.force);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn option_output_device_is_not_protected() {
+        let args = Args::build_from_args(["encrypt", "-f", "in.txt", "-o", "/dev/null"].iter());
+        assert!(args.is_ok());
+    }
+
+    #[test]
+    fn option_suffix_encrypt_next_to_input() {
+        let args =
+            Args::build_from_args(["encrypt", "-f", "dir/photo.jpg", "--suffix", ".age"].iter())
+                .unwrap();
+        assert_eq!(args.suffix.as_deref(), Some(".age"));
+        assert_eq!(
+            args.output,
+            Output::File(PathBuf::from("dir/photo.jpg.age"))
+        );
+    }
+
+    #[test]
+    fn option_suffix_decrypt_strips_suffix() {
+        let args =
+            Args::build_from_args(["decrypt", "-f", "photo.jpg.age", "--suffix", ".age"].iter())
+                .unwrap();
+        assert_eq!(args.output, Output::File(PathBuf::from("photo.jpg")));
+    }
+
+    #[test]
+    fn option_suffix_decrypt_without_suffix_is_error() {
+        let err = Args::build_from_args(["decrypt", "-f", "photo.jpg", "--suffix", ".age"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Cannot name the output, 'photo.jpg' does not end with '.age'"
+        );
+    }
+
+    #[test]
+    fn option_suffix_with_output_directory() {
+        let args = Args::build_from_args(
+            [
+                "encrypt", "-f", "in.txt", "--suffix", ".age", "-o", FIXTURES,
+            ]
+            .iter(),
+        )
+        .unwrap();
+        assert_eq!(
+            args.output,
+            Output::File(PathBuf::from(FIXTURES).join("in.txt.age"))
+        );
+    }
+
+    #[test]
+    fn option_suffix_with_output_file_is_error() {
+        let err = Args::build_from_args(
+            [
+                "encrypt", "-f", "in.txt", "--suffix", ".age", "-o", "out.age",
+            ]
+            .iter(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(
+            err,
+            "'--suffix' cannot be used with an output file, only a directory"
+        );
+    }
+
+    #[test]
+    fn option_suffix_in_place_is_error() {
+        let err =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "-i", "--suffix", ".age"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "'--suffix' cannot be used with '--in-place'");
+    }
+
+    #[test]
+    fn option_suffix_requires_file() {
+        let err = Args::build_from_args(["encrypt", "hello", "--suffix", ".age"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--suffix' requires an input file, with '--file'");
+    }
+
+    #[test]
+    fn option_suffix_invalid_is_error() {
+        for suffix in ["", "/enc", "a/b"] {
+            let err = Args::build_from_args(["encrypt", "-f", "in.txt", "--suffix", suffix].iter())
+                .unwrap_err()
+                .to_string();
+            assert_eq!(err, format!("Invalid suffix '{suffix}'"));
+        }
+    }
+
+    #[test]
+    fn option_shred_regular() {
+        let lorem = format!("{FIXTURES}lorem.txt");
+        let args =
+            Args::build_from_args(["encrypt", "-f", &lorem, "-o", "out.enc", "--shred"].iter())
+                .unwrap();
+        assert!(args.shred);
+    }
+
+    #[test]
+    fn option_shred_only_for_encrypt() {
+        let lorem = format!("{FIXTURES}lorem.txt");
+        let args =
+            Args::build_from_args(["decrypt", "-f", &lorem, "-o", "out.txt", "--shred"].iter());
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn option_shred_in_place_is_error() {
+        let lorem = format!("{FIXTURES}lorem.txt");
+        let err = Args::build_from_args(["encrypt", "-f", &lorem, "-i", "--shred"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--shred' cannot be used with '--in-place'");
+
+        let err = Args::build_from_args(["encrypt", "-f", &lorem, "-o", &lorem, "--shred"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--shred' cannot be used with '--in-place'");
+    }
+
+    #[test]
+    fn option_shred_requires_input_file() {
+        let err = Args::build_from_args(["encrypt", "hello", "-o", "out.enc", "--shred"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--shred' requires an input file, with '--file'");
+    }
+
+    #[test]
+    fn option_shred_requires_output_file() {
+        let lorem = format!("{FIXTURES}lorem.txt");
+        let err = Args::build_from_args(["encrypt", "-f", &lorem, "--shred"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "'--shred' requires an output file, with '--output' or '--suffix'"
+        );
+    }
+
+    #[test]
+    fn option_shred_missing_file_is_error() {
+        let err =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "-o", "out.enc", "--shred"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "Cannot shred 'in.txt', it is not a regular file");
+    }
+
+    #[test]
+    fn option_suffix_only_for_encrypt_and_decrypt() {
+        let args = Args::build_from_args(["rekey", "-f", "in.txt", "--suffix", ".age"].iter());
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn option_output_file_is_kept() {
+        let args =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "-o", "out.enc"].iter()).unwrap();
+        assert_eq!(args.output, Output::File(PathBuf::from("out.enc")));
+    }
+
+    #[test]
+    fn option_short_yes_regular() {
+        let args = Args::build_from_args(["encrypt", "-y"].iter()).unwrap();
+        assert!(args.yes);
+    }
+
+    #[test]
+    fn option_long_yes_regular() {
+        let args = Args::build_from_args(["encrypt", "--yes"].iter()).unwrap();
+        assert!(args.yes);
+    }
+
+    #[test]
+    fn option_pre_filter_regular() {
+        let args = Args::build_from_args(["encrypt", "--pre-filter", "gzip"].iter()).unwrap();
+        assert_eq!(args.pre_filter.as_deref(), Some("gzip"));
+    }
+
+    #[test]
+    fn option_pre_filter_missing_command() {
+        let err = Args::build_from_args(["encrypt", "--pre-filter"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Expected command after '--pre-filter'");
+    }
+
+    #[test]
+    fn option_post_filter_regular() {
+        let args = Args::build_from_args(["decrypt", "--post-filter", "gunzip"].iter()).unwrap();
+        assert_eq!(args.post_filter.as_deref(), Some("gunzip"));
+    }
+
+    #[test]
+    fn option_post_filter_with_peek_is_error() {
+        let err =
+            Args::build_from_args(["decrypt", "--post-filter", "gunzip", "--peek", "8"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "'--post-filter' cannot be used with '--peek'");
+    }
+
+    #[test]
+    fn option_confirm_then_clear_regular() {
+        let args = Args::build_from_args(["keygen", "--confirm-then-clear"].iter()).unwrap();
+        assert!(args.confirm_then_clear);
+    }
+
+    #[test]
+    fn option_protect_regular() {
+        let args = Args::build_from_args(["keygen", "-a", "hpke", "--protect"].iter()).unwrap();
+        assert!(args.protect);
+    }
+
+    #[test]
+    fn option_protect_requires_passphrase() {
+        let env = environment::Fake {
+            stdin_terminal: false,
+            ..Default::default()
+        };
+        let err = Args::build_from_args_with_env(["keygen", "--protect"].iter(), &env)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "'--protect' requires a passphrase, in JOLOKIA_PASSWORD, or typed in on a terminal"
+        );
+
+        let env = env.with_var(PASSWORD_ENV_VAR, "correct horse");
+        assert!(Args::build_from_args_with_env(["keygen", "--protect"].iter(), &env).is_ok());
+    }
+
+    #[test]
+    fn option_protect_insecure_algorithm_is_error() {
+        let err = Args::build_from_args(["keygen", "-a", "rot", "--protect"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--protect' cannot be used with ROT-n");
+    }
+
+    #[test]
+    fn option_protect_only_for_keygen() {
+        let err = Args::build_from_args(["encrypt", "--protect"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unknown argument: '--protect' (if it is the message, put it after '--')"
+        );
+    }
+
+    #[test]
+    fn option_public_output_regular() {
+        let args = Args::build_from_args(
+            [
+                "keygen",
+                "-a",
+                "hpke",
+                "-o",
+                "private.key",
+                "--public-output",
+                "public.key",
+            ]
+            .iter(),
+        )
+        .unwrap();
+        assert_eq!(args.public_output, Some(PathBuf::from("public.key")));
+    }
+
+    #[test]
+    fn option_public_output_symmetric_algorithm_is_error() {
+        let err = Args::build_from_args(
+            [
+                "keygen",
+                "-o",
+                "private.key",
+                "--public-output",
+                "public.key",
+            ]
+            .iter(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(
+            err,
+            "'--public-output' cannot be used with ChaCha20-Poly1305"
+        );
+    }
+
+    #[test]
+    fn option_public_output_requires_output() {
+        let err =
+            Args::build_from_args(["keygen", "-a", "age", "--public-output", "public.key"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(
+            err,
+            "'--public-output' requires an output file for the private key, with '--output'"
+        );
+    }
+
+    #[test]
+    fn option_qr_regular() {
+        let args = Args::build_from_args(["keygen", "--qr"].iter()).unwrap();
+        assert!(args.qr);
+
+        let args = Args::build_from_args(["encrypt", "--qr", "hello"].iter()).unwrap();
+        assert!(args.qr);
+    }
+
+    #[test]
+    fn option_qr_only_for_keygen_and_encrypt() {
+        let err = Args::build_from_args(["decrypt", "--qr"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'--qr'"));
+    }
+
+    #[test]
+    fn option_qr_is_base64() {
+        for option in ["--raw", "--base64", "--armor", "--framed"] {
+            let err = Args::build_from_args(["encrypt", "--qr", option, "hello"].iter())
+                .unwrap_err()
+                .to_string();
+            assert_eq!(err, format!("'--qr' cannot be used with '{option}'"));
+        }
+    }
+
+    #[test]
+    fn option_qr_rot_is_not_raw() {
+        let args = Args::build_from_args(["encrypt", "-a", "rot", "--qr", "hello"].iter()).unwrap();
+        assert_eq!(args.armor, Armor::Auto);
+    }
+
+    #[test]
+    fn option_encoding_regular() {
+        let args = Args::build_from_args(["encrypt", "--encoding", "hex", "hello"].iter()).unwrap();
+        assert_eq!(args.encoding, Some(TextEncoding::Hex));
+
+        let args = Args::build_from_args(["keygen", "--encoding", "Base32"].iter()).unwrap();
+        assert_eq!(args.encoding, Some(TextEncoding::Base32));
+    }
+
+    #[test]
+    fn option_encoding_unknown_is_error() {
+        let err = Args::build_from_args(["encrypt", "--encoding", "base58"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unrecognized encoding 'base58', expected base64, hex, or base32"
+        );
+    }
+
+    #[test]
+    fn option_encoding_is_text() {
+        for option in ["--raw", "--base64", "--armor", "--framed", "--qr"] {
+            let err =
+                Args::build_from_args(["encrypt", "--encoding", "hex", option, "hello"].iter())
+                    .unwrap_err()
+                    .to_string();
+            assert_eq!(err, format!("'--encoding' cannot be used with '{option}'"));
+        }
+    }
+
+    #[test]
+    fn option_encoding_age_keygen_is_error() {
+        let err = Args::build_from_args(["keygen", "-a", "age", "--encoding", "hex"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--encoding' cannot be used with age keys");
+    }
+
+    #[test]
+    fn option_encoding_converts_key_to_base64() {
+        let key = format!("chacha20poly1305:{}", "00".repeat(32));
+        let args =
+            Args::build_from_args(["decrypt", "--encoding", "hex", "-k", &key, "hello"].iter())
+                .unwrap();
+
+        let expected = key::tag("chacha20poly1305", &(&[0u8; 32]).base64_encode());
+        assert_eq!(args.key.unwrap().expose_secret(), expected);
+    }
+
+    #[test]
+    fn option_encoding_invalid_key_is_error() {
+        let err = Args::build_from_args(
+            [
+                "decrypt",
+                "--encoding",
+                "base32",
+                "-k",
+                "not base32!",
+                "hello",
+            ]
+            .iter(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(err, "Not a valid base32 key");
+    }
+
+    #[test]
+    fn protected_key_is_locked() {
+        let protected = key::protect("chacha20poly1305", &[0u8; 32], b"correct horse").unwrap();
+        let mut args = Args::build_from_args(["decrypt", "-k", &protected].iter()).unwrap();
+        assert!(args.locked_key);
+
+        args.unlock_key(&SecretString::from("correct horse"))
+            .unwrap();
+        assert!(!args.locked_key);
+        let expected = key::tag("chacha20poly1305", &(&[0u8; 32]).base64_encode());
+        assert!(args.key.is_some_and(|k| k.expose_secret() == expected));
+    }
+
+    #[test]
+    fn protected_key_wrong_passphrase_is_error() {
+        let protected = key::protect("chacha20poly1305", &[0u8; 32], b"correct horse").unwrap();
+        let mut args = Args::build_from_args(["decrypt", "-k", &protected].iter()).unwrap();
+
+        let err = args
+            .unlock_key(&SecretString::from("battery staple"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Could not unlock the key, the passphrase is likely wrong"
+        );
+        assert!(args.locked_key);
+    }
+
+    #[test]
+    fn protected_key_requires_passphrase() {
+        let protected = key::protect("chacha20poly1305", &[0u8; 32], b"correct horse").unwrap();
+        let env = environment::Fake {
+            stdin_terminal: false,
+            ..Default::default()
+        };
+        let err = Args::build_from_args_with_env(["decrypt", "-k", &protected].iter(), &env)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "The key is protected, its passphrase must be in JOLOKIA_PASSWORD, or typed in on a terminal"
+        );
+    }
+
+    #[test]
+    fn protected_key_prompted_is_locked() {
+        let protected = key::protect("chacha20poly1305", &[0u8; 32], b"correct horse").unwrap();
+        let mut args = Args::build_from_args(["decrypt", "-k", "-"].iter()).unwrap();
+        args.set_prompted_key(&SecretString::from(protected))
+            .unwrap();
+        assert!(args.locked_key);
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn ssh_public_key_selects_hpke() {
+        let file = format!("{FIXTURES}id_ed25519.pub");
+        let args = Args::build_from_args(["encrypt", "-k", &file].iter()).unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::Hpke));
+        assert!(
+            args.key
+                .is_some_and(|k| k.expose_secret().starts_with("hpke:"))
+        );
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn ssh_private_key_selects_hpke() {
+        let file = format!("{FIXTURES}id_ed25519");
+        let args = Args::build_from_args(["decrypt", "-k", &file].iter()).unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::Hpke));
+        assert!(
+            args.key
+                .is_some_and(|k| k.expose_secret().starts_with("hpke:"))
+        );
+    }
+
+    #[test]
+    fn ssh_key_other_algorithm_is_error() {
+        let file = format!("{FIXTURES}id_ed25519.pub");
+        let err = Args::build_from_args(["encrypt", "-a", "chacha", "-k", &file].iter());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "SSH keys can only be used with HPKE, not ChaCha20-Poly1305"
+        );
+    }
+
+    #[test]
+    fn ssh_key_invalid_is_error() {
+        let err = Args::build_from_args(["encrypt", "-k", "ssh-ed25519 AAAA"].iter());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Not a valid 'ssh-ed25519' key"
+        );
+
+        let err = Args::build_from_args(["encrypt", "-k", "ssh-rsa AAAA"].iter());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "'ssh-rsa' keys are not supported, only 'ssh-ed25519' keys are"
+        );
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn ssh_key_rekey_new_key() {
+        let file = format!("{FIXTURES}id_ed25519.pub");
+        let args =
+            Args::build_from_args(["rekey", "-k", "abc=", "--new-key", &file].iter()).unwrap();
+        assert_eq!(args.algorithm, None);
+        assert_eq!(args.new_algorithm, Some(Algorithm::Hpke));
+        assert!(
+            args.new_key
+                .is_some_and(|k| k.expose_secret().starts_with("hpke:"))
+        );
+    }
+
+    #[test]
+    fn age_recipient_selects_age() {
+        let recipient = "age1hxrsgh77k8jzk60gdfccf3n094ffzfdvuldlptkarpprq4079g9q9lslkq";
+        let args = Args::build_from_args(["encrypt", "-k", recipient].iter()).unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::Age));
+        assert!(
+            args.key
+                .is_some_and(|k| k.expose_secret().starts_with("age:"))
+        );
+    }
+
+    #[test]
+    fn age_identity_file_selects_age() {
+        let file = format!("{FIXTURES}age_identity.txt");
+        let args = Args::build_from_args(["decrypt", "-k", &file].iter()).unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::Age));
+        assert!(
+            args.key
+                .is_some_and(|k| k.expose_secret().starts_with("age:"))
+        );
+    }
+
+    #[test]
+    fn age_key_other_algorithm_is_error() {
+        let file = format!("{FIXTURES}age_identity.txt");
+        let err = Args::build_from_args(["decrypt", "-a", "hpke", "-k", &file].iter());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "age keys can only be used with age, not HPKE"
+        );
+
+        let err = Args::build_from_args(["encrypt", "-k", "age1abc"].iter());
+        assert_eq!(err.unwrap_err().to_string(), "Not a valid age key");
+    }
+
+    #[test]
+    fn option_output_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert_eq!(args.output, Output::Stdout);
+    }
+
+    #[test]
+    fn option_output_redirected() {
+        let env = environment::Fake {
+            stdout_terminal: false,
+            ..Default::default()
+        };
+        let args = Args::build_from_args_with_env(["encrypt"].iter(), &env).unwrap();
+        assert_eq!(args.output, Output::Redirected);
+    }
+
+    #[test]
+    fn option_output_file_is_not_redirected() {
+        let env = environment::Fake {
+            stdout_terminal: false,
+            ..Default::default()
+        };
+        let args =
+            Args::build_from_args_with_env(["encrypt", "-o", "out.enc"].iter(), &env).unwrap();
+        assert_eq!(args.output, Output::File(PathBuf::from("out.enc")));
+    }
+
+    #[test]
+    fn option_short_output_regular() {
+        let args = Args::build_from_args(["encrypt", "-o", "out.enc"].iter()).unwrap();
+        assert_eq!(args.output, Output::File(PathBuf::from("out.enc")));
+    }
+
+    #[test]
+    fn option_long_output_regular() {
+        let args = Args::build_from_args(["encrypt", "--output", "out.enc"].iter()).unwrap();
+        assert_eq!(args.output, Output::File(PathBuf::from("out.enc")));
+    }
+
+    #[test]
+    fn option_message_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert!(args.message.is_none());
+    }
+
+    #[test]
+    fn option_message_from_stdin() {
+        let env = environment::Fake {
+            has_stdin: true,
+            ..Default::default()
+        };
+        let args = Args::build_from_args_with_env(["encrypt"].iter(), &env).unwrap();
+        assert_eq!(args.message, Some(Message::Stdin));
+    }
+
+    #[test]
+    fn option_message_explicit_over_stdin() {
+        let env = environment::Fake {
+            has_stdin: true,
+            ..Default::default()
+        };
+        let args = Args::build_from_args_with_env(["encrypt", "hello"].iter(), &env).unwrap();
+        assert_eq!(args.message, Some(Message::String("hello".to_string())));
+    }
+
+    #[test]
+    fn option_key_from_env() {
+        let env = environment::Fake::default().with_var(KEY_ENV_VAR, "from-env");
+        let args = Args::build_from_args_with_env(["encrypt"].iter(), &env).unwrap();
+        assert_eq!(args.key.unwrap().expose_secret(), "from-env");
+    }
+
+    #[test]
+    fn option_key_explicit_over_env() {
+        let env = environment::Fake::default().with_var(KEY_ENV_VAR, "from-env");
+        let args =
+            Args::build_from_args_with_env(["encrypt", "-k", "explicit"].iter(), &env).unwrap();
+        assert_eq!(args.key.unwrap().expose_secret(), "explicit");
+    }
+
+    #[test]
+    fn option_entropy_default() {
+        let args = Args::build_from_args(["keygen"].iter()).unwrap();
+        assert_eq!(args.entropy, None);
+    }
+
+    #[test]
+    fn option_entropy_system() {
+        let args = Args::build_from_args(["keygen", "--entropy", "system"].iter()).unwrap();
+        assert_eq!(args.entropy, Some(entropy::Source::System));
+    }
+
+    #[test]
+    fn option_entropy_strict() {
+        let args = Args::build_from_args(["encrypt", "--entropy", "strict"].iter()).unwrap();
+        assert_eq!(args.entropy, Some(entropy::Source::strict()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn option_entropy_device() {
+        let args = Args::build_from_args(["keygen", "--entropy", "/dev/urandom"].iter()).unwrap();
+        assert_eq!(
+            args.entropy,
+            Some(entropy::Source::Device(PathBuf::from("/dev/urandom")))
+        );
+    }
+
+    #[test]
+    fn option_entropy_regular_file_is_error() {
+        let file = format!("{FIXTURES}lorem.txt");
+        let err = Args::build_from_args(["keygen", "--entropy", &file].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Invalid entropy source"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn option_entropy_dev_zero_is_error() {
+        let err = Args::build_from_args(["keygen", "--entropy", "/dev/zero"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Invalid entropy source '/dev/zero'"));
+    }
+
+    #[test]
+    fn option_entropy_decrypt_is_error() {
+        let err = Args::build_from_args(["decrypt", "hello", "--entropy", "strict"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'--entropy'"));
+    }
+
+    #[test]
+    fn option_expire_regular() {
+        let args = Args::build_from_args(["encrypt", "--expire", "48h"].iter()).unwrap();
+        assert_eq!(args.expire, Some(Duration::from_hours(48)));
+    }
+
+    #[test]
+    fn option_expire_invalid_is_error() {
+        let err = Args::build_from_args(["encrypt", "--expire", "48"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Invalid duration '48'");
+    }
+
+    #[test]
+    fn option_expire_not_for_decrypt() {
+        let err = Args::build_from_args(["decrypt", "--expire", "48h"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'--expire'"));
+    }
+
+    #[test]
+    fn option_expire_insecure_algorithm_is_error() {
+        let err = Args::build_from_args(["encrypt", "--expire", "1d", "-a", "rot"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--expire' cannot be used with ROT-n");
+    }
+
+    #[test]
+    fn option_strict_regular() {
+        let args = Args::build_from_args(["decrypt", "--strict"].iter()).unwrap();
+        assert!(args.strict);
+    }
+
+    #[test]
+    fn option_id_hint_regular() {
+        let args = Args::build_from_args(["encrypt", "--id-hint"].iter()).unwrap();
+        assert!(args.id_hint);
+    }
+
+    #[test]
+    fn option_id_hint_only_for_encrypt() {
+        let err = Args::build_from_args(["decrypt", "--id-hint"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'--id-hint'"));
+    }
+
+    #[test]
+    fn option_id_hint_password_is_error() {
+        let err = Args::build_from_args(["encrypt", "--id-hint", "--password", "-k", "x"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--id-hint' cannot be used with '--password'");
+    }
+
+    #[test]
+    fn option_id_hint_other_algorithm_is_error() {
+        let err = Args::build_from_args(["encrypt", "--id-hint", "-a", "hpke"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--id-hint' cannot be used with HPKE");
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(Args::parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(Args::parse_duration("30m"), Some(Duration::from_mins(30)));
+        assert_eq!(Args::parse_duration("48h"), Some(Duration::from_hours(48)));
+        assert_eq!(
+            Args::parse_duration("7d"),
+            Some(Duration::from_hours(7 * 24))
+        );
+        assert_eq!(
+            Args::parse_duration("2w"),
+            Some(Duration::from_hours(2 * 7 * 24))
+        );
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        assert_eq!(Args::parse_duration(""), None);
+        assert_eq!(Args::parse_duration("h"), None);
+        assert_eq!(Args::parse_duration("-1h"), None);
+        assert_eq!(Args::parse_duration("1y"), None);
+        assert_eq!(Args::parse_duration("99999999999999999w"), None);
+    }
+
+    #[test]
+    fn option_password_from_key() {
+        let args = Args::build_from_args(["encrypt", "--password", "-k", "a:b"].iter()).unwrap();
+        assert!(args.password);
+        assert_eq!(args.key_source, KeySource::Password);
+        // Base64 for `a:b`.
+        assert_eq!(args.key.unwrap().expose_secret(), "YTpi");
+    }
+
+    #[test]
+    fn option_password_from_env() {
+        let env = environment::Fake::default()
+            .with_var(PASSWORD_ENV_VAR, "a:b")
+            .with_var(KEY_ENV_VAR, "from-env");
+        let args = Args::build_from_args_with_env(["encrypt", "--password"].iter(), &env).unwrap();
+        assert_eq!(args.key_source, KeySource::Password);
+        assert_eq!(args.key.unwrap().expose_secret(), "YTpi");
+    }
+
+    #[test]
+    fn option_password_prompt() {
+        let args = Args::build_from_args(["encrypt", "--password"].iter()).unwrap();
+        assert!(args.prompt_key);
+        assert!(args.key.is_none());
+        assert_eq!(args.key_source, KeySource::Password);
+    }
+
+    #[test]
+    fn option_password_requires_passphrase() {
+        let env = environment::Fake {
+            stdin_terminal: false,
+            ..Default::default()
+        }
+        .with_var(KEY_ENV_VAR, "from-env");
+        let err = Args::build_from_args_with_env(["encrypt", "--password"].iter(), &env)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "'--password' requires a passphrase, with '--key' or in JOLOKIA_PASSWORD"
+        );
+    }
+
+    #[test]
+    fn option_password_set_prompted_key() {
+        let mut args = Args::build_from_args(["encrypt", "--password"].iter()).unwrap();
+        args.set_prompted_key(&SecretString::from("a:b")).unwrap();
+        assert_eq!(args.key.unwrap().expose_secret(), "YTpi");
+    }
+
+    #[test]
+    fn option_key_prompt() {
+        let env = environment::Fake::default().with_var(KEY_ENV_VAR, "from-env");
+        let args = Args::build_from_args_with_env(["encrypt", "-k", "-"].iter(), &env).unwrap();
+        assert!(args.prompt_key);
+        assert!(args.key.is_none());
+        assert_eq!(args.key_source, KeySource::Prompt);
+    }
+
+    #[test]
+    fn option_key_prompt_not_a_terminal_is_error() {
+        let env = environment::Fake {
+            stdin_terminal: false,
+            ..Default::default()
+        };
+        let err = Args::build_from_args_with_env(["encrypt", "-k", "-"].iter(), &env)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Cannot prompt for the key, 'stdin' is not a terminal");
+    }
+
+    #[test]
+    fn option_key_prompt_rotn_is_normalized() {
+        let mut args = Args::build_from_args(["encrypt", "-a", "rot", "-k", "-"].iter()).unwrap();
+        args.set_prompted_key(&SecretString::from("13")).unwrap();
+        assert_eq!(args.key.unwrap().expose_secret(), "DQ");
+    }
+
+    #[test]
+    fn option_password_other_algorithm_is_error() {
+        let err = Args::build_from_args(["encrypt", "--password", "-k", "p", "-a", "hpke"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "'--password' only works with ChaCha20-Poly1305, and age"
+        );
+    }
+
+    #[test]
+    fn option_password_framed_is_error() {
+        let err = Args::build_from_args(["encrypt", "--password", "-k", "p", "--framed"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--password' cannot be used with '--framed'");
+    }
+
+    #[test]
+    fn option_password_not_for_keygen() {
+        let err = Args::build_from_args(["keygen", "--password"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Unknown argument: '--password'");
+    }
+
+    #[test]
+    fn option_no_warn_from_env() {
+        let env =
+            environment::Fake::default().with_var(crate::cmd::warning::NO_WARN_ENV_VAR, "W001");
+        let args = Args::build_from_args_with_env(["encrypt"].iter(), &env).unwrap();
+        assert_eq!(args.no_warn, vec![Warning::DefaultKey]);
+    }
+
+    #[test]
+    fn option_short_file_regular() {
+        let args = Args::build_from_args(["encrypt", "-f", "in.txt"].iter()).unwrap();
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("in.txt"))));
+    }
+
+    #[test]
+    fn option_long_file_regular() {
+        let args = Args::build_from_args(["encrypt", "--file", "in.txt"].iter()).unwrap();
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("in.txt"))));
+    }
+
+    #[test]
+    fn option_aad_default() {
+        let args = Args::build_from_args(["encrypt", "-f", "in.txt"].iter()).unwrap();
+        assert!(args.aad.is_none());
+    }
+
+    #[test]
+    fn option_aad_message_after_file() {
+        let args = Args::build_from_args(["encrypt", "-f", "in.txt", "context"].iter()).unwrap();
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("in.txt"))));
+        assert_eq!(args.aad.as_deref(), Some("context"));
+    }
+
+    #[test]
+    fn option_aad_message_before_file() {
+        let args = Args::build_from_args(["encrypt", "context", "-f", "in.txt"].iter()).unwrap();
+        assert_eq!(args.message, Some(Message::File(PathBuf::from("in.txt"))));
+        assert_eq!(args.aad.as_deref(), Some("context"));
+    }
+
+    #[test]
+    fn option_aad_only_once() {
+        let err = Args::build_from_args(["encrypt", "-f", "in.txt", "context", "other"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'other'"));
+    }
+
+    #[test]
+    fn option_max_stdin_size_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert!(args.max_stdin_size.is_none());
+    }
+
+    #[test]
+    fn option_max_stdin_size_regular() {
+        let args = Args::build_from_args(["encrypt", "--max-stdin-size", "1024"].iter()).unwrap();
+        assert_eq!(args.max_stdin_size, Some(1024));
+    }
+
+    #[test]
+    fn option_max_stdin_size_invalid_is_error() {
+        let err = Args::build_from_args(["encrypt", "--max-stdin-size", "1k"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'1k'"));
+    }
+
+    #[test]
+    fn option_buffer_size_default() {
+        let args = Args::build_from_args(["encrypt"].iter()).unwrap();
+        assert!(args.buffer_size.is_none());
+        assert_eq!(args.output_buffer_size(), DEFAULT_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn option_buffer_size_regular() {
+        let args = Args::build_from_args(["decrypt", "--buffer-size", "1048576"].iter()).unwrap();
+        assert_eq!(args.output_buffer_size(), 1_048_576);
+    }
+
+    #[test]
+    fn option_buffer_size_invalid_is_error() {
+        for size in ["0", "1M", "-1"] {
+            let err = Args::build_from_args(["encrypt", "--buffer-size", size].iter())
+                .unwrap_err()
+                .to_string();
+            assert_eq!(err, format!("Invalid size '{size}'"));
+        }
+    }
+
+    #[test]
+    fn option_buffer_size_not_for_keygen() {
+        let err = Args::build_from_args(["keygen", "--buffer-size", "1024"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Unknown argument: '--buffer-size'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn command_bench_regular() {
+        let args = Args::build_from_args(["bench"].iter()).unwrap();
+        assert_eq!(args.command, Some(Command::Bench));
+        assert!(args.algorithm.is_none());
+        assert!(args.bench_size.is_none());
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn command_bench_algorithm_and_size() {
+        let args = Args::build_from_args(["bench", "-a", "hpke", "--size", "1024"].iter()).unwrap();
+        assert_eq!(args.algorithm, Some(Algorithm::Hpke));
+        assert_eq!(args.bench_size, Some(1024));
+    }
+
+    #[test]
+    fn option_size_invalid_is_error() {
+        let err = Args::build_from_args(["bench", "--size", "1M"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Invalid size '1M'");
+    }
+
+    #[test]
+    fn option_size_only_for_bench() {
+        let err = Args::build_from_args(["encrypt", "--size", "1024"].iter())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Unknown argument: '--size'"), "{err}");
+    }
+
+    #[test]
+    fn option_peek_default() {
+        let args = Args::build_from_args(["decrypt"].iter()).unwrap();
+        assert!(args.peek.is_none());
+    }
+
+    #[test]
+    fn option_peek_regular() {
+        let args = Args::build_from_args(["decrypt", "--peek", "64"].iter()).unwrap();
+        assert_eq!(args.peek, Some(64));
+    }
+
+    #[test]
+    fn option_peek_only_for_decrypt() {
+        let args = Args::build_from_args(["encrypt", "--peek", "64"].iter());
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn option_range_default() {
+        let args = Args::build_from_args(["decrypt"].iter()).unwrap();
+        assert!(args.range.is_none());
+    }
+
+    #[test]
+    fn option_range_regular() {
+        let args = Args::build_from_args(["decrypt", "--range", "100:200"].iter()).unwrap();
+        assert_eq!(args.range, Some(100..200));
+    }
+
+    #[test]
+    fn option_range_open_ended() {
+        let args = Args::build_from_args(["decrypt", "--range", "100:"].iter()).unwrap();
+        assert_eq!(args.range, Some(100..u64::MAX));
+
+        let args = Args::build_from_args(["decrypt", "--range", ":200"].iter()).unwrap();
+        assert_eq!(args.range, Some(0..200));
+    }
+
+    #[test]
+    fn option_range_invalid_is_error() {
+        for range in ["100", "a:b", "-1:5", "1:2:3"] {
+            let err = Args::build_from_args(["decrypt", "--range", range].iter())
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains(&format!("'{range}'")), "{range}: {err}");
+        }
+    }
+
+    #[test]
+    fn option_range_start_after_end_is_error() {
+        let err = Args::build_from_args(["decrypt", "--range", "200:100"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Invalid range '200:100', the start is after the end");
+    }
+
+    #[test]
+    fn option_range_only_for_decrypt() {
+        let args = Args::build_from_args(["encrypt", "--range", "0:10"].iter());
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn option_range_with_peek_is_error() {
+        let err = Args::build_from_args(["decrypt", "--range", "0:10", "--peek", "8"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--range' cannot be used with '--peek'");
+    }
+
+    #[test]
+    fn option_range_with_framed_is_error() {
+        let err = Args::build_from_args(["decrypt", "--range", "0:10", "--framed"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--range' cannot be used with '--framed'");
+    }
+
+    #[test]
+    fn option_range_in_place_is_error() {
+        let err =
+            Args::build_from_args(["decrypt", "--range", "0:10", "-i", "-f", "in.txt"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "'--range' cannot be used with '--in-place'");
+    }
+
+    #[test]
+    fn option_no_warn_regular() {
+        let args =
+            Args::build_from_args(["encrypt", "--no-warn", "W001", "--no-warn", "W002"].iter())
+                .unwrap();
+        assert!(args.no_warn.contains(&Warning::DefaultKey));
+        assert!(args.no_warn.contains(&Warning::InsecureAlgorithm));
+    }
+
+    #[test]
+    fn option_no_warn_list() {
+        let args =
+            Args::build_from_args(["encrypt", "--no-warn", "default-key,W002"].iter()).unwrap();
+        assert!(args.no_warn.contains(&Warning::DefaultKey));
+        assert!(args.no_warn.contains(&Warning::InsecureAlgorithm));
+    }
+
+    #[test]
+    fn option_no_warn_unknown_is_error() {
+        let args = Args::build_from_args(["encrypt", "--no-warn", "W999"].iter());
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn option_short_help_regular() {
+        let args = Args::build_from_args(["-h"].iter()).unwrap();
+        assert!(args.short_help);
+        assert!(!args.long_help);
+    }
+
+    #[test]
+    fn option_long_help_regular() {
+        let args = Args::build_from_args(["--help"].iter()).unwrap();
+        assert!(!args.short_help);
+        assert!(args.long_help);
+    }
+
+    #[test]
+    fn option_progress_for_files() {
+        let args = Args::build_from_args(["encrypt", "-f", "in.txt"].iter()).unwrap();
+        assert!(args.progress);
+    }
+
+    #[test]
+    fn option_progress_for_piped_stdin() {
+        let env = environment::Fake {
+            has_stdin: true,
+            stdin_terminal: false,
+            ..Default::default()
+        };
+        let args = Args::build_from_args_with_env(["decrypt"].iter(), &env).unwrap();
+        assert!(args.progress);
+    }
+
+    #[test]
+    fn option_progress_not_for_typed_in_messages() {
+        let args = Args::build_from_args(["encrypt", "hello"].iter()).unwrap();
+        assert!(!args.progress);
+
+        let env = environment::Fake {
+            has_stdin: true,
+            ..Default::default()
+        };
+        let args = Args::build_from_args_with_env(["encrypt"].iter(), &env).unwrap();
+        assert!(!args.progress);
+    }
+
+    #[test]
+    fn option_progress_not_if_stderr_is_redirected() {
+        let env = environment::Fake {
+            stderr_terminal: false,
+            ..Default::default()
+        };
+        let args =
+            Args::build_from_args_with_env(["encrypt", "-f", "in.txt"].iter(), &env).unwrap();
+        assert!(!args.progress);
+    }
+
+    #[test]
+    fn option_no_progress_regular() {
+        let args =
+            Args::build_from_args(["encrypt", "-f", "in.txt", "--no-progress"].iter()).unwrap();
+        assert!(!args.progress);
+    }
+
+    #[test]
+    fn option_no_progress_only_for_encrypt_and_decrypt() {
+        let args = Args::build_from_args(["keygen", "--no-progress"].iter());
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn option_verbose_and_quiet() {
+        let args = Args::build_from_args(["encrypt", "-v", "hello"].iter()).unwrap();
+        assert_eq!(args.verbosity, Verbosity::Verbose);
+
+        let args = Args::build_from_args(["--quiet", "encrypt", "-f", "in.txt"].iter()).unwrap();
+        assert_eq!(args.verbosity, Verbosity::Quiet);
+        assert!(!args.progress);
+    }
+
+    #[test]
+    fn option_verbose_with_quiet_is_error() {
+        let err = Args::build_from_args(["encrypt", "-v", "-q", "hello"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'--verbose' cannot be used with '--quiet'");
+    }
+
+    #[test]
+    fn option_preserve_times_regular() {
+        let args = Args::build_from_args(
+            [
+                "encrypt",
+                "-f",
+                "in.txt",
+                "-o",
+                "out.enc",
+                "--preserve-times",
+            ]
+            .iter(),
+        )
+        .unwrap();
+        assert!(args.preserve_times);
+
+        let args =
+            Args::build_from_args(["decrypt", "-f", "in.txt", "-i", "--preserve-times"].iter())
+                .unwrap();
+        assert!(args.preserve_times);
+    }
+
+    #[test]
+    fn option_preserve_times_requires_file() {
+        let err =
+            Args::build_from_args(["encrypt", "hello", "-o", "out.enc", "--preserve-times"].iter())
+                .unwrap_err()
+                .to_string();
+        assert_eq!(err, "'--preserve-times' requires '--file'");
+    }
+
+    #[test]
+    fn option_preserve_times_requires_output_file() {
+        let err = Args::build_from_args(["encrypt", "-f", "in.txt", "--preserve-times"].iter())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "'--preserve-times' requires '--output' or '--in-place'"
+        );
+    }
+
+    #[test]
+    fn option_preserve_times_only_for_ciphering() {
+        let args = Args::build_from_args(["keygen", "--preserve-times"].iter());
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn option_no_pager_regular() {
+        let args = Args::build_from_args(["--help", "--no-pager"].iter()).unwrap();
+        assert!(args.no_pager);
+    }
+
+    #[test]
+    fn option_short_version_regular() {
+        let args = Args::build_from_args(["-V"].iter()).unwrap();
+        assert!(args.version);
+    }
+
+    #[test]
+    fn option_long_version_regular() {
+        let args = Args::build_from_args(["--version"].iter()).unwrap();
+        assert!(args.version);
+    }
+
+    #[test]
+    fn debug_output_redacts_secrets() {
+        let args = Args::build_from_args(["encrypt", "-k", "s3cr3t-key", "s3cr3t-message"].iter())
+            .unwrap();
+
+        let debug = format!("{args:?}");
+
+        assert!(!debug.contains("s3cr3t"));
+        assert!(debug.contains("REDACTED"));
     }
 
     #[test]
     fn command_unknown_is_error() {
-        let err = Args::build_from_args(["unknown"].iter()).unwrap_err();
+        let err = Args::build_from_args(["unknown"].iter())
+            .unwrap_err()
+            .to_string();
         assert!(err.contains("'unknown'"));
     }
 }
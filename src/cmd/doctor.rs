@@ -0,0 +1,422 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use secrecy::zeroize::Zeroizing;
+
+use jolokia::cipher::age;
+use jolokia::key;
+#[cfg(feature = "hpke")]
+use jolokia::ssh;
+use jolokia::traits::{Base64Decode, Cipher};
+
+use super::{cli, ui};
+
+/// A misconfiguration, and how to fix it.
+#[derive(Debug, Eq, PartialEq)]
+struct Problem {
+    what: String,
+    fix: String,
+}
+
+impl Problem {
+    fn new(what: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            what: what.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+/// Check the environment for common misconfigurations.
+///
+/// Prints a line per check, and a fix for each problem found.
+pub fn doctor(algorithm: cli::Algorithm) -> Result<(), String> {
+    let cipher: Box<dyn Cipher> = algorithm.into();
+    let current_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+
+    let checks = [
+        check_key_env(cipher.as_ref(), algorithm),
+        check_leftover_temporary_files(&current_dir),
+        check_dir_is_writable(&current_dir),
+        check_locale(),
+    ];
+
+    let mut nb_problems = 0;
+    for check in checks {
+        match check {
+            Ok(ok) => println!("{}: {ok}", ui::Color::success("ok")),
+            Err(problem) => {
+                nb_problems += 1;
+                println!(
+                    "{warning}: {}\n  fix: {}",
+                    problem.what,
+                    problem.fix,
+                    warning = ui::Color::warning("warning"),
+                );
+            }
+        }
+    }
+
+    match nb_problems {
+        0 => Ok(()),
+        1 => Err("Found 1 problem".to_string()),
+        n => Err(format!("Found {n} problems")),
+    }
+}
+
+fn check_key_env(cipher: &dyn Cipher, algorithm: cli::Algorithm) -> Result<String, Problem> {
+    let var = cli::KEY_ENV_VAR;
+    let Ok(value) = env::var(var) else {
+        return Ok(format!(
+            "`{var}` is not set, keys must be passed with `--key`."
+        ));
+    };
+    let value = Zeroizing::new(value);
+
+    // Like `--key`, the variable can point to a file.
+    let path = Path::new(value.as_str());
+    let key = if path.is_file() {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Err(Problem::new(
+                format!(
+                    "`{var}` points to '{}', which is unreadable.",
+                    path.display()
+                ),
+                "Check the permissions of the key file.",
+            ));
+        };
+        let content = Zeroizing::new(content);
+        if content.trim_end().is_empty() {
+            return Err(Problem::new(
+                format!("`{var}` points to '{}', which is empty.", path.display()),
+                format!(
+                    "Generate a key with `{bin} keygen > {}`.",
+                    path.display(),
+                    bin = env!("CARGO_BIN_NAME"),
+                ),
+            ));
+        }
+        Zeroizing::new(content.trim_end().to_string())
+    } else if value.contains(['/', '\\']) {
+        // Likely meant to be a file. If it isn't, it's used as-is.
+        return Err(Problem::new(
+            format!(
+                "`{var}` looks like a path, but '{}' does not exist.",
+                path.display()
+            ),
+            "Fix the path, otherwise it is used as the key itself.",
+        ));
+    } else {
+        value
+    };
+
+    check_key(cipher, algorithm, &key).map(|()| format!("`{var}` contains a valid key."))
+}
+
+fn check_key(cipher: &dyn Cipher, algorithm: cli::Algorithm, key: &str) -> Result<(), Problem> {
+    if algorithm.is_toy() {
+        // Not base64, nothing to check.
+        return Ok(());
+    }
+
+    // SSH keys select HPKE, unless another algorithm is forced.
+    #[cfg(feature = "hpke")]
+    if ssh::is_ssh_key(key) {
+        return match ssh::to_hpke_key(key) {
+            Ok(_) if algorithm == cli::Algorithm::Hpke => Ok(()),
+            Ok(_) => Err(Problem::new(
+                format!(
+                    "SSH keys are for {}, not {algorithm}.",
+                    cli::Algorithm::Hpke
+                ),
+                "Select HPKE with `-a hpke`, or leave the algorithm out.",
+            )),
+            Err(e) => Err(Problem::new(
+                format!("The SSH key is not usable: {e}"),
+                "Use an unencrypted `ssh-ed25519` key.",
+            )),
+        };
+    }
+
+    // Same for age keys, with age.
+    if age::is_age_key(key) {
+        return match age::decode_key(key) {
+            Ok(_) if algorithm == cli::Algorithm::Age => Ok(()),
+            Ok(_) => Err(Problem::new(
+                format!("age keys are for {}, not {algorithm}.", cli::Algorithm::Age),
+                "Select age with `-a age`, or leave the algorithm out.",
+            )),
+            Err(_) => Err(Problem::new(
+                "The age key is not valid.",
+                format!(
+                    "Use a key from `age-keygen`, or `{bin} keygen -a age`.",
+                    bin = env!("CARGO_BIN_NAME")
+                ),
+            )),
+        };
+    }
+
+    // Without the passphrase, only the algorithm can be checked.
+    let is_protected = key::is_protected(key);
+    let key = key.strip_prefix("protected:").unwrap_or(key);
+
+    let (algorithm_id, key) = key::split_tag(key);
+    if let Some(algorithm_id) = algorithm_id
+        && algorithm_id != cipher.algorithm_id()
+    {
+        return Err(Problem::new(
+            format!(
+                "The key is for '{algorithm_id}', not '{}'.",
+                cipher.algorithm_id()
+            ),
+            format!("Select the matching algorithm with `-a {algorithm_id}`."),
+        ));
+    }
+
+    if is_protected {
+        return Ok(());
+    }
+
+    let Ok(key) = key.base64_decode() else {
+        return Err(Problem::new(
+            "The key is not valid base64.",
+            format!(
+                "Generate a new key with `{bin} keygen`.",
+                bin = env!("CARGO_BIN_NAME")
+            ),
+        ));
+    };
+    let key = Zeroizing::new(key);
+
+    // Cheapest way to validate a key, for any algorithm.
+    if cipher.encrypt(&key, b"").is_err() {
+        return Err(Problem::new(
+            format!(
+                "The key has the wrong size ({}).",
+                ui::format::bytes(key.len() as u64)
+            ),
+            format!(
+                "Generate a new key with `{bin} keygen`.",
+                bin = env!("CARGO_BIN_NAME")
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// In-place operations write to `<file>.<timestamp>.<crate>` first.
+fn check_leftover_temporary_files(dir: &Path) -> Result<String, Problem> {
+    let leftovers = find_leftover_temporary_files(dir);
+    if leftovers.is_empty() {
+        return Ok(format!(
+            "No leftover temporary files in '{}'.",
+            dir.display()
+        ));
+    }
+
+    let leftovers: Vec<String> = leftovers
+        .iter()
+        .map(|file| format!("'{}'", file.display()))
+        .collect();
+    Err(Problem::new(
+        format!(
+            "Found leftover temporary files (interrupted in-place operation): {}.",
+            leftovers.join(", ")
+        ),
+        "They may contain partial output. Check them, then delete them.",
+    ))
+}
+
+fn find_leftover_temporary_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut leftovers: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_temporary_file(path))
+        .collect();
+    leftovers.sort();
+    leftovers
+}
+
+fn is_temporary_file(path: &Path) -> bool {
+    if path
+        .extension()
+        .is_none_or(|e| e != env!("CARGO_CRATE_NAME"))
+    {
+        return false;
+    }
+    // Timestamp.
+    path.file_stem()
+        .map(Path::new)
+        .and_then(Path::extension)
+        .and_then(|timestamp| timestamp.to_str())
+        .is_some_and(|timestamp| {
+            !timestamp.is_empty() && timestamp.bytes().all(|c| c.is_ascii_digit())
+        })
+}
+
+/// In-place operations need to create temporary files next to the file.
+fn check_dir_is_writable(dir: &Path) -> Result<String, Problem> {
+    let probe = dir.join(format!(
+        ".{}-doctor-{}",
+        env!("CARGO_CRATE_NAME"),
+        process::id()
+    ));
+    match fs::File::create(&probe) {
+        Ok(_) => {
+            _ = fs::remove_file(&probe);
+            Ok(format!("'{}' is writable.", dir.display()))
+        }
+        Err(reason) => Err(Problem::new(
+            format!("'{}' is not writable: {reason}.", dir.display()),
+            "In-place operations will fail here, write to another directory with `-o`.",
+        )),
+    }
+}
+
+fn check_locale() -> Result<String, Problem> {
+    // Same precedence as POSIX.
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()));
+    match locale {
+        Some(locale) if !is_utf8_locale(&locale) => Err(Problem::new(
+            format!("The locale ('{locale}') is not UTF-8."),
+            "Messages typed in the terminal may be mangled, `export LANG=C.UTF-8`.",
+        )),
+        Some(locale) => Ok(format!("The locale ('{locale}') is UTF-8.")),
+        None => Ok("No locale set.".to_string()),
+    }
+}
+
+fn is_utf8_locale(locale: &str) -> bool {
+    let locale = locale.to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_key_valid() {
+        let cipher: Box<dyn Cipher> = cli::Algorithm::ChaCha20Poly1305.into();
+        let res = check_key(
+            cipher.as_ref(),
+            cli::Algorithm::ChaCha20Poly1305,
+            "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
+        );
+
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn check_key_other_algorithm() {
+        let cipher: Box<dyn Cipher> = cli::Algorithm::ChaCha20Poly1305.into();
+        let problem = check_key(
+            cipher.as_ref(),
+            cli::Algorithm::ChaCha20Poly1305,
+            "hpke:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            problem.what,
+            "The key is for 'hpke', not 'chacha20poly1305'."
+        );
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn check_key_ssh() {
+        let key = fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/id_ed25519.pub"
+        ))
+        .unwrap();
+
+        let cipher: Box<dyn Cipher> = cli::Algorithm::Hpke.into();
+        assert!(check_key(cipher.as_ref(), cli::Algorithm::Hpke, &key).is_ok());
+
+        let cipher: Box<dyn Cipher> = cli::Algorithm::ChaCha20Poly1305.into();
+        let problem =
+            check_key(cipher.as_ref(), cli::Algorithm::ChaCha20Poly1305, &key).unwrap_err();
+        assert_eq!(
+            problem.what,
+            "SSH keys are for HPKE, not ChaCha20-Poly1305."
+        );
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn check_key_age() {
+        let key = "age1hxrsgh77k8jzk60gdfccf3n094ffzfdvuldlptkarpprq4079g9q9lslkq";
+
+        let cipher: Box<dyn Cipher> = cli::Algorithm::Age.into();
+        assert!(check_key(cipher.as_ref(), cli::Algorithm::Age, key).is_ok());
+
+        let cipher: Box<dyn Cipher> = cli::Algorithm::Hpke.into();
+        let problem = check_key(cipher.as_ref(), cli::Algorithm::Hpke, key).unwrap_err();
+        assert_eq!(problem.what, "age keys are for age, not HPKE.");
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn check_key_protected() {
+        let cipher: Box<dyn Cipher> = cli::Algorithm::Hpke.into();
+        let key = key::protect("hpke", &[0u8; 32], b"correct horse").unwrap();
+
+        assert!(check_key(cipher.as_ref(), cli::Algorithm::Hpke, &key).is_ok());
+
+        let cipher: Box<dyn Cipher> = cli::Algorithm::ChaCha20Poly1305.into();
+        let problem =
+            check_key(cipher.as_ref(), cli::Algorithm::ChaCha20Poly1305, &key).unwrap_err();
+        assert_eq!(
+            problem.what,
+            "The key is for 'hpke', not 'chacha20poly1305'."
+        );
+    }
+
+    #[test]
+    fn check_key_not_base64() {
+        let cipher: Box<dyn Cipher> = cli::Algorithm::ChaCha20Poly1305.into();
+        let problem =
+            check_key(cipher.as_ref(), cli::Algorithm::ChaCha20Poly1305, "@@@").unwrap_err();
+
+        assert_eq!(problem.what, "The key is not valid base64.");
+    }
+
+    #[test]
+    fn check_key_wrong_size() {
+        let cipher: Box<dyn Cipher> = cli::Algorithm::ChaCha20Poly1305.into();
+        let problem =
+            check_key(cipher.as_ref(), cli::Algorithm::ChaCha20Poly1305, "AAAA").unwrap_err();
+
+        assert_eq!(problem.what, "The key has the wrong size (3 bytes).");
+    }
+
+    #[test]
+    fn is_temporary_file_regular() {
+        assert!(is_temporary_file(Path::new("cat.1700000000000000.jolokia")));
+        assert!(is_temporary_file(Path::new("/tmp/cat.1.jolokia")));
+    }
+
+    #[test]
+    fn is_temporary_file_other_files() {
+        assert!(!is_temporary_file(Path::new("cat.gif")));
+        assert!(!is_temporary_file(Path::new("cat.jolokia")));
+        assert!(!is_temporary_file(Path::new("cat.gif.jolokia")));
+        assert!(!is_temporary_file(Path::new("cat.1700000000000000.txt")));
+    }
+
+    #[test]
+    fn is_utf8_locale_regular() {
+        assert!(is_utf8_locale("en_US.UTF-8"));
+        assert!(is_utf8_locale("C.utf8"));
+        assert!(!is_utf8_locale("POSIX"));
+        assert!(!is_utf8_locale("en_US.ISO-8859-1"));
+    }
+}
@@ -0,0 +1,115 @@
+//! Access to the process environment.
+//!
+//! Anything that depends on the outside world (terminals, environment
+//! variables, time) goes through [`Environment`]. In tests, a [`Fake`]
+//! environment makes this behaviour deterministic, and testable.
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::time::SystemTime;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+pub trait Environment {
+    /// Whether `stdin` can be read from at all.
+    fn has_stdin(&self) -> bool;
+
+    /// Whether `stream` is a terminal (as opposed to a pipe or file).
+    fn is_terminal(&self, stream: Stream) -> bool;
+
+    /// Value of environment variable `key`, if set and valid Unicode.
+    fn var(&self, key: &str) -> Option<String>;
+
+    /// Current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The real environment of the process.
+pub struct System;
+
+impl Environment for System {
+    fn has_stdin(&self) -> bool {
+        true
+    }
+
+    fn is_terminal(&self, stream: Stream) -> bool {
+        match stream {
+            Stream::Stdin => io::stdin().is_terminal(),
+            Stream::Stdout => io::stdout().is_terminal(),
+            Stream::Stderr => io::stderr().is_terminal(),
+        }
+    }
+
+    fn var(&self, key: &str) -> Option<String> {
+        env::var(key).ok()
+    }
+
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A controlled environment, for tests.
+///
+/// By default, it is an interactive terminal with no `stdin` to read
+/// from, no variables, and the clock is stuck at the Unix epoch.
+#[cfg(test)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Fake {
+    pub has_stdin: bool,
+    pub stdin_terminal: bool,
+    pub stdout_terminal: bool,
+    pub stderr_terminal: bool,
+    pub vars: std::collections::HashMap<String, String>,
+    pub now: Option<SystemTime>,
+}
+
+#[cfg(test)]
+impl Default for Fake {
+    fn default() -> Self {
+        Self {
+            has_stdin: false,
+            stdin_terminal: true,
+            stdout_terminal: true,
+            stderr_terminal: true,
+            vars: std::collections::HashMap::new(),
+            now: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Fake {
+    pub fn with_var(mut self, key: &str, value: &str) -> Self {
+        self.vars.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+#[cfg(test)]
+impl Environment for Fake {
+    fn has_stdin(&self) -> bool {
+        self.has_stdin
+    }
+
+    fn is_terminal(&self, stream: Stream) -> bool {
+        match stream {
+            Stream::Stdin => self.stdin_terminal,
+            Stream::Stdout => self.stdout_terminal,
+            Stream::Stderr => self.stderr_terminal,
+        }
+    }
+
+    fn var(&self, key: &str) -> Option<String> {
+        self.vars.get(key).cloned()
+    }
+
+    fn now(&self) -> SystemTime {
+        self.now.unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+}
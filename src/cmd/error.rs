@@ -0,0 +1,152 @@
+//! Errors of the command line, and the exit codes they map to.
+//!
+//! Scripts can tell failures apart by exit code, without parsing
+//! messages:
+//!
+//! | Code | Meaning                                           |
+//! | ---- | ------------------------------------------------- |
+//! | 0    | Success                                           |
+//! | 1    | Any other failure                                 |
+//! | 2    | Bad usage (invalid arguments)                     |
+//! | 3    | Partial failure (some frames or files failed)     |
+//! | 4    | Decryption failed (wrong key, or corrupted data)  |
+//! | 5    | I/O error (missing file, cut short input, ...)    |
+//! | 6    | Unsupported algorithm, or outdated format         |
+//! | 7    | Invalid key (malformed, or for another algorithm) |
+//! | 129  | Hung up (`SIGHUP`)                                |
+//! | 130  | Interrupted (`SIGINT`, Ctrl-C)                    |
+//! | 141  | Broken pipe                                       |
+//! | 143  | Terminated (`SIGTERM`)                            |
+
+use std::fmt;
+
+use jolokia::traits::Error;
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+pub const EXIT_DECRYPT: i32 = 4;
+pub const EXIT_IO: i32 = 5;
+pub const EXIT_ALGORITHM: i32 = 6;
+pub const EXIT_KEY: i32 = 7;
+/// 128 + `SIGINT`, like a command killed by the signal.
+pub const EXIT_INTERRUPTED: i32 = 130;
+/// 128 + `SIGTERM`, like a command killed by the signal.
+pub const EXIT_TERMINATED: i32 = 143;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum CliError {
+    /// From the cipher, the exit code depends on the variant.
+    Cipher(Error),
+    /// Reading or writing files, outside of the cipher.
+    Io(String),
+    /// Invalid arguments.
+    Usage(String),
+    /// A key that can't be used, whatever the data.
+    Key(String),
+    Other(String),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Cipher(error) => match error {
+                Error::Decrypt
+                | Error::KeyHint { .. }
+                | Error::Signature
+                | Error::Base64Decode(_)
+                | Error::Decode { .. } => EXIT_DECRYPT,
+                Error::Key => EXIT_KEY,
+                Error::Read(_) | Error::Write(_) => EXIT_IO,
+                Error::Algorithm | Error::Outdated | Error::AssociatedData => EXIT_ALGORITHM,
+                Error::Encrypt
+                | Error::TooLarge(_)
+                | Error::Parameter(_)
+                | Error::Entropy(_)
+                | Error::Platform(_)
+                | Error::Other(_) => EXIT_FAILURE,
+            },
+            Self::Io(_) => EXIT_IO,
+            Self::Usage(_) => EXIT_USAGE,
+            Self::Key(_) => EXIT_KEY,
+            Self::Other(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cipher(Error::Outdated) => write!(
+                f,
+                "{}\nUse 'migrate' to re-encrypt it, or '--legacy' to decrypt it anyway.",
+                Error::Outdated
+            ),
+            Self::Cipher(error) => error.fmt(f),
+            Self::Io(reason) | Self::Usage(reason) | Self::Key(reason) | Self::Other(reason) => {
+                f.write_str(reason)
+            }
+        }
+    }
+}
+
+impl From<Error> for CliError {
+    fn from(error: Error) -> Self {
+        Self::Cipher(error)
+    }
+}
+
+impl From<String> for CliError {
+    fn from(reason: String) -> Self {
+        Self::Other(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_by_cipher_error() {
+        for (error, code) in [
+            (Error::Decrypt, EXIT_DECRYPT),
+            (Error::Base64Decode(String::new()), EXIT_DECRYPT),
+            (Error::Key, EXIT_KEY),
+            (Error::Read(String::new()), EXIT_IO),
+            (Error::Algorithm, EXIT_ALGORITHM),
+            (Error::Outdated, EXIT_ALGORITHM),
+            (Error::Encrypt, EXIT_FAILURE),
+        ] {
+            assert_eq!(CliError::from(error).exit_code(), code);
+        }
+    }
+
+    #[test]
+    fn exit_code_other() {
+        assert_eq!(CliError::Io(String::new()).exit_code(), EXIT_IO);
+        assert_eq!(CliError::Usage(String::new()).exit_code(), EXIT_USAGE);
+        assert_eq!(CliError::Key(String::new()).exit_code(), EXIT_KEY);
+        assert_eq!(CliError::from(String::new()).exit_code(), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn display_is_the_reason() {
+        assert_eq!(
+            CliError::Io("No such file".to_string()).to_string(),
+            "No such file"
+        );
+        assert_eq!(
+            CliError::from(Error::Algorithm).to_string(),
+            Error::Algorithm.to_string()
+        );
+    }
+
+    #[test]
+    fn display_outdated_suggests_migrate() {
+        let message = CliError::from(Error::Outdated).to_string();
+
+        assert!(message.starts_with(&Error::Outdated.to_string()));
+        assert!(message.contains("'migrate'"));
+        assert!(message.contains("'--legacy'"));
+    }
+}
@@ -0,0 +1,179 @@
+//! External filter commands (`--pre-filter` and `--post-filter`).
+//!
+//! A filter is a shell command, data goes in through its `stdin`, and
+//! comes out of its `stdout`. One side of the filter is connected to
+//! the cipher, the other side is pumped in a background thread.
+
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+pub struct Filter {
+    command: String,
+    child: Child,
+    pump: JoinHandle<io::Result<()>>,
+}
+
+impl Filter {
+    /// Spawn `command` with `input` as its `stdin`.
+    ///
+    /// The filtered data is read from the returned `stdout`.
+    pub fn reading_from(
+        command: &str,
+        mut input: Box<dyn Read + Send>,
+    ) -> Result<(Self, ChildStdout), String> {
+        let mut child = spawn(command)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let pump = thread::spawn(move || match io::copy(&mut input, &mut stdin) {
+            // The filter doesn't need all of the input (e.g., `head`).
+            Err(reason) if reason.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            res => res.map(|_| ()),
+        });
+
+        Ok((
+            Self {
+                command: command.to_string(),
+                child,
+                pump,
+            },
+            stdout,
+        ))
+    }
+
+    /// Spawn `command` with `output` as its `stdout`.
+    ///
+    /// The data to filter is written into the returned `stdin`. It must
+    /// be dropped before calling [`Filter::wait()`], for the filter to
+    /// see the end of the input.
+    pub fn writing_to(
+        command: &str,
+        mut output: Box<dyn Write + Send>,
+    ) -> Result<(Self, ChildStdin), String> {
+        let mut child = spawn(command)?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+
+        let pump = thread::spawn(move || {
+            io::copy(&mut stdout, &mut output)?;
+            output.flush()
+        });
+
+        Ok((
+            Self {
+                command: command.to_string(),
+                child,
+                pump,
+            },
+            stdin,
+        ))
+    }
+
+    /// Wait for the filter to finish, and check that it succeeded.
+    pub fn wait(mut self) -> Result<(), String> {
+        let status = self
+            .child
+            .wait()
+            .map_err(|reason| format!("Could not wait for filter '{}': {reason}", self.command))?;
+        let pumped = self
+            .pump
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("pump thread panicked")));
+
+        if !status.success() {
+            return Err(format!("Filter '{}' failed ({status})", self.command));
+        }
+        pumped.map_err(|reason| format!("Could not pipe data through '{}': {reason}", self.command))
+    }
+}
+
+fn spawn(command: &str) -> Result<Child, String> {
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+
+    shell
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|reason| format!("Could not run filter '{command}': {reason}"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// `Vec<u8>` that can be shared with the pump thread.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn filter_reading_from() {
+        let input = Box::new(io::Cursor::new(b"hello, world".to_vec()));
+        let (filter, mut stdout) = Filter::reading_from("tr a-z A-Z", input).unwrap();
+
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).unwrap();
+
+        assert!(filter.wait().is_ok());
+        assert_eq!(output, "HELLO, WORLD");
+    }
+
+    #[test]
+    fn filter_reading_from_partial_read_is_ok() {
+        let input = Box::new(io::Cursor::new(vec![b'a'; 1_000_000]));
+        let (filter, mut stdout) = Filter::reading_from("head -c 3", input).unwrap();
+
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).unwrap();
+
+        assert!(filter.wait().is_ok());
+        assert_eq!(output, "aaa");
+    }
+
+    #[test]
+    fn filter_writing_to() {
+        let output = SharedBuffer::default();
+        let (filter, mut stdin) =
+            Filter::writing_to("tr a-z A-Z", Box::new(output.clone())).unwrap();
+
+        stdin.write_all(b"hello, world").unwrap();
+        drop(stdin);
+
+        assert!(filter.wait().is_ok());
+        assert_eq!(*output.0.lock().unwrap(), b"HELLO, WORLD");
+    }
+
+    #[test]
+    fn filter_failure_is_error() {
+        let input = Box::new(io::empty());
+        let (filter, _) = Filter::reading_from("exit 3", input).unwrap();
+
+        let err = filter.wait().unwrap_err();
+
+        assert_eq!(err, "Filter 'exit 3' failed (exit status: 3)");
+    }
+}
@@ -0,0 +1,272 @@
+//! Structured help.
+//!
+//! Commands and arguments are described once, and rendered into the
+//! short help (`-h`), the long help (`--help`), and the man page
+//! (`man`). This way, they can't drift apart.
+
+use std::fmt::Write;
+
+/// Column at which descriptions start in the short help.
+const DESCRIPTION_COLUMN: usize = 26;
+
+pub struct Entry {
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// Only applies in the context of the preceding entry.
+    pub nested: bool,
+}
+
+impl Entry {
+    const fn new(usage: &'static str, description: &'static str) -> Self {
+        Self {
+            usage,
+            description,
+            nested: false,
+        }
+    }
+
+    const fn nested(usage: &'static str, description: &'static str) -> Self {
+        Self {
+            usage,
+            description,
+            nested: true,
+        }
+    }
+}
+
+pub struct Section {
+    pub title: &'static str,
+    pub entries: &'static [Entry],
+}
+
+pub const USAGE: &str = "[<options>] <command> [<args>]";
+
+pub const SECTIONS: &[Section] = &[
+    Section {
+        title: "Commands",
+        entries: &[
+            Entry::new("keygen", "Generate cipher key"),
+            Entry::nested("--bits <N>", "Key size in bits"),
+            Entry::nested("--params <K=V,...>", "Algorithm-specific parameters"),
+            Entry::nested(
+                "--confirm-then-clear",
+                "Clear the key from the screen on Enter",
+            ),
+            Entry::nested("--protect", "Protect the key with a passphrase"),
+            Entry::nested("--public-output <F>", "Write the public key to file F"),
+            Entry::new("encrypt", "Encrypt plaintext"),
+            Entry::new("decrypt", "Decrypt ciphertext"),
+            Entry::new("rekey", "Re-encrypt ciphertext with a new key"),
+            Entry::nested("--new-key <KEY>", "Key to re-encrypt with"),
+            Entry::nested("--new-algorithm <A>", "Algorithm to re-encrypt with"),
+            Entry::new("migrate", "Re-encrypt ciphertext in the latest format"),
+            Entry::new("doctor", "Diagnose common misconfigurations"),
+            Entry::new("bench", "Measure cipher throughput"),
+            Entry::nested("--size <N>", "Bytes to encrypt per algorithm"),
+            Entry::new("inspect", "Show ciphertext metadata (no key needed)"),
+            Entry::new("check", "Check ciphertext integrity (no output)"),
+            Entry::new("sign", "Sign message (Ed25519)"),
+            Entry::nested("--keygen", "Generate signing keys"),
+            Entry::new("verify", "Check message signature"),
+            Entry::nested("--signature <SIG>", "Signature (base64, or file)"),
+            Entry::new("man", "Print the man page"),
+        ],
+    },
+    Section {
+        title: "Args",
+        entries: &[
+            Entry::new("<MESSAGE>", "Message (with -f, associated data)"),
+            Entry::new(
+                "-a, --algorithm <ALGO>",
+                "Cipher algorithm (default: ChaCha20-Poly1305)",
+            ),
+            Entry::new("-k, --key <KEY>", "Cipher key (base64)"),
+            Entry::new("--key-name <NAME>", "Use the named key from the keyring"),
+            Entry::new("--password", "Derive the key from a passphrase"),
+            Entry::new("-r, --raw", "Handle message as raw binary"),
+            Entry::new("--base64", "Handle message as base64 text"),
+            Entry::new("--armor", "Wrap base64 in BEGIN/END markers"),
+            Entry::new("--qr", "Show key or ciphertext as a QR code"),
+            Entry::new("--encoding <ENC>", "Use hex or base32 instead of base64"),
+            Entry::new("--framed", "One message per line, one frame each"),
+            Entry::nested("--continue-on-error", "Skip frames that fail to decrypt"),
+            Entry::new("-f, --file <FILE>", "Read message from file"),
+            Entry::nested("-i, --in-place", "Write output to input file"),
+            Entry::nested("-y, --yes", "Do not ask for confirmation"),
+            Entry::nested("--preserve-times", "Keep the times of the input file"),
+            Entry::new("--max-stdin-size <N>", "Fail if stdin exceeds N bytes"),
+            Entry::new("--buffer-size <N>", "Buffer N bytes of output per write"),
+            Entry::new("--mmap", "Memory-map the input file"),
+            Entry::new("-o, --output <FILE>", "Write output to file (or directory)"),
+            Entry::nested("--force", "Overwrite the output file if it exists"),
+            Entry::new("--suffix <SUFFIX>", "Name output after input (e.g., .enc)"),
+            Entry::new("--shred", "Overwrite and delete input once encrypted"),
+            Entry::new("--expire <DURATION>", "Expire the message (e.g., 48h)"),
+            Entry::nested("--strict", "Refuse to decrypt expired messages"),
+            Entry::new("--legacy", "Decrypt outdated formats (see migrate)"),
+            Entry::new("--id-hint", "Embed a hint of the key in the message"),
+            Entry::new("--peek <N>", "Decrypt and show only the first N bytes"),
+            Entry::new("--range <START:END>", "Decrypt only the bytes in a range"),
+            Entry::new("--pre-filter <CMD>", "Pipe input through CMD, then encrypt"),
+            Entry::new(
+                "--post-filter <CMD>",
+                "Decrypt, then pipe output through CMD",
+            ),
+            Entry::new("--no-warn <ID,...>", "Silence warnings (e.g., W001)"),
+            Entry::new("--no-progress", "Do not show a progress bar"),
+            Entry::new("--dry-run", "Show what would be done, and exit"),
+            Entry::new(
+                "--entropy <SOURCE>",
+                "Randomness: system, strict, or device",
+            ),
+        ],
+    },
+    Section {
+        title: "Options",
+        entries: &[
+            Entry::new("-h, --help", "Show help message and exit"),
+            Entry::nested("--no-pager", "Do not page the help message"),
+            Entry::new("-V, --version", "Show the version and exit"),
+            Entry::new("-v, --verbose", "Show key source, algorithm, and sizes"),
+            Entry::new("-q, --quiet", "Show errors only"),
+        ],
+    },
+];
+
+pub fn short_help_message() -> String {
+    let mut help = format!(
+        "{description}\n\nUsage: {bin} {USAGE}\n",
+        description = env!("CARGO_PKG_DESCRIPTION"),
+        bin = env!("CARGO_BIN_NAME"),
+    );
+    for section in SECTIONS {
+        _ = write!(help, "\n{}:\n", section.title);
+        for entry in section.entries {
+            let indent = if entry.nested { "    " } else { "  " };
+            let usage = format!("{indent}{}", entry.usage);
+            _ = writeln!(help, "{usage:<DESCRIPTION_COLUMN$}{}", entry.description);
+        }
+    }
+    help
+}
+
+/// Render the man page, in `roff` format.
+pub fn man_page() -> String {
+    let bin = env!("CARGO_BIN_NAME");
+    let mut man = format!(
+        "\
+.TH {name} 1 \"\" \"{package} {version}\" \"User Commands\"
+.SH NAME
+{bin} \\- {description}
+.SH SYNOPSIS
+.B {bin}
+{usage}
+",
+        name = bin.to_uppercase(),
+        package = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        description = roff_escape(env!("CARGO_PKG_DESCRIPTION")),
+        usage = roff_escape(USAGE),
+    );
+    for section in SECTIONS {
+        _ = writeln!(man, ".SH {}", section.title.to_uppercase());
+        for entry in section.entries {
+            if entry.nested {
+                man.push_str(".RS\n");
+            }
+            _ = write!(
+                man,
+                ".TP\n.B {}\n{}\n",
+                roff_escape(entry.usage),
+                roff_escape(entry.description)
+            );
+            if entry.nested {
+                man.push_str(".RE\n");
+            }
+        }
+    }
+    _ = write!(
+        man,
+        ".SH SEE ALSO\nFull documentation: \\fB{bin} \\-\\-help\\fR\n"
+    );
+    man
+}
+
+fn roff_escape(text: &str) -> String {
+    let text = text.replace('\\', "\\\\").replace('-', "\\-");
+    // Leading `.` and `'` are control characters.
+    if text.starts_with(['.', '\'']) {
+        format!("\\&{text}")
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_help_has_all_sections() {
+        let help = short_help_message();
+
+        for section in SECTIONS {
+            assert!(help.contains(&format!("\n{}:\n", section.title)));
+        }
+    }
+
+    #[test]
+    fn short_help_descriptions_are_aligned() {
+        let help = short_help_message();
+
+        for entry in SECTIONS.iter().flat_map(|s| s.entries) {
+            let line = help
+                .lines()
+                .find(|line| line.trim_start().starts_with(entry.usage))
+                .unwrap();
+            assert_eq!(
+                line.find(entry.description),
+                Some(DESCRIPTION_COLUMN),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn short_help_usages_fit_in_column() {
+        for entry in SECTIONS.iter().flat_map(|s| s.entries) {
+            let indent = if entry.nested { 4 } else { 2 };
+            // At least 2 spaces between usage and description.
+            assert!(
+                indent + entry.usage.len() + 2 <= DESCRIPTION_COLUMN,
+                "{}",
+                entry.usage
+            );
+        }
+    }
+
+    #[test]
+    fn man_page_has_all_entries() {
+        let man = man_page();
+
+        for section in SECTIONS {
+            assert!(man.contains(&format!(".SH {}\n", section.title.to_uppercase())));
+            for entry in section.entries {
+                assert!(man.contains(&format!(".B {}\n", roff_escape(entry.usage))));
+                assert!(man.contains(&roff_escape(entry.description)));
+            }
+        }
+    }
+
+    #[test]
+    fn roff_escape_dashes_and_backslashes() {
+        assert_eq!(roff_escape("-a, --algo"), "\\-a, \\-\\-algo");
+        assert_eq!(roff_escape("a\\b"), "a\\\\b");
+    }
+
+    #[test]
+    fn roff_escape_leading_control_character() {
+        assert_eq!(roff_escape(".foo"), "\\&.foo");
+        assert_eq!(roff_escape("'foo"), "\\&'foo");
+    }
+}
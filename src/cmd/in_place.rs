@@ -0,0 +1,214 @@
+//! Crash-safe in-place file replacement.
+//!
+//! The output is written to a temporary file next to the original (in
+//! the same directory, so on the same file system, where a rename is
+//! atomic). Once it is complete, it is flushed to disk and renamed over
+//! the original, and then the directory is flushed, so the rename
+//! itself survives a crash.
+//!
+//! At any point, the original is either intact or fully replaced. If
+//! anything fails, or if interrupted (see [`signal`]), the temporary
+//! file is removed.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::CliError;
+use super::metadata::Metadata;
+use super::signal;
+use super::ui::log;
+
+/// A temporary file that replaces `file` on [`commit()`], or that is
+/// removed if dropped before.
+///
+/// [`commit()`]: InPlaceFile::commit
+pub struct InPlaceFile {
+    file: PathBuf,
+    temp_path: PathBuf,
+    temp: fs::File,
+    metadata: Option<Metadata>,
+    committed: bool,
+}
+
+impl InPlaceFile {
+    /// Create `temp_path`, to replace `file`.
+    ///
+    /// `temp_path` must be in the same directory as `file`, and must not
+    /// exist. It gets the metadata of `file` (with its times if
+    /// `with_times`, see [`Metadata`]).
+    pub fn create(file: &Path, temp_path: &Path, with_times: bool) -> Result<Self, CliError> {
+        let open_error = |e: io::Error| {
+            CliError::Io(format!(
+                "Could not open file for writing '{}': {e}",
+                temp_path.display()
+            ))
+        };
+        signal::install_handler();
+        let temp = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)
+            .map_err(open_error)?;
+        let in_place = Self {
+            file: file.to_path_buf(),
+            temp_path: temp_path.to_path_buf(),
+            temp,
+            metadata: Metadata::of(file, with_times).ok(),
+            committed: false,
+        };
+        signal::remove_on_exit(temp_path);
+        log::info(format_args!("temporary file: '{}'", temp_path.display()));
+
+        if let Some(ref metadata) = in_place.metadata {
+            metadata.apply_access(&in_place.temp).map_err(open_error)?;
+        }
+        Ok(in_place)
+    }
+
+    /// Write into the temporary file, through a buffer of `buffer_size`
+    /// bytes.
+    pub fn writer(&self, buffer_size: usize) -> Result<Box<dyn Write + Send>, CliError> {
+        let temp = self.temp.try_clone().map_err(|e| {
+            CliError::Io(format!(
+                "Could not open file for writing '{}': {e}",
+                self.temp_path.display()
+            ))
+        })?;
+        Ok(Box::new(io::BufWriter::with_capacity(buffer_size, temp)))
+    }
+
+    /// Replace `file` with the temporary file.
+    ///
+    /// Writers must have been flushed.
+    pub fn commit(mut self) -> Result<(), CliError> {
+        let override_error = |e: io::Error| {
+            CliError::Io(format!("Could not override '{}': {e}", self.file.display()))
+        };
+
+        if let Some(ref metadata) = self.metadata {
+            metadata.apply_times(&self.temp).map_err(override_error)?;
+        }
+        self.temp.sync_all().map_err(override_error)?;
+        fs::rename(&self.temp_path, &self.file).map_err(override_error)?;
+        self.committed = true;
+        signal::keep(&self.temp_path);
+
+        // The file is replaced, but the rename could still be lost in a
+        // crash. Directories can only be synced on Unix.
+        #[cfg(unix)]
+        if let Some(dir) = self.file.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            fs::File::open(dir)
+                .and_then(|dir| dir.sync_all())
+                .map_err(override_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for InPlaceFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            _ = fs::remove_file(&self.temp_path);
+            signal::keep(&self.temp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    use crate::cmd::cli::DEFAULT_BUFFER_SIZE;
+
+    /// A fresh directory, removed on drop.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("jolokia-in-place-{name}-{}", process::id()));
+            _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn commit_replaces_file() {
+        let dir = TestDir::new("commit");
+        let file = dir.0.join("file.txt");
+        let temp_path = dir.0.join("file.tmp");
+        fs::write(&file, "old").unwrap();
+
+        let in_place = InPlaceFile::create(&file, &temp_path, false).unwrap();
+        let mut writer = in_place.writer(DEFAULT_BUFFER_SIZE).unwrap();
+        writer.write_all(b"new").unwrap();
+        writer.flush().unwrap();
+        in_place.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert!(!temp_path.exists());
+    }
+
+    #[test]
+    fn drop_without_commit_removes_temporary_file() {
+        let dir = TestDir::new("drop");
+        let file = dir.0.join("file.txt");
+        let temp_path = dir.0.join("file.tmp");
+        fs::write(&file, "old").unwrap();
+
+        let in_place = InPlaceFile::create(&file, &temp_path, false).unwrap();
+        in_place
+            .writer(DEFAULT_BUFFER_SIZE)
+            .unwrap()
+            .write_all(b"new")
+            .unwrap();
+        drop(in_place);
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old");
+        assert!(!temp_path.exists());
+    }
+
+    #[test]
+    fn existing_temporary_file_is_not_overwritten() {
+        let dir = TestDir::new("exists");
+        let file = dir.0.join("file.txt");
+        let temp_path = dir.0.join("file.tmp");
+        fs::write(&temp_path, "someone else's").unwrap();
+
+        assert!(InPlaceFile::create(&file, &temp_path, false).is_err());
+        assert_eq!(fs::read_to_string(&temp_path).unwrap(), "someone else's");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temporary_file_has_permissions_of_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TestDir::new("permissions");
+        let file = dir.0.join("file.txt");
+        let temp_path = dir.0.join("file.tmp");
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let in_place = InPlaceFile::create(&file, &temp_path, false).unwrap();
+        in_place.commit().unwrap();
+
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
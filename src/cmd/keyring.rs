@@ -0,0 +1,267 @@
+//! Named keys, kept together in a keyring file.
+//!
+//! The keyring lives in `$XDG_CONFIG_HOME/jolokia/keyring.toml`, or in
+//! `~/.config/jolokia/keyring.toml`. It holds one table per key:
+//!
+//! ```toml
+//! # Shared with the team.
+//! [work]
+//! algorithm = "chacha20-poly1305"
+//! key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+//!
+//! [backups]
+//! algorithm = "hpke"
+//! key = "~/.keys/backups.pub"
+//! ```
+//!
+//! `key` is either the key itself, or a key file. `algorithm` is
+//! optional, it takes the same names as `--algorithm`.
+//!
+//! Only the subset of TOML needed for this is understood: tables,
+//! basic strings (without escapes), and comments.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use secrecy::{ExposeSecret, SecretString, zeroize::Zeroizing};
+
+use super::cli::Algorithm;
+use super::environment::Environment;
+
+#[derive(Debug)]
+pub struct Entry {
+    pub algorithm: Option<Algorithm>,
+    pub key: SecretString,
+}
+
+/// Location of the keyring file, if it can be known.
+pub fn path(env: &dyn Environment) -> Option<PathBuf> {
+    let config = match env.var("XDG_CONFIG_HOME") {
+        Some(config) if !config.is_empty() => PathBuf::from(config),
+        _ => PathBuf::from(env.var("HOME")?).join(".config"),
+    };
+    Some(config.join("jolokia").join("keyring.toml"))
+}
+
+/// Find the key named `name` in the keyring.
+///
+/// A leading `~/` in the key is expanded, so key files can be given
+/// relative to the home directory.
+pub fn lookup(name: &str, env: &dyn Environment) -> Result<Entry, String> {
+    let Some(path) = path(env) else {
+        return Err("Cannot locate the keyring, 'HOME' is not set".to_string());
+    };
+    let mut entries = load(&path)?;
+
+    let Some(position) = entries.iter().position(|(n, _)| n == name) else {
+        return Err(format!(
+            "No key named '{name}' in the keyring '{}'",
+            path.display()
+        ));
+    };
+    let (_, mut entry) = entries.swap_remove(position);
+
+    if let Some(file) = entry.key.expose_secret().strip_prefix("~/")
+        && let Some(home) = env.var("HOME")
+    {
+        entry.key = SecretString::from(Path::new(&home).join(file).display().to_string());
+    }
+
+    Ok(entry)
+}
+
+fn load(path: &Path) -> Result<Vec<(String, Entry)>, String> {
+    let content = Zeroizing::new(
+        fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read the keyring '{}': {e}", path.display()))?,
+    );
+    parse(&content).map_err(|e| format!("Invalid keyring '{}': {e}", path.display()))
+}
+
+fn parse(content: &str) -> Result<Vec<(String, Entry)>, String> {
+    let mut entries = Vec::new();
+    // Name, algorithm, and key of the table being read.
+    let mut table: Option<(String, Option<Algorithm>, Option<SecretString>)> = None;
+
+    for (i, line) in content.lines().enumerate() {
+        let line_nb = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = without_comment(name).strip_suffix(']') else {
+                return Err(format!("line {line_nb}: expected '[name]'"));
+            };
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(format!("line {line_nb}: empty key name"));
+            }
+            if entries.iter().any(|(n, _)| n == name)
+                || table.as_ref().is_some_and(|(n, _, _)| n == name)
+            {
+                return Err(format!("line {line_nb}: duplicate key '{name}'"));
+            }
+            if let Some(previous) = table.take() {
+                entries.push(into_entry(previous)?);
+            }
+            table = Some((name.to_string(), None, None));
+            continue;
+        }
+
+        let Some((field, value)) = line.split_once('=') else {
+            return Err(format!(
+                "line {line_nb}: expected '[name]' or 'field = \"value\"'"
+            ));
+        };
+        let Some((_, algorithm, key)) = table.as_mut() else {
+            return Err(format!("line {line_nb}: field outside of a '[name]'"));
+        };
+        let Some(value) = string_value(value) else {
+            return Err(format!("line {line_nb}: expected a quoted string"));
+        };
+
+        match field.trim() {
+            "algorithm" => {
+                let Ok(value) = value.parse() else {
+                    return Err(format!("line {line_nb}: unrecognized algorithm '{value}'"));
+                };
+                *algorithm = Some(value);
+            }
+            "key" => *key = Some(SecretString::from(value)),
+            field => return Err(format!("line {line_nb}: unknown field '{field}'")),
+        }
+    }
+
+    if let Some(last) = table {
+        entries.push(into_entry(last)?);
+    }
+
+    Ok(entries)
+}
+
+fn into_entry(
+    (name, algorithm, key): (String, Option<Algorithm>, Option<SecretString>),
+) -> Result<(String, Entry), String> {
+    let Some(key) = key else {
+        return Err(format!("'{name}' has no 'key'"));
+    };
+    Ok((name, Entry { algorithm, key }))
+}
+
+/// Parse `"value"`, optionally followed by a comment.
+fn string_value(value: &str) -> Option<&str> {
+    let value = value.trim().strip_prefix('"')?;
+    let (value, rest) = value.split_once('"')?;
+    if value.contains('\\') {
+        return None;
+    }
+    without_comment(rest).is_empty().then_some(value)
+}
+
+/// Strip a trailing `# comment`, and whitespace.
+fn without_comment(line: &str) -> &str {
+    line.split_once('#')
+        .map_or(line, |(line, _)| line)
+        .trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cmd::environment::Fake;
+
+    const KEYRING: &str = r#"
+# Shared with the team.
+[work]
+algorithm = "chacha20-poly1305"
+key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"  # Rotated yearly.
+
+[backups]
+key = "~/.keys/backups.key"
+"#;
+
+    #[test]
+    fn parse_regular() {
+        let entries = parse(KEYRING).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "work");
+        assert_eq!(entries[0].1.algorithm, Some(Algorithm::ChaCha20Poly1305));
+        assert_eq!(
+            entries[0].1.key.expose_secret(),
+            "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ"
+        );
+        assert_eq!(entries[1].0, "backups");
+        assert_eq!(entries[1].1.algorithm, None);
+        assert_eq!(entries[1].1.key.expose_secret(), "~/.keys/backups.key");
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(parse("# Nothing yet.\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_missing_key_is_error() {
+        let err = parse("[work]\nalgorithm = \"hpke\"\n").unwrap_err();
+        assert_eq!(err, "'work' has no 'key'");
+    }
+
+    #[test]
+    fn parse_duplicate_name_is_error() {
+        let err = parse("[work]\nkey = \"a\"\n[work]\nkey = \"b\"\n").unwrap_err();
+        assert_eq!(err, "line 3: duplicate key 'work'");
+    }
+
+    #[test]
+    fn parse_field_outside_table_is_error() {
+        let err = parse("key = \"a\"\n").unwrap_err();
+        assert_eq!(err, "line 1: field outside of a '[name]'");
+    }
+
+    #[test]
+    fn parse_unknown_field_is_error() {
+        let err = parse("[work]\nkey = \"a\"\ncolor = \"red\"\n").unwrap_err();
+        assert_eq!(err, "line 3: unknown field 'color'");
+    }
+
+    #[test]
+    fn parse_unquoted_value_is_error() {
+        let err = parse("[work]\nkey = a\n").unwrap_err();
+        assert_eq!(err, "line 2: expected a quoted string");
+    }
+
+    #[test]
+    fn parse_unrecognized_algorithm_is_error() {
+        let err = parse("[work]\nalgorithm = \"des\"\nkey = \"a\"\n").unwrap_err();
+        assert_eq!(err, "line 2: unrecognized algorithm 'des'");
+    }
+
+    #[test]
+    fn path_xdg_config_home() {
+        let env = Fake::default()
+            .with_var("XDG_CONFIG_HOME", "/config")
+            .with_var("HOME", "/home/user");
+        assert_eq!(
+            path(&env),
+            Some(PathBuf::from("/config/jolokia/keyring.toml"))
+        );
+    }
+
+    #[test]
+    fn path_home() {
+        let env = Fake::default().with_var("HOME", "/home/user");
+        assert_eq!(
+            path(&env),
+            Some(PathBuf::from("/home/user/.config/jolokia/keyring.toml"))
+        );
+    }
+
+    #[test]
+    fn path_unknown() {
+        assert_eq!(path(&Fake::default()), None);
+    }
+}
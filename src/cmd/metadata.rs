@@ -0,0 +1,200 @@
+//! File metadata, carried over from the input to the output.
+//!
+//! An encrypted backup should be as protected as the original, and a
+//! restored file should be usable like the original (e.g., a script
+//! stays executable). So the output gets the permissions and, where
+//! allowed, the ownership of the input. Times are only carried over on
+//! demand (`--preserve-times`), by default, the output is new.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::CliError;
+
+/// What is carried over from a file.
+pub struct Metadata {
+    permissions: fs::Permissions,
+    #[cfg(unix)]
+    owner: (u32, u32),
+    times: Option<fs::FileTimes>,
+}
+
+impl Metadata {
+    /// The metadata of `file`, with its times if `with_times`.
+    pub fn of(file: &Path, with_times: bool) -> io::Result<Self> {
+        let metadata = fs::metadata(file)?;
+        let times = if with_times {
+            Some(
+                fs::FileTimes::new()
+                    .set_accessed(metadata.accessed()?)
+                    .set_modified(metadata.modified()?),
+            )
+        } else {
+            None
+        };
+        Ok(Self {
+            permissions: metadata.permissions(),
+            #[cfg(unix)]
+            owner: {
+                use std::os::unix::fs::MetadataExt;
+                (metadata.uid(), metadata.gid())
+            },
+            times,
+        })
+    }
+
+    /// Give `file` the same permissions and ownership.
+    ///
+    /// This must happen before anything is written, or the content
+    /// could briefly (or, on failure, permanently) be less protected
+    /// than the original.
+    pub fn apply_access(&self, file: &fs::File) -> io::Result<()> {
+        // Only root can give files away, users can only change the group
+        // (to one of theirs). Keeping the defaults is not an error.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::fchown;
+
+            let (uid, gid) = self.owner;
+            if fchown(file, Some(uid), Some(gid)).is_err() {
+                _ = fchown(file, None, Some(gid));
+            }
+        }
+        // After `chown()`, which can clear set-user-ID bits.
+        file.set_permissions(self.permissions.clone())
+    }
+
+    /// Give `file` the same times, if they were kept.
+    ///
+    /// This must happen after everything is written, or writing would
+    /// update them.
+    pub fn apply_times(&self, file: &fs::File) -> io::Result<()> {
+        match self.times {
+            Some(times) => file.set_times(times),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An output file, created with the metadata of an input file.
+pub struct OutputFile {
+    file: fs::File,
+    metadata: Metadata,
+}
+
+impl OutputFile {
+    /// Create `file`, like `input` (with its times if `with_times`).
+    pub fn create(file: &Path, input: &Path, with_times: bool) -> Result<Self, CliError> {
+        let metadata = Metadata::of(input, with_times)
+            .map_err(|e| CliError::Io(format!("Could not read '{}': {e}", input.display())))?;
+        let open_error = |e: io::Error| {
+            CliError::Io(format!(
+                "Could not open file for writing '{}': {e}",
+                file.display()
+            ))
+        };
+        let output = fs::File::create(file).map_err(open_error)?;
+        metadata.apply_access(&output).map_err(open_error)?;
+        Ok(Self {
+            file: output,
+            metadata,
+        })
+    }
+
+    /// Write into the file, through a buffer of `buffer_size` bytes.
+    pub fn writer(&self, buffer_size: usize) -> Result<Box<dyn Write + Send>, CliError> {
+        let file = self
+            .file
+            .try_clone()
+            .map_err(|e| CliError::Io(format!("Could not open file for writing: {e}")))?;
+        Ok(Box::new(io::BufWriter::with_capacity(buffer_size, file)))
+    }
+
+    /// Apply the times, once everything is written.
+    ///
+    /// Writers must have been flushed.
+    pub fn finish(self) -> Result<(), CliError> {
+        self.metadata
+            .apply_times(&self.file)
+            .map_err(|e| CliError::Io(format!("Could not set file times: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+    use std::time::{Duration, SystemTime};
+
+    fn test_file(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("jolokia-metadata-{name}-{}", process::id()))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn apply_access_copies_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (from, to) = (test_file("perm-from"), test_file("perm-to"));
+        fs::write(&from, "").unwrap();
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o751)).unwrap();
+
+        let metadata = Metadata::of(&from, false).unwrap();
+        metadata
+            .apply_access(&fs::File::create(&to).unwrap())
+            .unwrap();
+        let mode = fs::metadata(&to).unwrap().permissions().mode();
+        _ = fs::remove_file(&from);
+        _ = fs::remove_file(&to);
+
+        assert_eq!(mode & 0o777, 0o751);
+    }
+
+    #[test]
+    fn apply_times_copies_modification_time() {
+        let (from, to) = (test_file("times-from"), test_file("times-to"));
+        fs::write(&from, "").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let metadata = Metadata::of(&from, true).unwrap();
+        metadata
+            .apply_times(&fs::File::create(&to).unwrap())
+            .unwrap();
+        let copied = fs::metadata(&to).unwrap().modified().unwrap();
+        _ = fs::remove_file(&from);
+        _ = fs::remove_file(&to);
+
+        assert_eq!(copied, modified);
+    }
+
+    #[test]
+    fn apply_times_without_times_does_nothing() {
+        let (from, to) = (test_file("no-times-from"), test_file("no-times-to"));
+        fs::write(&from, "").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let metadata = Metadata::of(&from, false).unwrap();
+        metadata
+            .apply_times(&fs::File::create(&to).unwrap())
+            .unwrap();
+        let modified = fs::metadata(&to).unwrap().modified().unwrap();
+        _ = fs::remove_file(&from);
+        _ = fs::remove_file(&to);
+
+        assert_ne!(modified, SystemTime::UNIX_EPOCH);
+    }
+}
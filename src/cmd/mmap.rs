@@ -0,0 +1,96 @@
+//! Memory-mapped input (`--mmap`).
+//!
+//! A regular file is read straight from a map of it, instead of through
+//! `read()` calls into a small buffer. On large files and slow disks,
+//! the kernel reads ahead in bigger strides.
+//!
+//! Anything that can't be mapped (pipes, devices, empty files, or a map
+//! that fails) is read the usual way, chunk by chunk.
+//!
+//! The map is read-only, but another process could still change the
+//! file while it is being read. If it is truncated, reading past the new
+//! end kills the process (`SIGBUS`). This is why mapping is opt-in.
+
+use std::fs;
+use std::io::{self, Read};
+
+use memmap2::Mmap;
+
+/// Read `file` from a map of it, if possible.
+pub fn reader(file: fs::File) -> Box<dyn Read + Send> {
+    match map(&file) {
+        Some(map) => Box::new(io::Cursor::new(map)),
+        None => Box::new(io::BufReader::new(file)),
+    }
+}
+
+fn map(file: &fs::File) -> Option<Mmap> {
+    let metadata = file.metadata().ok()?;
+    // Pipes and devices have no fixed size, empty files can't be mapped.
+    if !metadata.is_file() || metadata.len() == 0 {
+        return None;
+    }
+    // SAFETY: The map is only read. The file changing underneath is the
+    // caveat of `--mmap` (see the module documentation).
+    let map = unsafe { Mmap::map(file) }.ok()?;
+    // Ciphers read from start to end, the kernel can read ahead.
+    #[cfg(unix)]
+    {
+        _ = map.advise(memmap2::Advice::Sequential);
+    }
+    Some(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    fn test_file(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("jolokia-mmap-{name}-{}", process::id()))
+    }
+
+    fn read_all(mut reader: Box<dyn Read + Send>) -> Vec<u8> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn reader_regular_file_is_mapped() {
+        let file = test_file("regular");
+        let content: Vec<u8> = (0..100_000u32).map(|i| i.to_le_bytes()[0]).collect();
+        fs::write(&file, &content).unwrap();
+
+        let f = fs::File::open(&file).unwrap();
+        assert!(map(&f).is_some());
+        let read = read_all(reader(f));
+        _ = fs::remove_file(&file);
+
+        assert_eq!(read, content);
+    }
+
+    #[test]
+    fn reader_empty_file_falls_back() {
+        let file = test_file("empty");
+        fs::write(&file, "").unwrap();
+
+        let f = fs::File::open(&file).unwrap();
+        assert!(map(&f).is_none());
+        let read = read_all(reader(f));
+        _ = fs::remove_file(&file);
+
+        assert!(read.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reader_device_falls_back() {
+        let f = fs::File::open("/dev/null").unwrap();
+
+        assert!(map(&f).is_none());
+        assert!(read_all(reader(f)).is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+//! The phases of an encryption or decryption.
+//!
+//! An [`Operation`] goes through the same phases every time:
+//!
+//! ```text
+//! Resolve → Plan → Execute → Commit → Done
+//! ```
+//!
+//! - _Resolve_ settles the algorithm, and builds the cipher.
+//! - _Plan_ decides what is going to happen (see [`ExecutionPlan`]).
+//!   With `--dry-run`, this is where it ends.
+//! - _Execute_ asks what must be asked (confirmation, key), wires the
+//!   input and the output, and runs the cipher.
+//! - _Commit_ moves in-place output into place, once everything else
+//!   has succeeded.
+//!
+//! The order lives here, what each phase does lives in [`Phases`]. This
+//! keeps the transitions testable without touching the terminal or the
+//! file system.
+
+use jolokia::traits::Cipher;
+
+use super::plan::ExecutionPlan;
+use super::{CliError, FramedReport};
+
+/// What each phase does. [`Operation`] decides when.
+pub trait Phases {
+    /// Settle the algorithm, and build the cipher.
+    fn resolve(&mut self) -> Result<Box<dyn Cipher>, CliError>;
+
+    /// Decide what is going to happen.
+    fn plan(&mut self) -> Result<ExecutionPlan, CliError>;
+
+    /// Stop after planning (`--dry-run`).
+    fn dry_run(&self) -> bool;
+
+    /// Ask what must be asked, wire the input and the output, and run
+    /// the cipher.
+    fn execute(
+        &mut self,
+        cipher: &dyn Cipher,
+        plan: &ExecutionPlan,
+    ) -> Result<FramedReport, CliError>;
+
+    /// Move the output of an in-place operation over the input.
+    fn commit(&mut self, plan: &ExecutionPlan) -> Result<(), CliError>;
+}
+
+/// How an operation ended.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Completed,
+    /// `--dry-run`, nothing was done.
+    Planned(ExecutionPlan),
+    /// Some frames were skipped (`--continue-on-error`).
+    Partial(FramedReport),
+}
+
+pub enum Operation {
+    Resolve,
+    Plan {
+        cipher: Box<dyn Cipher>,
+    },
+    Execute {
+        cipher: Box<dyn Cipher>,
+        plan: ExecutionPlan,
+    },
+    Commit {
+        plan: ExecutionPlan,
+        report: FramedReport,
+    },
+    Done(Outcome),
+}
+
+impl Operation {
+    /// Go through all the phases.
+    pub fn run(phases: &mut dyn Phases) -> Result<Outcome, CliError> {
+        let mut operation = Self::Resolve;
+        loop {
+            operation = match operation.step(phases)? {
+                Self::Done(outcome) => return Ok(outcome),
+                operation => operation,
+            };
+        }
+    }
+
+    /// Go to the next phase.
+    pub fn step(self, phases: &mut dyn Phases) -> Result<Self, CliError> {
+        Ok(match self {
+            Self::Resolve => Self::Plan {
+                cipher: phases.resolve()?,
+            },
+            Self::Plan { cipher } => {
+                let plan = phases.plan()?;
+                if phases.dry_run() {
+                    Self::Done(Outcome::Planned(plan))
+                } else {
+                    Self::Execute { cipher, plan }
+                }
+            }
+            Self::Execute { cipher, plan } => {
+                let report = phases.execute(cipher.as_ref(), &plan)?;
+                Self::Commit { plan, report }
+            }
+            Self::Commit { plan, report } => {
+                // In-place operations can't skip frames (see
+                // `ExecutionPlan::new()`), there's never anything to
+                // commit after a partial failure.
+                if report.nb_skipped > 0 {
+                    Self::Done(Outcome::Partial(report))
+                } else {
+                    if plan.in_place {
+                        phases.commit(&plan)?;
+                    }
+                    Self::Done(Outcome::Completed)
+                }
+            }
+            Self::Done(outcome) => Self::Done(outcome),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use jolokia::cipher::ChaCha20Poly1305;
+
+    use super::*;
+    use crate::cmd::cli::{self, KeySource};
+    use crate::cmd::plan::{Encoding, Input};
+
+    #[derive(Default)]
+    struct Fake {
+        dry_run: bool,
+        in_place: bool,
+        nb_skipped: usize,
+        fail_at: Option<&'static str>,
+        calls: Vec<&'static str>,
+    }
+
+    impl Fake {
+        fn call(&mut self, phase: &'static str) -> Result<(), CliError> {
+            self.calls.push(phase);
+            if self.fail_at == Some(phase) {
+                return Err(format!("{phase} failed").into());
+            }
+            Ok(())
+        }
+    }
+
+    impl Phases for Fake {
+        fn resolve(&mut self) -> Result<Box<dyn Cipher>, CliError> {
+            self.call("resolve")?;
+            Ok(Box::new(ChaCha20Poly1305))
+        }
+
+        fn plan(&mut self) -> Result<ExecutionPlan, CliError> {
+            self.call("plan")?;
+            Ok(plan(self.in_place))
+        }
+
+        fn dry_run(&self) -> bool {
+            self.dry_run
+        }
+
+        fn execute(&mut self, _: &dyn Cipher, _: &ExecutionPlan) -> Result<FramedReport, CliError> {
+            self.call("execute")?;
+            Ok(FramedReport {
+                nb_frames: 3,
+                nb_skipped: self.nb_skipped,
+            })
+        }
+
+        fn commit(&mut self, _: &ExecutionPlan) -> Result<(), CliError> {
+            self.call("commit")
+        }
+    }
+
+    fn plan(in_place: bool) -> ExecutionPlan {
+        let file = PathBuf::from("cat.gif");
+        ExecutionPlan {
+            command: cli::Command::Encrypt,
+            algorithm: cli::Algorithm::ChaCha20Poly1305,
+            key_source: KeySource::Argument,
+            input: Input::File(file.clone()),
+            output: cli::Output::File(file.clone()),
+            encoding: Encoding::Default,
+            pre_filter: None,
+            post_filter: None,
+            in_place,
+            temp_path: in_place.then(|| file.with_extension("jolokia")),
+        }
+    }
+
+    #[test]
+    fn operation_regular() {
+        let mut phases = Fake::default();
+
+        let outcome = Operation::run(&mut phases).unwrap();
+
+        assert_eq!(outcome, Outcome::Completed);
+        assert_eq!(phases.calls, ["resolve", "plan", "execute"]);
+    }
+
+    #[test]
+    fn operation_in_place_commits() {
+        let mut phases = Fake {
+            in_place: true,
+            ..Default::default()
+        };
+
+        let outcome = Operation::run(&mut phases).unwrap();
+
+        assert_eq!(outcome, Outcome::Completed);
+        assert_eq!(phases.calls, ["resolve", "plan", "execute", "commit"]);
+    }
+
+    #[test]
+    fn operation_dry_run_stops_after_plan() {
+        let mut phases = Fake {
+            dry_run: true,
+            in_place: true,
+            ..Default::default()
+        };
+
+        let outcome = Operation::run(&mut phases).unwrap();
+
+        assert_eq!(outcome, Outcome::Planned(plan(true)));
+        assert_eq!(phases.calls, ["resolve", "plan"]);
+    }
+
+    #[test]
+    fn operation_partial_failure() {
+        let mut phases = Fake {
+            nb_skipped: 1,
+            ..Default::default()
+        };
+
+        let outcome = Operation::run(&mut phases).unwrap();
+
+        assert_eq!(
+            outcome,
+            Outcome::Partial(FramedReport {
+                nb_frames: 3,
+                nb_skipped: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn operation_failed_execution_does_not_commit() {
+        let mut phases = Fake {
+            in_place: true,
+            fail_at: Some("execute"),
+            ..Default::default()
+        };
+
+        let err = Operation::run(&mut phases).unwrap_err();
+
+        assert_eq!(err.to_string(), "execute failed");
+        assert_eq!(phases.calls, ["resolve", "plan", "execute"]);
+    }
+
+    #[test]
+    fn operation_step_by_step() {
+        let mut phases = Fake::default();
+
+        let operation = Operation::Resolve.step(&mut phases).unwrap();
+        assert!(matches!(operation, Operation::Plan { .. }));
+
+        let operation = operation.step(&mut phases).unwrap();
+        assert!(matches!(operation, Operation::Execute { .. }));
+
+        let operation = operation.step(&mut phases).unwrap();
+        assert!(matches!(operation, Operation::Commit { .. }));
+
+        let operation = operation.step(&mut phases).unwrap();
+        assert!(matches!(operation, Operation::Done(Outcome::Completed)));
+    }
+}
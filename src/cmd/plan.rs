@@ -0,0 +1,435 @@
+//! What an encryption or decryption is going to do.
+//!
+//! All decisions (where the key comes from, where the output goes, and
+//! whether it goes through a temporary file) are made once, up front,
+//! in an [`ExecutionPlan`]. The plan is then either executed, or shown
+//! with `--dry-run`.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::Format;
+use super::cli::{self, KeySource};
+use super::environment::Environment;
+use super::qr;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Input {
+    /// The message is given on the command line.
+    Argument,
+    File(PathBuf),
+    Stdin,
+}
+
+/// How the ciphertext is encoded, and why.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    /// `--raw`, or `--base64`.
+    Explicit { raw: bool },
+    /// `--armor`.
+    Armored,
+    /// Base64, unless told otherwise.
+    Default,
+    /// Frames are lines of base64.
+    Framed,
+    /// Base64, as a QR code (`--qr`).
+    Qr(qr::Image),
+    /// `--encoding`, hex or base32 (or base64) instead of raw.
+    Text(cli::TextEncoding),
+    /// Told apart from the first bytes of the input.
+    Detected,
+}
+
+impl Encoding {
+    /// Decide how `command` encodes ciphertext.
+    ///
+    /// Encryption defaults to base64. Decryption (and inspection)
+    /// decides from the input, raw ciphertexts start with a header.
+    pub fn new(command: cli::Command, args: &cli::Args) -> Self {
+        if args.qr {
+            return Self::Qr(match args.output {
+                cli::Output::File(ref file) => qr::Image::for_file(file),
+                cli::Output::Stdout | cli::Output::Redirected => qr::Image::Text,
+            });
+        }
+        if let Some(encoding) = args.encoding {
+            return Self::Text(encoding);
+        }
+        match args.armor {
+            cli::Armor::Raw => Self::Explicit { raw: true },
+            cli::Armor::Base64 => Self::Explicit { raw: false },
+            cli::Armor::Armored => Self::Armored,
+            cli::Armor::Auto if args.framed => Self::Framed,
+            cli::Armor::Auto if command == cli::Command::Encrypt => Self::Default,
+            cli::Armor::Auto => Self::Detected,
+        }
+    }
+
+    /// How the ciphertext is encoded, `None` if it must be detected.
+    pub fn format(self) -> Option<Format> {
+        match self {
+            Self::Explicit { raw: true } => Some(Format::Raw),
+            Self::Explicit { raw: false }
+            | Self::Default
+            | Self::Framed
+            | Self::Text(cli::TextEncoding::Base64) => Some(Format::Base64),
+            Self::Armored => Some(Format::Armored),
+            Self::Qr(image) => Some(Format::Qr(image)),
+            Self::Text(cli::TextEncoding::Hex) => Some(Format::Hex),
+            Self::Text(cli::TextEncoding::Base32) => Some(Format::Base32),
+            Self::Detected => None,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ExecutionPlan {
+    pub command: cli::Command,
+    pub algorithm: cli::Algorithm,
+    pub key_source: KeySource,
+    pub input: Input,
+    pub output: cli::Output,
+    pub encoding: Encoding,
+    /// Command the input goes through, before encryption.
+    pub pre_filter: Option<String>,
+    /// Command the output goes through, after decryption.
+    pub post_filter: Option<String>,
+    /// The input file is also the output file.
+    pub in_place: bool,
+    /// In-place output is written here first, then renamed over the
+    /// input file (`Some` if, and only if, `in_place`).
+    pub temp_path: Option<PathBuf>,
+}
+
+impl ExecutionPlan {
+    pub fn new(
+        command: cli::Command,
+        args: &cli::Args,
+        env: &dyn Environment,
+    ) -> Result<Self, String> {
+        let algorithm = args.algorithm.unwrap_or_default();
+
+        let key_source = args.effective_key_source();
+
+        let input = match args.message {
+            Some(cli::Message::String(_)) => Input::Argument,
+            Some(cli::Message::File(ref file)) => Input::File(file.clone()),
+            Some(cli::Message::Stdin) => Input::Stdin,
+            None => return Err("You must provide a message".to_string()),
+        };
+
+        let in_place = match (&input, &args.output) {
+            (Input::File(input), cli::Output::File(output)) => is_same_file(input, output),
+            _ => false,
+        };
+
+        if in_place && args.continue_on_error {
+            // Replacing the input would lose the frames that failed.
+            return Err("'--continue-on-error' cannot be used in-place".to_string());
+        }
+
+        // FIFOs and devices cannot be replaced by the temporary file.
+        if in_place
+            && let Input::File(ref file) = input
+            && !file.is_file()
+        {
+            return Err(format!(
+                "Cannot work in-place on '{}', it is not a regular file",
+                file.display()
+            ));
+        }
+
+        let temp_path = match args.output {
+            cli::Output::File(ref file) if in_place => {
+                Some(build_temporary_file_path(file, env.now()))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            command,
+            algorithm,
+            key_source,
+            input,
+            output: args.output.clone(),
+            encoding: Encoding::new(command, args),
+            pre_filter: args.pre_filter.clone(),
+            post_filter: args.post_filter.clone(),
+            in_place,
+            temp_path,
+        })
+    }
+}
+
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    let (Ok(a), Ok(b)) = (a.canonicalize(), b.canonicalize()) else {
+        return false;
+    };
+    a == b
+}
+
+/// `<stem>.<timestamp>.<crate>`, next to the file.
+pub fn build_temporary_file_path(file: &Path, now: SystemTime) -> PathBuf {
+    let mut extension = env!("CARGO_CRATE_NAME").to_string();
+    if let Ok(timestamp) = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|t| t.as_micros())
+    {
+        extension = format!("{timestamp}.{extension}");
+    }
+    file.with_extension(extension)
+}
+
+impl fmt::Display for ExecutionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let command = match self.command {
+            cli::Command::Encrypt => "encrypt",
+            cli::Command::Decrypt => "decrypt",
+            _ => unreachable!("only encryption and decryption are planned"),
+        };
+        writeln!(f, "command:   {command}")?;
+        writeln!(f, "algorithm: {}", self.algorithm)?;
+
+        writeln!(f, "key:       {}", self.key_source)?;
+
+        match self.input {
+            Input::Argument => writeln!(f, "input:     argument")?,
+            Input::File(ref file) => writeln!(f, "input:     '{}'", file.display())?,
+            Input::Stdin => writeln!(f, "input:     stdin")?,
+        }
+        if let Some(ref pre_filter) = self.pre_filter {
+            writeln!(f, "filter:    '{pre_filter}' (before encryption)")?;
+        }
+        if let Some(ref post_filter) = self.post_filter {
+            writeln!(f, "filter:    '{post_filter}' (after decryption)")?;
+        }
+
+        match self.encoding {
+            Encoding::Explicit { raw: true } => writeln!(f, "encoding:  raw")?,
+            Encoding::Explicit { raw: false } | Encoding::Default => {
+                writeln!(f, "encoding:  base64")?;
+            }
+            Encoding::Armored => writeln!(f, "encoding:  armored")?,
+            Encoding::Framed => writeln!(f, "encoding:  base64 (framed)")?,
+            Encoding::Qr(qr::Image::Text) => writeln!(f, "encoding:  QR code")?,
+            Encoding::Qr(qr::Image::Png) => writeln!(f, "encoding:  QR code (PNG)")?,
+            Encoding::Text(encoding) => writeln!(f, "encoding:  {encoding}")?,
+            Encoding::Detected => writeln!(f, "encoding:  detected from input")?,
+        }
+
+        match (&self.output, &self.temp_path) {
+            (cli::Output::File(file), Some(temp_path)) => writeln!(
+                f,
+                "output:    '{}' (in-place, through '{}')",
+                file.display(),
+                temp_path.display()
+            ),
+            (cli::Output::File(file), None) => writeln!(f, "output:    '{}'", file.display()),
+            (cli::Output::Stdout, _) => writeln!(f, "output:    stdout"),
+            (cli::Output::Redirected, _) => writeln!(f, "output:    stdout (redirected)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::iter_on_single_items)]
+
+    use std::time::Duration;
+
+    use super::*;
+    use crate::cmd::environment::Fake;
+
+    const LOREM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lorem.txt");
+
+    fn plan(cli_args: &[&str]) -> Result<ExecutionPlan, String> {
+        let args = cli::Args::build_from_args(cli_args.iter()).unwrap();
+        ExecutionPlan::new(args.command.unwrap(), &args, &Fake::default())
+    }
+
+    #[test]
+    fn plan_without_message_is_error() {
+        let err = plan(&["encrypt"]).unwrap_err();
+        assert_eq!(err, "You must provide a message");
+    }
+
+    #[test]
+    fn plan_regular() {
+        let plan = plan(&["encrypt", "hello", "-k", "abc"]).unwrap();
+
+        assert_eq!(plan.algorithm, cli::Algorithm::ChaCha20Poly1305);
+        assert_eq!(plan.key_source, KeySource::Argument);
+        assert_eq!(plan.input, Input::Argument);
+        assert_eq!(plan.output, cli::Output::Stdout);
+        assert!(!plan.in_place);
+        assert_eq!(plan.temp_path, None);
+    }
+
+    #[test]
+    fn plan_default_key() {
+        let plan = plan(&["encrypt", "hello"]).unwrap();
+        assert_eq!(plan.key_source, KeySource::Default);
+    }
+
+    #[test]
+    fn plan_fixed_key() {
+        let plan = plan(&["encrypt", "hello", "-a", "rot"]).unwrap();
+        assert_eq!(plan.key_source, KeySource::Fixed);
+    }
+
+    #[test]
+    fn plan_output_file_is_not_in_place() {
+        let plan = plan(&["encrypt", "-f", LOREM, "-o", "out.enc"]).unwrap();
+
+        assert_eq!(plan.input, Input::File(PathBuf::from(LOREM)));
+        assert_eq!(plan.output, cli::Output::File(PathBuf::from("out.enc")));
+        assert!(!plan.in_place);
+        assert_eq!(plan.temp_path, None);
+    }
+
+    #[test]
+    fn plan_in_place() {
+        let args = cli::Args::build_from_args(["encrypt", "-f", LOREM, "-i"].iter()).unwrap();
+        let env = Fake {
+            now: Some(SystemTime::UNIX_EPOCH + Duration::from_micros(1_700_001)),
+            ..Default::default()
+        };
+
+        let plan = ExecutionPlan::new(cli::Command::Encrypt, &args, &env).unwrap();
+
+        assert!(plan.in_place);
+        assert_eq!(
+            plan.temp_path,
+            Some(PathBuf::from(LOREM).with_extension("1700001.jolokia"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn plan_in_place_not_regular_file_is_error() {
+        let err = plan(&["encrypt", "-f", "/dev/null", "-i"]).unwrap_err();
+        assert_eq!(
+            err,
+            "Cannot work in-place on '/dev/null', it is not a regular file"
+        );
+    }
+
+    #[test]
+    fn plan_in_place_continue_on_error_is_error() {
+        let err = plan(&[
+            "decrypt",
+            "--framed",
+            "--continue-on-error",
+            "-f",
+            LOREM,
+            "-i",
+        ])
+        .unwrap_err();
+        assert_eq!(err, "'--continue-on-error' cannot be used in-place");
+    }
+
+    #[test]
+    fn plan_display() {
+        let plan = plan(&["decrypt", "-f", LOREM, "-o", "out.txt"]).unwrap();
+
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "\
+command:   decrypt
+algorithm: ChaCha20-Poly1305
+key:       default (not secure)
+input:     '{LOREM}'
+encoding:  detected from input
+output:    'out.txt'
+"
+            )
+        );
+    }
+
+    #[test]
+    fn plan_display_filter() {
+        let plan = plan(&["encrypt", "hello", "--pre-filter", "gzip"]).unwrap();
+        assert!(
+            plan.to_string()
+                .contains("input:     argument\nfilter:    'gzip' (before encryption)\n")
+        );
+    }
+
+    #[test]
+    fn plan_display_in_place() {
+        let args = cli::Args::build_from_args(["encrypt", "-f", LOREM, "-i"].iter()).unwrap();
+        let plan = ExecutionPlan::new(cli::Command::Encrypt, &args, &Fake::default()).unwrap();
+
+        assert!(plan.to_string().contains(&format!(
+            "output:    '{LOREM}' (in-place, through '{}')",
+            PathBuf::from(LOREM).with_extension("0.jolokia").display()
+        )));
+    }
+
+    #[test]
+    fn plan_encoding_explicit() {
+        let encrypt = plan(&["encrypt", "hello", "-o", "out.enc", "--base64"]).unwrap();
+        assert_eq!(encrypt.encoding, Encoding::Explicit { raw: false });
+
+        let decrypt = plan(&["decrypt", "hello", "--raw"]).unwrap();
+        assert_eq!(decrypt.encoding, Encoding::Explicit { raw: true });
+    }
+
+    #[test]
+    fn plan_encoding_armored() {
+        let plan = plan(&["encrypt", "hello", "-o", "out.enc", "--armor"]).unwrap();
+        assert_eq!(plan.encoding, Encoding::Armored);
+        assert_eq!(plan.encoding.format(), Some(Format::Armored));
+        assert!(plan.to_string().contains("encoding:  armored\n"));
+    }
+
+    #[test]
+    fn plan_encoding_base64_to_file() {
+        let plan = plan(&["encrypt", "hello", "-o", "out.enc"]).unwrap();
+        assert_eq!(plan.encoding, Encoding::Default);
+        assert_eq!(plan.encoding.format(), Some(Format::Base64));
+        assert!(plan.to_string().contains("encoding:  base64\n"));
+    }
+
+    #[test]
+    fn plan_encoding_base64_to_stdout() {
+        let plan = plan(&["encrypt", "hello"]).unwrap();
+        assert_eq!(plan.encoding, Encoding::Default);
+        assert_eq!(plan.encoding.format(), Some(Format::Base64));
+    }
+
+    #[test]
+    fn plan_encoding_framed() {
+        let plan = plan(&["encrypt", "hello", "--framed", "-o", "out.enc"]).unwrap();
+        assert_eq!(plan.encoding, Encoding::Framed);
+        assert_eq!(plan.encoding.format(), Some(Format::Base64));
+    }
+
+    #[test]
+    fn plan_encoding_qr() {
+        let text = plan(&["encrypt", "hello", "--qr"]).unwrap();
+        assert_eq!(text.encoding, Encoding::Qr(qr::Image::Text));
+        assert!(text.to_string().contains("encoding:  QR code\n"));
+
+        let png = plan(&["encrypt", "hello", "--qr", "-o", "out.png"]).unwrap();
+        assert_eq!(png.encoding, Encoding::Qr(qr::Image::Png));
+        assert_eq!(png.encoding.format(), Some(Format::Qr(qr::Image::Png)));
+    }
+
+    #[test]
+    fn plan_encoding_text() {
+        let plan = plan(&["encrypt", "hello", "-o", "out.enc", "--encoding", "base32"]).unwrap();
+        assert_eq!(plan.encoding, Encoding::Text(cli::TextEncoding::Base32));
+        assert_eq!(plan.encoding.format(), Some(Format::Base32));
+        assert!(plan.to_string().contains("encoding:  base32\n"));
+    }
+
+    #[test]
+    fn plan_encoding_detected() {
+        let plan = plan(&["decrypt", "-f", LOREM]).unwrap();
+        assert_eq!(plan.encoding, Encoding::Detected);
+        assert_eq!(plan.encoding.format(), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! External subcommands (plugins).
+//!
+//! Like `git` and `cargo`, `jolokia foo` runs `jolokia-foo` if it is
+//! found on the `PATH`, with the remaining arguments. Built-in commands
+//! always take precedence.
+//!
+//! Plugins get the path to the `jolokia` executable in the `JOLOKIA`
+//! environment variable, to call back into it (e.g., to encrypt with
+//! the user's key). The rest of the environment, including the key
+//! variable, is passed as-is.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::environment::Environment;
+
+/// Points plugins to the executable that launched them.
+pub const EXE_ENV_VAR: &str = "JOLOKIA";
+
+/// Find the plugin executable for subcommand `name`.
+pub fn find(name: &str, env: &dyn Environment) -> Option<PathBuf> {
+    if !is_valid_name(name) {
+        return None;
+    }
+    let file_name = format!(
+        "{bin}-{name}{suffix}",
+        bin = env!("CARGO_BIN_NAME"),
+        suffix = env::consts::EXE_SUFFIX,
+    );
+    let path = env.var("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Run the plugin, and return its exit code.
+pub fn run(plugin: &Path, args: &[String]) -> Result<i32, String> {
+    let mut command = Command::new(plugin);
+    command.args(args);
+    if let Ok(exe) = env::current_exe() {
+        command.env(EXE_ENV_VAR, exe);
+    }
+    let status = command
+        .status()
+        .map_err(|reason| format!("Could not run '{}': {reason}", plugin.display()))?;
+    // No code means killed by a signal.
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Subcommands are bare names, not options or paths.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::environment::Fake;
+
+    #[test]
+    fn is_valid_name_regular() {
+        assert!(is_valid_name("kms"));
+        assert!(is_valid_name("aws-kms"));
+        assert!(is_valid_name("hsm_2"));
+    }
+
+    #[test]
+    fn is_valid_name_invalid() {
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("--key"));
+        assert!(!is_valid_name("../kms"));
+        assert!(!is_valid_name("kms/bin"));
+        assert!(!is_valid_name("hello, world"));
+    }
+
+    #[test]
+    fn find_without_path_is_none() {
+        assert_eq!(find("kms", &Fake::default()), None);
+    }
+
+    #[test]
+    fn find_invalid_name_is_none() {
+        let env = Fake::default().with_var("PATH", "/bin:/usr/bin");
+        assert_eq!(find("../sh", &env), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_regular() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("jolokia-plugin-find-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plugin = dir.join("jolokia-find-test");
+        std::fs::write(&plugin, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let not_executable = dir.join("jolokia-not-executable");
+        std::fs::write(&not_executable, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&not_executable, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let env = Fake::default().with_var("PATH", &format!("/nonexistent:{}", dir.display()));
+
+        assert_eq!(find("find-test", &env), Some(plugin));
+        assert_eq!(find("not-executable", &env), None);
+        assert_eq!(find("missing", &env), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! QR codes, for keys and short ciphertexts (`--qr`).
+//!
+//! On a terminal, a QR code is drawn with half blocks, two rows of
+//! modules per line of text. Light modules are drawn, dark ones are
+//! left blank: the code is meant to be read off a dark background, like
+//! `qrencode -t UTF8` does.
+//!
+//! Files named `*.png` get a PNG image instead, for printing.
+
+use std::path::Path;
+
+use qrcodegen::{QrCode, QrCodeEcc};
+
+use super::CliError;
+
+/// Most bytes a QR code can hold (version 40, low error correction).
+pub const MAX_LEN: usize = 2953;
+
+/// Modules of blank space around the code, on a terminal.
+const TEXT_QUIET_ZONE: i32 = 2;
+/// Modules of blank space around the code, in an image (the standard).
+const PNG_QUIET_ZONE: i32 = 4;
+/// Pixels per module, in an image.
+const PNG_SCALE: i32 = 8;
+
+/// How a QR code is rendered.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Image {
+    /// Half blocks, for terminals.
+    Text,
+    /// A PNG image.
+    Png,
+}
+
+impl Image {
+    /// PNG for `.png` files, text otherwise.
+    pub fn for_file(file: &Path) -> Self {
+        if file
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        {
+            Self::Png
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// Render `data` as a QR code.
+///
+/// Error correction is as high as the length of `data` allows.
+pub fn render(data: &[u8], image: Image) -> Result<Vec<u8>, CliError> {
+    let qr = QrCode::encode_binary(data, QrCodeEcc::Low).map_err(|_| {
+        CliError::Other(format!(
+            "Too long for a QR code ({} bytes, at most {MAX_LEN})",
+            data.len()
+        ))
+    })?;
+    match image {
+        Image::Text => Ok(to_text(&qr).into_bytes()),
+        Image::Png => to_png(&qr),
+    }
+}
+
+fn to_text(qr: &QrCode) -> String {
+    let range = -TEXT_QUIET_ZONE..qr.size() + TEXT_QUIET_ZONE;
+    // Outside the code, and in the quiet zone, modules are light.
+    let is_light = |x, y| range.contains(&y) && !qr.get_module(x, y);
+
+    let mut text = String::new();
+    for y in range.clone().step_by(2) {
+        for x in range.clone() {
+            text.push(match (is_light(x, y), is_light(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn to_png(qr: &QrCode) -> Result<Vec<u8>, CliError> {
+    let error = |e: png::EncodingError| CliError::Other(format!("Could not encode PNG: {e}"));
+
+    let modules = qr.size() + 2 * PNG_QUIET_ZONE;
+    let side = u32::try_from(modules * PNG_SCALE).expect("QR codes are small");
+
+    let mut pixels = Vec::with_capacity((side * side) as usize);
+    for y in 0..modules * PNG_SCALE {
+        for x in 0..modules * PNG_SCALE {
+            let is_dark = qr.get_module(
+                x / PNG_SCALE - PNG_QUIET_ZONE,
+                y / PNG_SCALE - PNG_QUIET_ZONE,
+            );
+            pixels.push(if is_dark { 0x00 } else { 0xff });
+        }
+    }
+
+    let mut image = Vec::new();
+    let mut encoder = png::Encoder::new(&mut image, side, side);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(error)?;
+    writer.write_image_data(&pixels).map_err(error)?;
+    writer.finish().map_err(error)?;
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_for_file() {
+        assert_eq!(Image::for_file(Path::new("key.png")), Image::Png);
+        assert_eq!(Image::for_file(Path::new("KEY.PNG")), Image::Png);
+        assert_eq!(Image::for_file(Path::new("key.txt")), Image::Text);
+        assert_eq!(Image::for_file(Path::new("png")), Image::Text);
+    }
+
+    #[test]
+    fn render_text_is_square() {
+        let text = String::from_utf8(render(b"hello", Image::Text).unwrap()).unwrap();
+
+        // Version 1 is 21 modules, plus the quiet zone on both sides.
+        let side: usize = 21 + 2 * 2;
+        assert_eq!(text.lines().count(), side.div_ceil(2));
+        assert!(text.lines().all(|line| line.chars().count() == side));
+        // The quiet zone is light.
+        assert!(text.lines().next().unwrap().chars().all(|c| c == '█'));
+    }
+
+    #[test]
+    fn render_png_is_png() {
+        let image = render(b"hello", Image::Png).unwrap();
+
+        assert_eq!(&image[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn render_max_len() {
+        assert!(render(&[b'a'; MAX_LEN], Image::Text).is_ok());
+
+        let err = render(&[b'a'; MAX_LEN + 1], Image::Text).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Too long for a QR code (2954 bytes, at most 2953)"
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! Key rotation: decrypt with the old key, and encrypt with the new one.
+//!
+//! Both run side by side in a single pass, decryption on the calling
+//! thread, encryption on another, joined by an in-memory [`pipe()`].
+//! The plaintext only ever exists in (zeroized) buffers, it is never
+//! written to disk.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+
+use jolokia::expiry::Expiring;
+use jolokia::inspect;
+use jolokia::stream::pipe;
+use jolokia::traits::{Cipher, Error};
+
+use super::cli::Algorithm;
+use super::in_place::InPlaceFile;
+use super::plan;
+use super::{CliError, Format, decode_key, detect_format};
+
+/// What to rekey from, and to.
+pub struct Rekey<'a> {
+    pub old_cipher: &'a dyn Cipher,
+    pub old_key: &'a [u8],
+    pub new_algorithm: Algorithm,
+    pub new_key: &'a [u8],
+    /// Associated data, the same for both ciphertexts.
+    pub aad: &'a [u8],
+    /// Give rekeyed files their original times.
+    pub preserve_times: bool,
+    /// Bytes of output buffered before a write, for files.
+    pub buffer_size: usize,
+}
+
+impl Rekey<'_> {
+    /// Re-encrypt `ciphertext` into `output`.
+    ///
+    /// The output is raw, base64, or armored as `to_format` says, or
+    /// like the input if `None`. An expiry date is carried over as-is.
+    pub fn stream<R: Read, W: Write + Send>(
+        &self,
+        ciphertext: R,
+        output: W,
+        to_format: Option<Format>,
+        add_newline: bool,
+    ) -> Result<(), CliError> {
+        let old_key = decode_key(self.old_cipher.algorithm_id(), self.old_key)?;
+
+        let (from_format, mut ciphertext) = detect_format(ciphertext, None)?;
+        let mut source = from_format.source(&mut ciphertext);
+
+        let mut prefix = Vec::new();
+        (&mut source)
+            .take(inspect::EXPIRY_PREFIX_LEN as u64)
+            .read_to_end(&mut prefix)
+            .map_err(|e| Error::Read(e.to_string()))?;
+        let mut new_cipher = Expiring::new(self.new_algorithm.into());
+        if let Some(expires_at) = inspect::expires_at(&prefix) {
+            new_cipher = new_cipher.expires_at(expires_at);
+        }
+        let mut source = io::Cursor::new(prefix).chain(source);
+
+        let to_format = to_format.unwrap_or(from_format);
+        let (writer, mut reader) = pipe();
+
+        thread::scope(|scope| {
+            let encryption = scope.spawn(|| {
+                let encrypted = super::encrypt(
+                    &new_cipher,
+                    self.new_key,
+                    self.aad,
+                    &mut reader,
+                    output,
+                    to_format,
+                    add_newline,
+                );
+                (encrypted, reader.is_interrupted())
+            });
+
+            let mut writer = writer;
+            let decrypted = self
+                .old_cipher
+                .decrypt_stream_with_aad(&old_key, self.aad, &mut source, &mut writer)
+                .map(|_| ())
+                .map_err(CliError::from);
+            // Only a complete plaintext may be encrypted in full.
+            if decrypted.is_ok() {
+                writer.close();
+            } else {
+                drop(writer);
+            }
+
+            let (encrypted, interrupted) = encryption
+                .join()
+                .unwrap_or_else(|_| (Err("Encryption thread panicked".to_string().into()), false));
+
+            match (decrypted, encrypted) {
+                // Encryption stopped because decryption did.
+                (Err(reason), _) if interrupted => Err(reason),
+                (_, Err(reason)) => Err(reason),
+                (decrypted, Ok(())) => decrypted,
+            }
+        })
+    }
+
+    /// Rekey `file` in place.
+    ///
+    /// The output goes to a temporary file next to it first, which then
+    /// replaces it (see [`InPlaceFile`]). If anything fails, `file` is
+    /// left untouched.
+    pub fn file(
+        &self,
+        file: &Path,
+        to_format: Option<Format>,
+        now: SystemTime,
+    ) -> Result<(), CliError> {
+        let input = fs::File::open(file)
+            .map_err(|e| CliError::Io(format!("Could not read '{}': {e}", file.display())))?;
+        let in_place = InPlaceFile::create(
+            file,
+            &plan::build_temporary_file_path(file, now),
+            self.preserve_times,
+        )?;
+
+        let mut output = in_place.writer(self.buffer_size)?;
+        self.stream(io::BufReader::new(input), &mut output, to_format, false)?;
+        output.flush().map_err(|e| Error::Write(e.to_string()))?;
+
+        in_place.commit()
+    }
+}
+
+/// Whether `file` has headers in an old version, that `migrate` would
+/// upgrade (see [`jolokia::format`]).
+///
+/// Files that can't be inspected count as outdated, so migrating them
+/// reports why.
+pub fn is_outdated(file: &Path) -> bool {
+    let Ok(input) = fs::File::open(file) else {
+        return true;
+    };
+    let Ok((format, mut ciphertext)) = detect_format(io::BufReader::new(input), None) else {
+        return true;
+    };
+    let mut source = format.source(&mut ciphertext);
+    inspect::inspect(&mut source).map_or(true, |metadata| metadata.outdated)
+}
+
+/// Regular files in `dir` and its subdirectories, sorted.
+///
+/// Symbolic links are not followed, they could lead out of `dir`.
+pub fn files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| format!("Could not read directory '{}': {e}", dir.display()))?;
+        for entry in entries {
+            let entry =
+                entry.map_err(|e| format!("Could not read directory '{}': {e}", dir.display()))?;
+            let file_type = entry
+                .file_type()
+                .map_err(|e| format!("Could not read '{}': {e}", entry.path().display()))?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::time::Duration;
+
+    #[cfg(feature = "hpke")]
+    use secrecy::ExposeSecret;
+
+    #[cfg(feature = "hpke")]
+    use jolokia::traits::{Base64Encode, GeneratedKey, KeygenOptions};
+
+    use super::*;
+
+    const OLD_KEY: &[u8] = b"aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ";
+    const NEW_KEY: &[u8] = b"9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    const LOREM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lorem.txt");
+
+    fn rekey(new_algorithm: Algorithm, new_key: &[u8]) -> Rekey<'_> {
+        Rekey {
+            old_cipher: &jolokia::cipher::ChaCha20Poly1305,
+            old_key: OLD_KEY,
+            new_algorithm,
+            new_key,
+            aad: b"",
+            preserve_times: false,
+            buffer_size: crate::cmd::cli::DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    fn encrypt(plaintext: &[u8], format: Format) -> Vec<u8> {
+        let mut encrypted = Vec::new();
+        super::super::encrypt(
+            &jolokia::cipher::ChaCha20Poly1305,
+            OLD_KEY,
+            b"",
+            plaintext,
+            &mut encrypted,
+            format,
+            false,
+        )
+        .unwrap();
+        encrypted
+    }
+
+    fn decrypt(cipher: &dyn Cipher, key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CliError> {
+        let mut decrypted = Vec::new();
+        super::super::decrypt(cipher, key, b"", ciphertext, &mut decrypted, None)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn rekey_regular() {
+        let plaintext = fs::read(LOREM).unwrap();
+        let encrypted = encrypt(&plaintext, Format::Raw);
+
+        let mut rekeyed = Vec::new();
+        rekey(Algorithm::ChaCha20Poly1305, NEW_KEY)
+            .stream(encrypted.as_slice(), &mut rekeyed, None, false)
+            .unwrap();
+
+        assert!(inspect::is_raw(&rekeyed));
+        assert_ne!(rekeyed, encrypted);
+        let cipher = jolokia::cipher::ChaCha20Poly1305;
+        assert!(decrypt(&cipher, OLD_KEY, &rekeyed).is_err());
+        assert_eq!(decrypt(&cipher, NEW_KEY, &rekeyed).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn rekey_keeps_base64() {
+        let encrypted = encrypt(b"hello, world", Format::Base64);
+
+        let mut rekeyed = Vec::new();
+        rekey(Algorithm::ChaCha20Poly1305, NEW_KEY)
+            .stream(encrypted.as_slice(), &mut rekeyed, None, false)
+            .unwrap();
+
+        assert!(!inspect::is_raw(&rekeyed));
+        let cipher = jolokia::cipher::ChaCha20Poly1305;
+        assert_eq!(
+            decrypt(&cipher, NEW_KEY, &rekeyed).unwrap(),
+            b"hello, world"
+        );
+    }
+
+    #[test]
+    fn rekey_keeps_armor() {
+        let encrypted = encrypt(b"hello, world", Format::Armored);
+
+        let mut rekeyed = Vec::new();
+        rekey(Algorithm::ChaCha20Poly1305, NEW_KEY)
+            .stream(encrypted.as_slice(), &mut rekeyed, None, false)
+            .unwrap();
+
+        assert!(jolokia::armor::is_armored(&rekeyed));
+        assert_ne!(rekeyed, encrypted);
+        let cipher = jolokia::cipher::ChaCha20Poly1305;
+        assert_eq!(
+            decrypt(&cipher, NEW_KEY, &rekeyed).unwrap(),
+            b"hello, world"
+        );
+    }
+
+    #[test]
+    fn rekey_explicit_encoding() {
+        let encrypted = encrypt(b"hello, world", Format::Base64);
+
+        let mut rekeyed = Vec::new();
+        rekey(Algorithm::ChaCha20Poly1305, NEW_KEY)
+            .stream(encrypted.as_slice(), &mut rekeyed, Some(Format::Raw), false)
+            .unwrap();
+
+        assert!(inspect::is_raw(&rekeyed));
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn rekey_to_another_algorithm() {
+        let cipher = jolokia::cipher::Hpke;
+        let GeneratedKey::Asymmetric { public, private } =
+            cipher.generate_key(&KeygenOptions::default()).unwrap()
+        else {
+            unreachable!()
+        };
+        let public = public.expose_secret().base64_encode();
+        let private = private.expose_secret().base64_encode();
+        let encrypted = encrypt(b"hello, world", Format::Raw);
+
+        let mut rekeyed = Vec::new();
+        rekey(Algorithm::Hpke, public.as_bytes())
+            .stream(encrypted.as_slice(), &mut rekeyed, None, false)
+            .unwrap();
+
+        assert_eq!(
+            decrypt(&cipher, private.as_bytes(), &rekeyed).unwrap(),
+            b"hello, world"
+        );
+    }
+
+    #[test]
+    fn rekey_keeps_expiry_date() {
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut encrypted = Vec::new();
+        super::super::encrypt(
+            &Expiring::new(Box::new(jolokia::cipher::ChaCha20Poly1305)).expires_at(date),
+            OLD_KEY,
+            b"",
+            &b"hello, world"[..],
+            &mut encrypted,
+            Format::Raw,
+            false,
+        )
+        .unwrap();
+
+        let cipher = Expiring::new(Box::new(jolokia::cipher::ChaCha20Poly1305));
+        let mut rekeyed = Vec::new();
+        Rekey {
+            old_cipher: &cipher,
+            ..rekey(Algorithm::ChaCha20Poly1305, NEW_KEY)
+        }
+        .stream(encrypted.as_slice(), &mut rekeyed, None, false)
+        .unwrap();
+
+        assert_eq!(inspect::expires_at(&rekeyed), Some(date));
+        assert_eq!(
+            decrypt(&cipher, NEW_KEY, &rekeyed).unwrap(),
+            b"hello, world"
+        );
+    }
+
+    #[test]
+    fn rekey_wrong_old_key_is_error() {
+        let encrypted = encrypt(b"hello, world", Format::Raw);
+
+        let mut rekeyed = Vec::new();
+        let err = Rekey {
+            old_key: NEW_KEY,
+            ..rekey(Algorithm::ChaCha20Poly1305, NEW_KEY)
+        }
+        .stream(encrypted.as_slice(), &mut rekeyed, None, false)
+        .unwrap_err();
+
+        assert_eq!(err, CliError::Cipher(Error::Decrypt));
+    }
+
+    #[test]
+    fn rekey_truncated_is_error() {
+        let plaintext = fs::read(LOREM).unwrap().repeat(1000);
+        let encrypted = encrypt(&plaintext, Format::Raw);
+        let truncated = &encrypted[..encrypted.len() - 100];
+
+        let mut rekeyed = Vec::new();
+        let err = rekey(Algorithm::ChaCha20Poly1305, NEW_KEY)
+            .stream(truncated, &mut rekeyed, None, false)
+            .unwrap_err();
+
+        // The error is decryption's, not the interrupted encryption's.
+        assert!(
+            err.to_string().starts_with("Could not read from input"),
+            "{err}"
+        );
+        // What was re-encrypted so far is not a valid ciphertext.
+        let cipher = jolokia::cipher::ChaCha20Poly1305;
+        assert!(decrypt(&cipher, NEW_KEY, &rekeyed).is_err());
+    }
+
+    #[test]
+    fn is_outdated_regular() {
+        let dir = env::temp_dir().join(format!("jolokia-rekey-outdated-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (latest, old, plaintext) = (dir.join("latest"), dir.join("old"), dir.join("plain"));
+        fs::write(&latest, encrypt(b"hello, world", Format::Base64)).unwrap();
+        fs::write(&old, b"CH20\x01 and more").unwrap();
+        fs::write(&plaintext, b"hello, world").unwrap();
+
+        let outdated = [&latest, &old, &plaintext].map(|file| is_outdated(file));
+        _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(outdated, [false, true, true]);
+    }
+
+    #[test]
+    fn rekey_invalid_new_key_is_error() {
+        let encrypted = encrypt(b"hello, world", Format::Raw);
+
+        let mut rekeyed = Vec::new();
+        let err = rekey(Algorithm::ChaCha20Poly1305, b"hpke:aZZfFANQ")
+            .stream(encrypted.as_slice(), &mut rekeyed, None, false)
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "This key is for 'hpke', not 'chacha20poly1305'"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Overwrite and remove plaintext, once it's encrypted (`--shred`).
+//!
+//! The file is overwritten in place with zeros, flushed to disk, and
+//! then removed, so its content isn't simply left in free blocks.
+//!
+//! This is best effort. SSDs remap writes (wear leveling), and
+//! copy-on-write or journaling file systems (Btrfs, ZFS, APFS, ...)
+//! write new blocks instead of overwriting old ones. Snapshots, backups,
+//! and synced copies are out of reach entirely.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::CliError;
+
+/// Overwrite `file` with zeros, and remove it.
+pub fn shred(file: &Path) -> Result<(), CliError> {
+    let error = |e: io::Error| CliError::Io(format!("Could not shred '{}': {e}", file.display()));
+
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(file)
+        .map_err(error)?;
+    let mut remaining = f.metadata().map_err(error)?.len();
+    let zeros = vec![0u8; 64 * 1024];
+    while remaining > 0 {
+        let n = usize::try_from(remaining).map_or(zeros.len(), |r| r.min(zeros.len()));
+        f.write_all(&zeros[..n]).map_err(error)?;
+        remaining -= n as u64;
+    }
+    // Or the zeros could stay in the page cache, and never be written
+    // over the content before the file is gone.
+    f.sync_all().map_err(error)?;
+    drop(f);
+
+    fs::remove_file(file).map_err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn shred_removes_file() {
+        let file = env::temp_dir().join(format!("jolokia-shred-{}", process::id()));
+        fs::write(&file, vec![b'x'; 100_000]).unwrap();
+
+        shred(&file).unwrap();
+
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn shred_missing_file_is_error() {
+        let file = env::temp_dir().join(format!("jolokia-shred-missing-{}", process::id()));
+
+        let err = shred(&file).unwrap_err();
+
+        assert!(matches!(err, CliError::Io(_)));
+        assert!(err.to_string().starts_with("Could not shred"), "{err}");
+    }
+}
@@ -0,0 +1,169 @@
+//! Interruptions (Ctrl-C, `kill`), and the cleanup they need.
+//!
+//! By default, an interrupted process dies on the spot, and leaves
+//! behind whatever it was writing. Temporary files registered with
+//! [`remove_on_exit()`] are removed first instead.
+//!
+//! `SIGINT`, `SIGTERM` and `SIGHUP` are handled (on Windows, Ctrl-C and
+//! Ctrl-Break). The handler is only installed once there is something
+//! to clean up (see [`install_handler()`]), until then, interruptions
+//! behave as usual.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+
+use super::error::EXIT_INTERRUPTED;
+#[cfg(unix)]
+use super::error::EXIT_TERMINATED;
+
+/// Temporary files to remove if interrupted.
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Remove `path` if the process is interrupted (or panics), until
+/// [`keep()`] is called.
+pub fn remove_on_exit(path: &Path) {
+    install_handler();
+    pending().push(path.to_path_buf());
+}
+
+/// Handle interruptions from now on.
+///
+/// [`remove_on_exit()`] does it too, but calling it before creating
+/// the file leaves no window where an interruption would miss it.
+pub fn install_handler() {
+    static HANDLER: Once = Once::new();
+    // If it can't be installed, interruptions still work, they just
+    // leave the files behind.
+    HANDLER.call_once(|| {
+        if ctrlc::set_handler(remove_all_and_exit).is_ok() {
+            #[cfg(unix)]
+            unix::record_received();
+        }
+    });
+}
+
+/// Stop removing `path` (it was moved into place, or removed already).
+pub fn keep(path: &Path) {
+    pending().retain(|pending| pending != path);
+}
+
+/// Remove all registered temporary files.
+///
+/// For exits that skip destructors (`process::exit()`, aborting
+/// panics).
+pub fn remove_all() {
+    for path in pending().drain(..) {
+        _ = fs::remove_file(path);
+    }
+}
+
+fn remove_all_and_exit() {
+    remove_all();
+    process::exit(exit_code());
+}
+
+/// 128 + the signal, like a command killed by it.
+fn exit_code() -> i32 {
+    #[cfg(unix)]
+    match unix::received() {
+        Some(libc::SIGINT) | None => (),
+        Some(libc::SIGTERM) => return EXIT_TERMINATED,
+        Some(signal) => return 128 + signal,
+    }
+    EXIT_INTERRUPTED
+}
+
+/// `ctrlc` doesn't say which signal was received, its handlers are
+/// wrapped to record it.
+#[cfg(unix)]
+mod unix {
+    use std::mem;
+    use std::ptr;
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+    const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+    /// The last signal received (0 if none).
+    static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+    /// The wrapped handlers, in the order of [`SIGNALS`].
+    static WRAPPED: [AtomicUsize; SIGNALS.len()] = [const { AtomicUsize::new(0) }; SIGNALS.len()];
+
+    /// Record signals before `ctrlc` handles them.
+    pub fn record_received() {
+        for (&signal, wrapped) in SIGNALS.iter().zip(&WRAPPED) {
+            // SAFETY: `action` is fully initialized by `sigaction()`. It
+            // is only replaced if it is a plain handler (not a default,
+            // ignored, or `SA_SIGINFO` one), with a handler of the same
+            // kind that calls it.
+            unsafe {
+                let mut action: libc::sigaction = mem::zeroed();
+                if libc::sigaction(signal, ptr::null(), &raw mut action) != 0
+                    || action.sa_sigaction == libc::SIG_DFL
+                    || action.sa_sigaction == libc::SIG_IGN
+                    || action.sa_flags & libc::SA_SIGINFO != 0
+                {
+                    continue;
+                }
+                wrapped.store(action.sa_sigaction, Ordering::Release);
+                action.sa_sigaction = record as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                libc::sigaction(signal, &raw const action, ptr::null_mut());
+            }
+        }
+    }
+
+    /// The last signal received, if any.
+    pub fn received() -> Option<i32> {
+        match RECEIVED.load(Ordering::Acquire) {
+            0 => None,
+            signal => Some(signal),
+        }
+    }
+
+    extern "C" fn record(signal: libc::c_int) {
+        // Only async-signal-safe operations here (atomics, and the
+        // wrapped handler, which is too).
+        RECEIVED.store(signal, Ordering::Release);
+        let Some(index) = SIGNALS.iter().position(|&s| s == signal) else {
+            return;
+        };
+        let wrapped = WRAPPED[index].load(Ordering::Acquire);
+        if wrapped != 0 {
+            // SAFETY: Only plain handlers are stored (see
+            // `record_received()`).
+            let wrapped = unsafe {
+                mem::transmute::<libc::sighandler_t, extern "C" fn(libc::c_int)>(wrapped)
+            };
+            wrapped(signal);
+        }
+    }
+}
+
+fn pending() -> MutexGuard<'static, Vec<PathBuf>> {
+    // A panic while holding the lock doesn't corrupt a list of paths.
+    PENDING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    fn is_pending(path: &Path) -> bool {
+        pending().iter().any(|pending| pending == path)
+    }
+
+    #[test]
+    fn remove_on_exit_then_keep() {
+        let path = env::temp_dir().join(format!("jolokia-signal-keep-{}", process::id()));
+
+        remove_on_exit(&path);
+        assert!(is_pending(&path));
+        keep(&path);
+
+        assert!(!is_pending(&path));
+    }
+}
@@ -1,3 +1,9 @@
 pub mod color;
+pub mod format;
+pub mod log;
+pub mod pager;
+pub mod progress;
+pub mod prompt;
+pub mod terminal;
 
 pub use color::Color;
@@ -45,6 +45,13 @@ impl Color {
         Self::color(YELLOW, string)
     }
 
+    // Success.
+
+    #[must_use]
+    pub fn success(string: &str) -> Cow<'_, str> {
+        Self::color(GREEN, string)
+    }
+
     // Generic.
 
     /// Return string without adding color.
@@ -147,6 +154,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn color_success_is_green() {
+        assert_eq!(
+            Color::success("this is a success"),
+            "\x1b[0;92mthis is a success\x1b[0m"
+        );
+    }
+
     #[test]
     fn color_none_has_no_effect() {
         assert_eq!(Color::none("same as input"), "same as input");
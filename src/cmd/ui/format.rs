@@ -0,0 +1,129 @@
+#![allow(dead_code)]
+
+//! Human-readable formatting of numbers, sizes, and durations.
+//!
+//! Everything that reports sizes or durations to the user should go
+//! through here, so output stays consistent across features.
+
+use std::env;
+use std::time::Duration;
+
+const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+/// Format a byte count with binary units (e.g., `1.5 MiB`).
+pub fn bytes(n: u64) -> String {
+    if n < 1024 {
+        return if n == 1 {
+            "1 byte".to_string()
+        } else {
+            format!("{n} bytes")
+        };
+    }
+    #[allow(clippy::cast_precision_loss)] // Display only.
+    let mut size = n as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Format a duration, coarsely (e.g., `850 ms`, `12.3 s`, `2 min 05 s`).
+pub fn duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs == 0 {
+        format!("{} ms", d.as_millis())
+    } else if secs < 60 {
+        format!("{:.1} s", d.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{} min {:02} s", secs / 60, secs % 60)
+    } else {
+        format!("{} h {:02} min", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+/// Format an integer with thousands separators (e.g., `1,234,567`).
+///
+/// The separator follows the numeric locale (`LC_ALL`, `LC_NUMERIC`,
+/// `LANG`), for the most common languages.
+pub fn thousands(n: u64) -> String {
+    thousands_with(n, thousands_separator())
+}
+
+fn thousands_with(n: u64, separator: char) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(separator);
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+fn thousands_separator() -> char {
+    let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()));
+    locale.map_or(',', |locale| separator_for_locale(&locale))
+}
+
+fn separator_for_locale(locale: &str) -> char {
+    // `de_DE.UTF-8` -> `de`.
+    let language = locale
+        .split(['_', '.', '@', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    match language.as_str() {
+        "de" | "da" | "es" | "id" | "it" | "nl" | "pt" | "tr" => '.',
+        "cs" | "fi" | "fr" | "nb" | "pl" | "ru" | "sk" | "sv" | "uk" => '\u{202f}',
+        _ => ',',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_below_one_kib() {
+        assert_eq!(bytes(0), "0 bytes");
+        assert_eq!(bytes(1), "1 byte");
+        assert_eq!(bytes(1023), "1023 bytes");
+    }
+
+    #[test]
+    fn bytes_binary_units() {
+        assert_eq!(bytes(1024), "1.0 KiB");
+        assert_eq!(bytes(1536), "1.5 KiB");
+        assert_eq!(bytes(1024 * 1024), "1.0 MiB");
+        assert_eq!(bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn duration_ranges() {
+        assert_eq!(duration(Duration::from_millis(850)), "850 ms");
+        assert_eq!(duration(Duration::from_millis(12_340)), "12.3 s");
+        assert_eq!(duration(Duration::from_secs(125)), "2 min 05 s");
+        assert_eq!(duration(Duration::from_mins(3 * 60 + 7)), "3 h 07 min");
+    }
+
+    #[test]
+    fn thousands_with_separator() {
+        assert_eq!(thousands_with(0, ','), "0");
+        assert_eq!(thousands_with(999, ','), "999");
+        assert_eq!(thousands_with(1000, ','), "1,000");
+        assert_eq!(thousands_with(1_234_567, '.'), "1.234.567");
+    }
+
+    #[test]
+    fn separator_for_common_locales() {
+        assert_eq!(separator_for_locale("en_US.UTF-8"), ',');
+        assert_eq!(separator_for_locale("C"), ',');
+        assert_eq!(separator_for_locale("de_DE.UTF-8"), '.');
+        assert_eq!(separator_for_locale("fr_FR"), '\u{202f}');
+    }
+}
@@ -0,0 +1,78 @@
+//! Messages about what is going on, on `stderr`.
+//!
+//! `-v` shows the details: where the key comes from, which algorithm
+//! is used, temporary files, byte counts. `-q` silences everything but
+//! errors (warnings, progress, hints).
+//!
+//! The verbosity is global, messages come from all over (including
+//! cipher callbacks, like expiry warnings).
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use super::Color;
+use super::color::{ATTENUATE, RESET};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Verbosity {
+    /// `-q`, errors only.
+    Quiet,
+    #[default]
+    Normal,
+    /// `-v`, details too.
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    }
+}
+
+pub fn is_quiet() -> bool {
+    verbosity() == Verbosity::Quiet
+}
+
+/// Print a detail, with `-v` only.
+pub fn info(message: impl fmt::Display) {
+    if verbosity() == Verbosity::Verbose {
+        eprintln!("{}", format_info(message));
+    }
+}
+
+fn format_info(message: impl fmt::Display) -> String {
+    format!(
+        "{attenuate}info: {message}{reset}",
+        attenuate = Color::maybe_color(ATTENUATE),
+        reset = Color::maybe_color(RESET),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_round_trip() {
+        for verbosity in [Verbosity::Quiet, Verbosity::Verbose, Verbosity::Normal] {
+            set_verbosity(verbosity);
+            assert_eq!(self::verbosity(), verbosity);
+        }
+    }
+
+    #[test]
+    fn format_info_regular() {
+        assert_eq!(
+            format_info(format_args!("key: {}", "--key")),
+            "\x1b[0;90minfo: key: --key\x1b[0m"
+        );
+    }
+}
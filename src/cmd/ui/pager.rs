@@ -0,0 +1,109 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+
+use lessify::{PAGER, Pager};
+
+use super::Color;
+
+#[derive(Debug, Eq, PartialEq)]
+enum Policy {
+    Page,
+    Print,
+    PrintWithNotice(&'static str),
+}
+
+/// Output `content` through the pager, or print it if paging is not
+/// possible or not wanted.
+///
+/// Contrary to [`Pager::page_or_print()`], problems with the pager are
+/// reported with a one-line notice, instead of being silent.
+pub fn page_or_print(content: &str, no_pager: bool) {
+    let term = env::var("TERM").ok();
+    let policy = policy(no_pager, is_stdout_terminal(), term.as_deref());
+
+    match policy {
+        Policy::Page => {
+            // Other errors likely mean the pager exited before reading
+            // everything (e.g., the user quit early). The content was
+            // shown already.
+            if let Err(reason) = Pager::page(content)
+                && matches!(
+                    reason.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                )
+            {
+                // E.g., `PAGER` points to a binary that doesn't exist.
+                notice(&format!("Could not start pager '{}': {reason}", *PAGER));
+                print(content);
+            }
+        }
+        Policy::Print => print(content),
+        Policy::PrintWithNotice(reason) => {
+            notice(reason);
+            print(content);
+        }
+    }
+}
+
+fn policy(no_pager: bool, is_terminal: bool, term: Option<&str>) -> Policy {
+    if no_pager || !is_terminal {
+        Policy::Print
+    } else if term == Some("dumb") {
+        Policy::PrintWithNotice("Terminal is dumb, not using a pager")
+    } else {
+        Policy::Page
+    }
+}
+
+fn notice(reason: &str) {
+    eprintln!(
+        "{note}: {reason}. Use `--no-pager` to silence this.",
+        note = Color::warning("note"),
+    );
+}
+
+fn print(content: &str) {
+    let mut stdout = io::stdout();
+    // `write!` instead of `print!`, to not panic on broken pipes.
+    if content.ends_with('\n') {
+        _ = write!(stdout, "{content}");
+    } else {
+        _ = writeln!(stdout, "{content}");
+    }
+}
+
+fn is_stdout_terminal() -> bool {
+    #![allow(unreachable_code)]
+    #[cfg(test)]
+    return false;
+    io::stdout().is_terminal()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_page_in_terminal() {
+        assert_eq!(policy(false, true, Some("xterm-256color")), Policy::Page);
+        assert_eq!(policy(false, true, None), Policy::Page);
+    }
+
+    #[test]
+    fn policy_no_pager_prints() {
+        assert_eq!(policy(true, true, Some("xterm-256color")), Policy::Print);
+    }
+
+    #[test]
+    fn policy_not_a_terminal_prints() {
+        assert_eq!(policy(false, false, Some("xterm-256color")), Policy::Print);
+    }
+
+    #[test]
+    fn policy_dumb_terminal_prints_with_notice() {
+        assert!(matches!(
+            policy(false, true, Some("dumb")),
+            Policy::PrintWithNotice(_)
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+//! Progress bar, for long encryptions and decryptions.
+//!
+//! The bar is drawn on `stderr`, on a single line that is redrawn in
+//! place, and cleared when done. Short operations never show it: it
+//! only appears after [`DELAY`], so it doesn't flicker.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use super::format;
+
+/// How long to wait before showing the bar.
+const DELAY: Duration = Duration::from_millis(500);
+
+/// How often to redraw the bar.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+const WIDTH: usize = 24;
+
+/// Draws progress on `stderr`. The line is cleared on drop.
+pub struct ProgressBar {
+    total: Option<u64>,
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+impl ProgressBar {
+    /// `total` is the size of the input, if known (for the percentage
+    /// and the ETA).
+    pub fn new(total: Option<u64>) -> Self {
+        Self {
+            total,
+            started: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    /// Report `bytes` processed so far, and redraw if it's time.
+    pub fn update(&mut self, bytes: u64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.started);
+        if elapsed < DELAY
+            || self
+                .drawn
+                .is_some_and(|at| now.duration_since(at) < INTERVAL)
+        {
+            return;
+        }
+        self.drawn = Some(now);
+
+        let mut stderr = io::stderr().lock();
+        // Progress is a nicety, failing to draw it is not an error.
+        _ = write!(stderr, "\r{}\x1b[K", render(bytes, self.total, elapsed));
+        _ = stderr.flush();
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            _ = write!(io::stderr(), "\r\x1b[K");
+        }
+    }
+}
+
+/// `[=========>      ]  45%  120.5 MiB / 267.0 MiB  85.2 MiB/s  ETA 1.7 s`
+///
+/// Without a total, only the bytes and the throughput are shown.
+fn render(bytes: u64, total: Option<u64>, elapsed: Duration) -> String {
+    #[allow(clippy::cast_precision_loss)] // Display only.
+    let throughput = bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Display only.
+    let speed = format!("{}/s", format::bytes(throughput as u64));
+
+    let Some(total) = total.filter(|&total| total > 0) else {
+        return format!("{}  {speed}", format::bytes(bytes));
+    };
+    let bytes = bytes.min(total);
+
+    #[allow(clippy::cast_precision_loss)] // Display only.
+    let ratio = bytes as f64 / total as f64;
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )] // Display only.
+    let filled = (ratio * WIDTH as f64) as usize;
+    let bar = if filled >= WIDTH {
+        "=".repeat(WIDTH)
+    } else {
+        format!("{}>{}", "=".repeat(filled), " ".repeat(WIDTH - filled - 1))
+    };
+
+    #[allow(clippy::cast_precision_loss)] // Display only.
+    let eta = if throughput > 0.0 {
+        let remaining = (total - bytes) as f64 / throughput;
+        format!(
+            "  ETA {}",
+            format::duration(Duration::from_secs_f64(remaining))
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        "[{bar}] {:>3.0}%  {} / {}  {speed}{eta}",
+        ratio * 100.0,
+        format::bytes(bytes),
+        format::bytes(total),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn render_with_total() {
+        let line = render(50 * MIB, Some(200 * MIB), Duration::from_secs(2));
+
+        assert_eq!(
+            line,
+            "[======>                 ]  25%  50.0 MiB / 200.0 MiB  25.0 MiB/s  ETA 6.0 s"
+        );
+    }
+
+    #[test]
+    fn render_complete() {
+        let line = render(200 * MIB, Some(200 * MIB), Duration::from_secs(4));
+
+        assert_eq!(
+            line,
+            "[========================] 100%  200.0 MiB / 200.0 MiB  50.0 MiB/s  ETA 0 ms"
+        );
+    }
+
+    #[test]
+    fn render_without_total() {
+        let line = render(50 * MIB, None, Duration::from_secs(2));
+
+        assert_eq!(line, "50.0 MiB  25.0 MiB/s");
+    }
+
+    #[test]
+    fn render_more_than_total_is_capped() {
+        let line = render(300 * MIB, Some(200 * MIB), Duration::from_secs(1));
+
+        assert!(line.contains("100%  200.0 MiB / 200.0 MiB"));
+    }
+
+    #[test]
+    fn render_empty_total_has_no_bar() {
+        let line = render(0, Some(0), Duration::from_secs(1));
+
+        assert_eq!(line, "0 bytes  0 bytes/s");
+    }
+}
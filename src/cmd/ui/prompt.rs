@@ -0,0 +1,70 @@
+use std::io::{self, BufRead, Write};
+
+use crate::cmd::environment::{Environment, Stream};
+
+/// Ask for confirmation before doing something destructive.
+///
+/// The question is only asked if `stdin` is a terminal. Otherwise,
+/// there is no one to ask, and the answer is yes (scripts must not
+/// hang). `assume_yes` (`--yes`) skips the question altogether.
+///
+/// Anything but an explicit yes is a no.
+pub fn confirm(question: &str, assume_yes: bool, env: &dyn Environment) -> bool {
+    if assume_yes || !env.is_terminal(Stream::Stdin) {
+        return true;
+    }
+    ask(question, &mut io::stdin().lock(), &mut io::stderr()).unwrap_or(false)
+}
+
+fn ask(question: &str, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<bool> {
+    write!(output, "{question} [y/N] ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(is_yes(&answer))
+}
+
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::environment::Fake;
+
+    #[test]
+    fn confirm_assume_yes() {
+        assert!(confirm("Continue?", true, &Fake::default()));
+    }
+
+    #[test]
+    fn confirm_not_a_terminal_is_yes() {
+        let env = Fake {
+            stdin_terminal: false,
+            ..Default::default()
+        };
+        assert!(confirm("Continue?", false, &env));
+    }
+
+    #[test]
+    fn ask_prints_question() {
+        let mut output = Vec::new();
+        ask("Continue?", &mut b"y\n".as_slice(), &mut output).unwrap();
+        assert_eq!(output, b"Continue? [y/N] ");
+    }
+
+    #[test]
+    fn ask_yes() {
+        assert!(ask("Continue?", &mut b"y\n".as_slice(), &mut io::sink()).unwrap());
+        assert!(ask("Continue?", &mut b" YES \n".as_slice(), &mut io::sink()).unwrap());
+    }
+
+    #[test]
+    fn ask_default_is_no() {
+        assert!(!ask("Continue?", &mut b"\n".as_slice(), &mut io::sink()).unwrap());
+        assert!(!ask("Continue?", &mut b"".as_slice(), &mut io::sink()).unwrap());
+        assert!(!ask("Continue?", &mut b"yep\n".as_slice(), &mut io::sink()).unwrap());
+    }
+}
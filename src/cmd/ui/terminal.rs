@@ -0,0 +1,86 @@
+use std::io::{self, BufRead, Write};
+
+use secrecy::{ExposeSecret, SecretString};
+
+/// Wait for Enter, then erase the last `nb_lines` lines printed.
+///
+/// This assumes the lines fit in the width of the terminal, and that
+/// the terminal understands ANSI escape sequences. Lines that scrolled
+/// out of the screen are out of reach.
+pub fn wait_for_enter_then_clear(message: &str, nb_lines: usize) -> io::Result<()> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{message}")?;
+    stderr.flush()?;
+
+    let mut enter = String::new();
+    io::stdin().lock().read_line(&mut enter)?;
+
+    // The message, and the line Enter moved to, go too.
+    write!(stderr, "{}", clear_lines(nb_lines + 1))?;
+    stderr.flush()
+}
+
+/// Read a secret from the terminal, without echoing it.
+///
+/// The prompt and the input go through the terminal directly, not
+/// through `stdin` and `stderr`, which may be redirected.
+pub fn read_secret(prompt: &str) -> Result<SecretString, String> {
+    let secret = rpassword::prompt_password(prompt).map_err(|e| e.to_string())?;
+    let secret = SecretString::from(secret);
+    if secret.expose_secret().is_empty() {
+        return Err("Nothing was entered".to_string());
+    }
+    Ok(secret)
+}
+
+/// Read a new secret, and have it typed a second time.
+///
+/// With no echo, typos go unnoticed, and data encrypted with a mistyped
+/// secret would be lost.
+pub fn read_new_secret(prompt: &str, confirm_prompt: &str) -> Result<SecretString, String> {
+    let secret = read_secret(prompt)?;
+    let confirmation = read_secret(confirm_prompt)?;
+    check_same(secret, &confirmation)
+}
+
+fn check_same(secret: SecretString, confirmation: &SecretString) -> Result<SecretString, String> {
+    if secret.expose_secret() != confirmation.expose_secret() {
+        return Err("The entries do not match".to_string());
+    }
+    Ok(secret)
+}
+
+/// Move up `nb_lines`, and erase everything below.
+fn clear_lines(nb_lines: usize) -> String {
+    if nb_lines == 0 {
+        return String::new();
+    }
+    format!("\x1b[{nb_lines}A\r\x1b[0J")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_lines_regular() {
+        assert_eq!(clear_lines(3), "\x1b[3A\r\x1b[0J");
+    }
+
+    #[test]
+    fn clear_lines_none() {
+        assert_eq!(clear_lines(0), "");
+    }
+
+    #[test]
+    fn check_same_regular() {
+        let secret = check_same(SecretString::from("foo"), &SecretString::from("foo")).unwrap();
+        assert_eq!(secret.expose_secret(), "foo");
+    }
+
+    #[test]
+    fn check_same_mismatch_is_error() {
+        let err = check_same(SecretString::from("foo"), &SecretString::from("fo0")).unwrap_err();
+        assert_eq!(err, "The entries do not match");
+    }
+}
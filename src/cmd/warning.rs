@@ -0,0 +1,177 @@
+use std::str::FromStr;
+
+use super::environment::Environment;
+
+/// Suppress warnings by ID or name (e.g., `W001,insecure-algorithm`).
+pub const NO_WARN_ENV_VAR: &str = "JOLOKIA_NO_WARN";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Warning {
+    /// The default cipher key is used.
+    DefaultKey,
+    /// The algorithm provides no real confidentiality.
+    InsecureAlgorithm,
+    /// The message is past its expiry date.
+    Expired,
+    /// Shredding may not reach all copies of the data.
+    Shred,
+}
+
+impl Warning {
+    pub const ALL: [Self; 4] = [
+        Self::DefaultKey,
+        Self::InsecureAlgorithm,
+        Self::Expired,
+        Self::Shred,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::DefaultKey => "W001",
+            Self::InsecureAlgorithm => "W002",
+            Self::Expired => "W003",
+            Self::Shred => "W004",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DefaultKey => "default-key",
+            Self::InsecureAlgorithm => "insecure-algorithm",
+            Self::Expired => "expired",
+            Self::Shred => "shred",
+        }
+    }
+
+    /// Dedicated environment variable (e.g., `JOLOKIA_NO_WARN_DEFAULT_KEY`).
+    pub fn env_var(self) -> String {
+        format!(
+            "{NO_WARN_ENV_VAR}_{}",
+            self.name().to_uppercase().replace('-', "_")
+        )
+    }
+
+    /// Parse a comma-separated list of warning IDs or names.
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|warning| !warning.is_empty())
+            .map(|warning| {
+                warning
+                    .parse()
+                    .map_err(|()| format!("Unknown warning '{warning}'"))
+            })
+            .collect()
+    }
+
+    /// Collect warnings suppressed through the environment.
+    ///
+    /// Unknown warnings in the environment are ignored, there is no
+    /// reason to fail over a variable that may have been set for
+    /// another version of the program.
+    pub fn suppressed_by_env(env: &dyn Environment) -> Vec<Self> {
+        let mut suppressed: Vec<Self> = env
+            .var(NO_WARN_ENV_VAR)
+            .map(|list| {
+                list.split(',')
+                    .filter_map(|warning| warning.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        for warning in Self::ALL {
+            if env.var(&warning.env_var()).is_some_and(|v| !v.is_empty()) {
+                suppressed.push(warning);
+            }
+        }
+        suppressed
+    }
+}
+
+impl FromStr for Warning {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase().replace('_', "-");
+        Self::ALL
+            .into_iter()
+            .find(|warning| s == warning.id().to_lowercase() || s == warning.name())
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::environment::Fake;
+
+    #[test]
+    fn warning_ids_are_unique() {
+        for (i, a) in Warning::ALL.iter().enumerate() {
+            for b in &Warning::ALL[i + 1..] {
+                assert_ne!(a.id(), b.id());
+                assert_ne!(a.name(), b.name());
+            }
+        }
+    }
+
+    #[test]
+    fn warning_from_id() {
+        assert_eq!("W001".parse(), Ok(Warning::DefaultKey));
+        assert_eq!("w002".parse(), Ok(Warning::InsecureAlgorithm));
+    }
+
+    #[test]
+    fn warning_from_name() {
+        assert_eq!("default-key".parse(), Ok(Warning::DefaultKey));
+        assert_eq!("INSECURE_ALGORITHM".parse(), Ok(Warning::InsecureAlgorithm));
+    }
+
+    #[test]
+    fn warning_from_unknown_is_error() {
+        assert_eq!("W999".parse::<Warning>(), Err(()));
+    }
+
+    #[test]
+    fn warning_env_var() {
+        assert_eq!(Warning::DefaultKey.env_var(), "JOLOKIA_NO_WARN_DEFAULT_KEY");
+        assert_eq!(
+            Warning::InsecureAlgorithm.env_var(),
+            "JOLOKIA_NO_WARN_INSECURE_ALGORITHM"
+        );
+    }
+
+    #[test]
+    fn warning_suppressed_by_env() {
+        let env = Fake::default()
+            .with_var(NO_WARN_ENV_VAR, "W002,unknown")
+            .with_var("JOLOKIA_NO_WARN_DEFAULT_KEY", "1");
+
+        assert_eq!(
+            Warning::suppressed_by_env(&env),
+            vec![Warning::InsecureAlgorithm, Warning::DefaultKey]
+        );
+    }
+
+    #[test]
+    fn warning_suppressed_by_env_empty_value_is_ignored() {
+        let env = Fake::default().with_var("JOLOKIA_NO_WARN_DEFAULT_KEY", "");
+
+        assert_eq!(Warning::suppressed_by_env(&env), vec![]);
+    }
+
+    #[test]
+    fn warning_parse_list() {
+        assert_eq!(
+            Warning::parse_list("W001, insecure-algorithm,"),
+            Ok(vec![Warning::DefaultKey, Warning::InsecureAlgorithm])
+        );
+    }
+
+    #[test]
+    fn warning_parse_list_unknown_is_error() {
+        assert_eq!(
+            Warning::parse_list("W001,nope"),
+            Err("Unknown warning 'nope'".to_string())
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Text encodings of binary data: base64, hex, and base32.
+//!
+//! Keys and ciphertexts are base64 by default. Some systems can't take
+//! base64 (e.g., DNS TXT records ignore case, legacy tools expect hex),
+//! an [`Encoding`] writes them in another alphabet.
+//!
+//! Encoded text never has padding, like the base64 jolokia has always
+//! written. Padding is accepted on decoding, though.
+
+pub mod base32;
+pub mod base64;
+pub mod hex;
+
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+use crate::traits::{Error, Result};
+
+use base64::NewlineTrimmer;
+
+/// A text encoding of binary data.
+pub trait Encoding: Sync {
+    /// Lowercase name (e.g., `hex`).
+    fn name(&self) -> &'static str;
+
+    /// When written to, it encodes the bytes into `writer`.
+    ///
+    /// The end of the text is written when the sink is dropped.
+    fn sink<'a>(&self, writer: &'a mut dyn Write) -> Box<dyn Write + 'a>;
+
+    /// When read from, it decodes the text of `reader` as bytes.
+    ///
+    /// Trailing newlines are ignored.
+    fn source<'a>(&self, reader: &'a mut dyn Read) -> Box<dyn Read + 'a>;
+
+    /// Encode `bytes` in memory.
+    fn encode(&self, bytes: &[u8]) -> String {
+        let mut encoded = Vec::new();
+        let mut sink = self.sink(&mut encoded);
+        sink.write_all(bytes).expect("this is all in memory");
+        drop(sink); // The end is written on drop.
+        String::from_utf8_lossy(&encoded).to_string()
+    }
+
+    /// Decode `text` in memory.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `text` is not valid in this encoding.
+    fn decode(&self, text: &[u8]) -> Result<Vec<u8>> {
+        let mut reader = io::Cursor::new(text);
+        let mut decoded = Vec::new();
+        io::copy(&mut self.source(&mut reader), &mut decoded).map_err(|e| Error::Decode {
+            encoding: self.name(),
+            reason: e.to_string(),
+        })?;
+        Ok(decoded)
+    }
+}
+
+/// All encodings, base64 (the default) first.
+pub const ENCODINGS: &[&dyn Encoding] = &[&base64::Base64, &hex::Hex, &base32::Base32];
+
+/// The encoding called `name` (e.g., `hex`), case-insensitively.
+#[must_use]
+pub fn by_name(name: &str) -> Option<&'static dyn Encoding> {
+    ENCODINGS
+        .iter()
+        .find(|encoding| encoding.name().eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// An encoding of fixed-size blocks (e.g., 5 bytes to 8 characters in
+/// base32), streamed by [`BlockSink`] and [`BlockSource`].
+trait Block {
+    const BYTES: usize;
+    const CHARS: usize;
+
+    /// Encode a block, it is shorter than `BYTES` only at the end.
+    fn encode(bytes: &[u8], text: &mut Vec<u8>);
+
+    /// Decode a block, it is shorter than `CHARS` only at the end.
+    fn decode(text: &[u8], bytes: &mut Vec<u8>) -> io::Result<()>;
+}
+
+struct BlockSink<'a, B: Block, W: Write + ?Sized> {
+    writer: &'a mut W,
+    /// Bytes of an incomplete block.
+    pending: Vec<u8>,
+    block: PhantomData<B>,
+}
+
+impl<'a, B: Block, W: Write + ?Sized> BlockSink<'a, B, W> {
+    fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer,
+            pending: Vec::with_capacity(B::BYTES),
+            block: PhantomData,
+        }
+    }
+}
+
+impl<B: Block, W: Write + ?Sized> Write for BlockSink<'_, B, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let complete = self.pending.len() / B::BYTES * B::BYTES;
+
+        let mut text = Vec::with_capacity(complete / B::BYTES * B::CHARS);
+        for block in self.pending[..complete].chunks(B::BYTES) {
+            B::encode(block, &mut text);
+        }
+        self.writer.write_all(&text)?;
+
+        self.pending.drain(..complete);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<B: Block, W: Write + ?Sized> Drop for BlockSink<'_, B, W> {
+    fn drop(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut text = Vec::with_capacity(B::CHARS);
+        B::encode(&self.pending, &mut text);
+        // Like the base64 encoder, errors can't be reported from here.
+        _ = self.writer.write_all(&text);
+    }
+}
+
+struct BlockSource<'a, B: Block, R: Read + ?Sized> {
+    reader: NewlineTrimmer<'a, R>,
+    /// Text of an incomplete block.
+    pending: Vec<u8>,
+    decoded: Vec<u8>,
+    /// Position of the next byte to return, in `decoded`.
+    position: usize,
+    is_eof: bool,
+    block: PhantomData<B>,
+}
+
+impl<'a, B: Block, R: Read + ?Sized> BlockSource<'a, B, R> {
+    fn new(reader: &'a mut R) -> Self {
+        Self {
+            reader: NewlineTrimmer::new(reader),
+            pending: Vec::with_capacity(B::CHARS),
+            decoded: Vec::new(),
+            position: 0,
+            is_eof: false,
+            block: PhantomData,
+        }
+    }
+}
+
+impl<B: Block, R: Read + ?Sized> Read for BlockSource<'_, B, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.position < self.decoded.len() {
+                let n = buf.len().min(self.decoded.len() - self.position);
+                buf[..n].copy_from_slice(&self.decoded[self.position..self.position + n]);
+                self.position += n;
+                return Ok(n);
+            }
+            if self.is_eof {
+                return Ok(0);
+            }
+
+            self.decoded.clear();
+            self.position = 0;
+
+            let mut text = [0u8; 8 * 1024];
+            let n = self.reader.read(&mut text)?;
+            if n == 0 {
+                self.is_eof = true;
+                if !self.pending.is_empty() {
+                    B::decode(&self.pending, &mut self.decoded)?;
+                }
+                continue;
+            }
+
+            self.pending.extend_from_slice(&text[..n]);
+            let complete = self.pending.len() / B::CHARS * B::CHARS;
+            for block in self.pending[..complete].chunks(B::CHARS) {
+                B::decode(block, &mut self.decoded)?;
+            }
+            self.pending.drain(..complete);
+        }
+    }
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn by_name_regular() {
+        assert_eq!(by_name("base64").unwrap().name(), "base64");
+        assert_eq!(by_name("hex").unwrap().name(), "hex");
+        assert_eq!(by_name("BASE32").unwrap().name(), "base32");
+        assert!(by_name("base58").is_none());
+    }
+
+    #[test]
+    fn round_trip_all_encodings() {
+        let bytes: Vec<u8> = (0..=255).collect();
+
+        for encoding in ENCODINGS {
+            for len in 0..bytes.len() {
+                let encoded = encoding.encode(&bytes[..len]);
+                assert_eq!(
+                    encoding.decode(encoded.as_bytes()).unwrap(),
+                    &bytes[..len],
+                    "{}",
+                    encoding.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sink_handles_split_writes() {
+        for encoding in ENCODINGS {
+            let mut encoded = Vec::new();
+            let mut sink = encoding.sink(&mut encoded);
+            for byte in b"hello, world!" {
+                sink.write_all(&[*byte]).unwrap();
+            }
+            drop(sink);
+
+            assert_eq!(
+                String::from_utf8(encoded).unwrap(),
+                encoding.encode(b"hello, world!")
+            );
+        }
+    }
+
+    #[test]
+    fn source_ignores_trailing_newlines() {
+        for encoding in ENCODINGS {
+            let encoded = format!("{}\n\n", encoding.encode(b"hello, world!"));
+
+            assert_eq!(
+                encoding.decode(encoded.as_bytes()).unwrap(),
+                b"hello, world!"
+            );
+        }
+    }
+}
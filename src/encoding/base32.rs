@@ -0,0 +1,135 @@
+//! Base32 encoding and decoding (RFC 4648, without padding).
+//!
+//! The alphabet is uppercase letters and digits, case doesn't matter on
+//! decoding. It survives case-insensitive systems (e.g., DNS) that would
+//! corrupt base64.
+
+use std::io::{self, Read, Write};
+
+use super::{Block, BlockSink, BlockSource, Encoding, invalid_data};
+
+const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Base32, 8 characters per 5 bytes.
+pub struct Base32;
+
+impl Encoding for Base32 {
+    fn name(&self) -> &'static str {
+        "base32"
+    }
+
+    fn sink<'a>(&self, writer: &'a mut dyn Write) -> Box<dyn Write + 'a> {
+        Box::new(BlockSink::<Self, _>::new(writer))
+    }
+
+    fn source<'a>(&self, reader: &'a mut dyn Read) -> Box<dyn Read + 'a> {
+        Box::new(BlockSource::<Self, _>::new(reader))
+    }
+}
+
+impl Block for Base32 {
+    const BYTES: usize = 5;
+    const CHARS: usize = 8;
+
+    fn encode(bytes: &[u8], text: &mut Vec<u8>) {
+        // The block, as a 40-bit big-endian number.
+        let block = bytes.iter().enumerate().fold(0u64, |block, (i, &byte)| {
+            block | u64::from(byte) << (32 - 8 * i)
+        });
+
+        let nb_chars = (bytes.len() * 8).div_ceil(5);
+        for i in 0..nb_chars {
+            let index = (block >> (35 - 5 * i)) & 0x1f;
+            text.push(ALPHABET[usize::try_from(index).expect("5 bits")]);
+        }
+    }
+
+    fn decode(text: &[u8], bytes: &mut Vec<u8>) -> io::Result<()> {
+        let text = trim_padding(text);
+
+        // 2, 4, 5, 7, or 8 characters, for 1 to 5 bytes.
+        let nb_bytes = text.len() * 5 / 8;
+        if nb_bytes == 0 || (nb_bytes * 8).div_ceil(5) != text.len() {
+            return Err(invalid_data("Invalid length".to_string()));
+        }
+
+        let mut block = 0u64;
+        for (i, &c) in text.iter().enumerate() {
+            block |= u64::from(value(c)?) << (35 - 5 * i);
+        }
+        // The 40 bits are the last 5 of the 8 bytes.
+        bytes.extend_from_slice(&block.to_be_bytes()[3..3 + nb_bytes]);
+        Ok(())
+    }
+}
+
+fn trim_padding(text: &[u8]) -> &[u8] {
+    let end = text.iter().rposition(|&c| c != b'=').map_or(0, |i| i + 1);
+    &text[..end]
+}
+
+fn value(c: u8) -> io::Result<u8> {
+    match c {
+        b'A'..=b'Z' => Ok(c - b'A'),
+        b'a'..=b'z' => Ok(c - b'a'),
+        b'2'..=b'7' => Ok(c - b'2' + 26),
+        _ => Err(invalid_data(format!(
+            "Invalid character '{}'",
+            char::from(c).escape_default()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4648, section 10 (without padding).
+    const VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "MY"),
+        ("fo", "MZXQ"),
+        ("foo", "MZXW6"),
+        ("foob", "MZXW6YQ"),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI"),
+    ];
+
+    #[test]
+    fn base32_encode_rfc_4648() {
+        for (plaintext, encoded) in VECTORS {
+            assert_eq!(Base32.encode(plaintext.as_bytes()), *encoded);
+        }
+    }
+
+    #[test]
+    fn base32_decode_rfc_4648() {
+        for (plaintext, encoded) in VECTORS {
+            assert_eq!(
+                Base32.decode(encoded.as_bytes()).unwrap(),
+                plaintext.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn base32_decode_lowercase_and_padding() {
+        assert_eq!(Base32.decode(b"mzxw6ytboi======").unwrap(), b"foobar");
+        assert_eq!(Base32.decode(b"MZXW6===").unwrap(), b"foo");
+    }
+
+    #[test]
+    fn base32_decode_invalid_length_is_error() {
+        let err = Base32.decode(b"MZX").unwrap_err();
+        assert_eq!(err.to_string(), "Could not decode base32: Invalid length");
+    }
+
+    #[test]
+    fn base32_decode_invalid_character_is_error() {
+        let err = Base32.decode(b"MZ1Q").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Could not decode base32: Invalid character '1'"
+        );
+    }
+}
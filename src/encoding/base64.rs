@@ -1,18 +1,45 @@
-//! Base64 encoding and decoding.
+//! Base64 encoding and decoding (standard alphabet, without padding).
 
 use std::io::{self, Read, Write};
 
 use base64::engine;
 use base64::prelude::BASE64_STANDARD_NO_PAD;
 use base64::{read::DecoderReader, write::EncoderWriter};
+use base64ct::{Base64Unpadded, Encoding as _};
+use secrecy::zeroize::Zeroizing;
 
+use super::Encoding;
 use crate::traits::{self, Base64Decode, Base64Encode, Error};
 
-// TODO: Implement dedicated `encode_key()`/`decode_key()` methods.
-//  As per the `GeneralPurpose` engine docs:
-//      It is not constant-time, though, so it is vulnerable to timing
-//      side-channel attacks. For loading cryptographic keys, etc, it is
-//      suggested to use the forthcoming constant-time implementation.
+/// Encode a key as base64, in constant time.
+///
+/// The `GeneralPurpose` engine behind [`Base64Encode`] looks up tables
+/// with the bytes it encodes, how long that takes can leak them through
+/// timing side-channels. That's fine for ciphertexts, not for keys.
+#[must_use]
+pub fn encode_key(key: &[u8]) -> Zeroizing<String> {
+    Zeroizing::new(Base64Unpadded::encode_string(key))
+}
+
+/// Decode a base64 key, in constant time (see [`encode_key()`]).
+///
+/// Like [`Base64Decode`], trailing newlines are ignored.
+///
+/// # Errors
+///
+/// Errors if `key` is not valid base64. Where it's invalid is not said,
+/// that would take branching on the key.
+pub fn decode_key(key: &[u8]) -> traits::Result<Zeroizing<Vec<u8>>> {
+    let len = key.iter().rposition(|&c| c != b'\n').map_or(0, |i| i + 1);
+    let key = &key[..len];
+
+    let mut decoded = Zeroizing::new(vec![0u8; key.len() * 3 / 4]);
+    let len = Base64Unpadded::decode(key, &mut decoded)
+        .map_err(|_| Error::Base64Decode("Malformed key".to_string()))?
+        .len();
+    decoded.truncate(len);
+    Ok(decoded)
+}
 
 impl Base64Encode for &[u8] {
     fn base64_encode(&self) -> String {
@@ -60,19 +87,44 @@ impl Base64Decode for String {
     }
 }
 
+/// Base64, the default encoding.
+pub struct Base64;
+
+impl Encoding for Base64 {
+    fn name(&self) -> &'static str {
+        "base64"
+    }
+
+    fn sink<'a>(&self, writer: &'a mut dyn Write) -> Box<dyn Write + 'a> {
+        Box::new(Base64Sink::new(writer))
+    }
+
+    fn source<'a>(&self, reader: &'a mut dyn Read) -> Box<dyn Read + 'a> {
+        Box::new(Base64Source::new(reader))
+    }
+
+    fn encode(&self, bytes: &[u8]) -> String {
+        bytes.base64_encode()
+    }
+
+    fn decode(&self, text: &[u8]) -> traits::Result<Vec<u8>> {
+        text.base64_decode()
+    }
+}
+
 /// When written to, it encodes the bytes as base64.
-pub struct Base64Sink<'a, W: Write> {
+pub struct Base64Sink<'a, W: Write + ?Sized> {
     encoder: EncoderWriter<'a, engine::GeneralPurpose, &'a mut W>,
 }
 
-impl<'a, W: Write> Base64Sink<'a, W> {
+impl<'a, W: Write + ?Sized> Base64Sink<'a, W> {
     pub fn new(writer: &'a mut W) -> Self {
         let encoder = EncoderWriter::new(writer, &BASE64_STANDARD_NO_PAD);
         Self { encoder }
     }
 }
 
-impl<W: Write> Write for Base64Sink<'_, W> {
+impl<W: Write + ?Sized> Write for Base64Sink<'_, W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.encoder.write(buf)
     }
@@ -82,13 +134,13 @@ impl<W: Write> Write for Base64Sink<'_, W> {
     }
 }
 
-struct NewlineTrimmer<'a, R: Read> {
+pub(super) struct NewlineTrimmer<'a, R: Read + ?Sized> {
     reader: &'a mut R,
     is_trimmed: bool,
 }
 
-impl<'a, R: Read> NewlineTrimmer<'a, R> {
-    fn new(reader: &'a mut R) -> Self {
+impl<'a, R: Read + ?Sized> NewlineTrimmer<'a, R> {
+    pub(super) fn new(reader: &'a mut R) -> Self {
         Self {
             reader,
             is_trimmed: false,
@@ -96,7 +148,7 @@ impl<'a, R: Read> NewlineTrimmer<'a, R> {
     }
 }
 
-impl<T: Read> Read for NewlineTrimmer<'_, T> {
+impl<T: Read + ?Sized> Read for NewlineTrimmer<'_, T> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let mut n = self.reader.read(buf)?;
 
@@ -129,11 +181,11 @@ impl<T: Read> Read for NewlineTrimmer<'_, T> {
 }
 
 /// When read from, it decodes base64 as bytes.
-pub struct Base64Source<'a, R: Read> {
+pub struct Base64Source<'a, R: Read + ?Sized> {
     decoder: DecoderReader<'a, engine::GeneralPurpose, NewlineTrimmer<'a, R>>,
 }
 
-impl<'a, R: Read> Base64Source<'a, R> {
+impl<'a, R: Read + ?Sized> Base64Source<'a, R> {
     pub fn new(reader: &'a mut R) -> Self {
         let newline_stripper = NewlineTrimmer::new(reader);
         let decoder = DecoderReader::new(newline_stripper, &BASE64_STANDARD_NO_PAD);
@@ -141,7 +193,7 @@ impl<'a, R: Read> Base64Source<'a, R> {
     }
 }
 
-impl<R: Read> Read for Base64Source<'_, R> {
+impl<R: Read + ?Sized> Read for Base64Source<'_, R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.decoder.read(buf)
     }
@@ -192,6 +244,40 @@ pub mod tests {
         assert_eq!(plaintext, "hello, world!");
     }
 
+    #[test]
+    fn base64_key_same_as_general_purpose() {
+        for len in 0..=64 {
+            let key: Vec<u8> = (0..len).map(|i: u8| i.wrapping_mul(37)).collect();
+
+            let encoded = encode_key(&key);
+            assert_eq!(*encoded, key.base64_encode());
+            assert_eq!(*decode_key(encoded.as_bytes()).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn base64_decode_key_trailing_newlines() {
+        let key = decode_key(b"aGVsbG8sIHdvcmxkIQ\n\n").unwrap();
+
+        assert_eq!(*key, b"hello, world!");
+    }
+
+    #[test]
+    fn base64_decode_key_invalid_is_error() {
+        for key in [
+            "aGVsbG8sIHdvcmxkIQ==",
+            "aGVs*G8",
+            "aGVsb",
+            "aGVsbG8\nsIHdvcmxkIQ",
+        ] {
+            assert_eq!(
+                decode_key(key.as_bytes()).unwrap_err(),
+                Error::Base64Decode("Malformed key".to_string()),
+                "{key}"
+            );
+        }
+    }
+
     #[test]
     fn base64_encode_sink_short() {
         let plaintext = b"hello, world!";
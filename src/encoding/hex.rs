@@ -0,0 +1,88 @@
+//! Hex encoding and decoding (lowercase, either case on decoding).
+
+use std::io::{self, Read, Write};
+
+use super::{Block, BlockSink, BlockSource, Encoding, invalid_data};
+
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Hex, two digits per byte.
+pub struct Hex;
+
+impl Encoding for Hex {
+    fn name(&self) -> &'static str {
+        "hex"
+    }
+
+    fn sink<'a>(&self, writer: &'a mut dyn Write) -> Box<dyn Write + 'a> {
+        Box::new(BlockSink::<Self, _>::new(writer))
+    }
+
+    fn source<'a>(&self, reader: &'a mut dyn Read) -> Box<dyn Read + 'a> {
+        Box::new(BlockSource::<Self, _>::new(reader))
+    }
+}
+
+impl Block for Hex {
+    const BYTES: usize = 1;
+    const CHARS: usize = 2;
+
+    fn encode(bytes: &[u8], text: &mut Vec<u8>) {
+        for byte in bytes {
+            text.push(DIGITS[usize::from(byte >> 4)]);
+            text.push(DIGITS[usize::from(byte & 0x0f)]);
+        }
+    }
+
+    fn decode(text: &[u8], bytes: &mut Vec<u8>) -> io::Result<()> {
+        let [high, low] = text else {
+            return Err(invalid_data("Odd number of digits".to_string()));
+        };
+        bytes.push(digit(*high)? << 4 | digit(*low)?);
+        Ok(())
+    }
+}
+
+fn digit(c: u8) -> io::Result<u8> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(invalid_data(format!(
+            "Invalid digit '{}'",
+            char::from(c).escape_default()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_encode() {
+        assert_eq!(Hex.encode(b""), "");
+        assert_eq!(Hex.encode(b"foobar"), "666f6f626172");
+        assert_eq!(Hex.encode(&[0x00, 0xff, 0x0f]), "00ff0f");
+    }
+
+    #[test]
+    fn hex_decode_either_case() {
+        assert_eq!(Hex.decode(b"666F6f626172").unwrap(), b"foobar");
+    }
+
+    #[test]
+    fn hex_decode_odd_length_is_error() {
+        let err = Hex.decode(b"666").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Could not decode hex: Odd number of digits"
+        );
+    }
+
+    #[test]
+    fn hex_decode_invalid_digit_is_error() {
+        let err = Hex.decode(b"6g").unwrap_err();
+        assert_eq!(err.to_string(), "Could not decode hex: Invalid digit 'g'");
+    }
+}
@@ -0,0 +1,355 @@
+//! Randomness, for keys, nonces, and salts.
+//!
+//! All randomness goes through here, never through the RNG helpers of
+//! dependencies (which panic on failure). This way, failing to get
+//! randomness is an [`Error::Entropy`], and where it comes from can be
+//! changed process-wide (see [`set_source()`]).
+//!
+//! Without the `os-rng` feature (e.g., on targets with no operating
+//! system), [`Source::System`] is an error, and randomness must be
+//! injected (see below). Random devices require the `fs` feature.
+//!
+//! For reproducible output (fixtures, simulations), an RNG can be
+//! injected for the duration of a closure, on the current thread (see
+//! [`with_rng()`]). Everywhere else, randomness is untouched.
+//!
+//! ```
+//! use jolokia::cipher::ChaCha20Poly1305;
+//! use jolokia::entropy::{self, rand_core};
+//! use jolokia::traits::Cipher;
+//! # use std::convert::Infallible;
+//! # use rand_core::{TryCryptoRng, TryRng};
+//! # struct Counter(u8);
+//! # impl TryRng for Counter {
+//! #     type Error = Infallible;
+//! #     fn try_next_u32(&mut self) -> Result<u32, Infallible> { unimplemented!() }
+//! #     fn try_next_u64(&mut self) -> Result<u64, Infallible> { unimplemented!() }
+//! #     fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Infallible> {
+//! #         dst.fill_with(|| { self.0 = self.0.wrapping_add(1); self.0 });
+//! #         Ok(())
+//! #     }
+//! # }
+//! # impl TryCryptoRng for Counter {}
+//! # fn seeded_rng() -> Counter { Counter(0) }
+//!
+//! let key = [0u8; 32];
+//! let a = entropy::with_rng(seeded_rng(), || ChaCha20Poly1305.encrypt(&key, b"hello"));
+//! let b = entropy::with_rng(seeded_rng(), || ChaCha20Poly1305.encrypt(&key, b"hello"));
+//!
+//! assert_eq!(a.unwrap(), b.unwrap());
+//! ```
+
+use std::cell::RefCell;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::Read;
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+pub use rand_core;
+use rand_core::CryptoRng;
+#[cfg(feature = "hpke")]
+use rand_core::{TryCryptoRng, TryRng};
+
+use crate::traits::{self, Error};
+
+/// Device read by [`Source::strict()`].
+///
+/// On Linux, this has the semantics of `getrandom()` with
+/// `GRND_RANDOM`: reads block until the kernel considers the pool
+/// properly seeded.
+#[cfg(feature = "fs")]
+pub const STRICT_DEVICE: &str = "/dev/random";
+
+/// Where randomness comes from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Source {
+    /// The operating system's CSPRNG (`getrandom()`).
+    #[default]
+    System,
+    /// A random device, like a hardware RNG (e.g., `/dev/hwrng`).
+    ///
+    /// There is no fallback, if the device can't be read, it's an error.
+    #[cfg(feature = "fs")]
+    Device(PathBuf),
+}
+
+#[cfg(feature = "fs")]
+impl Source {
+    /// The blocking system device (see [`STRICT_DEVICE`]).
+    #[must_use]
+    pub fn strict() -> Self {
+        Self::Device(PathBuf::from(STRICT_DEVICE))
+    }
+}
+
+static SOURCE: RwLock<Source> = RwLock::new(Source::System);
+
+thread_local! {
+    /// RNG injected with [`with_rng()`], takes precedence over [`SOURCE`].
+    static INJECTED: RefCell<Option<Box<dyn CryptoRng>>> = const { RefCell::new(None) };
+}
+
+/// Use `source` for all randomness, from now on.
+pub fn set_source(source: Source) {
+    *SOURCE
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = source;
+}
+
+/// The source in use.
+#[must_use]
+pub fn source() -> Source {
+    SOURCE
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clone()
+}
+
+/// Run `f` with all randomness drawn from `rng`, on this thread.
+///
+/// This is meant for reproducible output (e.g., test fixtures). Don't
+/// use it to encrypt real data, a predictable RNG makes keys and nonces
+/// predictable.
+///
+/// Calls can be nested, the previous RNG is restored when `f` returns
+/// (or panics).
+pub fn with_rng<T>(rng: impl CryptoRng + 'static, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Box<dyn CryptoRng>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            INJECTED.with_borrow_mut(|injected| *injected = previous);
+        }
+    }
+
+    let previous = INJECTED.with_borrow_mut(|injected| injected.replace(Box::new(rng)));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Fill `buf` with random bytes.
+///
+/// # Errors
+///
+/// Errors if the source can't provide the bytes.
+pub fn fill(buf: &mut [u8]) -> traits::Result<()> {
+    let injected = INJECTED
+        .with_borrow_mut(|injected| injected.as_mut().map(|rng| rng.fill_bytes(buf)).is_some());
+    if injected {
+        return Ok(());
+    }
+    match source() {
+        #[cfg(feature = "os-rng")]
+        Source::System => getrandom::fill(buf).map_err(|e| Error::Entropy(e.to_string())),
+        #[cfg(not(feature = "os-rng"))]
+        Source::System => Err(Error::Entropy(
+            "No system randomness (built without 'os-rng')".to_string(),
+        )),
+        #[cfg(feature = "fs")]
+        Source::Device(path) => File::open(&path)
+            .and_then(|mut device| device.read_exact(buf))
+            .map_err(|e| Error::Entropy(format!("'{}': {e}", path.display()))),
+    }
+}
+
+/// Generate `N` random bytes.
+///
+/// # Errors
+///
+/// Errors if the source can't provide the bytes.
+pub fn generate<const N: usize>() -> traits::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    fill(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// An RNG, for dependencies that require one.
+///
+/// Some APIs take an infallible RNG, so the bytes are drawn up-front
+/// (where it can fail), and handed out as they are requested. Asking
+/// for more than was drawn is a bug.
+#[cfg(feature = "hpke")]
+pub(crate) struct Drawn<const N: usize> {
+    bytes: [u8; N],
+    used: usize,
+}
+
+#[cfg(feature = "hpke")]
+impl<const N: usize> Drawn<N> {
+    pub(crate) fn new() -> traits::Result<Self> {
+        Ok(Self {
+            bytes: generate()?,
+            used: 0,
+        })
+    }
+}
+
+#[cfg(feature = "hpke")]
+impl<const N: usize> Drop for Drawn<N> {
+    fn drop(&mut self) {
+        secrecy::zeroize::Zeroize::zeroize(&mut self.bytes);
+    }
+}
+
+#[cfg(feature = "hpke")]
+impl<const N: usize> TryRng for Drawn<N> {
+    type Error = core::convert::Infallible;
+
+    fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+        let mut bytes = [0u8; 4];
+        self.try_fill_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+        let mut bytes = [0u8; 8];
+        self.try_fill_bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+        let end = self.used + dst.len();
+        assert!(end <= N, "not enough random bytes were drawn");
+        dst.copy_from_slice(&self.bytes[self.used..end]);
+        self.used = end;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "hpke")]
+impl<const N: usize> TryCryptoRng for Drawn<N> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand_core::{TryCryptoRng, TryRng};
+
+    #[cfg(feature = "os-rng")]
+    #[test]
+    fn entropy_generate_system() {
+        let a = generate::<32>().unwrap();
+        let b = generate::<32>().unwrap();
+
+        assert_ne!(a, b);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn entropy_source_strict_is_device() {
+        assert_eq!(
+            Source::strict(),
+            Source::Device(PathBuf::from("/dev/random"))
+        );
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn entropy_drawn_hands_out_bytes_in_order() {
+        let mut rng = Drawn::<8> {
+            bytes: [1, 2, 3, 4, 5, 6, 7, 8],
+            used: 0,
+        };
+
+        let mut a = [0u8; 3];
+        let mut b = [0u8; 5];
+        rng.try_fill_bytes(&mut a).unwrap();
+        rng.try_fill_bytes(&mut b).unwrap();
+
+        assert_eq!(a, [1, 2, 3]);
+        assert_eq!(b, [4, 5, 6, 7, 8]);
+    }
+
+    struct Counter(u8);
+
+    impl TryRng for Counter {
+        type Error = core::convert::Infallible;
+
+        fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+            unimplemented!()
+        }
+
+        fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+            unimplemented!()
+        }
+
+        fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+            dst.fill_with(|| {
+                self.0 = self.0.wrapping_add(1);
+                self.0
+            });
+            Ok(())
+        }
+    }
+
+    impl TryCryptoRng for Counter {}
+
+    #[test]
+    fn entropy_with_rng_is_used() {
+        let bytes = with_rng(Counter(0), generate::<4>).unwrap();
+
+        assert_eq!(bytes, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn entropy_with_rng_is_restored() {
+        let inner = with_rng(Counter(0), || {
+            let inner = with_rng(Counter(100), generate::<2>).unwrap();
+            assert_eq!(generate::<2>().unwrap(), [1, 2]);
+            inner
+        });
+
+        assert_eq!(inner, [101, 102]);
+        assert!(INJECTED.with_borrow(Option::is_none));
+    }
+
+    #[test]
+    fn entropy_with_rng_is_restored_after_panic() {
+        let res = std::panic::catch_unwind(|| with_rng(Counter(0), || panic!("oops")));
+
+        assert!(res.is_err());
+        assert!(INJECTED.with_borrow(Option::is_none));
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn entropy_with_rng_makes_ciphers_deterministic() {
+        use crate::cipher::{ChaCha20Poly1305, Hpke};
+        use crate::traits::{Cipher, KeygenOptions};
+
+        let key = with_rng(Counter(0), || {
+            Hpke.generate_key(&KeygenOptions::default()).unwrap()
+        });
+        let public = secrecy::ExposeSecret::expose_secret(key.get_asymmetric_public()).to_vec();
+
+        for cipher in [&ChaCha20Poly1305 as &dyn Cipher, &Hpke] {
+            let key = if cipher.algorithm_id() == "hpke" {
+                public.clone()
+            } else {
+                vec![0u8; 32]
+            };
+            let a = with_rng(Counter(0), || cipher.encrypt(&key, b"hello")).unwrap();
+            let b = with_rng(Counter(0), || cipher.encrypt(&key, b"hello")).unwrap();
+            let c = cipher.encrypt(&key, b"hello").unwrap();
+
+            assert_eq!(a, b);
+            assert_ne!(a, c);
+        }
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    #[should_panic(expected = "not enough random bytes were drawn")]
+    fn entropy_drawn_exhausted_is_bug() {
+        let mut rng = Drawn::<4> {
+            bytes: [0; 4],
+            used: 0,
+        };
+
+        rng.try_fill_bytes(&mut [0u8; 5]).unwrap();
+    }
+}
@@ -0,0 +1,385 @@
+//! Advisory expiry dates for ciphertexts.
+//!
+//! [`Expiring`] wraps a cipher, and puts an expiry header in front of
+//! the ciphertext:
+//!
+//! ```text
+//! [ header (5) ]        b"EXPR\x01", magic and version
+//! [ expires at (8) ]    seconds since the Unix epoch (8-byte BE)
+//! [ ciphertext ]
+//! ```
+//!
+//! The header is not encrypted, but it is authenticated: it is part of
+//! the associated data of the ciphertext. Changing the date makes
+//! decryption fail.
+//!
+//! Expiry is _advisory_. Anyone with the key can still decrypt the
+//! message after the date, it is up to the reader to act on it (see
+//! [`Expiring::on_expiry()`]).
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime};
+
+use crate::traits::{
+    self, Cipher, CountingReader, CountingWriter, Error, GeneratedKey, KeygenOptions, StreamStats,
+};
+use crate::wire::read_up_to;
+
+// Contains magic (4-bytes) and version (1-byte).
+pub(crate) const MAGIC: &[u8; 5] = b"EXPR\x01";
+
+pub(crate) const HEADER_LEN: usize = MAGIC.len() + 8;
+
+type Check = Box<dyn Fn(SystemTime) -> traits::Result<()> + Send + Sync>;
+
+/// Cipher decorator that adds (and reads) expiry dates.
+///
+/// Ciphertexts without an expiry header decrypt as usual, so the same
+/// decorator can decrypt both kinds.
+pub struct Expiring {
+    cipher: Box<dyn Cipher>,
+    expires_at: Option<SystemTime>,
+    check: Option<Check>,
+}
+
+impl Expiring {
+    #[must_use]
+    pub fn new(cipher: Box<dyn Cipher>) -> Self {
+        Self {
+            cipher,
+            expires_at: None,
+            check: None,
+        }
+    }
+
+    /// Embed `expires_at` in the messages this cipher encrypts.
+    ///
+    /// The date is stored with a precision of one second.
+    #[must_use]
+    pub fn expires_at(mut self, expires_at: SystemTime) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Call `check` with the expiry date of messages that have one.
+    ///
+    /// The check runs before decryption starts, returning an error
+    /// refuses to decrypt the message. Note that at this point, the
+    /// date is not yet authenticated (a tampered date makes decryption
+    /// fail afterwards).
+    #[must_use]
+    pub fn on_expiry(
+        mut self,
+        check: impl Fn(SystemTime) -> traits::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.check = Some(Box::new(check));
+        self
+    }
+}
+
+impl Cipher for Expiring {
+    fn algorithm_id(&self) -> &'static str {
+        self.cipher.algorithm_id()
+    }
+
+    fn generate_key(&self, options: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        self.cipher.generate_key(options)
+    }
+
+    fn encrypt_stream(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.encrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn decrypt_stream(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.decrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn encrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let Some(expires_at) = self.expires_at else {
+            return self
+                .cipher
+                .encrypt_stream_with_aad(key, aad, reader, writer);
+        };
+
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
+        let header = header(expires_at);
+        writer
+            .write_all(&header)
+            .map_err(|e| Error::Write(e.to_string()))?;
+
+        let stats = self.cipher.encrypt_stream_with_aad(
+            key,
+            &[&header, aad].concat(),
+            &mut reader,
+            &mut writer,
+        )?;
+
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            ..stats
+        })
+    }
+
+    fn decrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
+        let mut header = [0u8; HEADER_LEN];
+        let n = read_up_to(&mut reader, &mut header[..MAGIC.len()])?;
+
+        if &header[..n] != MAGIC {
+            // Not ours, put back what was read.
+            let stats = self.cipher.decrypt_stream_with_aad(
+                key,
+                aad,
+                &mut io::Cursor::new(&header[..n]).chain(&mut reader),
+                &mut writer,
+            )?;
+            return Ok(StreamStats {
+                bytes_in: reader.count,
+                bytes_out: writer.count,
+                ..stats
+            });
+        }
+
+        reader
+            .read_exact(&mut header[MAGIC.len()..])
+            .map_err(|e| Error::Read(e.to_string()))?;
+
+        // Dates too far to be represented never come.
+        if let Some(ref check) = self.check
+            && let Some(expires_at) = expires_at(&header)
+        {
+            check(expires_at)?;
+        }
+
+        let stats = self.cipher.decrypt_stream_with_aad(
+            key,
+            &[&header, aad].concat(),
+            &mut reader,
+            &mut writer,
+        )?;
+
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            ..stats
+        })
+    }
+}
+
+fn header(expires_at: SystemTime) -> [u8; HEADER_LEN] {
+    // Dates before the epoch make no sense here, they are clamped.
+    let secs = expires_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    let mut header = [0u8; HEADER_LEN];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[MAGIC.len()..].copy_from_slice(&secs.to_be_bytes());
+    header
+}
+
+pub(crate) fn expires_at(header: &[u8; HEADER_LEN]) -> Option<SystemTime> {
+    let mut secs = [0u8; 8];
+    secs.copy_from_slice(&header[MAGIC.len()..]);
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(u64::from_be_bytes(secs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::cipher::ChaCha20Poly1305;
+    #[cfg(feature = "rotn")]
+    use crate::cipher::RotN;
+
+    const KEY: [u8; 32] = [0u8; 32];
+
+    fn date(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn expiring_round_trip() {
+        let seen = Arc::new(Mutex::new(None));
+        let seen_by_check = Arc::clone(&seen);
+
+        let encrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .expires_at(date(1_700_000_000))
+            .encrypt(&KEY, b"hello")
+            .unwrap();
+        let decrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .on_expiry(move |expires_at| {
+                *seen_by_check.lock().unwrap() = Some(expires_at);
+                Ok(())
+            })
+            .decrypt(&KEY, &encrypted)
+            .unwrap();
+
+        assert_eq!(decrypted, b"hello");
+        assert_eq!(*seen.lock().unwrap(), Some(date(1_700_000_000)));
+    }
+
+    #[test]
+    fn expiring_header_format() {
+        let encrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .expires_at(date(0x0102_0304))
+            .encrypt(&KEY, b"hello")
+            .unwrap();
+
+        assert_eq!(
+            &encrypted[..HEADER_LEN],
+            b"EXPR\x01\0\0\0\0\x01\x02\x03\x04"
+        );
+    }
+
+    #[test]
+    fn expiring_check_refuses() {
+        let encrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .expires_at(date(1_700_000_000))
+            .encrypt(&KEY, b"hello")
+            .unwrap();
+
+        let mut output = Vec::new();
+        let err = Expiring::new(Box::new(ChaCha20Poly1305))
+            .on_expiry(|_| Err(Error::Other("Expired.".to_string())))
+            .decrypt_stream(&KEY, &mut encrypted.as_slice(), &mut output)
+            .unwrap_err();
+
+        assert_eq!(err, Error::Other("Expired.".to_string()));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn expiring_tampered_date_is_error() {
+        let mut encrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .expires_at(date(1_700_000_000))
+            .encrypt(&KEY, b"hello")
+            .unwrap();
+        encrypted[HEADER_LEN - 1] ^= 1;
+
+        let err = Expiring::new(Box::new(ChaCha20Poly1305))
+            .decrypt(&KEY, &encrypted)
+            .unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn expiring_without_header_is_passed_through() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, b"hello").unwrap();
+
+        let decrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .on_expiry(|_| panic!("no expiry header"))
+            .decrypt(&KEY, &encrypted)
+            .unwrap();
+
+        assert_eq!(decrypted, b"hello");
+    }
+
+    #[test]
+    fn expiring_stream_stats_include_header() {
+        let cipher = Expiring::new(Box::new(ChaCha20Poly1305)).expires_at(date(1_700_000_000));
+
+        let mut encrypted = Vec::new();
+        let stats = cipher
+            .encrypt_stream(&KEY, &mut io::Cursor::new(b"hello"), &mut encrypted)
+            .unwrap();
+        assert_eq!(stats.bytes_out, encrypted.len() as u64);
+
+        let mut decrypted = Vec::new();
+        let stats = cipher
+            .decrypt_stream(&KEY, &mut io::Cursor::new(&encrypted), &mut decrypted)
+            .unwrap();
+        assert_eq!(
+            stats,
+            StreamStats {
+                bytes_in: encrypted.len() as u64,
+                bytes_out: 5,
+                chunks: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn expiring_stream_stats_when_passed_through() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, b"hello").unwrap();
+
+        let mut decrypted = Vec::new();
+        let stats = Expiring::new(Box::new(ChaCha20Poly1305))
+            .decrypt_stream(&KEY, &mut io::Cursor::new(&encrypted), &mut decrypted)
+            .unwrap();
+
+        // The peeked bytes are counted once.
+        assert_eq!(stats.bytes_in, encrypted.len() as u64);
+    }
+
+    #[test]
+    fn expiring_without_date_is_passed_through() {
+        let encrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .encrypt(&KEY, b"hello")
+            .unwrap();
+
+        let decrypted = ChaCha20Poly1305.decrypt(&KEY, &encrypted).unwrap();
+
+        assert_eq!(decrypted, b"hello");
+    }
+
+    #[cfg(feature = "rotn")]
+    #[test]
+    fn expiring_short_input_is_passed_through() {
+        // Shorter than the magic, it goes to the cipher as-is.
+        let decrypted = Expiring::new(Box::new(RotN))
+            .decrypt(&[13], b"uryy")
+            .unwrap();
+        assert_eq!(decrypted, b"hell");
+    }
+
+    #[test]
+    fn expiring_with_aad() {
+        let cipher = Expiring::new(Box::new(ChaCha20Poly1305)).expires_at(date(1_700_000_000));
+
+        let mut encrypted = Vec::new();
+        cipher
+            .encrypt_stream_with_aad(&KEY, b"v1", &mut &b"hello"[..], &mut encrypted)
+            .unwrap();
+
+        let err = cipher
+            .decrypt_stream_with_aad(&KEY, b"v2", &mut encrypted.as_slice(), &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err, Error::Decrypt);
+
+        let mut decrypted = Vec::new();
+        cipher
+            .decrypt_stream_with_aad(&KEY, b"v1", &mut encrypted.as_slice(), &mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, b"hello");
+    }
+}
@@ -0,0 +1,163 @@
+//! Ciphertext formats, and their versions.
+//!
+//! Every layer of a ciphertext starts with a 5-byte header: a 4-byte ID
+//! and a 1-byte version (e.g., `b"CH20\x02"`). [`HEADERS`] lists all
+//! the headers jolokia reads, with the [`Decoder`] that handles what
+//! follows, and whether it is still the one jolokia writes.
+//!
+//! Old versions stay in the list: they are decrypted, but not written
+//! anymore. `jolokia migrate` re-encrypts them in the latest version.
+//! They may lack newer protections, [`set_read_outdated()`] turns them
+//! down instead.
+//!
+//! age has its own format and versioning, it is not listed.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cipher::chacha;
+#[cfg(feature = "password")]
+use crate::cipher::password;
+#[cfg(feature = "hpke")]
+use crate::cipher::{hpke, hpke_pq};
+use crate::{expiry, key_hint};
+
+/// What a header introduces.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Decoder {
+    /// Expiry date, then another layer (see [`crate::expiry`]).
+    Expiry,
+    /// Hint of the key, then another layer (see [`crate::key_hint`]).
+    KeyHint,
+    /// Salt and KDF parameters, then the stream.
+    #[cfg(feature = "password")]
+    Password,
+    /// Encapsulated key, then the stream.
+    #[cfg(feature = "hpke")]
+    Hpke,
+    /// Encapsulated key (X-Wing), then the stream.
+    #[cfg(feature = "hpke")]
+    HpkePq,
+    /// ChaCha20-Poly1305 stream, with or without the trailer that
+    /// detects truncation at a chunk boundary.
+    ChaCha { trailer: bool },
+}
+
+/// A known header.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Header {
+    pub bytes: &'static [u8; 5],
+    pub decoder: Decoder,
+    /// Whether jolokia still writes this version.
+    pub latest: bool,
+}
+
+/// All the headers jolokia reads (of the ciphers compiled in).
+pub const HEADERS: &[Header] = &[
+    Header {
+        bytes: expiry::MAGIC,
+        decoder: Decoder::Expiry,
+        latest: true,
+    },
+    Header {
+        bytes: key_hint::MAGIC,
+        decoder: Decoder::KeyHint,
+        latest: true,
+    },
+    #[cfg(feature = "password")]
+    Header {
+        bytes: password::HEADER,
+        decoder: Decoder::Password,
+        latest: true,
+    },
+    #[cfg(feature = "hpke")]
+    Header {
+        bytes: hpke::HEADER,
+        decoder: Decoder::Hpke,
+        latest: true,
+    },
+    #[cfg(feature = "hpke")]
+    Header {
+        bytes: hpke_pq::HEADER,
+        decoder: Decoder::HpkePq,
+        latest: true,
+    },
+    Header {
+        bytes: chacha::HEADER,
+        decoder: Decoder::ChaCha { trailer: true },
+        latest: true,
+    },
+    Header {
+        bytes: chacha::HEADER_V1,
+        decoder: Decoder::ChaCha { trailer: false },
+        latest: false,
+    },
+];
+
+static READ_OUTDATED: AtomicBool = AtomicBool::new(true);
+
+/// Decrypt old versions (the default), or fail with [`Error::Outdated`]
+/// from now on.
+///
+/// [`Error::Outdated`]: crate::traits::Error::Outdated
+pub fn set_read_outdated(read: bool) {
+    READ_OUTDATED.store(read, Ordering::Relaxed);
+}
+
+/// Whether old versions are decrypted.
+#[must_use]
+pub fn reads_outdated() -> bool {
+    READ_OUTDATED.load(Ordering::Relaxed)
+}
+
+/// The header `bytes` start with, if any.
+#[must_use]
+pub fn lookup(bytes: &[u8]) -> Option<&'static Header> {
+    HEADERS
+        .iter()
+        .find(|header| bytes.starts_with(header.bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_regular() {
+        let header = lookup(b"CH20\x02 and more").unwrap();
+
+        assert_eq!(header.decoder, Decoder::ChaCha { trailer: true });
+        assert!(header.latest);
+    }
+
+    #[test]
+    fn lookup_old_version() {
+        let header = lookup(b"CH20\x01").unwrap();
+
+        assert_eq!(header.decoder, Decoder::ChaCha { trailer: false });
+        assert!(!header.latest);
+    }
+
+    #[test]
+    fn lookup_unknown() {
+        assert_eq!(lookup(b"CH20\x03"), None);
+        assert_eq!(lookup(b"CH20"), None);
+        assert_eq!(lookup(b""), None);
+    }
+
+    #[test]
+    fn headers_are_unique() {
+        for (i, header) in HEADERS.iter().enumerate() {
+            assert_eq!(lookup(header.bytes), Some(&HEADERS[i]));
+        }
+    }
+
+    #[test]
+    fn one_latest_version_per_id() {
+        for header in HEADERS.iter().filter(|header| header.latest) {
+            let ids = HEADERS
+                .iter()
+                .filter(|other| other.latest && other.bytes[..4] == header.bytes[..4]);
+            assert_eq!(ids.count(), 1);
+        }
+    }
+}
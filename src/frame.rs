@@ -0,0 +1,122 @@
+//! Outer framing for multiple messages on a single stream.
+//!
+//! Ciphertexts know where they end, but only once they are being
+//! decrypted. To write independent messages sequentially to a single
+//! pipe or socket, each message is wrapped in a frame:
+//!
+//! ```text
+//! [ payload length (4-byte BE) ][ payload ]
+//! [ payload length (4-byte BE) ][ payload ]
+//!   ⋮
+//! ```
+//!
+//! A reader consumes exactly one frame at a time, which means a
+//! long-lived consumer can process messages as they come, without
+//! waiting for the end of the stream.
+
+use std::io::{self, Read, Write};
+
+use crate::traits::{self, Error};
+
+/// Write `payload` as a single frame.
+///
+/// # Errors
+///
+/// Errors if the payload is larger than 4 GiB, or if write fails.
+pub fn write_frame(writer: &mut dyn Write, payload: &[u8]) -> traits::Result<()> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| Error::Other("Frame is too large.".to_string()))?
+        .to_be_bytes();
+    writer
+        .write_all(&len)
+        .map_err(|e| Error::Write(e.to_string()))?;
+    writer
+        .write_all(payload)
+        .map_err(|e| Error::Write(e.to_string()))?;
+    Ok(())
+}
+
+/// Read the payload of the next frame.
+///
+/// Returns `None` if the stream ends cleanly, between two frames.
+///
+/// # Errors
+///
+/// Errors if the stream ends in the middle of a frame, or if read
+/// fails.
+pub fn read_frame(reader: &mut dyn Read) -> traits::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    let mut n = 0;
+    while n < len.len() {
+        match reader.read(&mut len[n..]) {
+            Ok(0) if n == 0 => return Ok(None),
+            Ok(0) => return Err(Error::Read("Truncated frame length".to_string())),
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(Error::Read(e.to_string())),
+        }
+    }
+    let len = u64::from(u32::from_be_bytes(len));
+
+    // Don't trust `len` for allocation, grow as data comes in.
+    let mut payload = Vec::new();
+    reader
+        .take(len)
+        .read_to_end(&mut payload)
+        .map_err(|e| Error::Read(e.to_string()))?;
+    if payload.len() as u64 != len {
+        return Err(Error::Read("Truncated frame".to_string()));
+    }
+
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn frame_write_format() {
+        let mut stream = Vec::new();
+
+        write_frame(&mut stream, b"hello").unwrap();
+
+        assert_eq!(stream, b"\x00\x00\x00\x05hello");
+    }
+
+    #[test]
+    fn frame_round_trip_multiple() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"hello").unwrap();
+        write_frame(&mut stream, b"").unwrap();
+        write_frame(&mut stream, b"world").unwrap();
+
+        let mut reader = Cursor::new(stream);
+
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"hello");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"world");
+        assert!(read_frame(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn frame_read_empty_stream() {
+        assert!(read_frame(&mut Cursor::new(b"")).unwrap().is_none());
+    }
+
+    #[test]
+    fn frame_read_truncated_length() {
+        let err = read_frame(&mut Cursor::new(b"\x00\x00")).unwrap_err();
+
+        assert_eq!(err, Error::Read("Truncated frame length".to_string()));
+    }
+
+    #[test]
+    fn frame_read_truncated_payload() {
+        let err = read_frame(&mut Cursor::new(b"\x00\x00\x00\x05hel")).unwrap_err();
+
+        assert_eq!(err, Error::Read("Truncated frame".to_string()));
+    }
+}
@@ -0,0 +1,502 @@
+//! Ciphertext metadata, read without the key.
+//!
+//! Everything jolokia writes in the clear (headers, nonce prefix, chunk
+//! lengths) can be read back without decrypting anything. This helps
+//! tell "wrong key" from "corrupted data" apart: if the structure of a
+//! ciphertext is intact, it's likely the key.
+
+use std::io::{self, Read};
+use std::time::SystemTime;
+
+#[cfg(feature = "age")]
+use crate::cipher::age;
+#[cfg(feature = "password")]
+use crate::cipher::password;
+use crate::cipher::{CipherRegistry, chacha};
+#[cfg(feature = "hpke")]
+use crate::cipher::{hpke, hpke_pq};
+use crate::format::{self, Decoder};
+#[cfg(feature = "password")]
+use crate::kdf::{self, Params};
+use crate::traits::{self, Error};
+use crate::wire::read_up_to;
+use crate::{expiry, key_hint};
+
+/// What can be read from a ciphertext without the key.
+///
+/// Layers are listed from the outside in: an expiry date, a key hint,
+/// then either a password or an HPKE encapsulated key, then the
+/// ChaCha20-Poly1305 stream every algorithm ends up in.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Metadata {
+    /// Advisory expiry date (see [`crate::expiry`]).
+    pub expires_at: Option<SystemTime>,
+    /// Hint of the key (see [`crate::key_hint`]).
+    pub key_hint: Option<String>,
+    /// KDF parameters, if the key is derived from a password.
+    #[cfg(feature = "password")]
+    pub password: Option<Params>,
+    /// Length of the HPKE encapsulated key, if HPKE.
+    pub encapsulated_key_len: Option<usize>,
+    /// Whether the HPKE KEM is post-quantum hybrid (X-Wing).
+    pub post_quantum: bool,
+    pub nonce_prefix: [u8; 7],
+    /// Number of chunks (excluding the end marker).
+    pub nb_chunks: usize,
+    /// Size of the largest chunk, tag included.
+    pub max_chunk_len: usize,
+    /// Total size of the chunks, tags included.
+    pub chunks_len: u64,
+    /// Whether a header is an old version, that `jolokia migrate` would
+    /// upgrade (see [`crate::format`]).
+    pub outdated: bool,
+    /// Whether the stream ends with the end marker (and the trailer,
+    /// if the format has one). If not, the ciphertext is truncated.
+    pub complete: bool,
+}
+
+impl Metadata {
+    /// Size of the plaintext (chunks minus their 16-byte tags).
+    #[must_use]
+    pub fn plaintext_len(&self) -> u64 {
+        self.chunks_len.saturating_sub(16 * self.nb_chunks as u64)
+    }
+}
+
+/// Read the metadata of a ciphertext, in raw bytes.
+///
+/// # Errors
+///
+/// Errors if the input is not a jolokia ciphertext (ROT-n and Brainfuck
+/// have no metadata), if a header is cut short, or if a chunk length is
+/// impossible.
+pub fn inspect(reader: &mut dyn Read) -> traits::Result<Metadata> {
+    let mut metadata = Metadata::default();
+
+    let mut header = read_header(reader)?;
+
+    if header == *expiry::MAGIC {
+        let mut expiry = [0u8; expiry::HEADER_LEN];
+        expiry[..header.len()].copy_from_slice(&header);
+        read_exact(reader, &mut expiry[header.len()..])?;
+        metadata.expires_at = expiry::expires_at(&expiry);
+        header = read_header(reader)?;
+    }
+
+    if header == *key_hint::MAGIC {
+        let mut hint = [0u8; key_hint::HEADER_LEN];
+        hint[..header.len()].copy_from_slice(&header);
+        read_exact(reader, &mut hint[header.len()..])?;
+        metadata.key_hint = Some(key_hint::hint_of(&hint));
+        header = read_header(reader)?;
+    }
+
+    #[cfg(feature = "password")]
+    if header == *password::HEADER {
+        let mut salt = [0u8; kdf::SALT_LEN];
+        let mut params = [0u8; Params::LEN];
+        read_exact(reader, &mut salt)?;
+        read_exact(reader, &mut params)?;
+        metadata.password = Some(Params::from_bytes_unchecked(&params));
+        header = read_header(reader)?;
+    }
+
+    #[cfg(feature = "hpke")]
+    if header == *hpke::HEADER || header == *hpke_pq::HEADER {
+        metadata.post_quantum = header == *hpke_pq::HEADER;
+        let mut len = [0u8; 2];
+        read_exact(reader, &mut len)?;
+        let len = usize::from(u16::from_be_bytes(len));
+        skip_exact(reader, len as u64)?;
+        metadata.encapsulated_key_len = Some(len);
+        header = read_header(reader)?;
+    }
+
+    let Some(format::Header {
+        decoder: Decoder::ChaCha { trailer },
+        latest,
+        ..
+    }) = format::lookup(&header)
+    else {
+        return Err(Error::Algorithm);
+    };
+    let has_trailer = *trailer;
+    metadata.outdated = !latest;
+
+    read_exact(reader, &mut metadata.nonce_prefix)?;
+
+    loop {
+        let mut len = [0u8; 4];
+        if read_up_to(reader, &mut len)? < len.len() {
+            // Truncated, the end marker is missing.
+            break;
+        }
+        let len = u32::from_be_bytes(len);
+        if len == 0 {
+            metadata.complete =
+                !has_trailer || skip_exact(reader, chacha::TRAILER_LEN as u64).is_ok();
+            break;
+        }
+        if len as usize > chacha::MAX_CHUNK_LEN {
+            return Err(Error::Other(format!(
+                "Chunk {} has an invalid length ({len} bytes).",
+                metadata.nb_chunks + 1
+            )));
+        }
+        let read = io::copy(&mut reader.take(u64::from(len)), &mut io::sink())
+            .map_err(|e| Error::Read(e.to_string()))?;
+
+        metadata.nb_chunks += 1;
+        metadata.max_chunk_len = metadata.max_chunk_len.max(len as usize);
+        metadata.chunks_len += read;
+
+        if read < u64::from(len) {
+            // Truncated, in the middle of a chunk.
+            break;
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Whether a ciphertext is raw bytes (as opposed to base64), from its
+/// first bytes.
+///
+/// Raw ciphertexts start with a known header. Base64 never starts with
+/// one, the encoded headers all look different (e.g., `CH20` becomes
+/// `Q0gy`). ROT-n and Brainfuck have no header, they are never detected
+/// as raw.
+///
+/// age files (binary or armored) are raw too, age does its own armor.
+/// Their headers may be longer than the prefix, the prefix only has to
+/// match as far as it goes (at least 5 bytes).
+///
+/// So are ciphertexts of [registered](CipherRegistry) ciphers.
+#[must_use]
+pub fn is_raw(prefix: &[u8]) -> bool {
+    format::lookup(prefix).is_some()
+        || CipherRegistry::global().detect(prefix).is_some()
+        || is_age(prefix)
+}
+
+#[cfg(feature = "age")]
+fn is_age(prefix: &[u8]) -> bool {
+    [age::MAGIC, age::ARMOR_BEGIN].iter().any(|header| {
+        let n = prefix.len().min(header.len());
+        n >= AGE_PREFIX_LEN && prefix[..n] == header[..n]
+    })
+}
+
+#[cfg(not(feature = "age"))]
+const fn is_age(_prefix: &[u8]) -> bool {
+    false
+}
+
+// As long as the other headers, detection reads no more than that.
+#[cfg(feature = "age")]
+const AGE_PREFIX_LEN: usize = 5;
+
+/// Length of the prefix [`expires_at()`] needs to find the date.
+pub const EXPIRY_PREFIX_LEN: usize = expiry::HEADER_LEN;
+
+/// Expiry date of a raw ciphertext, from its first bytes.
+///
+/// `None` if the ciphertext has no expiry date, or if `prefix` is
+/// shorter than [`EXPIRY_PREFIX_LEN`]. Like everything else read here,
+/// the date is not authenticated (decryption is what checks it).
+#[must_use]
+pub fn expires_at(prefix: &[u8]) -> Option<SystemTime> {
+    let header: &[u8; expiry::HEADER_LEN] = prefix.get(..expiry::HEADER_LEN)?.try_into().ok()?;
+    if !header.starts_with(expiry::MAGIC) {
+        return None;
+    }
+    expiry::expires_at(header)
+}
+
+fn read_header(reader: &mut dyn Read) -> traits::Result<[u8; 5]> {
+    let mut header = [0u8; 5];
+    read_exact(reader, &mut header)?;
+    Ok(header)
+}
+
+fn read_exact(reader: &mut dyn Read, buf: &mut [u8]) -> traits::Result<()> {
+    reader
+        .read_exact(buf)
+        .map_err(|e| Error::Read(e.to_string()))
+}
+
+fn skip_exact(reader: &mut dyn Read, n: u64) -> traits::Result<()> {
+    let skipped =
+        io::copy(&mut reader.take(n), &mut io::sink()).map_err(|e| Error::Read(e.to_string()))?;
+    if skipped < n {
+        return Err(Error::Read("Unexpected end of input.".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::cipher::ChaCha20Poly1305;
+    #[cfg(feature = "password")]
+    use crate::cipher::Password;
+    #[cfg(feature = "rotn")]
+    use crate::cipher::RotN;
+    #[cfg(feature = "hpke")]
+    use crate::cipher::{Hpke, HpkePq};
+    use crate::expiry::Expiring;
+    #[cfg(feature = "password")]
+    use crate::kdf::TEST_PARAMS;
+    use crate::key_hint::KeyHinted;
+    use crate::traits::{Base64Encode, Cipher};
+    #[cfg(feature = "hpke")]
+    use crate::traits::{GeneratedKey, KeygenOptions};
+
+    const KEY: [u8; 32] = [0u8; 32];
+
+    #[test]
+    fn inspect_chacha() {
+        let plaintext = vec![b'a'; 5000];
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, &plaintext).unwrap();
+
+        let metadata = inspect(&mut encrypted.as_slice()).unwrap();
+
+        assert_eq!(metadata.nonce_prefix, encrypted[5..12]);
+        assert_eq!(metadata.nb_chunks, 2);
+        assert_eq!(metadata.max_chunk_len, 4096 + 16);
+        assert_eq!(metadata.plaintext_len(), 5000);
+        assert!(metadata.complete);
+        assert_eq!(metadata.expires_at, None);
+        #[cfg(feature = "password")]
+        assert_eq!(metadata.password, None);
+        assert_eq!(metadata.encapsulated_key_len, None);
+    }
+
+    #[test]
+    fn is_raw_regular() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, b"hello").unwrap();
+        let armored = encrypted.base64_encode();
+
+        assert!(is_raw(&encrypted));
+        assert!(!is_raw(armored.as_bytes()));
+    }
+
+    #[test]
+    fn is_raw_all_headers() {
+        #[cfg(feature = "password")]
+        let encrypted = Expiring::new(Box::new(Password::with_params(TEST_PARAMS)))
+            .expires_at(SystemTime::UNIX_EPOCH)
+            .encrypt(b"password", b"hello")
+            .unwrap();
+        #[cfg(feature = "password")]
+        assert!(is_raw(&encrypted));
+
+        for header in [
+            key_hint::MAGIC,
+            #[cfg(feature = "password")]
+            password::HEADER,
+            #[cfg(feature = "hpke")]
+            hpke::HEADER,
+            #[cfg(feature = "hpke")]
+            hpke_pq::HEADER,
+            chacha::HEADER,
+            chacha::HEADER_V1,
+        ] {
+            assert!(is_raw(header));
+            assert!(!is_raw(header.base64_encode().as_bytes()));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "age")]
+    fn is_raw_age() {
+        assert!(is_raw(b"age-encryption.org/v1\n-> X25519 "));
+        assert!(is_raw(b"-----BEGIN AGE ENCRYPTED FILE-----\n"));
+        assert!(!is_raw(b"age"));
+        assert!(!is_raw(b"-----BEGIN JOLOKIA MESSAGE-----"));
+        assert!(!is_raw(b"age-e".base64_encode().as_bytes()));
+    }
+
+    #[test]
+    fn is_raw_short_or_other() {
+        assert!(!is_raw(b""));
+        assert!(!is_raw(b"CH2"));
+        assert!(!is_raw(b"hello, world"));
+    }
+
+    #[test]
+    fn expires_at_regular() {
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let encrypted = Expiring::new(Box::new(ChaCha20Poly1305))
+            .expires_at(date)
+            .encrypt(&KEY, b"hello")
+            .unwrap();
+
+        assert_eq!(expires_at(&encrypted), Some(date));
+        assert_eq!(expires_at(&encrypted[..EXPIRY_PREFIX_LEN]), Some(date));
+    }
+
+    #[test]
+    fn expires_at_none() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, b"hello").unwrap();
+        assert_eq!(expires_at(&encrypted), None);
+        assert_eq!(expires_at(b"EXPR\x01"), None);
+        assert_eq!(expires_at(b""), None);
+    }
+
+    #[test]
+    fn inspect_empty_plaintext() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, b"").unwrap();
+
+        let metadata = inspect(&mut encrypted.as_slice()).unwrap();
+
+        assert_eq!(metadata.nb_chunks, 0);
+        assert_eq!(metadata.plaintext_len(), 0);
+        assert!(metadata.complete);
+    }
+
+    #[test]
+    fn inspect_truncated() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, &[b'a'; 5000]).unwrap();
+        let truncated = &encrypted[..encrypted.len() - 100];
+
+        let metadata = inspect(&mut &truncated[..]).unwrap();
+
+        assert_eq!(metadata.nb_chunks, 2);
+        assert!(!metadata.complete);
+    }
+
+    #[test]
+    fn inspect_truncated_trailer() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, &[b'a'; 5000]).unwrap();
+        let truncated = &encrypted[..encrypted.len() - 10];
+
+        let metadata = inspect(&mut &truncated[..]).unwrap();
+
+        assert_eq!(metadata.nb_chunks, 2);
+        assert_eq!(metadata.plaintext_len(), 5000);
+        assert!(!metadata.complete);
+    }
+
+    #[test]
+    fn inspect_outdated() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, b"hello").unwrap();
+        assert!(!inspect(&mut encrypted.as_slice()).unwrap().outdated);
+
+        let mut encrypted = encrypted[..encrypted.len() - chacha::TRAILER_LEN].to_vec();
+        encrypted[..5].copy_from_slice(chacha::HEADER_V1);
+        let metadata = inspect(&mut encrypted.as_slice()).unwrap();
+
+        assert!(metadata.outdated);
+        assert!(metadata.complete);
+    }
+
+    #[test]
+    fn inspect_invalid_chunk_length_is_error() {
+        let mut encrypted = ChaCha20Poly1305.encrypt(&KEY, b"hello").unwrap();
+        encrypted[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let err = inspect(&mut encrypted.as_slice()).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::Other("Chunk 1 has an invalid length (4294967295 bytes).".to_string())
+        );
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn inspect_hpke() {
+        let GeneratedKey::Asymmetric { public, .. } =
+            Hpke.generate_key(&KeygenOptions::default()).unwrap()
+        else {
+            unreachable!()
+        };
+        let encrypted = Hpke
+            .encrypt(secrecy::ExposeSecret::expose_secret(&public), b"hello")
+            .unwrap();
+
+        let metadata = inspect(&mut encrypted.as_slice()).unwrap();
+
+        assert_eq!(metadata.encapsulated_key_len, Some(32));
+        assert!(!metadata.post_quantum);
+        assert_eq!(metadata.plaintext_len(), 5);
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn inspect_hpke_pq() {
+        let GeneratedKey::Asymmetric { public, .. } =
+            HpkePq.generate_key(&KeygenOptions::default()).unwrap()
+        else {
+            unreachable!()
+        };
+        let encrypted = HpkePq
+            .encrypt(secrecy::ExposeSecret::expose_secret(&public), b"hello")
+            .unwrap();
+
+        let metadata = inspect(&mut encrypted.as_slice()).unwrap();
+
+        assert_eq!(metadata.encapsulated_key_len, Some(1120));
+        assert!(metadata.post_quantum);
+        assert_eq!(metadata.plaintext_len(), 5);
+    }
+
+    #[test]
+    #[cfg(feature = "password")]
+    fn inspect_password_with_expiry() {
+        let expires_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let encrypted = Expiring::new(Box::new(Password::with_params(TEST_PARAMS)))
+            .expires_at(expires_at)
+            .encrypt(b"hunter2", b"hello")
+            .unwrap();
+
+        let metadata = inspect(&mut encrypted.as_slice()).unwrap();
+
+        assert_eq!(metadata.expires_at, Some(expires_at));
+        assert_eq!(metadata.password, Some(TEST_PARAMS));
+        assert_eq!(metadata.plaintext_len(), 5);
+    }
+
+    #[test]
+    fn inspect_key_hint_with_expiry() {
+        let expires_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let encrypted = Expiring::new(Box::new(
+            KeyHinted::new(Box::new(ChaCha20Poly1305)).with_hint(),
+        ))
+        .expires_at(expires_at)
+        .encrypt(&KEY, b"hello")
+        .unwrap();
+
+        let metadata = inspect(&mut encrypted.as_slice()).unwrap();
+
+        let hint = &encrypted[expiry::HEADER_LEN..][..key_hint::HEADER_LEN];
+        assert_eq!(
+            metadata.key_hint,
+            Some(key_hint::hint_of(hint.try_into().unwrap()))
+        );
+        assert_eq!(metadata.expires_at, Some(expires_at));
+        assert_eq!(metadata.plaintext_len(), 5);
+        assert!(metadata.complete);
+    }
+
+    #[cfg(feature = "rotn")]
+    #[test]
+    fn inspect_rotn_is_error() {
+        let encrypted = RotN.encrypt(&[13], b"hello, world").unwrap();
+        assert_eq!(
+            inspect(&mut encrypted.as_slice()).unwrap_err(),
+            Error::Algorithm
+        );
+    }
+
+    #[test]
+    fn inspect_short_input_is_error() {
+        assert!(matches!(
+            inspect(&mut &b"CH2"[..]).unwrap_err(),
+            Error::Read(_)
+        ));
+    }
+}
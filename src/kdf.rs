@@ -0,0 +1,198 @@
+//! Key derivation from passwords (Argon2id).
+//!
+//! Parameters are stored alongside the ciphertext, so they can be
+//! raised over time without breaking older ciphertexts.
+
+use argon2::{Algorithm, Argon2, Version};
+use secrecy::zeroize::Zeroizing;
+
+use crate::traits::{self, Error};
+
+pub const SALT_LEN: usize = 16;
+
+pub const KEY_LEN: usize = 32;
+
+/// Argon2id cost parameters.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Params {
+    /// Memory size, in KiB.
+    pub memory_kib: u32,
+    /// Number of passes over the memory.
+    pub iterations: u32,
+    /// Number of lanes.
+    pub parallelism: u32,
+}
+
+impl Params {
+    /// Serialized size, in bytes.
+    pub const LEN: usize = 12;
+
+    /// RFC 9106's second recommended option (64 MiB, 3 passes), on a
+    /// single lane, as the derivation is not multi-threaded.
+    pub const DEFAULT: Self = Self {
+        memory_kib: 64 * 1024,
+        iterations: 3,
+        parallelism: 1,
+    };
+
+    /// Upper bounds for parameters read from a ciphertext.
+    ///
+    /// Parameters come from the input, without limits, a crafted header
+    /// could make decryption use all the memory, or run forever.
+    pub const MAX: Self = Self {
+        memory_kib: 2 * 1024 * 1024,
+        iterations: 64,
+        parallelism: 16,
+    };
+
+    /// Serialize as three 4-byte big-endian integers.
+    #[must_use]
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[0..4].copy_from_slice(&self.memory_kib.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.iterations.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.parallelism.to_be_bytes());
+        bytes
+    }
+
+    /// Deserialize what [`Params::to_bytes()`] produced.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the parameters exceed [`Params::MAX`].
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> traits::Result<Self> {
+        let params = Self::from_bytes_unchecked(bytes);
+        params.check()?;
+        Ok(params)
+    }
+
+    /// Deserialize, without checking the bounds (for display only).
+    pub(crate) fn from_bytes_unchecked(bytes: &[u8; Self::LEN]) -> Self {
+        let u32_at =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Self {
+            memory_kib: u32_at(0),
+            iterations: u32_at(4),
+            parallelism: u32_at(8),
+        }
+    }
+
+    fn check(self) -> traits::Result<()> {
+        if self.memory_kib > Self::MAX.memory_kib
+            || self.iterations > Self::MAX.iterations
+            || self.parallelism > Self::MAX.parallelism
+        {
+            return Err(Error::Parameter(format!(
+                "KDF parameters exceed the maximum (memory: {} KiB, iterations: {}, parallelism: {}).",
+                self.memory_kib, self.iterations, self.parallelism
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Cheap parameters, for fast tests.
+#[cfg(test)]
+pub(crate) const TEST_PARAMS: Params = Params {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+
+/// Derive a 32-byte key from `password` and `salt`.
+///
+/// # Errors
+///
+/// Errors if the password is empty, or if the parameters are invalid.
+pub fn derive_key(
+    password: &[u8],
+    salt: &[u8; SALT_LEN],
+    params: Params,
+) -> traits::Result<Zeroizing<[u8; KEY_LEN]>> {
+    if password.is_empty() {
+        return Err(Error::Key);
+    }
+    params.check()?;
+
+    let argon2_params = argon2::Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(KEY_LEN),
+    )
+    .map_err(|e| Error::Parameter(format!("{e}.")))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params);
+
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    argon2
+        .hash_password_into(password, salt, key.as_mut_slice())
+        .map_err(|e| Error::Parameter(format!("{e}.")))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_key_is_deterministic() {
+        let salt = [7u8; SALT_LEN];
+
+        let a = derive_key(b"hunter2", &salt, TEST_PARAMS).unwrap();
+        let b = derive_key(b"hunter2", &salt, TEST_PARAMS).unwrap();
+
+        assert_eq!(*a, *b);
+    }
+
+    #[test]
+    fn derive_key_depends_on_everything() {
+        let salt = [7u8; SALT_LEN];
+        let key = derive_key(b"hunter2", &salt, TEST_PARAMS).unwrap();
+
+        let other_password = derive_key(b"hunter3", &salt, TEST_PARAMS).unwrap();
+        let other_salt = derive_key(b"hunter2", &[8u8; SALT_LEN], TEST_PARAMS).unwrap();
+        let other_params = derive_key(
+            b"hunter2",
+            &salt,
+            Params {
+                iterations: 2,
+                ..TEST_PARAMS
+            },
+        )
+        .unwrap();
+
+        assert_ne!(*key, *other_password);
+        assert_ne!(*key, *other_salt);
+        assert_ne!(*key, *other_params);
+    }
+
+    #[test]
+    fn derive_key_empty_password_is_error() {
+        let err = derive_key(b"", &[0u8; SALT_LEN], TEST_PARAMS).unwrap_err();
+        assert_eq!(err, Error::Key);
+    }
+
+    #[test]
+    fn params_bytes_round_trip() {
+        let params = Params::DEFAULT;
+        assert_eq!(Params::from_bytes(&params.to_bytes()), Ok(params));
+    }
+
+    #[test]
+    fn params_above_max_is_error() {
+        let params = Params {
+            memory_kib: u32::MAX,
+            ..Params::DEFAULT
+        };
+        assert!(matches!(
+            Params::from_bytes(&params.to_bytes()),
+            Err(Error::Parameter(_))
+        ));
+    }
+}
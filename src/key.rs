@@ -0,0 +1,255 @@
+//! Algorithm tags for serialized keys.
+//!
+//! A key is just bytes, and nothing prevents a key generated for one
+//! algorithm from being used with another (e.g., a ChaCha20-Poly1305
+//! key is also a valid X25519 scalar). To catch mistakes, serialized
+//! keys are prefixed with the ID of the algorithm they belong to (see
+//! [`Cipher::algorithm_id()`](crate::traits::Cipher::algorithm_id)):
+//!
+//! ```text
+//! chacha20poly1305:hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w
+//! ```
+//!
+//! The tag is optional, untagged keys are accepted as-is. The separator
+//! (`:`) is not part of the base64 alphabet, so there is no ambiguity.
+//!
+//! With the `password` feature, keys can also be protected by a
+//! passphrase (see `protect()`). They are encrypted with `Password`,
+//! and carry one more prefix:
+//!
+//! ```text
+//! protected:hpke:UEFTUwEY3s0gUm...
+//! ```
+//!
+//! The algorithm ID stays in the clear, but it is authenticated (as
+//! associated data), it can't be changed.
+
+use secrecy::{ExposeSecret, SecretSlice, zeroize::Zeroizing};
+
+#[cfg(feature = "password")]
+use crate::cipher::Password;
+#[cfg(feature = "age")]
+use crate::cipher::{Age, age};
+use crate::encoding::base64;
+#[cfg(any(feature = "age", feature = "password"))]
+use crate::traits::Cipher;
+#[cfg(feature = "password")]
+use crate::traits::{Base64Decode, Base64Encode};
+use crate::traits::{Error, Result};
+
+const SEPARATOR: char = ':';
+
+const PROTECTED: &str = "protected";
+
+/// Prefix base64-encoded `key` with `algorithm_id`.
+#[must_use]
+pub fn tag(algorithm_id: &str, key: &str) -> String {
+    format!("{algorithm_id}{SEPARATOR}{key}")
+}
+
+/// Split `key` into algorithm ID (if any), and base64-encoded key.
+#[must_use]
+pub fn split_tag(key: &str) -> (Option<&str>, &str) {
+    match key.split_once(SEPARATOR) {
+        Some((algorithm_id, key)) => (Some(algorithm_id), key),
+        None => (None, key),
+    }
+}
+
+/// Serialize generated keys for `algorithm_id`, as the CLI prints them.
+///
+/// Keys are tagged base64, except age keys, which use age's own
+/// encoding (`AGE-SECRET-KEY-1...` and `age1...`).
+///
+/// # Errors
+///
+/// Errors if an age key has the wrong length.
+pub fn encode(
+    algorithm_id: &str,
+    secret: &SecretSlice<u8>,
+    public: Option<&SecretSlice<u8>>,
+) -> Result<(Zeroizing<String>, Option<String>)> {
+    #[cfg(feature = "age")]
+    if algorithm_id == Age.algorithm_id() {
+        let secret = age::encode_identity(secret.expose_secret())?;
+        let public = public
+            .map(|public| age::encode_recipient(public.expose_secret()))
+            .transpose()?;
+        return Ok((secret, public));
+    }
+    let tag = |key: &SecretSlice<u8>| {
+        let key = base64::encode_key(key.expose_secret());
+        tag(algorithm_id, &key)
+    };
+    Ok((Zeroizing::new(tag(secret)), public.map(tag)))
+}
+
+/// Parse a key serialized with [`encode()`], into raw bytes.
+///
+/// # Errors
+///
+/// Errors if the key is malformed, or if it is tagged for another
+/// algorithm.
+pub fn decode(algorithm_id: &str, key: &str) -> Result<Zeroizing<Vec<u8>>> {
+    #[cfg(feature = "age")]
+    if algorithm_id == Age.algorithm_id() {
+        return age::decode_key(key);
+    }
+    match split_tag(key.trim()) {
+        (Some(tag), _) if tag != algorithm_id => Err(Error::Key),
+        (_, key) => base64::decode_key(key.as_bytes()),
+    }
+}
+
+/// Encrypt `key` (raw bytes) with a key derived from `passphrase`.
+///
+/// # Errors
+///
+/// Errors if encryption fails (e.g., no randomness for the salt).
+#[cfg(feature = "password")]
+pub fn protect(algorithm_id: &str, key: &[u8], passphrase: &[u8]) -> Result<String> {
+    let mut encrypted = Vec::new();
+    Password::default().encrypt_stream_with_aad(
+        passphrase,
+        algorithm_id.as_bytes(),
+        &mut &key[..],
+        &mut encrypted,
+    )?;
+    Ok(format!(
+        "{PROTECTED}{SEPARATOR}{}",
+        tag(algorithm_id, &encrypted.base64_encode())
+    ))
+}
+
+/// Whether `key` was protected by a passphrase (see [`protect()`]).
+#[must_use]
+pub fn is_protected(key: &str) -> bool {
+    key.strip_prefix(PROTECTED)
+        .is_some_and(|key| key.starts_with(SEPARATOR))
+}
+
+/// Decrypt a key protected with [`protect()`].
+///
+/// Returns the algorithm ID, and the key (raw bytes).
+///
+/// # Errors
+///
+/// Errors if `key` is not a protected key, or if the passphrase is
+/// wrong.
+#[cfg(feature = "password")]
+pub fn unprotect<'a>(key: &'a str, passphrase: &[u8]) -> Result<(&'a str, Zeroizing<Vec<u8>>)> {
+    let Some((PROTECTED, key)) = key.split_once(SEPARATOR) else {
+        return Err(Error::Key);
+    };
+    let (Some(algorithm_id), encrypted) = split_tag(key) else {
+        return Err(Error::Key);
+    };
+    let encrypted = encrypted.base64_decode()?;
+
+    let mut decrypted = Zeroizing::new(Vec::new());
+    Password::default().decrypt_stream_with_aad(
+        passphrase,
+        algorithm_id.as_bytes(),
+        &mut encrypted.as_slice(),
+        &mut *decrypted,
+    )?;
+    Ok((algorithm_id, decrypted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_tag_regular() {
+        assert_eq!(tag("hpke", "abc="), "hpke:abc=");
+    }
+
+    #[test]
+    fn key_split_tag_tagged() {
+        assert_eq!(split_tag("hpke:abc="), (Some("hpke"), "abc="));
+    }
+
+    #[test]
+    fn key_split_tag_untagged() {
+        assert_eq!(split_tag("abc="), (None, "abc="));
+    }
+
+    #[test]
+    fn key_tag_round_trip() {
+        let tagged = tag("chacha20poly1305", "abc=");
+
+        assert_eq!(split_tag(&tagged), (Some("chacha20poly1305"), "abc="));
+    }
+
+    #[test]
+    fn key_encode_decode_round_trip() {
+        let secret = SecretSlice::from(b"secret key".to_vec());
+        let public = SecretSlice::from(b"public key".to_vec());
+
+        let (encoded_secret, encoded_public) = encode("hpke", &secret, Some(&public)).unwrap();
+
+        assert!(encoded_secret.starts_with("hpke:"));
+        assert_eq!(
+            decode("hpke", &encoded_secret).unwrap().as_slice(),
+            b"secret key"
+        );
+        assert_eq!(
+            decode("hpke", &encoded_public.unwrap()).unwrap().as_slice(),
+            b"public key"
+        );
+    }
+
+    #[test]
+    fn key_decode_other_algorithm_is_error() {
+        assert!(matches!(
+            decode("hpke", "chacha20poly1305:abc"),
+            Err(Error::Key)
+        ));
+    }
+
+    #[test]
+    fn key_decode_untagged() {
+        assert_eq!(decode("hpke", "YWJj").unwrap().as_slice(), b"abc");
+    }
+
+    #[test]
+    #[cfg(feature = "password")]
+    fn key_protect_round_trip() {
+        let protected = protect("hpke", b"secret key", b"correct horse").unwrap();
+
+        assert!(protected.starts_with("protected:hpke:"));
+        assert!(is_protected(&protected));
+        let (algorithm_id, key) = unprotect(&protected, b"correct horse").unwrap();
+        assert_eq!(algorithm_id, "hpke");
+        assert_eq!(key.as_slice(), b"secret key");
+    }
+
+    #[test]
+    #[cfg(feature = "password")]
+    fn key_unprotect_wrong_passphrase_is_error() {
+        let protected = protect("hpke", b"secret key", b"correct horse").unwrap();
+
+        let err = unprotect(&protected, b"battery staple").unwrap_err();
+        assert!(matches!(err, Error::Decrypt));
+    }
+
+    #[test]
+    #[cfg(feature = "password")]
+    fn key_unprotect_other_algorithm_is_error() {
+        let protected = protect("hpke", b"secret key", b"correct horse").unwrap();
+        let swapped = protected.replace(":hpke:", ":chacha20poly1305:");
+
+        let err = unprotect(&swapped, b"correct horse").unwrap_err();
+        assert!(matches!(err, Error::Decrypt));
+    }
+
+    #[test]
+    #[cfg(feature = "password")]
+    fn key_unprotect_not_protected_is_error() {
+        assert!(!is_protected("hpke:abc="));
+        assert!(!is_protected("protectedabc="));
+        assert!(matches!(unprotect("hpke:abc=", b"x"), Err(Error::Key)));
+        assert!(matches!(unprotect("protected:abc=", b"x"), Err(Error::Key)));
+    }
+}
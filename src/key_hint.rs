@@ -0,0 +1,324 @@
+//! Key hints: a short fingerprint of the key, in the ciphertext.
+//!
+//! [`KeyHinted`] wraps a cipher, and puts a key hint header in front of
+//! the ciphertext:
+//!
+//! ```text
+//! [ header (5) ]        b"KHNT\x01", magic and version
+//! [ salt (8) ]          random, different for every message
+//! [ hint (4) ]          SHA-256(domain || salt || key), truncated
+//! [ ciphertext ]
+//! ```
+//!
+//! With the hint, decrypting with the wrong key says which key the
+//! message was encrypted for, instead of "wrong key, or corrupted".
+//! The hint is salted, two messages encrypted with the same key do not
+//! have the same hint, so hints can't be used to link messages.
+//!
+//! Like the expiry header, the hint is not encrypted, but it is
+//! authenticated: it is part of the associated data of the ciphertext.
+//!
+//! Hints are for random keys only. Four bytes of a password hash would
+//! let anyone check password guesses offline, without the KDF.
+
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::entropy;
+use crate::traits::{
+    self, Cipher, CountingReader, CountingWriter, Error, GeneratedKey, KeygenOptions, StreamStats,
+};
+use crate::wire::read_up_to;
+
+// Contains magic (4-bytes) and version (1-byte).
+pub(crate) const MAGIC: &[u8; 5] = b"KHNT\x01";
+
+const SALT_LEN: usize = 8;
+const HINT_LEN: usize = 4;
+
+pub(crate) const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + HINT_LEN;
+
+const DOMAIN: &[u8] = b"jolokia key hint";
+
+/// Cipher decorator that adds (and checks) key hints.
+///
+/// Ciphertexts without a key hint header decrypt as usual, so the same
+/// decorator can decrypt both kinds.
+pub struct KeyHinted {
+    cipher: Box<dyn Cipher>,
+    with_hint: bool,
+}
+
+impl KeyHinted {
+    #[must_use]
+    pub fn new(cipher: Box<dyn Cipher>) -> Self {
+        Self {
+            cipher,
+            with_hint: false,
+        }
+    }
+
+    /// Embed a hint of the key in the messages this cipher encrypts.
+    #[must_use]
+    pub fn with_hint(mut self) -> Self {
+        self.with_hint = true;
+        self
+    }
+}
+
+impl Cipher for KeyHinted {
+    fn algorithm_id(&self) -> &'static str {
+        self.cipher.algorithm_id()
+    }
+
+    fn generate_key(&self, options: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        self.cipher.generate_key(options)
+    }
+
+    fn encrypt_stream(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.encrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn decrypt_stream(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        self.decrypt_stream_with_aad(key, &[], reader, writer)
+    }
+
+    fn encrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        if !self.with_hint {
+            return self
+                .cipher
+                .encrypt_stream_with_aad(key, aad, reader, writer);
+        }
+
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
+        let header = header(entropy::generate()?, key);
+        writer
+            .write_all(&header)
+            .map_err(|e| Error::Write(e.to_string()))?;
+
+        let stats = self.cipher.encrypt_stream_with_aad(
+            key,
+            &[&header, aad].concat(),
+            &mut reader,
+            &mut writer,
+        )?;
+
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            ..stats
+        })
+    }
+
+    fn decrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> traits::Result<StreamStats> {
+        let mut reader = CountingReader::new(reader);
+        let mut writer = CountingWriter::new(writer);
+
+        let mut header = [0u8; HEADER_LEN];
+        let n = read_up_to(&mut reader, &mut header[..MAGIC.len()])?;
+
+        if &header[..n] != MAGIC {
+            // Not ours, put back what was read.
+            let stats = self.cipher.decrypt_stream_with_aad(
+                key,
+                aad,
+                &mut io::Cursor::new(&header[..n]).chain(&mut reader),
+                &mut writer,
+            )?;
+            return Ok(StreamStats {
+                bytes_in: reader.count,
+                bytes_out: writer.count,
+                ..stats
+            });
+        }
+
+        reader
+            .read_exact(&mut header[MAGIC.len()..])
+            .map_err(|e| Error::Read(e.to_string()))?;
+
+        let (salt, expected) = salt_and_hint(&header);
+        let provided = hint(salt, key);
+        if provided != expected {
+            return Err(Error::KeyHint {
+                expected: format_hint(expected),
+                provided: format_hint(provided),
+            });
+        }
+
+        let stats = self.cipher.decrypt_stream_with_aad(
+            key,
+            &[&header, aad].concat(),
+            &mut reader,
+            &mut writer,
+        )?;
+
+        Ok(StreamStats {
+            bytes_in: reader.count,
+            bytes_out: writer.count,
+            ..stats
+        })
+    }
+}
+
+fn header(salt: [u8; SALT_LEN], key: &[u8]) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[MAGIC.len()..MAGIC.len() + SALT_LEN].copy_from_slice(&salt);
+    header[MAGIC.len() + SALT_LEN..].copy_from_slice(&hint(salt, key));
+    header
+}
+
+fn hint(salt: [u8; SALT_LEN], key: &[u8]) -> [u8; HINT_LEN] {
+    let digest = Sha256::new()
+        .chain_update(DOMAIN)
+        .chain_update(salt)
+        .chain_update(key)
+        .finalize();
+    let mut hint = [0u8; HINT_LEN];
+    hint.copy_from_slice(&digest[..HINT_LEN]);
+    hint
+}
+
+fn salt_and_hint(header: &[u8; HEADER_LEN]) -> ([u8; SALT_LEN], [u8; HINT_LEN]) {
+    let (salt, hint) = header[MAGIC.len()..].split_at(SALT_LEN);
+    (
+        salt.try_into().expect("header is sized"),
+        hint.try_into().expect("header is sized"),
+    )
+}
+
+/// The hint of a header, formatted for humans (e.g., `ABCD-1234`).
+pub(crate) fn hint_of(header: &[u8; HEADER_LEN]) -> String {
+    format_hint(salt_and_hint(header).1)
+}
+
+fn format_hint(hint: [u8; HINT_LEN]) -> String {
+    format!(
+        "{:02X}{:02X}-{:02X}{:02X}",
+        hint[0], hint[1], hint[2], hint[3]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::ChaCha20Poly1305;
+    #[cfg(feature = "rotn")]
+    use crate::cipher::RotN;
+
+    const KEY: [u8; 32] = [0u8; 32];
+    const OTHER_KEY: [u8; 32] = [1u8; 32];
+
+    #[test]
+    fn key_hinted_round_trip() {
+        let cipher = KeyHinted::new(Box::new(ChaCha20Poly1305)).with_hint();
+
+        let encrypted = cipher.encrypt(&KEY, b"hello").unwrap();
+        let decrypted = cipher.decrypt(&KEY, &encrypted).unwrap();
+
+        assert_eq!(&encrypted[..MAGIC.len()], MAGIC);
+        assert_eq!(decrypted, b"hello");
+    }
+
+    #[test]
+    fn key_hinted_wrong_key_says_which() {
+        let cipher = KeyHinted::new(Box::new(ChaCha20Poly1305)).with_hint();
+        let encrypted = cipher.encrypt(&KEY, b"hello").unwrap();
+
+        let err = cipher.decrypt(&OTHER_KEY, &encrypted).unwrap_err();
+
+        let header: &[u8; HEADER_LEN] = encrypted[..HEADER_LEN].try_into().unwrap();
+        let (salt, _) = salt_and_hint(header);
+        assert_eq!(
+            err,
+            Error::KeyHint {
+                expected: hint_of(header),
+                provided: format_hint(hint(salt, &OTHER_KEY)),
+            }
+        );
+    }
+
+    #[test]
+    fn key_hinted_hints_are_salted() {
+        let cipher = KeyHinted::new(Box::new(ChaCha20Poly1305)).with_hint();
+
+        let a = cipher.encrypt(&KEY, b"hello").unwrap();
+        let b = cipher.encrypt(&KEY, b"hello").unwrap();
+
+        assert_ne!(a[..HEADER_LEN], b[..HEADER_LEN]);
+    }
+
+    #[test]
+    fn key_hinted_tampered_hint_is_error() {
+        let cipher = KeyHinted::new(Box::new(ChaCha20Poly1305)).with_hint();
+        let mut encrypted = cipher.encrypt(&KEY, b"hello").unwrap();
+        // Change the salt and the hint together, so they still match.
+        let header = header([9; SALT_LEN], &KEY);
+        encrypted[..HEADER_LEN].copy_from_slice(&header);
+
+        let err = cipher.decrypt(&KEY, &encrypted).unwrap_err();
+
+        assert_eq!(err, Error::Decrypt);
+    }
+
+    #[test]
+    fn key_hinted_without_header_is_passed_through() {
+        let encrypted = ChaCha20Poly1305.encrypt(&KEY, b"hello").unwrap();
+
+        let decrypted = KeyHinted::new(Box::new(ChaCha20Poly1305))
+            .decrypt(&KEY, &encrypted)
+            .unwrap();
+
+        assert_eq!(decrypted, b"hello");
+    }
+
+    #[test]
+    fn key_hinted_without_hint_is_passed_through() {
+        let encrypted = KeyHinted::new(Box::new(ChaCha20Poly1305))
+            .encrypt(&KEY, b"hello")
+            .unwrap();
+
+        let decrypted = ChaCha20Poly1305.decrypt(&KEY, &encrypted).unwrap();
+
+        assert_eq!(decrypted, b"hello");
+    }
+
+    #[cfg(feature = "rotn")]
+    #[test]
+    fn key_hinted_short_input_is_passed_through() {
+        let decrypted = KeyHinted::new(Box::new(RotN))
+            .decrypt(&[13], b"uryy")
+            .unwrap();
+        assert_eq!(decrypted, b"hell");
+    }
+
+    #[test]
+    fn format_hint_regular() {
+        assert_eq!(format_hint([0xab, 0xcd, 0x12, 0x34]), "ABCD-1234");
+    }
+}
@@ -1,5 +1,47 @@
-pub mod base64;
+//! Simple, strong encryption.
+//!
+//! Every algorithm in [`cipher`] implements the one [`Cipher`] trait
+//! (in [`traits`]). It is object-safe, so algorithms picked at runtime
+//! are `Box<dyn Cipher>`:
+//!
+//! ```
+//! use jolokia::cipher::ChaCha20Poly1305;
+//! use jolokia::traits::{Cipher, KeygenOptions};
+//! use secrecy::ExposeSecret;
+//!
+//! let cipher: Box<dyn Cipher> = Box::new(ChaCha20Poly1305);
+//! let key = cipher.generate_key(&KeygenOptions::default()).unwrap();
+//! let key = key.get_symmetric().expose_secret();
+//!
+//! let ciphertext = cipher.encrypt(key, b"hello, world").unwrap();
+//! assert_eq!(cipher.decrypt(key, &ciphertext).unwrap(), b"hello, world");
+//! ```
+//!
+//! [`Cipher`]: traits::Cipher
+
+pub mod armor;
 pub mod cipher;
+pub mod encoding;
+pub mod entropy;
+pub mod expiry;
+pub mod format;
+pub mod frame;
+pub mod inspect;
+#[cfg(feature = "password")]
+pub mod kdf;
+pub mod key;
+pub mod key_hint;
+pub mod progress;
+pub mod seal;
+#[cfg(feature = "sign")]
+pub mod signer;
+#[cfg(feature = "sign")]
+pub mod ssh;
+// Threads are not supported in the browser.
+#[cfg(not(target_family = "wasm"))]
+pub mod stream;
 pub mod traits;
+mod wire;
 
+pub use seal::{open, seal};
 pub use traits::Error;
@@ -1,27 +1,44 @@
 mod cmd;
 
 use std::io::{self, Read, Write};
-use std::path::PathBuf;
-use std::sync::OnceLock;
-use std::time::SystemTime;
+use std::path::Path;
 use std::{env, fs, process};
 
-use lessify::Pager;
-use secrecy::{ExposeSecret, SecretSlice};
+use secrecy::{ExposeSecret, SecretSlice, SecretString, zeroize::Zeroizing};
 
-use jolokia::traits::{Cipher, GeneratedKey};
+use jolokia::expiry::Expiring;
+use jolokia::key_hint::KeyHinted;
+use jolokia::progress::ProgressReader;
+use jolokia::traits::{Cipher, Error, GeneratedKey};
+use jolokia::{cipher, entropy, format, signer};
 
-use cmd::{cli, ui};
+use cmd::environment::{self, Environment, Stream};
+use cmd::error::{EXIT_IO, EXIT_PARTIAL_FAILURE, EXIT_USAGE};
+use cmd::in_place::InPlaceFile;
+use cmd::metadata::OutputFile;
+use cmd::operation::{Operation, Outcome, Phases};
+use cmd::plan::ExecutionPlan;
+use cmd::warning::Warning;
+use cmd::{CliError, cli, ui};
 
-// TODO: This deserves refactoring. Error handling is inconsistent, and
-// `if is_in_place` logic is brittle because correctness is not enforced
-// by the compiler. But it's fine for now as long as we don't add new
-// features.
+/// The output was closed early (128 + `SIGPIPE`, like a command killed
+/// by the signal).
+const EXIT_BROKEN_PIPE: i32 = 141;
 
 fn main() {
-    let args = match cli::Args::build_from_args(env::args().skip(1)) {
+    set_panic_hook();
+
+    let cli_args: Vec<String> = env::args().skip(1).collect();
+    let mut args = match cli::Args::build_from_args(cli_args.iter()) {
         Ok(args) => args,
         Err(err) => {
+            // Not a built-in command, maybe a plugin.
+            if let Some((name, plugin_args)) = cli_args.split_first()
+                && let Some(plugin) = cmd::plugin::find(name, &environment::System)
+            {
+                run_plugin_and_exit(&plugin, plugin_args);
+            }
+
             eprintln!(
                 "\
 {fatal}: {err}.
@@ -29,25 +46,32 @@ Try '{bin} -h' for help.",
                 fatal = ui::Color::error("fatal"),
                 bin = env!("CARGO_BIN_NAME")
             );
-            process::exit(2);
+            process::exit(err.exit_code());
         }
     };
 
+    ui::log::set_verbosity(args.verbosity);
+
     if args.long_help {
-        long_help();
+        long_help(args.no_pager);
     } else if args.short_help {
         short_help();
     } else if args.version {
         version();
     } else if let Some(command) = args.command {
-        if let Err(reason) = execute_command(command, &args) {
+        if let Err(err) = execute_command(command, &mut args) {
+            let reason = err.to_string();
             eprintln!(
                 "{error}: {reason}{}",
                 // Errors from dependencies may or may not end with `.`.
                 if reason.ends_with('.') { "" } else { "." },
-                error = ui::Color::error("error"),
+                error = if matches!(err, CliError::Usage(_)) {
+                    ui::Color::error("fatal")
+                } else {
+                    ui::Color::error("error")
+                },
             );
-            process::exit(1);
+            process::exit(err.exit_code());
         }
     } else {
         // No arguments.
@@ -55,76 +79,752 @@ Try '{bin} -h' for help.",
     }
 }
 
-fn execute_command(command: cli::Command, args: &cli::Args) -> Result<(), String> {
+fn run_plugin_and_exit(plugin: &Path, args: &[String]) -> ! {
+    match cmd::plugin::run(plugin, args) {
+        Ok(code) => process::exit(code),
+        Err(reason) => {
+            eprintln!("{error}: {reason}.", error = ui::Color::error("error"));
+            process::exit(1);
+        }
+    }
+}
+
+/// Report panics without echoing arbitrary data.
+///
+/// The default hook prints the panic message, which may be formatted
+/// from runtime values (arguments, keys, plaintext). Only static
+/// messages are shown, as they can't contain any of these.
+fn set_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map_or_else(String::new, |message| format!(": {message}"));
+        let location = info
+            .location()
+            .map_or_else(String::new, |l| format!(" at {}:{}", l.file(), l.line()));
+        eprintln!(
+            "{fatal}: {package} panicked{location}{message}",
+            fatal = ui::Color::error("fatal"),
+            package = env!("CARGO_PKG_NAME"),
+        );
+        // Release builds abort on panic, without running destructors.
+        cmd::signal::remove_all();
+    }));
+}
+
+fn execute_command(command: cli::Command, args: &mut cli::Args) -> Result<(), CliError> {
+    if let Some(ref source) = args.entropy {
+        entropy::set_source(source.clone());
+    }
+    // Old formats lack newer protections, they are only read on demand.
+    format::set_read_outdated(args.legacy || command == cli::Command::Migrate);
     let algorithm = args.algorithm.unwrap_or_default();
-    let cipher: Box<dyn Cipher> = algorithm.into();
     let add_newline = args.output == cli::Output::Stdout;
 
+    if matches!(
+        command,
+        cli::Command::Encrypt
+            | cli::Command::Decrypt
+            | cli::Command::Rekey
+            | cli::Command::Migrate
+            | cli::Command::Check
+    ) {
+        ui::log::info(format_args!("algorithm: {algorithm}"));
+        ui::log::info(format_args!("key: {}", args.effective_key_source()));
+    }
+
+    if !args.dry_run {
+        confirm_overwrite(args)?;
+        confirm_shred(args)?;
+    }
+
     match command {
-        cli::Command::KeyGen => cmd::keygen(cipher.as_ref(), add_newline),
+        cli::Command::KeyGen => {
+            let cipher = build_cipher(command, algorithm, args);
+            keygen(cipher.as_ref(), args, add_newline)
+        }
+        cli::Command::Doctor => Ok(cmd::doctor::doctor(algorithm)?),
+        cli::Command::Bench => cmd::bench::bench(
+            args.algorithm.as_slice(),
+            args.bench_size.unwrap_or(cmd::bench::DEFAULT_SIZE),
+            io::stdout(),
+        ),
+        cli::Command::Inspect => {
+            let message = get_message_or_exit(args);
+            let format = cmd::plan::Encoding::new(command, args).format();
+            cmd::inspect(message, io::stdout(), format, environment::System.now())
+        }
+        cli::Command::Man => {
+            print!("{}", cmd::help::man_page());
+            Ok(())
+        }
+        cli::Command::Sign | cli::Command::Verify => sign_or_verify(command, args, add_newline),
+        cli::Command::Rekey | cli::Command::Migrate => {
+            let cipher = build_cipher(command, algorithm, args);
+            rekey(command, cipher.as_ref(), algorithm, args)
+        }
+        cli::Command::Decrypt if args.peek.is_some() => {
+            let cipher = build_cipher(command, algorithm, args);
+            peek(command, cipher.as_ref(), algorithm, args)
+        }
+        cli::Command::Decrypt if args.range.is_some() => {
+            let cipher = build_cipher(command, algorithm, args);
+            range(command, cipher.as_ref(), algorithm, args)
+        }
+        cli::Command::Check => {
+            let cipher = build_cipher(command, algorithm, args);
+            check(command, cipher.as_ref(), algorithm, args)
+        }
         cli::Command::Encrypt | cli::Command::Decrypt => {
-            let is_in_place = is_input_file_used_for_output(args);
+            match Operation::run(&mut CliPhases {
+                command,
+                args,
+                in_place: None,
+            })? {
+                Outcome::Completed if args.shred => shred_input(args)?,
+                Outcome::Completed => (),
+                Outcome::Planned(plan) => print!("{plan}"),
+                Outcome::Partial(report) => {
+                    eprintln!(
+                        "{error}: {} of {} frames could not be decrypted.",
+                        report.nb_skipped,
+                        report.nb_frames,
+                        error = ui::Color::error("error"),
+                    );
+                    process::exit(EXIT_PARTIAL_FAILURE);
+                }
+            }
+            Ok(())
+        }
+    }
+}
 
-            let cipher = cipher.as_ref();
-            let key = get_key_or_default(args, algorithm);
-            let message = get_message_or_exit(args);
-            let output = if is_in_place {
-                get_temporary_file_or_exit(args)
+/// Overwrite and remove the plaintext, now that it's encrypted.
+fn shred_input(args: &cli::Args) -> Result<(), CliError> {
+    let (Some(cli::Message::File(input)), cli::Output::File(output)) =
+        (&args.message, &args.output)
+    else {
+        unreachable!("checked by the CLI");
+    };
+    // The ciphertext must be on disk before the plaintext is gone.
+    fs::File::open(output)
+        .and_then(|output| output.sync_all())
+        .map_err(|e| CliError::Io(format!("Could not flush '{}': {e}", output.display())))?;
+
+    warn(
+        args,
+        Warning::Shred,
+        "Shredding is best effort. Copies of the plaintext may survive on SSDs, copy-on-write file systems, snapshots, and backups.",
+    );
+    cmd::shred::shred(input)
+}
+
+/// Encryption and decryption, for real (see [`cmd::operation`]).
+struct CliPhases<'a> {
+    command: cli::Command,
+    args: &'a mut cli::Args,
+    /// Output of in-place operations, until committed.
+    in_place: Option<InPlaceFile>,
+}
+
+impl Phases for CliPhases<'_> {
+    fn resolve(&mut self) -> Result<Box<dyn Cipher>, CliError> {
+        let algorithm = self.args.algorithm.unwrap_or_default();
+        Ok(build_cipher(self.command, algorithm, self.args))
+    }
+
+    fn plan(&mut self) -> Result<ExecutionPlan, CliError> {
+        ExecutionPlan::new(self.command, self.args, &environment::System).map_err(CliError::Usage)
+    }
+
+    fn dry_run(&self) -> bool {
+        self.args.dry_run
+    }
+
+    fn execute(
+        &mut self,
+        cipher: &dyn Cipher,
+        plan: &ExecutionPlan,
+    ) -> Result<cmd::FramedReport, CliError> {
+        confirm_in_place(self.command, plan, self.args)?;
+        prompt_for_key(self.command, self.args)?;
+
+        if plan.algorithm.is_toy() {
+            warn(
+                self.args,
+                Warning::InsecureAlgorithm,
+                "This algorithm is not secure, do not use it to protect sensitive data.",
+            );
+        }
+
+        let key = get_key_or_default(self.args, plan.algorithm);
+        let message = with_progress(get_message_or_exit(self.args), self.args);
+        let (output, output_file) =
+            if let (Some(temp_path), cli::Output::File(file)) = (&plan.temp_path, &plan.output) {
+                let in_place = InPlaceFile::create(file, temp_path, self.args.preserve_times)?;
+                let output = in_place.writer(self.args.output_buffer_size())?;
+                self.in_place = Some(in_place);
+                (output, None)
             } else {
-                get_output_or_exit(args)
+                get_output_like_input_or_exit(self.args)?
             };
 
-            let key = key.expose_secret();
-            if command == cli::Command::Encrypt {
-                cmd::encrypt(cipher, key, message, output, args.raw, add_newline)?;
-            } else if command == cli::Command::Decrypt {
-                cmd::decrypt(cipher, key, message, output, args.raw)?;
+        let (message, pre_filter): (Box<dyn Read>, _) = match plan.pre_filter {
+            Some(ref filter) => {
+                let (filter, message) = cmd::filter::Filter::reading_from(filter, message)?;
+                (Box::new(message), Some(filter))
             }
-
-            if is_in_place {
-                override_output_file_with_temporary_file_or_exit(args);
+            None => (message, None),
+        };
+        let (output, post_filter): (Box<dyn Write>, _) = match plan.post_filter {
+            Some(ref filter) => {
+                let (filter, output) = cmd::filter::Filter::writing_to(filter, output)?;
+                (Box::new(output), Some(filter))
+            }
+            None => (output, None),
+        };
+
+        let (output, output_closed) = cmd::OutputWatch::new(output);
+
+        let report = match cipher_message(
+            self.command,
+            cipher,
+            key.expose_secret(),
+            self.args,
+            plan.encoding,
+            message,
+            Box::new(output),
+        ) {
+            // The post-filter did not need all of the output (e.g.,
+            // `head`), its exit status decides.
+            Err(_) if output_closed.is_set() && post_filter.is_some() => {
+                cmd::FramedReport::default()
             }
+            // The consumer went away, stop quietly. This is not a
+            // decryption failure.
+            Err(_) if output_closed.is_set() => {
+                cmd::signal::remove_all();
+                process::exit(EXIT_BROKEN_PIPE)
+            }
+            res => res?,
+        };
 
-            Ok(())
+        // The message and output are consumed, filters see the end
+        // of their input.
+        for filter in [pre_filter, post_filter].into_iter().flatten() {
+            filter.wait()?;
         }
+        if let Some(output_file) = output_file {
+            output_file.finish()?;
+        }
+
+        Ok(report)
     }
+
+    fn commit(&mut self, _: &ExecutionPlan) -> Result<(), CliError> {
+        match self.in_place.take() {
+            Some(in_place) => in_place.commit(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Show progress while the message is read (see `--no-progress`).
+///
+/// The bar is cleared when the message is dropped.
+fn with_progress(message: Box<dyn Read + Send>, args: &cli::Args) -> Box<dyn Read + Send> {
+    if !args.progress {
+        return message;
+    }
+    let total = match args.message {
+        Some(cli::Message::File(ref file)) => fs::metadata(file).ok().map(|m| m.len()),
+        _ => None,
+    };
+    let mut bar = ui::progress::ProgressBar::new(total);
+    Box::new(ProgressReader::new(message, move |bytes| bar.update(bytes)))
+}
+
+/// Decrypt the first bytes of the message, and print them.
+fn peek(
+    command: cli::Command,
+    cipher: &dyn Cipher,
+    algorithm: cli::Algorithm,
+    args: &mut cli::Args,
+) -> Result<(), CliError> {
+    prompt_for_key(command, args)?;
+    let key = get_key_or_default(args, algorithm);
+    let message = get_message_or_exit(args);
+    let aad = args.aad.as_deref().unwrap_or_default().as_bytes();
+    let n = args.peek.expect("it is `Some`");
+    // Previews always go to `stdout`, never to a file (which could be
+    // the input file, with `--in-place`).
+    cmd::peek(
+        cipher,
+        key.expose_secret(),
+        aad,
+        message,
+        io::stdout(),
+        cmd::plan::Encoding::new(command, args).format(),
+        n,
+    )
 }
 
-fn is_input_file_used_for_output(args: &cli::Args) -> bool {
-    let (Some(cli::Message::File(input_file)), cli::Output::File(output_file)) =
+/// Decrypt the message, and throw the plaintext away.
+///
+/// Decryption authenticates everything (every chunk of a stream, and
+/// its end), so if it succeeds, the ciphertext is intact.
+fn check(
+    command: cli::Command,
+    cipher: &dyn Cipher,
+    algorithm: cli::Algorithm,
+    args: &mut cli::Args,
+) -> Result<(), CliError> {
+    prompt_for_key(command, args)?;
+    let key = get_key_or_default(args, algorithm);
+    let message = with_progress(get_message_or_exit(args), args);
+    let encoding = cmd::plan::Encoding::new(command, args);
+
+    cipher_message(
+        cli::Command::Decrypt,
+        cipher,
+        key.expose_secret(),
+        args,
+        encoding,
+        message,
+        Box::new(io::sink()),
+    )?;
+    println!("The ciphertext is intact.");
+    Ok(())
+}
+
+/// Decrypt a byte range of the message.
+///
+/// Raw ChaCha20-Poly1305 files are seeked into, only the chunks that
+/// cover the range are decrypted. Anything else is decrypted from the
+/// start, up to the end of the range.
+fn range(
+    command: cli::Command,
+    cipher: &dyn Cipher,
+    algorithm: cli::Algorithm,
+    args: &mut cli::Args,
+) -> Result<(), CliError> {
+    if let (Some(cli::Message::File(input)), cli::Output::File(output)) =
         (&args.message, &args.output)
-    else {
-        return false;
+        && cmd::plan::is_same_file(input, output)
+    {
+        return Err(format!(
+            "Cannot write the range to '{}', it is the input file",
+            output.display()
+        )
+        .into());
+    }
+
+    prompt_for_key(command, args)?;
+    let key = get_key_or_default(args, algorithm);
+    let aad = args.aad.as_deref().unwrap_or_default().as_bytes();
+    let range = args.range.clone().expect("it is `Some`");
+    let format = cmd::plan::Encoding::new(command, args).format();
+    let mut output = get_output_or_exit(args);
+
+    let is_seekable = algorithm == cli::Algorithm::ChaCha20Poly1305
+        && !args.password
+        && matches!(format, None | Some(cmd::Format::Raw));
+    if is_seekable && let Some(cli::Message::File(ref file)) = args.message {
+        let message = fs::File::open(file)
+            .map(io::BufReader::new)
+            .map_err(|e| CliError::Io(format!("Could not read '{}': {e}", file.display())))?;
+        let key = key.expose_secret();
+        if cmd::decrypt_range_seekable(key, aad, message, &mut output, range.clone())? {
+            return Ok(());
+        }
+    }
+
+    let message = get_message_or_exit(args);
+    cmd::decrypt_range(
+        cipher,
+        key.expose_secret(),
+        aad,
+        message,
+        output,
+        format,
+        range,
+    )
+}
+
+/// Decrypt with the old key, and encrypt with the new one.
+///
+/// In-place, a directory is rekeyed file by file. Files that fail are
+/// reported and left as they were, and the others are rekeyed anyway.
+///
+/// Migrating is rekeying with the same key. In-place, files already in
+/// the latest format are left as they are.
+fn rekey(
+    command: cli::Command,
+    cipher: &dyn Cipher,
+    algorithm: cli::Algorithm,
+    args: &mut cli::Args,
+) -> Result<(), CliError> {
+    let is_migrate = command == cli::Command::Migrate;
+    let (verb, past) = if is_migrate {
+        ("migrate", "migrated")
+    } else {
+        ("rekey", "rekeyed")
     };
-    let (Ok(input_file), Ok(output_file)) = (input_file.canonicalize(), output_file.canonicalize())
-    else {
-        return false;
+    let in_place = match (&args.message, &args.output) {
+        (Some(cli::Message::File(input)), cli::Output::File(output))
+            if cmd::plan::is_same_file(input, output) =>
+        {
+            Some(input.clone())
+        }
+        _ => None,
+    };
+    let files = match in_place {
+        Some(ref dir) if dir.is_dir() => cmd::rekey::files(dir)?
+            .into_iter()
+            .filter(|file| !is_migrate || cmd::rekey::is_outdated(file))
+            .collect(),
+        Some(ref file) if file.is_file() => vec![file.clone()],
+        Some(ref file) => {
+            return Err(format!(
+                "Cannot work in-place on '{}', it is not a regular file",
+                file.display()
+            )
+            .into());
+        }
+        None => Vec::new(),
+    };
+    if let Some(ref input) = in_place {
+        let size = if input.is_dir() {
+            format!(" ({} files)", files.len())
+        } else {
+            fs::metadata(input).map_or_else(
+                |_| String::new(),
+                |m| format!(" ({})", ui::format::bytes(m.len())),
+            )
+        };
+        let question = format!(
+            "About to {verb} '{}'{size} in place, continue?",
+            input.display()
+        );
+        if !ui::prompt::confirm(&question, args.yes, &environment::System) {
+            return Err("Aborted".to_string().into());
+        }
+    }
+
+    prompt_for_key(command, args)?;
+    let key = get_key_or_default(args, algorithm);
+    let new_key = match args.new_key {
+        Some(ref new_key) => new_key.expose_secret().as_bytes(),
+        None if is_migrate => key.expose_secret(),
+        None => unreachable!("checked by the CLI"),
+    };
+    let rekey = cmd::rekey::Rekey {
+        old_cipher: cipher,
+        old_key: key.expose_secret(),
+        new_algorithm: args.new_algorithm.expect("set by the CLI"),
+        new_key,
+        aad: args.aad.as_deref().unwrap_or_default().as_bytes(),
+        preserve_times: args.preserve_times,
+        buffer_size: args.output_buffer_size(),
+    };
+    let format = cmd::plan::Encoding::new(command, args).format();
+
+    let Some(input) = in_place else {
+        let message = get_message_or_exit(args);
+        let (output, output_file) = get_output_like_input_or_exit(args)?;
+        let add_newline = args.output == cli::Output::Stdout;
+        rekey.stream(message, output, format, add_newline)?;
+        if let Some(output_file) = output_file {
+            output_file.finish()?;
+        }
+        return Ok(());
+    };
+    if input.is_file() {
+        if is_migrate && !cmd::rekey::is_outdated(&input) {
+            return Ok(());
+        }
+        return rekey.file(&input, format, environment::System.now());
+    }
+
+    let mut nb_failed = 0;
+    for file in &files {
+        if let Err(reason) = rekey.file(file, format, environment::System.now()) {
+            nb_failed += 1;
+            eprintln!(
+                "{error}: Could not {verb} '{}': {reason}",
+                file.display(),
+                error = ui::Color::error("error"),
+            );
+        }
+    }
+    if nb_failed > 0 {
+        eprintln!(
+            "{error}: {nb_failed} of {} files could not be {past}.",
+            files.len(),
+            error = ui::Color::error("error"),
+        );
+        process::exit(EXIT_PARTIAL_FAILURE);
+    }
+    Ok(())
+}
+
+/// Sign `message`, check its signature, or generate a signing keypair.
+fn sign_or_verify(
+    command: cli::Command,
+    args: &mut cli::Args,
+    add_newline: bool,
+) -> Result<(), CliError> {
+    let signer = signer::Ed25519;
+    if args.signing_keygen {
+        cmd::keygen_signer(
+            &signer,
+            &args.keygen_options,
+            cmd::KeyFiles::default(),
+            add_newline,
+        )?;
+        return Ok(());
+    }
+
+    prompt_for_key(command, args)?;
+    let key = args.key.as_ref().expect("checked by the CLI");
+    let message = get_message_or_exit(args);
+
+    if command == cli::Command::Sign {
+        let output = get_output_or_exit(args);
+        cmd::sign(
+            &signer,
+            key.expose_secret().as_bytes(),
+            message,
+            output,
+            add_newline,
+        )
+    } else {
+        let signature = args.signature.as_deref().expect("checked by the CLI");
+        cmd::verify(&signer, key.expose_secret().as_bytes(), message, signature)?;
+        println!("The signature is valid.");
+        Ok(())
+    }
+}
+
+/// Encrypt or decrypt `message` into `output`.
+fn cipher_message(
+    command: cli::Command,
+    cipher: &dyn Cipher,
+    key: &[u8],
+    args: &cli::Args,
+    encoding: cmd::plan::Encoding,
+    message: Box<dyn Read>,
+    output: Box<dyn Write>,
+) -> Result<cmd::FramedReport, CliError> {
+    let aad = args.aad.as_deref().unwrap_or_default().as_bytes();
+    let add_newline = args.output == cli::Output::Stdout;
+    // Only decryption may have to detect it.
+    let format = encoding.format();
+    let is_raw = format == Some(cmd::Format::Raw);
+
+    match (command, args.framed) {
+        (cli::Command::Encrypt, true) => {
+            cmd::encrypt_framed(cipher, key, aad, message, output, is_raw)?;
+        }
+        (cli::Command::Decrypt, true) => {
+            return cmd::decrypt_framed(
+                cipher,
+                key,
+                aad,
+                message,
+                output,
+                is_raw,
+                args.continue_on_error,
+            );
+        }
+        (cli::Command::Encrypt, false) => {
+            let format = format.unwrap_or(cmd::Format::Base64);
+            cmd::encrypt(cipher, key, aad, message, output, format, add_newline)?;
+        }
+        (cli::Command::Decrypt, false) => {
+            cmd::decrypt(cipher, key, aad, message, output, format)?;
+        }
+        _ => unreachable!("only encryption and decryption cipher messages"),
+    }
+    Ok(cmd::FramedReport::default())
+}
+
+fn keygen(cipher: &dyn Cipher, args: &cli::Args, add_newline: bool) -> Result<(), CliError> {
+    // The key must be on screen, and someone must press Enter.
+    let is_terminal =
+        args.output == cli::Output::Stdout && environment::System.is_terminal(Stream::Stdin);
+    if args.confirm_then_clear && !is_terminal {
+        return Err(CliError::Usage(
+            "'--confirm-then-clear' requires a terminal".to_string(),
+        ));
+    }
+
+    let passphrase = if args.protect {
+        Some(new_passphrase()?)
+    } else {
+        None
+    };
+    let nb_lines = cmd::keygen(
+        cipher,
+        &args.keygen_options,
+        passphrase.as_ref().map(|p| p.expose_secret().as_bytes()),
+        cmd::KeyFiles {
+            secret: match &args.output {
+                cli::Output::File(file) => Some(file),
+                cli::Output::Stdout | cli::Output::Redirected => None,
+            },
+            public: args.public_output.as_deref(),
+        },
+        args.qr,
+        args.encoding.unwrap_or_default().encoding(),
+        add_newline,
+    )?;
+
+    if args.confirm_then_clear {
+        ui::terminal::wait_for_enter_then_clear(
+            "Press Enter to clear the key from the screen.",
+            nb_lines,
+        )
+        .map_err(|e| CliError::Io(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// `--force` overwrites existing outputs, ask first.
+fn confirm_overwrite(args: &cli::Args) -> Result<(), CliError> {
+    if !args.force {
+        return Ok(());
+    }
+    let output = match args.output {
+        cli::Output::File(ref file) => Some(file.as_path()),
+        cli::Output::Stdout | cli::Output::Redirected => None,
+    };
+    let outputs = [output, args.public_output.as_deref()];
+    for file in outputs.into_iter().flatten() {
+        // In-place operations ask on their own (see `confirm_in_place()`).
+        let is_input = matches!(
+            args.message,
+            Some(cli::Message::File(ref input)) if cmd::plan::is_same_file(input, file)
+        );
+        if !file.is_file() || is_input {
+            continue;
+        }
+        let question = format!("'{}' already exists, overwrite it?", file.display());
+        if !ui::prompt::confirm(&question, args.yes, &environment::System) {
+            return Err("Aborted".to_string().into());
+        }
+    }
+    Ok(())
+}
+
+/// `--shred` destroys the input once encrypted, ask first.
+fn confirm_shred(args: &cli::Args) -> Result<(), CliError> {
+    let (true, Some(cli::Message::File(input))) = (args.shred, &args.message) else {
+        return Ok(());
     };
-    input_file == output_file
+    let question = format!(
+        "About to shred '{}' once encrypted, continue?",
+        input.display()
+    );
+    if ui::prompt::confirm(&question, args.yes, &environment::System) {
+        Ok(())
+    } else {
+        Err("Aborted".to_string().into())
+    }
+}
+
+/// In-place operations overwrite the input, ask first.
+fn confirm_in_place(
+    command: cli::Command,
+    plan: &ExecutionPlan,
+    args: &cli::Args,
+) -> Result<(), CliError> {
+    let (true, cli::Output::File(file)) = (plan.in_place, &plan.output) else {
+        return Ok(());
+    };
+    let size = fs::metadata(file).map_or_else(
+        |_| String::new(),
+        |m| format!(" ({})", ui::format::bytes(m.len())),
+    );
+    let question = format!(
+        "About to {command} '{}'{size} in place, continue?",
+        file.display(),
+        command = if command == cli::Command::Encrypt {
+            "encrypt"
+        } else {
+            "decrypt"
+        },
+    );
+    if ui::prompt::confirm(&question, args.yes, &environment::System) {
+        Ok(())
+    } else {
+        Err("Aborted".to_string().into())
+    }
+}
+
+/// Have the key typed in (`--key -`, or `--password` on a terminal),
+/// and unlock it if it is protected by a passphrase.
+///
+/// On encryption, the key is typed in twice, a typo would make the data
+/// unrecoverable.
+fn prompt_for_key(command: cli::Command, args: &mut cli::Args) -> Result<(), CliError> {
+    if args.prompt_key {
+        let (prompt, confirm_prompt) = if args.password {
+            ("Passphrase: ", "Confirm passphrase: ")
+        } else {
+            ("Key: ", "Confirm key: ")
+        };
+        let key = if command == cli::Command::Encrypt {
+            ui::terminal::read_new_secret(prompt, confirm_prompt)?
+        } else {
+            ui::terminal::read_secret(prompt)?
+        };
+        args.set_prompted_key(&key)?;
+    }
+    if args.locked_key {
+        let passphrase = match environment::System.var(cli::PASSWORD_ENV_VAR) {
+            Some(passphrase) => SecretString::from(passphrase),
+            None => ui::terminal::read_secret("Passphrase for the key: ")?,
+        };
+        args.unlock_key(&passphrase)?;
+    }
+    Ok(())
+}
+
+/// Passphrase to protect a new key with, typed in twice on a terminal.
+fn new_passphrase() -> Result<SecretString, String> {
+    match environment::System.var(cli::PASSWORD_ENV_VAR) {
+        Some(passphrase) => Ok(SecretString::from(passphrase)),
+        None => ui::terminal::read_new_secret("Passphrase: ", "Confirm passphrase: "),
+    }
 }
 
 fn get_key_or_default(args: &cli::Args, algorithm: cli::Algorithm) -> SecretSlice<u8> {
-    if algorithm == cli::Algorithm::RotN || algorithm == cli::Algorithm::Brainfuck {
+    if algorithm.is_toy() {
         // Special do-not-warn cases.
         algorithm.default_key().get_symmetric().clone()
     } else if let Some(ref key) = args.key {
         SecretSlice::from(key.expose_secret().as_bytes().to_vec())
     } else {
-        eprintln!(
-            "\
-{warning}: Using {package}'s default cipher key.
+        warn(
+            args,
+            Warning::DefaultKey,
+            &format!(
+                "\
+Using {package}'s default cipher key.
 
                        {b}THIS IS NOT SECURE!{rt}
 
 Anyone using {package} will be able to decrypt your messages. To generate
 a unique cipher key, run `{bin} keygen`, and use it on the command line
 with `--key`, or set the `{key_env_var}` environment variable.",
-            warning = ui::Color::warning("warning"),
-            package = env!("CARGO_PKG_NAME"),
-            bin = env!("CARGO_BIN_NAME"),
-            key_env_var = cli::KEY_ENV_VAR,
-            b = ui::Color::maybe_color(ui::color::BOLD),
-            rt = ui::Color::maybe_color(ui::color::RESET),
+                package = env!("CARGO_PKG_NAME"),
+                bin = env!("CARGO_BIN_NAME"),
+                key_env_var = cli::KEY_ENV_VAR,
+                b = ui::Color::maybe_color(ui::color::BOLD),
+                rt = ui::Color::maybe_color(ui::color::RESET),
+            ),
         );
 
         let key = algorithm.default_key();
@@ -132,7 +832,9 @@ with `--key`, or set the `{key_env_var}` environment variable.",
             GeneratedKey::Symmetric(_) => key.get_symmetric(),
             GeneratedKey::Asymmetric { .. } => match args.command {
                 Some(cli::Command::Encrypt) => key.get_asymmetric_public(),
-                Some(cli::Command::Decrypt) => key.get_asymmetric_private(),
+                Some(cli::Command::Decrypt | cli::Command::Rekey | cli::Command::Check) => {
+                    key.get_asymmetric_private()
+                }
                 _ => unreachable!(),
             },
             GeneratedKey::None => unreachable!(),
@@ -141,10 +843,100 @@ with `--key`, or set the `{key_env_var}` environment variable.",
     }
 }
 
-fn get_message_or_exit(args: &cli::Args) -> Box<dyn Read> {
+fn build_cipher(
+    command: cli::Command,
+    algorithm: cli::Algorithm,
+    args: &cli::Args,
+) -> Box<dyn Cipher> {
+    let cipher: Box<dyn Cipher> = if args.password && algorithm == cli::Algorithm::Age {
+        Box::new(cipher::AgePassword::default())
+    } else if args.password {
+        Box::new(cipher::Password::default())
+    } else {
+        algorithm.into()
+    };
+    if matches!(
+        command,
+        cli::Command::Encrypt
+            | cli::Command::Decrypt
+            | cli::Command::Rekey
+            | cli::Command::Migrate
+            | cli::Command::Check
+    ) {
+        with_expiry(with_key_hint(cipher, args), args)
+    } else {
+        cipher
+    }
+}
+
+/// Add key hints (`--id-hint`), and check them.
+fn with_key_hint(cipher: Box<dyn Cipher>, args: &cli::Args) -> Box<dyn Cipher> {
+    let cipher = KeyHinted::new(cipher);
+    if args.id_hint {
+        Box::new(cipher.with_hint())
+    } else {
+        Box::new(cipher)
+    }
+}
+
+/// Add expiry dates (`--expire`), and act on them (`--strict`).
+fn with_expiry(cipher: Box<dyn Cipher>, args: &cli::Args) -> Box<dyn Cipher> {
+    let now = environment::System.now();
+    let mut cipher = Expiring::new(cipher);
+    if let Some(expire) = args.expire {
+        cipher = cipher.expires_at(now + expire);
+    }
+
+    let strict = args.strict;
+    let warn_expired = !args.no_warn.contains(&Warning::Expired);
+    Box::new(cipher.on_expiry(move |expires_at| {
+        let Ok(elapsed) = now.duration_since(expires_at) else {
+            return Ok(());
+        };
+        let message = format!(
+            "This message expired {} ago.",
+            ui::format::duration(elapsed)
+        );
+        if strict {
+            return Err(Error::Other(message));
+        }
+        if warn_expired {
+            print_warning(Warning::Expired, &message);
+        }
+        Ok(())
+    }))
+}
+
+/// Print warning, unless it was suppressed.
+fn warn(args: &cli::Args, warning: Warning, message: &str) {
+    if args.no_warn.contains(&warning) {
+        return;
+    }
+    print_warning(warning, message);
+}
+
+fn print_warning(warning: Warning, message: &str) {
+    if ui::log::is_quiet() {
+        return;
+    }
+    eprintln!(
+        "{warning}[{id}]: {message}",
+        warning = ui::Color::warning("warning"),
+        id = warning.id(),
+    );
+}
+
+#[cfg(not(windows))]
+const EOF_KEYS: &str = "Ctrl-D";
+#[cfg(windows)]
+const EOF_KEYS: &str = "Ctrl-Z, Enter,";
+
+fn get_message_or_exit(args: &cli::Args) -> Box<dyn Read + Send> {
     if let Some(ref message) = args.message {
         match message {
-            cli::Message::String(message) => Box::new(io::Cursor::new(message.to_owned())),
+            cli::Message::String(message) => {
+                Box::new(io::Cursor::new(Zeroizing::new(message.to_owned())))
+            }
             cli::Message::File(file) => {
                 let f = match fs::File::open(file) {
                     Ok(f) => f,
@@ -154,148 +946,96 @@ fn get_message_or_exit(args: &cli::Args) -> Box<dyn Read> {
                             file.display(),
                             error = ui::Color::error("error")
                         );
-                        process::exit(1);
+                        process::exit(EXIT_IO);
                     }
                 };
+                if args.mmap {
+                    return cmd::mmap::reader(f);
+                }
                 let reader = io::BufReader::new(f);
                 Box::new(reader)
             }
-            cli::Message::Stdin => Box::new(io::stdin()),
+            cli::Message::Stdin => {
+                if environment::System.is_terminal(Stream::Stdin) && !ui::log::is_quiet() {
+                    // Without this, it looks like the process is hanging.
+                    eprintln!("Reading message from stdin, press {EOF_KEYS} to finish.");
+                }
+                if let Some(limit) = args.max_stdin_size {
+                    Box::new(cmd::SizeLimitedReader::new(io::stdin(), limit))
+                } else {
+                    Box::new(io::stdin())
+                }
+            }
         }
     } else {
         eprintln!(
             "{fatal}: You must provide a message.",
             fatal = ui::Color::error("fatal")
         );
-        process::exit(2);
+        process::exit(EXIT_USAGE);
     }
 }
 
-fn get_output_or_exit(args: &cli::Args) -> Box<dyn Write> {
+/// Output is buffered (see `--buffer-size`). Stdout is line-buffered
+/// on its own, raw ciphertexts would be written a few bytes at a time.
+fn get_output_or_exit(args: &cli::Args) -> Box<dyn Write + Send> {
+    let buffer_size = args.output_buffer_size();
     match args.output {
-        cli::Output::File(ref file) => {
-            let f = match fs::File::create(file) {
-                Ok(f) => f,
-                Err(reason) => {
-                    eprintln!(
-                        "{error}: Could not open file for writing '{}': {reason}.",
-                        file.display(),
-                        error = ui::Color::error("error")
-                    );
-                    process::exit(1);
-                }
-            };
-            let writer = io::BufWriter::new(f);
-            Box::new(writer)
+        cli::Output::File(ref file) => create_file_or_exit(file, buffer_size),
+        cli::Output::Stdout | cli::Output::Redirected => {
+            Box::new(io::BufWriter::with_capacity(buffer_size, io::stdout()))
         }
-        cli::Output::Stdout | cli::Output::Redirected => Box::new(io::stdout()),
     }
 }
 
-fn get_temporary_file_or_exit(args: &cli::Args) -> Box<dyn Write> {
-    debug_assert!(
-        is_input_file_used_for_output(args),
-        "Should only get called if in-place ciphering."
-    );
-    let tmp_file = build_temporary_file_path(args);
-    let f = match fs::File::create(&tmp_file) {
+/// Like [`get_output_or_exit()`], but an output file made from an
+/// input file gets its metadata (see [`cmd::metadata`]).
+///
+/// The times are only applied by [`OutputFile::finish()`], once the
+/// output is written.
+fn get_output_like_input_or_exit(
+    args: &cli::Args,
+) -> Result<(Box<dyn Write + Send>, Option<OutputFile>), CliError> {
+    match (&args.message, &args.output) {
+        (Some(cli::Message::File(input)), cli::Output::File(file)) if input.is_file() => {
+            let output_file = OutputFile::create(file, input, args.preserve_times)?;
+            Ok((
+                output_file.writer(args.output_buffer_size())?,
+                Some(output_file),
+            ))
+        }
+        _ => Ok((get_output_or_exit(args), None)),
+    }
+}
+
+fn create_file_or_exit(file: &Path, buffer_size: usize) -> Box<dyn Write + Send> {
+    let f = match fs::File::create(file) {
         Ok(f) => f,
         Err(reason) => {
             eprintln!(
                 "{error}: Could not open file for writing '{}': {reason}.",
-                tmp_file.display(),
+                file.display(),
                 error = ui::Color::error("error")
             );
-            process::exit(1);
+            process::exit(EXIT_IO);
         }
     };
-    let writer = io::BufWriter::new(f);
+    let writer = io::BufWriter::with_capacity(buffer_size, f);
     Box::new(writer)
 }
 
-fn override_output_file_with_temporary_file_or_exit(args: &cli::Args) {
-    debug_assert!(
-        is_input_file_used_for_output(args),
-        "Should only get called if in-place ciphering."
-    );
-    let cli::Output::File(ref file) = args.output else {
-        unreachable!("if in-place, it's necessarily a file");
-    };
-    let tmp_file = build_temporary_file_path(args);
-    if let Err(reason) = std::fs::rename(tmp_file, file) {
-        eprintln!(
-            "{error}: Could not override '{}': {reason}.",
-            file.display(),
-            error = ui::Color::error("error")
-        );
-        process::exit(1);
-    }
-}
-
-// TODO: Not good that we need to call this twice, refactoring would do
-// some good. This whole in-place thing doesn't "fit in" with the
-// current design, it's too crafty.
-fn build_temporary_file_path(args: &cli::Args) -> PathBuf {
-    // File name can't change from create to rename.
-    static EXTENSION: OnceLock<String> = OnceLock::new();
-
-    let cli::Output::File(ref file) = args.output else {
-        unreachable!("if in-place, it's necessarily a file");
-    };
-
-    file.with_extension(EXTENSION.get_or_init(|| {
-        let mut extension = env!("CARGO_CRATE_NAME").to_string();
-        if let Ok(timestamp) = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|t| t.as_micros())
-        {
-            extension = format!("{timestamp}.{extension}");
-        }
-        extension
-    }))
-}
-
 fn short_help() {
-    println!("{}", short_help_message());
+    println!("{}", cmd::help::short_help_message());
     println!(
         "For full help, see `{bin} --help`.",
         bin = env!("CARGO_BIN_NAME")
     );
 }
 
-fn short_help_message() -> String {
-    format!(
-        "\
-{description}
-
-Usage: {bin} [<options>] <command> [<args>]
-
-Commands:
-  keygen                  Generate cipher key
-  encrypt                 Encrypt plaintext
-  decrypt                 Decrypt ciphertext
-
-Args:
-  <MESSAGE>
-  -a, --algorithm <ALGO>  Cipher algorithm (default: ChaCha20-Poly1305)
-  -k, --key <KEY>         Cipher key (base64)
-  -r, --raw               Handle message as raw binary
-  -f, --file <FILE>       Read message from file
-    -i, --in-place        Write output to input file
-  -o, --output <FILE>     Write output to file
-
-Options:
-  -h, --help              Show help message and exit
-  -V, --version           Show the version and exit
-",
-        description = env!("CARGO_PKG_DESCRIPTION"),
-        bin = env!("CARGO_BIN_NAME"),
-    )
-}
-
 #[allow(clippy::too_many_lines)]
-fn long_help() {
-    Pager::page_or_print(&format!(
+fn long_help(no_pager: bool) {
+    ui::pager::page_or_print(
+        &format!(
         "\
 {help}
 What does {package} do?
@@ -317,13 +1057,42 @@ Algorithms:
   {u}Name{rt}                 {u}Key Size{rt}               {u}Type{rt}
   ChaCha20-Poly1305    32-bytes (256-bits)    Symmetric
   HPKE                 32-bytes (256-bits)    Asymmetric
+  HPKE-PQ              1216-bytes (public)    Asymmetric
+  age                  32-bytes (256-bits)    Asymmetric
   ROT-n                0..255 (insecure)      Symmetric
 
+  Key generation can be tuned with `--bits` and `--params` for algorithms
+  that have variable parameters. The current algorithms all have fixed
+  parameters, and reject anything else.
+
   Accepted values:
     ChaCha20-Poly1305: chacha20poly1305, chacha20, chacha, cha20, cha
     HPKE:              hpke
+    HPKE-PQ:           hpke-pq, xwing
+    age:               age
     ROT-n:             rotn, rot
 
+  HPKE-PQ is HPKE with a post-quantum hybrid KEM (X-Wing, X25519
+  combined with ML-KEM-768). Ciphertexts stay safe if either of the two
+  holds, which protects data that must stay secret for years against a
+  future quantum computer. It has no default key.
+
+  age reads and writes the age file format, so files can be exchanged
+  with `age` and `rage`. Keys are age keys (`age1...` and
+  `AGE-SECRET-KEY-1...`), and select age on their own. An identity
+  file written by `age-keygen` works as a key file. With `--password`,
+  the passphrase goes through scrypt, like `age -p`:
+
+      {h}${rt} {bin} keygen -a age
+      {h}${rt} {bin} encrypt -f report.pdf -o report.pdf.age --raw -k age1hxrsgh77k8jzk60gdfccf3n094ffzfdvuldlptkarpprq4079g9q9lslkq
+      {h}${rt} {bin} decrypt -f report.pdf.age -k ~/.config/age/key.txt
+      {h}${rt} {bin} encrypt -f report.pdf -o report.pdf.age --raw -a age --password
+
+  age files are binary. Write them with `--raw` for age to read them
+  ({bin} writes base64 by default). Armored files (`age -a`) can be decrypted, but not
+  written. Only one recipient is supported, there is no default key,
+  and `--aad` and `--expire` can't be used.
+
   For example:
 
       {h}${rt} {bin} encrypt \"hello\"
@@ -339,37 +1108,138 @@ Key:
   To generate a new key run:
 
       {h}${rt} {bin} keygen
-      hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w
+      chacha20poly1305:hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w
+
+  Generated keys are tagged with the algorithm they belong to, and using
+  a key with another algorithm is an error. The tag is optional, a bare
+  base64 key works just as well.
+
+  If you copy the key from the terminal (e.g., while sharing your
+  screen), `--confirm-then-clear` waits for you to press Enter, and
+  erases the key from the screen:
+
+      {h}${rt} {bin} keygen --confirm-then-clear
+
+  Or skip the terminal entirely, and write the key to a file with
+  `--output`. The file is only readable by you (mode `0600`):
+
+      {h}${rt} {bin} keygen -o jolokia.key
+
+  For keypairs, the private key goes to the file and the public key is
+  printed, unless `--public-output` names a file for it too:
+
+      {h}${rt} {bin} keygen -a hpke -o private.key
+      hpke:...
+      {h}${rt} {bin} keygen -a hpke -o private.key --public-output public.key
 
   To use the key, pass it as `--key` or `-k`:
 
-      {h}${rt} {bin} encrypt \"foo\" --key hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w
+      {h}${rt} {bin} encrypt \"foo\" --key chacha20poly1305:hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w
       Q0gyMAGSwlWJdALzAAAAE448viN3l+rwa7W4RdkRI0V/VckAAAAA
 
   Or as an environment variable (but `--key` has precedence):
 
-      {h}${rt} export {key_env_var}=hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w
+      {h}${rt} export {key_env_var}=chacha20poly1305:hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w
       {h}${rt} {bin} encrypt \"foo\"
       Q0gyMAGSwlWJdALzAAAAE448viN3l+rwa7W4RdkRI0V/VckAAAAA
 
   The key can also be the name of a file that contains a key:
 
-      {h}${rt} echo hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w > /secrets/{bin}.key
+      {h}${rt} echo chacha20poly1305:hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w > /secrets/{bin}.key
       {h}${rt} {bin} decrypt --key /secrets/{bin}.key Q0gyMAGSwlWJdALzAAAAE448viN3l+rwa7W4RdkRI0V/VckAAAAA
       foo
 
   To set a key permanently, the recommended solution is to point the
   environment variable to a file:
 
-      {h}${rt} echo hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w > ~/.{bin}.key
+      {h}${rt} echo chacha20poly1305:hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w > ~/.{bin}.key
       {h}${rt} echo 'export {key_env_var}=\"$HOME/.{bin}.key\"' >> ~/.bashrc
 
+  With several keys, a keyring keeps them together, each under a
+  name, along with its algorithm. It lives in
+  `~/.config/{bin}/keyring.toml` (or under `$XDG_CONFIG_HOME`), and
+  keys can be key files there too:
+
+      [work]
+      algorithm = \"chacha20-poly1305\"
+      key = \"hNbaua5cGlUNsEp4HSUTSJG7gl5IURQiTvnABzhFW4w\"
+
+      [backups]
+      algorithm = \"hpke\"
+      key = \"~/.keys/backups.pub\"
+
+  `--key-name` picks a key. It takes precedence over the environment
+  variable, but can't be combined with `--key` or `--password`:
+
+      {h}${rt} {bin} encrypt -f report.pdf --key-name work
+
+  SSH keys work too, like with `age`. An `ssh-ed25519` public key
+  (e.g., from `https://github.com/<user>.keys`) encrypts, and the
+  matching private key decrypts. They are HPKE keys, HPKE is selected
+  for you. `ssh-rsa` keys, and private keys encrypted with a
+  passphrase, are not supported:
+
+      {h}${rt} {bin} encrypt -f report.pdf --key ~/.ssh/id_ed25519.pub
+      {h}${rt} {bin} decrypt -f report.pdf.enc --key ~/.ssh/id_ed25519
+
+  If something doesn't work as expected, `{bin} doctor` checks for
+  common misconfigurations (e.g., a key file that can't be read), and
+  suggests fixes.
+
+  `{bin} bench` measures how fast each algorithm encrypts on this
+  machine (`-a` for one only, `--size` for more or less than 16 MiB):
+
+      {h}${rt} {bin} bench -a hpke --size 104857600
+
+  Keys, nonces, and salts come from the system's CSPRNG. Where policy
+  requires it, `--entropy strict` reads from `/dev/random` instead
+  (which blocks until the kernel's pool is seeded), and `--entropy`
+  also takes a random device (`/dev/urandom`, `/dev/random`, or
+  `/dev/hwrng`). There is no fallback, if the source can't be read,
+  it's an error:
+
+      {h}${rt} {bin} keygen --entropy strict
+      {h}${rt} {bin} encrypt -f cat.gif --entropy /dev/hwrng
+
+Password:
+  If you'd rather remember a passphrase than keep a key file around,
+  `--password` derives the key from a passphrase (with Argon2id). The
+  passphrase is given with `--key`, or in the `{password_env_var}`
+  environment variable:
+
+      {h}${rt} export {password_env_var}=\"correct horse battery staple\"
+      {h}${rt} {bin} encrypt \"foo\" --password
+
+  Without either, on a terminal, the passphrase is asked for (without
+  echo, and twice when encrypting). This keeps it out of the shell
+  history. Likewise, `--key -` asks for the key on the terminal, for any
+  algorithm.
+
+  The salt and the KDF parameters are stored in the ciphertext. Password
+  mode uses ChaCha20-Poly1305, and cannot be combined with `--framed`
+  (every frame would pay for the key derivation).
+
+  A passphrase can also protect a key file. `keygen --protect` encrypts
+  the private key (or the key, if symmetric) with a key derived from a
+  passphrase, typed in twice on a terminal, or taken from
+  `{password_env_var}`. Protected keys start with `protected:`, and are
+  unlocked with the passphrase whenever they are used:
+
+      {h}${rt} {bin} keygen -a hpke --protect
+      {h}${rt} {bin} decrypt -a hpke -k ~/.keys/backups.key -f backups.enc
+      Passphrase for the key:
+
 Message:
   The message can be passed on the command line:
 
       {h}${rt} {bin} encrypt \"bar\"
       Q0gyMAHPNRsLieAOAAAAE/ssTCh2zCm73t+aQf9aKNepgPkAAAAA
 
+  A message that starts with `-` goes after `--`, otherwise it is taken
+  for a (mistyped) option:
+
+      {h}${rt} {bin} encrypt -- \"-n\"
+
   Or from a file:
 
       {h}${rt} {bin} encrypt --file bar.txt
@@ -380,17 +1250,208 @@ Message:
       {h}${rt} cat bar.txt | {bin} encrypt
       Q0gyMAHPNRsLieAOAAAAE/ssTCh2zCm73t+aQf9aKNepgPkAAAAA
 
+  If there is no message and `stdin` is a terminal, {package} reads the
+  message as you type it. Press {eof_keys} to finish.
+
+  In scripts, you can cap the size of the message read from `stdin`:
+
+      {h}${rt} some-command | {bin} encrypt --max-stdin-size 1048576
+
+  Output is written 64 KiB at a time, `--buffer-size` changes it (in
+  bytes). Larger writes mean fewer syscalls, for big files and pipes.
+
+  With `--mmap`, an input file is memory-mapped instead of read in small
+  chunks, which helps with multi-GB files on slow disks. Pipes are read
+  as usual. Don't change the file while it is being read.
+
+  Files and piped input that take a while show a progress bar on
+  `stderr` (bytes, throughput, and, for files, the time left). Pass
+  `--no-progress` to hide it.
+
   By definition, you can round-trip it:
 
       {h}${rt} {bin} encrypt \"hello, world\" -o encrypted.txt
       {h}${rt} {bin} decrypt -f encrypted.txt
       hello, world
 
+  If `--output` is a directory, the output is named after the input
+  file (`.enc` is added on encryption, and removed on decryption):
+
+      {h}${rt} {bin} encrypt -f cat.gif -o backups/    # backups/cat.gif.enc
+      {h}${rt} {bin} decrypt -f backups/cat.gif.enc -o restored/    # restored/cat.gif
+
+  With `--suffix`, the output is named after the input file too, but
+  with the given suffix, and next to the input (or in `--output`, if it's
+  a directory). This saves naming outputs one by one:
+
+      {h}${rt} {bin} encrypt -f cat.gif --suffix .enc    # cat.gif.enc
+      {h}${rt} {bin} decrypt -f cat.gif.enc --suffix .enc -o restored/    # restored/cat.gif
+
+  To encrypt a file and get rid of the plaintext, add `--shred`. Once
+  the ciphertext is safely on disk, the original is overwritten with
+  zeros and deleted (if anything fails before, it's left alone):
+
+      {h}${rt} {bin} encrypt -f diary.txt --suffix .enc --shred    # diary.txt.enc
+
+  This is best effort, and {package} warns about it (W004): SSDs,
+  copy-on-write file systems, snapshots and backups can all keep copies
+  of the plaintext that overwriting the file doesn't reach. Full-disk
+  encryption is the real answer there.
+
+  An existing file is never overwritten by accident. If the output
+  already exists (and it is not the input, see below), {package} refuses
+  to run, unless you add `--force`:
+
+      {h}${rt} {bin} encrypt -f cat.gif -o backups/
+      fatal: 'backups/cat.gif.enc' already exists, use '--force' to overwrite it.
+      Try '{bin} -h' for help.
+      {h}${rt} {bin} encrypt -f cat.gif -o backups/ --force
+
   You can also encrypt or decrypt a file in-place:
 
       {h}${rt} {bin} encrypt -f cat.gif --in-place
       {h}${rt} {bin} decrypt -f cat.gif -i
 
+  In a terminal, in-place operations ask for confirmation first, and so
+  do `--shred` and `--force` (if the output exists). Use `--yes` to skip
+  the question (when `stdin` is not a terminal, there is no one to ask,
+  and nothing is asked):
+
+      {h}${rt} {bin} encrypt -f cat.gif --in-place
+      About to encrypt 'cat.gif' (1.5 MiB) in place, continue? [y/N] y
+
+  The output goes to a temporary file next to the original, which only
+  replaces it once complete and flushed to disk. If anything fails (even
+  a crash or Ctrl-C), the original is left untouched and the temporary
+  file is removed.
+
+  Whether in-place or with `--output`, the output file gets the
+  permissions of the input file (and its owner, where allowed), so an
+  encrypted backup is as protected as the original, and a decrypted
+  script is still executable. To also keep its access and modification
+  times, add `--preserve-times`:
+
+      {h}${rt} {bin} encrypt -f cat.gif -o backups/ --preserve-times
+
+  `--file` also accepts named pipes (FIFOs), which are read as a stream
+  until the writer closes them, like `stdin`. A pipe has no size, and it
+  can't be replaced, so pipes can't be used in-place:
+
+      {h}${rt} mkfifo notes
+      {h}${rt} {bin} encrypt -f notes -o notes.enc &
+      {h}${rt} cat notes.txt > notes
+
+  To check what would happen before actually doing it, add `--dry-run`.
+  Nothing is read, and nothing is written:
+
+      {h}${rt} {bin} encrypt -f cat.gif --in-place --dry-run
+      command:   encrypt
+      algorithm: ChaCha20-Poly1305
+      key:       ${key_env_var}
+      input:     'cat.gif'
+      encoding:  base64
+      output:    'cat.gif' (in-place, through 'cat.1700000000000000.jolokia')
+
+  To identify what an encrypted file is, without decrypting all of it,
+  peek at the first bytes. Text is shown as-is, anything else is shown
+  as a hex dump:
+
+      {h}${rt} {bin} decrypt -r -f cat.gif.enc --peek 16
+      00000000: 47 49 46 38 39 61 f4 01 f4 01 f7 00 00 00 00 00  GIF89a..........
+
+  To get a slice of a large file back, decrypt a byte range (`start:end`,
+  end excluded, either can be left out). ChaCha20-Poly1305 encrypts in
+  chunks of 4 KiB, and raw files are seeked into: only the chunks
+  covering the range are read and decrypted, the rest of the file is not
+  checked. Anything else is decrypted from the start, up to the end of
+  the range:
+
+      {h}${rt} {bin} decrypt -f backup.tar.enc --range 1048576:1049600 -o slice.bin
+
+Associated Data:
+  If you pass both a message and a file, the message becomes associated
+  data. Associated data is not encrypted, nor is it stored in the output,
+  but it is bound to the ciphertext: decryption only succeeds if the exact
+  same associated data is provided.
+
+      {h}${rt} {bin} encrypt \"invoice-2024\" -f invoice.pdf -o invoice.enc
+      {h}${rt} {bin} decrypt \"invoice-2024\" -f invoice.enc -o invoice.pdf
+
+  This prevents ciphertexts from being swapped around unnoticed. ROT-n
+  does not support associated data.
+
+Expiry:
+  `--expire` embeds an expiry date in the ciphertext (e.g., for an invite
+  code valid for 48 hours). Units are `s`, `m`, `h`, `d`, and `w`.
+
+      {h}${rt} {bin} encrypt \"invite-code\" --expire 48h
+
+  After that date, `decrypt` warns that the message has expired, and
+  with `--strict`, it refuses to decrypt it altogether.
+
+  The date is not encrypted, but it is authenticated like associated
+  data (it cannot be changed). Expiry is advisory: it is up to the
+  recipient to honour it, there is no server to enforce it.
+
+Key Hints:
+  With several keys around, \"wrong key, or corrupted data\" does not
+  say much. `--id-hint` embeds a short fingerprint of the key in the
+  ciphertext, and decrypting with another key says so:
+
+      {h}${rt} {bin} encrypt -k key-2025.txt -f notes.txt -o notes.enc --id-hint
+      {h}${rt} {bin} decrypt -k key-2024.txt -f notes.enc
+      error: Wrong key.
+      The message was encrypted for key 3F9A-0C61, you provided 81D2-7BE4.
+
+  Fingerprints are salted, they change from one message to the next
+  (even with the same key), so they can't be used to tell which
+  messages share a key. Only compare them within the same message.
+  `inspect` shows the hint of a message.
+
+  Hints only work with ChaCha20-Poly1305 keys. Not with `--password`,
+  the fingerprint of a passphrase would let anyone check guesses
+  offline. `rekey` does not carry hints over.
+
+Key Rotation:
+  When a key must be replaced (e.g., it may have leaked, or policy
+  requires it), `rekey` decrypts with the old key, and encrypts with the
+  new one, in a single pass. The plaintext never touches the disk.
+
+      {h}${rt} {bin} rekey -k old.key --new-key new.key -f notes.enc -o notes.new.enc
+
+  Like with `encrypt`, files and whole directories can be rekeyed
+  in-place. Each file is rekeyed through a temporary file, and replaced
+  only if it succeeded. Files that fail are reported and left as they
+  were, and {bin} exits with code 3 (partial failure). Symbolic links
+  are not followed.
+
+      {h}${rt} {bin} rekey -k old.key --new-key new.key -f backups/ --in-place
+
+  `--new-algorithm` switches algorithms at the same time (with HPKE, the
+  old key is the private key, and the new one the public key). Raw,
+  base64, and armor stay as they were, unless `--raw`, `--base64`, or
+  `--armor` says otherwise, and expiry dates are kept. ROT-n can't be
+  rekeyed.
+
+Migration:
+  Old versions of the format may lack newer protections (e.g.,
+  ChaCha20-Poly1305 ciphertexts from before `CH20\\x02` don't detect
+  truncation at a chunk boundary), they are not decrypted unless you
+  add `--legacy`. `inspect` flags them as outdated, and `migrate`
+  re-encrypts them in the latest format, with the same key. It works
+  like `rekey`, files and directories can be migrated in-place, and
+  files already in the latest format are left untouched.
+
+      {h}${rt} {bin} decrypt -k my.key -f old.enc
+      fatal: The ciphertext is in an outdated format, it may lack newer protections.
+      Use 'migrate' to re-encrypt it, or '--legacy' to decrypt it anyway.
+      {h}${rt} {bin} decrypt -k my.key -f old.enc --legacy
+      {h}${rt} {bin} migrate -k my.key -f backups/ --in-place
+
+  HPKE ciphertexts are migrated with `rekey`, the public key being the
+  new key. Ciphertexts encrypted with `--password` can't be migrated
+  yet.
+
 Raw I/O:
   If you do not want base64 encoding, you can pass the `--raw` or `-r`
   flag. This makes sense for larger files for which you don't want the
@@ -401,8 +1462,181 @@ Raw I/O:
       hello, world
 
   Base64 is the simplest and safest option for most users. It makes it
-  easy to copy-paste and share ciphertext. Use `--raw` only if you know
-  what you're doing.
+  easy to copy-paste and share ciphertext.
+
+  Without either flag, ciphertext is base64, to files and pipes alike.
+  Frames are always base64 lines. On the way back, raw and base64 are
+  told apart from the first bytes, so `decrypt` and `inspect` don't need
+  to be told. `--base64` forces base64 (e.g., to decrypt a message that
+  happens to look raw), and whichever of `--raw`, `--base64`, and
+  `--armor` comes last wins. `--dry-run` shows the decision:
+
+      {h}${rt} {bin} encrypt -f notes.txt -o notes.enc --dry-run | grep encoding
+      encoding:  base64
+
+  For email, or anywhere long lines get wrapped, `--armor` wraps the
+  base64 in a block of text, between markers:
+
+      {h}${rt} {bin} encrypt --armor \"hello, world\"
+      -----BEGIN JOLOKIA MESSAGE-----
+      Algorithm: chacha20poly1305
+
+      Q0gyMAFIBYWJTT3WAAAAHMroR0jchUTRYRrUWBYTkBgggzO6Q931c+ZjskQAAAAA
+      -----END JOLOKIA MESSAGE-----
+
+  The armor is found and stripped on the way back, like raw is told
+  from base64. Anything after the end marker (e.g., an email signature)
+  is ignored.
+
+  To move a key or a short message to a phone, or to print it for an
+  offline backup, `--qr` shows it as a QR code (`keygen` and
+  `encrypt`). The code is drawn for dark terminals. With `-o`, a file
+  named `*.png` gets an image instead (keys are still only readable by
+  you):
+
+      {h}${rt} {bin} keygen --qr
+      {h}${rt} {bin} keygen --qr -o backup.png
+      {h}${rt} {bin} encrypt --qr \"meet at noon\"
+
+  A QR code holds at most 2953 bytes of base64, longer ciphertexts are
+  an error. Scan it, and decrypt the text as usual.
+
+  Some systems can't take base64 (e.g., DNS TXT records ignore case,
+  legacy tools expect hex). `--encoding hex` or `--encoding base32` is
+  used instead of base64, for both keys and ciphertexts, so the key
+  must be in that encoding too. Decoding ignores case, and base32
+  padding:
+
+      {h}${rt} {bin} keygen --encoding hex > hex.key
+      {h}${rt} {bin} encrypt --encoding hex -k hex.key \"hello, world\"
+      434832300205fb7449cc4ec400000015af429613988cc4e324cfcff62c7f9058922e...
+      {h}${rt} {bin} decrypt --encoding hex -k hex.key 434832300205fb7449cc4ec4...
+      hello, world
+
+  The encoding is text, it can't be combined with `--raw`, `--armor`,
+  `--framed`, or `--qr`. age keys are always Bech32.
+
+  If the output is piped into a command that exits early, jolokia stops
+  reading right away, prints nothing, and exits with code 141 (like a
+  command killed by `SIGPIPE`). This is not a decryption failure:
+
+      {h}${rt} {bin} decrypt -r -f movie.enc | head -c 1024 > start.bin
+
+Framing:
+  To connect a long-lived producer and consumer through a single pipe,
+  use `--framed`. Each line of input is encrypted as an independent
+  message, and written out as its own frame (a line of base64, or a
+  length-prefixed frame with `--raw`). On the other end, each frame is
+  decrypted back into a line as soon as it arrives.
+
+      {h}${rt} mkfifo messages
+      {h}${rt} producer | {bin} encrypt --framed -r > messages
+      {h}${rt} {bin} decrypt --framed -r -f messages | consumer
+
+  By default, decryption stops at the first frame that fails to decrypt.
+  With `--continue-on-error`, bad frames are reported and skipped
+  instead, and {bin} exits with code 3 (partial failure) at the end.
+  This is not allowed in-place, as the frames that failed would be lost.
+
+Filters:
+  To transform data on the way in or out, without an extra pipeline,
+  `--pre-filter` runs the input through a shell command before
+  encrypting, and `--post-filter` runs the output through a shell
+  command after decrypting. If the command fails, so does {package}.
+
+      {h}${rt} {bin} encrypt -r -f notes.txt --pre-filter gzip -o notes.gz.enc
+      {h}${rt} {bin} decrypt -r -f notes.gz.enc --post-filter gunzip
+
+Plugins:
+  Like `git` and `cargo`, {package} can be extended with external
+  subcommands. `{bin} foo` runs `{bin}-foo` if it is found on the
+  `PATH`, with the remaining arguments. Built-in commands always take
+  precedence.
+
+  Plugins get the path to the {package} executable in the `{exe_env_var}`
+  environment variable, to call back into it (e.g., to encrypt with the
+  user's key, which is passed through the environment as usual).
+
+      {h}${rt} {bin} kms fetch my-key    # Runs `{bin}-kms fetch my-key`.
+
+Inspect:
+  When decryption fails, it is either the wrong key, or corrupted data.
+  `inspect` reads what is stored in the clear (no key needed), which
+  helps telling the two apart:
+
+      {h}${rt} {bin} inspect Q0gyMAGnAk2xt/+cAAAAHYUv/WBO+VxMGHodIL0Qzjbtnv/LPpQd3CCcYW0kAAAAAA
+
+  It takes a message like `decrypt` does (argument, `-f`, or `stdin`,
+  with `--raw` for binary). If the structure is intact, the key is the
+  likely culprit. ROT-n has no metadata.
+
+Check:
+  `check` goes one step further, with the key: it decrypts the whole
+  ciphertext, checking every authentication tag along the way (and the
+  end of streams, so truncation is caught too), but the plaintext is
+  thrown away, nothing is written. This is for checking backups for bit
+  rot:
+
+      {h}${rt} {bin} check -k key.txt -f backups/cat.gif.enc
+      The ciphertext is intact.
+
+  If anything is off, `check` fails (exit code 4, or 5 if the
+  ciphertext is cut short). This is not `verify`, which checks
+  signatures (see below).
+
+Signatures:
+  When the data isn't secret, but must not be tampered with (e.g.,
+  files you distribute), sign it instead. Signatures are Ed25519, with
+  their own keys:
+
+      {h}${rt} {bin} sign --keygen
+      {h}${rt} {bin} sign -k <PRIVATE> -f release.tar.gz > release.tar.gz.sig
+      {h}${rt} {bin} verify -k <PUBLIC> --signature release.tar.gz.sig -f release.tar.gz
+
+  Signatures are detached, base64, and `--signature` takes either the
+  signature or a file containing it. The key is required, signing never
+  falls back to the cipher key or the default key. If the data was
+  modified, `verify` fails (exit code 4).
+
+Warnings:
+  Warnings have an ID, and can be silenced individually, either with
+  `--no-warn` or through the environment. This is meant for automation
+  that knowingly does something {package} would warn about (e.g., using
+  the default key in a test environment).
+
+  {u}ID{rt}      {u}Name{rt}                  {u}Environment Variable{rt}
+  W001    default-key           {w001_env_var}
+  W002    insecure-algorithm    {w002_env_var}
+  W003    expired               {w003_env_var}
+  W004    shred                 {w004_env_var}
+
+      {h}${rt} {bin} encrypt \"hello\" --no-warn W001
+      {h}${rt} export {no_warn_env_var}=default-key,W002
+
+  `-q` silences all warnings (and the progress bar), only errors are
+  shown. `-v` goes the other way, and tells where the key came from,
+  which algorithm is used, where in-place output is written first, and
+  how many bytes went in and out. When decryption fails with the wrong
+  key, this is the first thing to check:
+
+      {h}${rt} {bin} decrypt -v -f notes.enc
+
+Exit Codes:
+  Scripts can tell failures apart without parsing error messages:
+
+  {u}Code{rt}    {u}Meaning{rt}
+  0       Success
+  1       Any other failure
+  2       Bad usage (invalid arguments)
+  3       Partial failure (some frames or files were skipped)
+  4       Decryption failed (wrong key, or corrupted data)
+  5       I/O error (missing file, ciphertext cut short, ...)
+  6       Unsupported algorithm (e.g., the wrong `-a`), or old format
+  7       Invalid key (malformed, or for another algorithm)
+  129     Hung up (`SIGHUP`)
+  130     Interrupted (`SIGINT`, Ctrl-C)
+  141     Broken pipe
+  143     Terminated (`SIGTERM`)
 
 Compression:
   BYOC. {package} does not provide built-in compression, but you can
@@ -420,17 +1654,27 @@ Compression:
   It makes sense to combine compression with `--raw` to get the smallest
   file size possible.
 ",
-        help = short_help_message(),
+        help = cmd::help::short_help_message(),
         bin = env!("CARGO_BIN_NAME"),
         package = env!("CARGO_PKG_NAME"),
         key_env_var = cli::KEY_ENV_VAR,
+        password_env_var = cli::PASSWORD_ENV_VAR,
+        exe_env_var = cmd::plugin::EXE_ENV_VAR,
+        no_warn_env_var = cmd::warning::NO_WARN_ENV_VAR,
+        w001_env_var = Warning::DefaultKey.env_var(),
+        w002_env_var = Warning::InsecureAlgorithm.env_var(),
+        w003_env_var = Warning::Expired.env_var(),
+        w004_env_var = Warning::Shred.env_var(),
+        eof_keys = EOF_KEYS,
         warning = ui::Color::warning("warning"),
         caution = ui::Color::error("caution"),
         h = ui::Color::maybe_color(ui::color::HIGHLIGHT),
         b = ui::Color::maybe_color(ui::color::BOLD),
         u = ui::Color::maybe_color(ui::color::UNDERLINE),
         rt = ui::Color::maybe_color(ui::color::RESET),
-    ));
+        ),
+        no_pager,
+    );
 }
 
 fn version() {
@@ -0,0 +1,107 @@
+//! Progress of long streaming operations.
+//!
+//! Ciphers read their input as they go, so the number of bytes read is
+//! a measure of progress. [`ProgressReader`] wraps the input of any
+//! streaming method (e.g., [`Cipher::encrypt_stream()`]), and reports
+//! it to a callback:
+//!
+//! ```
+//! use jolokia::cipher::ChaCha20Poly1305;
+//! use jolokia::progress::ProgressReader;
+//! use jolokia::traits::Cipher;
+//!
+//! let key = [0u8; 32];
+//! let plaintext = vec![0u8; 10_000];
+//! let mut progress = Vec::new();
+//!
+//! let mut reader = ProgressReader::new(plaintext.as_slice(), |bytes| progress.push(bytes));
+//! ChaCha20Poly1305.encrypt_stream(&key, &mut reader, &mut Vec::new())?;
+//!
+//! assert_eq!(progress.last(), Some(&10_000));
+//! # Ok::<(), jolokia::Error>(())
+//! ```
+//!
+//! The callback is called on every read, which can be often. Anything
+//! expensive (like drawing a progress bar) should be throttled.
+//!
+//! [`Cipher::encrypt_stream()`]: crate::traits::Cipher::encrypt_stream
+
+use std::io::{self, Read};
+
+/// A reader that reports the total of bytes read through it.
+pub struct ProgressReader<R: Read, F: FnMut(u64)> {
+    inner: R,
+    bytes: u64,
+    on_progress: F,
+}
+
+impl<R: Read, F: FnMut(u64)> ProgressReader<R, F> {
+    /// Call `on_progress` with the bytes read so far, after each read.
+    pub fn new(inner: R, on_progress: F) -> Self {
+        Self {
+            inner,
+            bytes: 0,
+            on_progress,
+        }
+    }
+
+    /// Bytes read so far.
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Hand back the reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, F: FnMut(u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.bytes += n as u64;
+            (self.on_progress)(self.bytes);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cipher::ChaCha20Poly1305;
+    use crate::traits::Cipher;
+
+    #[test]
+    fn progress_reader_reports_running_total() {
+        let mut progress = Vec::new();
+        let mut reader = ProgressReader::new(&b"hello, world"[..], |bytes| progress.push(bytes));
+
+        let mut buf = [0u8; 5];
+        reader.read_exact(&mut buf).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+
+        assert_eq!(reader.bytes_read(), 12);
+        assert_eq!(progress, [5, 10, 12]);
+    }
+
+    #[test]
+    fn progress_reader_through_cipher() {
+        let key = [0u8; 32];
+        let plaintext = vec![0u8; 3 * 4096 + 10];
+        let mut calls = 0;
+
+        let mut reader = ProgressReader::new(plaintext.as_slice(), |_| calls += 1);
+        let stats = ChaCha20Poly1305
+            .encrypt_stream(&key, &mut reader, &mut Vec::new())
+            .unwrap();
+
+        assert_eq!(reader.bytes_read(), stats.bytes_in);
+        assert!(calls >= 4);
+    }
+}
@@ -0,0 +1,92 @@
+//! One-call encryption, for when all you need is a sealed blob.
+//!
+//! [`seal()`] encrypts with ChaCha20-Poly1305, and armors the result in
+//! base64 (the same output as `jolokia encrypt`). [`open()`] reverses
+//! it. This is the stable, high-level entry point of the library, the
+//! [`Cipher`] trait is there for anything more specific (streaming,
+//! associated data, other algorithms).
+//!
+//! ```
+//! // Any 32 random bytes (e.g., a decoded `jolokia keygen` key).
+//! let key = [42u8; 32];
+//!
+//! let sealed = jolokia::seal(&key, b"hello, world").unwrap();
+//! let opened = jolokia::open(&key, &sealed).unwrap();
+//!
+//! assert_eq!(opened, b"hello, world");
+//! ```
+//!
+//! Like the CLI, this does not compress. Compress before sealing if
+//! needed (and mind that compressing secrets next to attacker-chosen
+//! data can leak information through the size).
+
+use crate::cipher::ChaCha20Poly1305;
+use crate::traits::{self, Base64Decode, Base64Encode, Cipher};
+
+/// Encrypt `plaintext` with a 32-byte `key`, and armor it in base64.
+///
+/// # Errors
+///
+/// Errors if the key is not 32 bytes long, or if encryption fails.
+pub fn seal(key: &[u8], plaintext: &[u8]) -> traits::Result<String> {
+    Ok(ChaCha20Poly1305.encrypt(key, plaintext)?.base64_encode())
+}
+
+/// Decrypt what [`seal()`] produced.
+///
+/// Surrounding whitespace (e.g., a trailing newline) is ignored.
+///
+/// # Errors
+///
+/// Errors if `sealed` is not valid base64, if the key is wrong, or if
+/// the data was tampered with.
+pub fn open(key: &[u8], sealed: &str) -> traits::Result<Vec<u8>> {
+    ChaCha20Poly1305.decrypt(key, &sealed.trim().base64_decode()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::traits::Error;
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    #[test]
+    fn seal_open_round_trip() {
+        let sealed = seal(&KEY, b"hello, world").unwrap();
+        assert_eq!(open(&KEY, &sealed).unwrap(), b"hello, world");
+    }
+
+    #[test]
+    fn seal_is_base64_chacha() {
+        let sealed = seal(&KEY, b"hello").unwrap();
+        // `CH20` header.
+        assert!(sealed.starts_with("Q0gyMA"));
+    }
+
+    #[test]
+    fn open_ignores_surrounding_whitespace() {
+        let sealed = format!("  {}\n", seal(&KEY, b"hello").unwrap());
+        assert_eq!(open(&KEY, &sealed).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn open_wrong_key_is_error() {
+        let sealed = seal(&KEY, b"hello").unwrap();
+        assert_eq!(open(&[8u8; 32], &sealed).unwrap_err(), Error::Decrypt);
+    }
+
+    #[test]
+    fn open_invalid_base64_is_error() {
+        assert!(matches!(
+            open(&KEY, "not base64!"),
+            Err(Error::Base64Decode(_))
+        ));
+    }
+
+    #[test]
+    fn seal_wrong_key_size_is_error() {
+        assert!(seal(&[0u8; 16], b"hello").is_err());
+    }
+}
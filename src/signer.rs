@@ -0,0 +1,19 @@
+pub mod ed25519;
+
+pub use ed25519::Ed25519;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use static_assertions::assert_impl_all;
+
+    use super::*;
+
+    use crate::traits::Signer;
+
+    assert_impl_all!(Ed25519: Signer, Send, Sync);
+
+    assert_impl_all!(Box<dyn Signer>: Send, Sync);
+    assert_impl_all!(Arc<dyn Signer>: Send, Sync);
+}
@@ -0,0 +1,214 @@
+//! Ed25519 signatures.
+//!
+//! Data is signed with Ed25519ph (RFC 8032, section 5.1): it is hashed
+//! with SHA-512 first, and the hash is signed. This makes it possible to
+//! sign files of any size as a stream, without holding them in memory.
+//!
+//! # Signature Format
+//!
+//! ```text
+//! [ header (5) ]       b"ED25\x01", algorithm ID and version
+//! [ signature (64) ]
+//! ```
+//!
+//! Keys are 32 bytes: the private key is the seed the signing key is
+//! derived from, the public key is the verifying key.
+
+use std::io::Read;
+
+use ed25519_dalek::{Digest, Sha512, Signature, SigningKey, VerifyingKey};
+use secrecy::{SecretSlice, zeroize::Zeroizing};
+
+use crate::traits::{self, Error, GeneratedKey, KeygenOptions, Signer};
+use crate::{entropy, wire};
+
+// Contains algorithm name (4-bytes) and version (1-byte).
+const HEADER: &[u8; 5] = b"ED25\x01";
+
+// Binds signatures to jolokia, a signature made for another protocol
+// (with the same key) does not verify here, and vice versa.
+const CONTEXT: &[u8] = b"jolokia-ed25519ph-v1";
+
+const KEY_LEN: usize = 32;
+
+pub struct Ed25519;
+
+impl Signer for Ed25519 {
+    fn algorithm_id(&self) -> &'static str {
+        "ed25519"
+    }
+
+    /// Generate an Ed25519 32-byte (256-bit) keypair.
+    fn generate_key(&self, options: &KeygenOptions) -> traits::Result<GeneratedKey> {
+        options.expect_fixed_size(256)?;
+        let seed = Zeroizing::new(entropy::generate::<KEY_LEN>()?);
+        let signing_key = SigningKey::from_bytes(&seed);
+        Ok(GeneratedKey::Asymmetric {
+            public: SecretSlice::from(signing_key.verifying_key().to_bytes().to_vec()),
+            private: SecretSlice::from(seed.to_vec()),
+        })
+    }
+
+    fn sign_stream(&self, private_key: &[u8], reader: &mut dyn Read) -> traits::Result<Vec<u8>> {
+        let seed: Zeroizing<[u8; KEY_LEN]> =
+            Zeroizing::new(private_key.try_into().map_err(|_| Error::Key)?);
+        let signing_key = SigningKey::from_bytes(&seed);
+
+        let signature = signing_key
+            .sign_prehashed(hash(reader)?, Some(CONTEXT))
+            .map_err(|_| Error::Key)?;
+
+        Ok([HEADER.as_slice(), &signature.to_bytes()].concat())
+    }
+
+    fn verify_stream(
+        &self,
+        public_key: &[u8],
+        reader: &mut dyn Read,
+        signature: &[u8],
+    ) -> traits::Result<()> {
+        let public_key: &[u8; KEY_LEN] = public_key.try_into().map_err(|_| Error::Key)?;
+        let verifying_key = VerifyingKey::from_bytes(public_key).map_err(|_| Error::Key)?;
+
+        let Some(signature) = signature.strip_prefix(HEADER) else {
+            return Err(Error::Algorithm);
+        };
+        let signature = Signature::from_slice(signature).map_err(|_| Error::Signature)?;
+
+        verifying_key
+            .verify_prehashed_strict(hash(reader)?, Some(CONTEXT), &signature)
+            .map_err(|_| Error::Signature)
+    }
+}
+
+fn hash(reader: &mut dyn Read) -> traits::Result<Sha512> {
+    let mut hasher = Sha512::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = wire::read_up_to(reader, &mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher)
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+
+    fn keypair() -> (Vec<u8>, Vec<u8>) {
+        let key = Ed25519.generate_key(&KeygenOptions::default()).unwrap();
+        (
+            key.get_asymmetric_public().expose_secret().to_vec(),
+            key.get_asymmetric_private().expose_secret().to_vec(),
+        )
+    }
+
+    #[test]
+    fn ed25519_sign_verify_roundtrip() {
+        let (public, private) = keypair();
+
+        let signature = Ed25519.sign(&private, b"hello, world").unwrap();
+
+        Ed25519
+            .verify(&public, b"hello, world", &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn ed25519_signature_format() {
+        let (_, private) = keypair();
+
+        let signature = Ed25519.sign(&private, b"hello").unwrap();
+
+        assert_eq!(signature.len(), 5 + 64);
+        assert_eq!(&signature[..5], HEADER);
+    }
+
+    #[test]
+    fn ed25519_signature_is_deterministic() {
+        let (_, private) = keypair();
+
+        let a = Ed25519.sign(&private, b"hello").unwrap();
+        let b = Ed25519.sign(&private, b"hello").unwrap();
+
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn ed25519_modified_data_is_error() {
+        let (public, private) = keypair();
+
+        let signature = Ed25519.sign(&private, b"hello").unwrap();
+        let err = Ed25519.verify(&public, b"hellp", &signature).unwrap_err();
+
+        assert_eq!(err, Error::Signature);
+    }
+
+    #[test]
+    fn ed25519_modified_signature_is_error() {
+        let (public, private) = keypair();
+
+        let mut signature = Ed25519.sign(&private, b"hello").unwrap();
+        signature[10] ^= 1;
+        let err = Ed25519.verify(&public, b"hello", &signature).unwrap_err();
+
+        assert_eq!(err, Error::Signature);
+    }
+
+    #[test]
+    fn ed25519_other_key_is_error() {
+        let (_, private) = keypair();
+        let (other_public, _) = keypair();
+
+        let signature = Ed25519.sign(&private, b"hello").unwrap();
+        let err = Ed25519
+            .verify(&other_public, b"hello", &signature)
+            .unwrap_err();
+
+        assert_eq!(err, Error::Signature);
+    }
+
+    #[test]
+    fn ed25519_other_algorithm_is_error() {
+        let (public, _) = keypair();
+
+        let err = Ed25519
+            .verify(
+                &public,
+                b"hello",
+                &[b"HPKE\x01".as_slice(), &[0u8; 64]].concat(),
+            )
+            .unwrap_err();
+
+        assert_eq!(err, Error::Algorithm);
+    }
+
+    #[test]
+    fn ed25519_wrong_key_size_is_error() {
+        assert_eq!(Ed25519.sign(&[0u8; 16], b"hello").unwrap_err(), Error::Key);
+        assert_eq!(
+            Ed25519.verify(&[0u8; 16], b"hello", &[]).unwrap_err(),
+            Error::Key
+        );
+    }
+
+    #[test]
+    fn ed25519_streaming_matches_one_shot() {
+        let (public, private) = keypair();
+        let message = b"hello, world!".repeat(1000);
+
+        let signature = Ed25519
+            .sign_stream(&private, &mut message.as_slice())
+            .unwrap();
+
+        assert_eq!(signature, Ed25519.sign(&private, &message).unwrap());
+        Ed25519
+            .verify_stream(&public, &mut message.as_slice(), &signature)
+            .unwrap();
+    }
+}
@@ -0,0 +1,482 @@
+//! Encryption and decryption as plain `Write` and `Read` adapters.
+//!
+//! [`Cipher`] streams pull plaintext from a reader and push ciphertext
+//! into a writer, all in one call. [`EncryptWriter`] and
+//! [`DecryptReader`] turn that around, so jolokia fits into existing
+//! pipelines (e.g., [`io::copy()`]):
+//!
+//! ```
+//! use std::io::{self, Read};
+//!
+//! use jolokia::cipher::ChaCha20Poly1305;
+//! use jolokia::stream::{DecryptReader, EncryptWriter};
+//!
+//! // Any 32 random bytes (e.g., a decoded `jolokia keygen` key).
+//! let key = [42u8; 32];
+//!
+//! let mut writer = EncryptWriter::new(Box::new(ChaCha20Poly1305), &key, Vec::new());
+//! io::copy(&mut &b"hello, world"[..], &mut writer).unwrap();
+//! let ciphertext = writer.finish().unwrap();
+//!
+//! let ciphertext = io::Cursor::new(ciphertext);
+//! let mut reader = DecryptReader::new(Box::new(ChaCha20Poly1305), &key, ciphertext);
+//! let mut plaintext = Vec::new();
+//! reader.read_to_end(&mut plaintext).unwrap();
+//!
+//! assert_eq!(plaintext, b"hello, world");
+//! ```
+//!
+//! The cipher runs on a thread of its own, joined to the caller by an
+//! in-memory [`pipe()`]. This is why the cipher, the key, and the inner
+//! reader or writer are owned (`'static`).
+//!
+//! An [`EncryptWriter`] must be [finished](EncryptWriter::finish()).
+//! Dropping it before that leaves the ciphertext truncated, and it will
+//! not decrypt. A [`DecryptReader`] only returns plaintext that has been
+//! authenticated, and it fails at the end if the ciphertext was cut
+//! short.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use secrecy::zeroize::Zeroizing;
+
+use crate::traits::{self, Cipher, Error};
+
+/// Chunks of plaintext in flight between the two ends, at most.
+const PIPE_CAPACITY: usize = 16;
+
+/// Encrypts what is written to it, into the inner writer.
+pub struct EncryptWriter<W: Write + Send + 'static> {
+    pipe: Option<PipeWriter>,
+    encryption: Option<JoinHandle<(traits::Result<()>, W)>>,
+}
+
+impl<W: Write + Send + 'static> EncryptWriter<W> {
+    /// Encrypt into `output`, with `key` (raw bytes).
+    #[must_use]
+    pub fn new(cipher: Box<dyn Cipher>, key: &[u8], output: W) -> Self {
+        Self::with_aad(cipher, key, &[], output)
+    }
+
+    /// Like [`EncryptWriter::new()`], with associated data.
+    #[must_use]
+    pub fn with_aad(cipher: Box<dyn Cipher>, key: &[u8], aad: &[u8], mut output: W) -> Self {
+        let key = Zeroizing::new(key.to_vec());
+        let aad = aad.to_vec();
+        let (writer, mut reader) = pipe();
+
+        let encryption = thread::spawn(move || {
+            let res = cipher
+                .encrypt_stream_with_aad(&key, &aad, &mut reader, &mut output)
+                .map(|_| ());
+            (res, output)
+        });
+
+        Self {
+            pipe: Some(writer),
+            encryption: Some(encryption),
+        }
+    }
+
+    /// Write the end of the ciphertext, and hand back the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// Errors if encryption failed, or if writing to the inner writer
+    /// failed.
+    pub fn finish(mut self) -> traits::Result<W> {
+        if let Some(pipe) = self.pipe.take() {
+            pipe.close();
+        }
+        let (res, mut output) = self.join()?;
+        res?;
+        output.flush().map_err(|e| Error::Write(e.to_string()))?;
+        Ok(output)
+    }
+
+    fn join(&mut self) -> traits::Result<(traits::Result<()>, W)> {
+        // Already joined by a failed `write()`, the error was reported.
+        let Some(encryption) = self.encryption.take() else {
+            return Err(Error::Encrypt);
+        };
+        encryption
+            .join()
+            .map_err(|_| Error::Other("Encryption thread panicked".to_string()))
+    }
+}
+
+impl<W: Write + Send + 'static> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(ref mut pipe) = self.pipe else {
+            return Err(io::Error::other(Error::Encrypt));
+        };
+        if let Err(e) = pipe.write(buf) {
+            // The other end is gone, encryption failed. Tell why.
+            self.pipe = None;
+            return Err(match self.join() {
+                Ok((Err(reason), _)) | Err(reason) => io::Error::other(reason),
+                Ok((Ok(()), _)) => e,
+            });
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write + Send + 'static> Drop for EncryptWriter<W> {
+    fn drop(&mut self) {
+        // Not finished, the pipe is interrupted, and encryption stops
+        // before the end of the ciphertext.
+        self.pipe = None;
+        if self.encryption.is_some() {
+            _ = self.join();
+        }
+    }
+}
+
+/// Decrypts what is read from the inner reader.
+pub struct DecryptReader<R: Read + Send + 'static> {
+    pipe: PipeReader,
+    decryption: Option<JoinHandle<(traits::Result<()>, R)>>,
+}
+
+impl<R: Read + Send + 'static> DecryptReader<R> {
+    /// Decrypt from `input`, with `key` (raw bytes).
+    #[must_use]
+    pub fn new(cipher: Box<dyn Cipher>, key: &[u8], input: R) -> Self {
+        Self::with_aad(cipher, key, &[], input)
+    }
+
+    /// Like [`DecryptReader::new()`], with associated data.
+    #[must_use]
+    pub fn with_aad(cipher: Box<dyn Cipher>, key: &[u8], aad: &[u8], mut input: R) -> Self {
+        let key = Zeroizing::new(key.to_vec());
+        let aad = aad.to_vec();
+        let (mut writer, reader) = pipe();
+
+        let decryption = thread::spawn(move || {
+            let res = cipher
+                .decrypt_stream_with_aad(&key, &aad, &mut input, &mut writer)
+                .map(|_| writer.close());
+            (res, input)
+        });
+
+        Self {
+            pipe: reader,
+            decryption: Some(decryption),
+        }
+    }
+}
+
+impl<R: Read + Send + 'static> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.pipe.read(buf) {
+            Err(e) if self.pipe.is_interrupted() => {
+                // The other end is gone, decryption failed. Tell why.
+                let reason = match self.decryption.take().map(JoinHandle::join) {
+                    Some(Ok((Err(reason), _))) => reason,
+                    Some(Err(_)) => Error::Other("Decryption thread panicked".to_string()),
+                    Some(Ok((Ok(()), _))) | None => return Err(e),
+                };
+                Err(io::Error::other(reason))
+            }
+            res => res,
+        }
+    }
+}
+
+/// An in-memory, bounded pipe, for plaintext.
+///
+/// The plaintext only ever exists in (zeroized) buffers.
+#[must_use]
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let (sender, receiver) = mpsc::sync_channel(PIPE_CAPACITY);
+    let writer = PipeWriter { sender };
+    let reader = PipeReader {
+        receiver,
+        chunk: Zeroizing::new(Vec::new()),
+        position: 0,
+        closed: false,
+        interrupted: false,
+    };
+    (writer, reader)
+}
+
+/// Writing end of a [`pipe()`].
+///
+/// The end of the data must be marked with [`PipeWriter::close()`].
+/// Dropping the writer without closing it (e.g., decryption failed)
+/// makes the reader fail, instead of seeing the truncated data as
+/// complete.
+pub struct PipeWriter {
+    /// `None` marks the end of the data.
+    sender: SyncSender<Option<Zeroizing<Vec<u8>>>>,
+}
+
+impl PipeWriter {
+    pub fn close(self) {
+        // If the reader is gone, it has already failed.
+        _ = self.sender.send(None);
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.sender
+            .send(Some(Zeroizing::new(buf.to_vec())))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reading end of a [`pipe()`].
+pub struct PipeReader {
+    receiver: Receiver<Option<Zeroizing<Vec<u8>>>>,
+    chunk: Zeroizing<Vec<u8>>,
+    position: usize,
+    closed: bool,
+    interrupted: bool,
+}
+
+impl PipeReader {
+    /// The writer was dropped before closing the pipe.
+    #[must_use]
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.position == self.chunk.len() {
+            if self.closed {
+                return Ok(0);
+            }
+            match self.receiver.recv() {
+                Ok(Some(chunk)) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Ok(None) => self.closed = true,
+                Err(_) => {
+                    self.interrupted = true;
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "The writing end stopped early",
+                    ));
+                }
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    #[cfg(feature = "hpke")]
+    use secrecy::ExposeSecret;
+
+    use crate::cipher::ChaCha20Poly1305;
+    #[cfg(feature = "hpke")]
+    use crate::cipher::Hpke;
+    #[cfg(feature = "hpke")]
+    use crate::traits::KeygenOptions;
+
+    const KEY: &[u8; 32] = &[42u8; 32];
+
+    /// Output that outlives the writer it is moved into.
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn encrypt(plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        let mut writer = EncryptWriter::with_aad(Box::new(ChaCha20Poly1305), KEY, aad, Vec::new());
+        io::copy(&mut &plaintext[..], &mut writer).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn decrypt(key: &[u8], ciphertext: Vec<u8>, aad: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = DecryptReader::with_aad(
+            Box::new(ChaCha20Poly1305),
+            key,
+            aad,
+            io::Cursor::new(ciphertext),
+        );
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn round_trip_regular() {
+        // Several chunks.
+        let plaintext = "Lorem ipsum dolor sit amet. ".repeat(10_000);
+
+        let ciphertext = encrypt(plaintext.as_bytes(), b"");
+
+        assert_eq!(
+            ChaCha20Poly1305.decrypt(KEY, &ciphertext).unwrap(),
+            plaintext.as_bytes()
+        );
+        assert_eq!(decrypt(KEY, ciphertext, b"").unwrap(), plaintext.as_bytes());
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let ciphertext = encrypt(b"", b"");
+
+        assert_eq!(decrypt(KEY, ciphertext, b"").unwrap(), b"");
+    }
+
+    #[test]
+    fn round_trip_aad() {
+        let ciphertext = encrypt(b"hello, world", b"header");
+
+        assert!(decrypt(KEY, ciphertext.clone(), b"other").is_err());
+        assert_eq!(
+            decrypt(KEY, ciphertext, b"header").unwrap(),
+            b"hello, world"
+        );
+    }
+
+    #[cfg(feature = "hpke")]
+    #[test]
+    fn round_trip_asymmetric() {
+        let key = Hpke.generate_key(&KeygenOptions::default()).unwrap();
+        let public_key = key.get_asymmetric_public().expose_secret().to_vec();
+        let private_key = key.get_asymmetric_private().expose_secret().to_vec();
+
+        let mut writer = EncryptWriter::new(Box::new(Hpke), &public_key, Vec::new());
+        writer.write_all(b"hello, world").unwrap();
+        let ciphertext = writer.finish().unwrap();
+
+        let mut reader =
+            DecryptReader::new(Box::new(Hpke), &private_key, io::Cursor::new(ciphertext));
+        let mut plaintext = String::new();
+        reader.read_to_string(&mut plaintext).unwrap();
+
+        assert_eq!(plaintext, "hello, world");
+    }
+
+    #[test]
+    fn encrypt_not_finished_is_truncated() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+
+        let mut writer = EncryptWriter::new(
+            Box::new(ChaCha20Poly1305),
+            KEY,
+            SharedOutput(Arc::clone(&output)),
+        );
+        writer.write_all(&vec![0u8; 200_000]).unwrap();
+        drop(writer);
+
+        let ciphertext = output.lock().unwrap().clone();
+        assert!(ChaCha20Poly1305.decrypt(KEY, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn encrypt_wrong_key_is_error() {
+        let mut writer = EncryptWriter::new(Box::new(ChaCha20Poly1305), b"short", Vec::new());
+
+        // The cipher may fail before or after the first write.
+        let written = writer.write_all(&vec![0u8; 200_000]);
+        let finished = writer.finish();
+
+        assert!(written.is_err() || finished.is_err());
+    }
+
+    #[test]
+    fn encrypt_finish_after_failed_write_is_error() {
+        let mut writer = EncryptWriter::new(Box::new(ChaCha20Poly1305), b"short", Vec::new());
+
+        // Keep writing until the failure shows up.
+        while writer.write_all(&vec![0u8; 64 * 1024]).is_ok() {}
+
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn decrypt_wrong_key_is_error() {
+        let ciphertext = encrypt(b"hello, world", b"");
+
+        let err = decrypt(&[0u8; 32], ciphertext, b"").unwrap_err();
+
+        assert_eq!(err.to_string(), Error::Decrypt.to_string());
+    }
+
+    #[test]
+    fn decrypt_truncated_is_error() {
+        let mut ciphertext = encrypt(&vec![0u8; 200_000], b"");
+        ciphertext.truncate(ciphertext.len() / 2);
+
+        assert!(decrypt(KEY, ciphertext, b"").is_err());
+    }
+
+    #[test]
+    fn pipe_regular() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello, ").unwrap();
+        writer.write_all(b"world").unwrap();
+        writer.close();
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+
+        assert_eq!(data, b"hello, world");
+        assert!(!reader.is_interrupted());
+    }
+
+    #[test]
+    fn pipe_dropped_writer_is_error() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").unwrap();
+        drop(writer);
+
+        let mut data = Vec::new();
+        let err = reader.read_to_end(&mut data).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(data, b"hello");
+        assert!(reader.is_interrupted());
+    }
+
+    #[test]
+    fn pipe_dropped_reader_is_error() {
+        let (mut writer, reader) = pipe();
+        drop(reader);
+
+        let err = writer.write_all(b"hello").unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
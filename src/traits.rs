@@ -2,16 +2,36 @@ use std::fmt;
 use std::io::{self, Read, Write};
 
 use secrecy::SecretSlice;
+use secrecy::zeroize::{Zeroize, ZeroizeOnDrop};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Error {
     Encrypt,
     Decrypt,
     Algorithm,
+    /// The ciphertext is in an old version of the format, and reading
+    /// old versions is off (see [`crate::format::set_read_outdated()`]).
+    Outdated,
     Key,
+    /// The key does not match the hint of the message (see
+    /// [`crate::key_hint`]).
+    KeyHint {
+        expected: String,
+        provided: String,
+    },
+    AssociatedData,
+    Signature,
+    TooLarge(u64),
+    Parameter(String),
     Base64Decode(String),
+    /// Decoding another [`crate::encoding`] than base64.
+    Decode {
+        encoding: &'static str,
+        reason: String,
+    },
     Read(String),
     Write(String),
+    Entropy(String),
     Platform(String),
     Other(String),
 }
@@ -27,10 +47,33 @@ Could not decrypt input.
 You are likely using the wrong key, or the data is corrupted."
             ),
             Self::Algorithm => write!(f, "Incompatible cipher algorithm."),
+            Self::Outdated => write!(
+                f,
+                "The ciphertext is in an outdated format, it may lack newer protections."
+            ),
             Self::Key => write!(f, "The key is not compatible with the algorithm."),
+            Self::KeyHint { expected, provided } => write!(
+                f,
+                "\
+Wrong key.
+The message was encrypted for key {expected}, you provided {provided}."
+            ),
+            Self::AssociatedData => write!(f, "The algorithm does not support associated data."),
+            Self::Signature => write!(
+                f,
+                "\
+The signature is not valid.
+The data was modified, or it was not signed with this key."
+            ),
+            Self::TooLarge(limit) => {
+                write!(f, "Plaintext exceeds the maximum size ({limit} bytes).")
+            }
+            Self::Parameter(reason) => write!(f, "Invalid parameter: {reason}"),
             Self::Base64Decode(reason) => write!(f, "Could not decode base64: {reason}"),
+            Self::Decode { encoding, reason } => write!(f, "Could not decode {encoding}: {reason}"),
             Self::Read(reason) => write!(f, "Could not read from input: {reason}"),
             Self::Write(reason) => write!(f, "Could not write to output: {reason}"),
+            Self::Entropy(reason) => write!(f, "Could not get randomness: {reason}"),
             Self::Platform(reason) | Self::Other(reason) => write!(f, "{reason}"),
         }
     }
@@ -40,6 +83,40 @@ impl std::error::Error for Error {}
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Key generation parameters.
+///
+/// Algorithms with fixed parameters reject anything that doesn't match
+/// their defaults (see [`KeygenOptions::expect_fixed_size()`]).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeygenOptions {
+    /// Key size, in bits.
+    pub bits: Option<u32>,
+    /// Algorithm-specific `(name, value)` parameters.
+    pub params: Vec<(String, String)>,
+}
+
+impl KeygenOptions {
+    /// Ensure options are compatible with a fixed-size key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the requested size differs from `bits`, or if any
+    /// algorithm-specific parameter is given.
+    pub fn expect_fixed_size(&self, bits: u32) -> Result<()> {
+        if let Some(requested) = self.bits
+            && requested != bits
+        {
+            return Err(Error::Parameter(format!(
+                "Key size must be {bits} bits, not {requested}"
+            )));
+        }
+        if let Some((name, _)) = self.params.first() {
+            return Err(Error::Parameter(format!("Unknown parameter '{name}'")));
+        }
+        Ok(())
+    }
+}
+
 pub enum GeneratedKey {
     Symmetric(SecretSlice<u8>),
     Asymmetric {
@@ -90,10 +167,55 @@ impl GeneratedKey {
     }
 }
 
-pub trait Cipher {
+/// Keys are wiped on drop (they are [`SecretSlice`]s), or on demand.
+impl Zeroize for GeneratedKey {
+    fn zeroize(&mut self) {
+        match self {
+            Self::Symmetric(key) => key.zeroize(),
+            Self::Asymmetric { public, private } => {
+                public.zeroize();
+                private.zeroize();
+            }
+            Self::None => {}
+        }
+    }
+}
+
+impl ZeroizeOnDrop for GeneratedKey {}
+
+/// What a streaming operation went through.
+///
+/// Returned by the streaming methods of [`Cipher`], e.g., to print a
+/// summary, or to notice a suspiciously empty input.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StreamStats {
+    /// Bytes read from the input, headers included.
+    pub bytes_in: u64,
+    /// Bytes written to the output, headers included.
+    pub bytes_out: u64,
+    /// Number of encrypted chunks, `0` if the algorithm has none.
+    pub chunks: u64,
+}
+
+/// A cipher algorithm.
+///
+/// Implementations must be stateless: all the state needed for an
+/// operation lives in the arguments (key, input, output), never in the
+/// cipher itself. A single cipher can then be shared freely, which the
+/// `Send + Sync` bounds make explicit (e.g., `Arc<dyn Cipher>` can be
+/// used from multiple threads of a server).
+pub trait Cipher: Send + Sync {
+    /// Stable, lowercase identifier of the algorithm.
+    ///
+    /// Used to tag serialized keys (see [`crate::key`]).
+    fn algorithm_id(&self) -> &'static str;
+
     /// Generate cipher key.
-    #[must_use]
-    fn generate_key(&self) -> GeneratedKey;
+    ///
+    /// # Errors
+    ///
+    /// Errors if the options are not compatible with the algorithm.
+    fn generate_key(&self, options: &KeygenOptions) -> Result<GeneratedKey>;
 
     /// Encrypt plain bytes with key.
     ///
@@ -119,8 +241,28 @@ pub trait Cipher {
         Ok(decrypted)
     }
 
+    /// Decrypt ciphered bytes with key into a caller-provided buffer.
+    ///
+    /// Returns the size of the plaintext. Nothing gets allocated for
+    /// the plaintext, which makes memory usage predictable.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the plaintext does not fit in `output`, or in the same
+    /// cases as [`Cipher::decrypt()`].
+    fn decrypt_into(&self, key: &[u8], ciphertext: &[u8], output: &mut [u8]) -> Result<usize> {
+        let limit = output.len() as u64;
+        let mut writer = io::Cursor::new(output);
+        self.decrypt_stream_with_limit(key, &mut io::Cursor::new(ciphertext), &mut writer, limit)?;
+        // Bound by `output.len()`, it always fits in a `usize`.
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(writer.position() as usize)
+    }
+
     /// Encrypt stream of plain bytes with key.
     ///
+    /// Returns what went through, see [`StreamStats`].
+    ///
     /// # Errors
     ///
     /// Errors if encryption fails, or if read/write fails. Encryption
@@ -130,9 +272,11 @@ pub trait Cipher {
         key: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
-    ) -> Result<()>;
+    ) -> Result<StreamStats>;
 
-    /// Encrypt stream of plain bytes with key.
+    /// Decrypt stream of ciphered bytes with key.
+    ///
+    /// Returns what went through, see [`StreamStats`].
     ///
     /// # Errors
     ///
@@ -143,9 +287,236 @@ pub trait Cipher {
         key: &[u8],
         reader: &mut dyn Read,
         writer: &mut dyn Write,
+    ) -> Result<StreamStats>;
+
+    /// Encrypt stream of plain bytes with key and associated data.
+    ///
+    /// Associated data (AAD) is authenticated, but it is neither
+    /// encrypted nor included in the ciphertext. The exact same data
+    /// must be provided to decrypt.
+    ///
+    /// Empty associated data is the same as no associated data.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the algorithm does not support associated data, or in
+    /// the same cases as [`Cipher::encrypt_stream()`].
+    fn encrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> Result<StreamStats> {
+        if !aad.is_empty() {
+            return Err(Error::AssociatedData);
+        }
+        self.encrypt_stream(key, reader, writer)
+    }
+
+    /// Decrypt stream of ciphered bytes with key and associated data.
+    ///
+    /// See [`Cipher::encrypt_stream_with_aad()`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the algorithm does not support associated data, or in
+    /// the same cases as [`Cipher::decrypt_stream()`]. Associated data
+    /// mismatches are reported as decryption failures.
+    fn decrypt_stream_with_aad(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> Result<StreamStats> {
+        if !aad.is_empty() {
+            return Err(Error::AssociatedData);
+        }
+        self.decrypt_stream(key, reader, writer)
+    }
+
+    /// Encrypt stream of plain bytes, and return the ciphertext size.
+    ///
+    /// Ciphers only ever append to `writer` (they never seek), so the
+    /// ciphertext can be embedded anywhere in a larger container. The
+    /// returned size is what the container needs to record.
+    ///
+    /// On the other end, [`Cipher::decrypt_stream_with_aad()`] reads
+    /// exactly the ciphertext and nothing more, leaving `reader` right
+    /// after it.
+    ///
+    /// # Errors
+    ///
+    /// Errors in the same cases as [`Cipher::encrypt_stream_with_aad()`].
+    fn encrypt_stream_counted(
+        &self,
+        key: &[u8],
+        aad: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> Result<u64> {
+        let mut writer = CountingWriter::new(writer);
+        self.encrypt_stream_with_aad(key, aad, reader, &mut writer)?;
+        Ok(writer.count)
+    }
+
+    /// Decrypt stream of ciphered bytes with key, up to a maximum size.
+    ///
+    /// Protects against unbounded output. Decryption stops as soon as
+    /// the plaintext would exceed `max_plaintext_size` bytes; what was
+    /// written up to that point is left as-is, and must be discarded.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the plaintext exceeds `max_plaintext_size`, or in the
+    /// same cases as [`Cipher::decrypt_stream()`].
+    fn decrypt_stream_with_limit(
+        &self,
+        key: &[u8],
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        max_plaintext_size: u64,
+    ) -> Result<StreamStats> {
+        let mut writer = LimitWriter {
+            inner: writer,
+            remaining: max_plaintext_size,
+            exceeded: false,
+        };
+        match self.decrypt_stream(key, reader, &mut writer) {
+            Err(_) if writer.exceeded => Err(Error::TooLarge(max_plaintext_size)),
+            res => res,
+        }
+    }
+}
+
+/// A signature algorithm.
+///
+/// Signatures are detached: they are produced and checked separately
+/// from the data, which is left untouched. Like [`Cipher`],
+/// implementations must be stateless.
+pub trait Signer: Send + Sync {
+    /// Stable, lowercase identifier of the algorithm.
+    ///
+    /// Used to tag serialized keys (see [`crate::key`]).
+    fn algorithm_id(&self) -> &'static str;
+
+    /// Generate a signing (private) and verifying (public) keypair.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the options are not compatible with the algorithm.
+    fn generate_key(&self, options: &KeygenOptions) -> Result<GeneratedKey>;
+
+    /// Sign bytes with the private key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the key is invalid.
+    fn sign(&self, private_key: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+        self.sign_stream(private_key, &mut io::Cursor::new(message))
+    }
+
+    /// Verify the signature of bytes with the public key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the signature does not match, or if the key is invalid.
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+        self.verify_stream(public_key, &mut io::Cursor::new(message), signature)
+    }
+
+    /// Sign a stream of bytes with the private key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the key is invalid, or if read fails.
+    fn sign_stream(&self, private_key: &[u8], reader: &mut dyn Read) -> Result<Vec<u8>>;
+
+    /// Verify the signature of a stream of bytes with the public key.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the signature does not match ([`Error::Signature`]), if
+    /// the key is invalid, or if read fails.
+    fn verify_stream(
+        &self,
+        public_key: &[u8],
+        reader: &mut dyn Read,
+        signature: &[u8],
     ) -> Result<()>;
 }
 
+/// A reader that counts the bytes read through it.
+pub(crate) struct CountingReader<'a> {
+    inner: &'a mut dyn Read,
+    pub(crate) count: u64,
+}
+
+impl<'a> CountingReader<'a> {
+    pub(crate) fn new(inner: &'a mut dyn Read) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// A writer that counts the bytes written through it.
+pub(crate) struct CountingWriter<'a> {
+    inner: &'a mut dyn Write,
+    pub(crate) count: u64,
+}
+
+impl<'a> CountingWriter<'a> {
+    pub(crate) fn new(inner: &'a mut dyn Write) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A writer that refuses to write more than `remaining` bytes.
+///
+/// The write that would exceed the limit fails entirely, and is
+/// recorded, so the caller can tell it apart from other write errors.
+struct LimitWriter<'a> {
+    inner: &'a mut dyn Write,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl Write for LimitWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() as u64 > self.remaining {
+            self.exceeded = true;
+            return Err(io::Error::other("Plaintext exceeds the maximum size"));
+        }
+        let n = self.inner.write(buf)?;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 pub trait Base64Encode {
     /// Encode `self` in base64 string.
     #[must_use]
@@ -164,9 +535,38 @@ pub trait Base64Decode {
 #[cfg(test)]
 mod tests {
     use secrecy::ExposeSecret;
+    use secrecy::zeroize::Zeroizing;
+    use static_assertions::assert_impl_all;
 
     use super::*;
 
+    assert_impl_all!(GeneratedKey: Zeroize, ZeroizeOnDrop);
+    assert_impl_all!(SecretSlice<u8>: Zeroize, ZeroizeOnDrop);
+    assert_impl_all!(Zeroizing<Vec<u8>>: Zeroize, ZeroizeOnDrop);
+
+    #[test]
+    fn generated_key_zeroize() {
+        let mut key = GeneratedKey::Asymmetric {
+            public: SecretSlice::from(vec![1u8; 32]),
+            private: SecretSlice::from(vec![2u8; 32]),
+        };
+
+        key.zeroize();
+
+        assert!(
+            key.get_asymmetric_public()
+                .expose_secret()
+                .iter()
+                .all(|&b| b == 0)
+        );
+        assert!(
+            key.get_asymmetric_private()
+                .expose_secret()
+                .iter()
+                .all(|&b| b == 0)
+        );
+    }
+
     #[test]
     fn error_key_display() {
         assert_eq!(
@@ -175,6 +575,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn limit_writer_within_limit() {
+        let mut output = Vec::new();
+        let mut writer = LimitWriter {
+            inner: &mut output,
+            remaining: 5,
+            exceeded: false,
+        };
+
+        writer.write_all(b"hello").unwrap();
+
+        assert!(!writer.exceeded);
+        assert_eq!(output, b"hello");
+    }
+
+    #[test]
+    fn limit_writer_exceeds_limit() {
+        let mut output = Vec::new();
+        let mut writer = LimitWriter {
+            inner: &mut output,
+            remaining: 5,
+            exceeded: false,
+        };
+
+        writer.write_all(b"hel").unwrap();
+        assert!(writer.write_all(b"lo, world").is_err());
+
+        assert!(writer.exceeded);
+        assert_eq!(output, b"hel");
+    }
+
+    #[test]
+    fn keygen_options_fixed_size_default_is_ok() {
+        let options = KeygenOptions::default();
+
+        assert!(options.expect_fixed_size(256).is_ok());
+    }
+
+    #[test]
+    fn keygen_options_fixed_size_same_bits_is_ok() {
+        let options = KeygenOptions {
+            bits: Some(256),
+            ..Default::default()
+        };
+
+        assert!(options.expect_fixed_size(256).is_ok());
+    }
+
+    #[test]
+    fn keygen_options_fixed_size_other_bits_is_error() {
+        let options = KeygenOptions {
+            bits: Some(128),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options.expect_fixed_size(256).unwrap_err().to_string(),
+            "Invalid parameter: Key size must be 256 bits, not 128"
+        );
+    }
+
+    #[test]
+    fn keygen_options_fixed_size_params_is_error() {
+        let options = KeygenOptions {
+            params: vec![("memory".to_string(), "64".to_string())],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options.expect_fixed_size(256).unwrap_err().to_string(),
+            "Invalid parameter: Unknown parameter 'memory'"
+        );
+    }
+
     #[test]
     fn generated_key_get_symmetric() {
         let key = GeneratedKey::Symmetric(SecretSlice::from(vec![0, 1, 2, 3]));
@@ -0,0 +1,124 @@
+//! Helpers for reading from the wire.
+//!
+//! Lengths are encoded with fixed widths (`u16`, `u32`), but are used
+//! as `usize`, whose width depends on the platform. Rust guarantees
+//! `usize` is at least 16 bits, so `u16` lengths always fit (use
+//! `usize::from()`). `u32` lengths may not, and must go through here.
+//!
+//! Readers may return less than asked for (pipes and FIFOs do, as soon
+//! as _some_ data is available). A short read is not EOF, only `0` is.
+
+use std::io::{self, Read};
+
+use crate::traits::{self, Error};
+
+/// Like [`Read::read_exact()`], but a short read at EOF is fine.
+///
+/// Returns the number of bytes read, which is less than `buf.len()`
+/// only at EOF.
+///
+/// # Errors
+///
+/// Errors if reading fails.
+pub fn read_up_to(reader: &mut dyn Read, buf: &mut [u8]) -> traits::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(Error::Read(e.to_string())),
+        }
+    }
+    Ok(n)
+}
+
+/// Convert a `u32` length read from the wire into a `usize`.
+///
+/// # Errors
+///
+/// Errors if the length does not fit in the platform's `usize`.
+pub fn usize_from_u32(len: u32) -> traits::Result<usize> {
+    checked_usize(u64::from(len), usize::MAX as u64)
+}
+
+/// Platform-independent logic, `usize_max` stands in for `usize::MAX`.
+///
+/// This makes it possible to test the behaviour of narrower platforms
+/// (e.g., 16-bit) on any platform.
+fn checked_usize(len: u64, usize_max: u64) -> traits::Result<usize> {
+    if len > usize_max {
+        return Err(Error::Platform(format!(
+            "Length {len} does not fit in {}-bit platforms.",
+            u64::BITS - usize_max.leading_zeros()
+        )));
+    }
+    usize::try_from(len).map_err(|_| Error::Platform(format!("Length {len} does not fit.")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out at most `max` bytes per read, like a pipe would.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        max: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.max).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_up_to_fills_buffer_across_short_reads() {
+        let mut reader = Trickle {
+            data: b"hello, world",
+            max: 5,
+        };
+        let mut buf = [0u8; 8];
+
+        assert_eq!(read_up_to(&mut reader, &mut buf).unwrap(), 8);
+        assert_eq!(&buf, b"hello, w");
+    }
+
+    #[test]
+    fn read_up_to_stops_at_eof() {
+        let mut buf = [0u8; 8];
+
+        assert_eq!(read_up_to(&mut &b"hey"[..], &mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"hey");
+    }
+
+    #[test]
+    fn usize_from_u32_regular() {
+        assert_eq!(usize_from_u32(4112).unwrap(), 4112);
+    }
+
+    #[test]
+    fn checked_usize_32_bit_platform() {
+        let usize_max = u64::from(u32::MAX);
+
+        assert_eq!(checked_usize(4112, usize_max).unwrap(), 4112);
+        assert_eq!(
+            checked_usize(u64::from(u32::MAX), usize_max).unwrap() as u64,
+            u64::from(u32::MAX)
+        );
+    }
+
+    #[test]
+    fn checked_usize_16_bit_platform() {
+        let usize_max = u64::from(u16::MAX);
+
+        assert_eq!(checked_usize(4112, usize_max).unwrap(), 4112);
+        assert_eq!(
+            checked_usize(u64::from(u32::MAX), usize_max).unwrap_err(),
+            Error::Platform("Length 4294967295 does not fit in 16-bit platforms.".to_string())
+        );
+    }
+}
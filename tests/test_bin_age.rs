@@ -0,0 +1,114 @@
+mod utils;
+
+use std::fs;
+
+use utils::{FIXTURES_DIR, get_text_file, run, run_binary};
+
+#[test]
+fn age_keygen() {
+    let output = run(&["keygen", "-a", "age"]);
+    dbg!(&output);
+    let (recipient, identity) = output.stdout.split_once('\n').unwrap();
+    dbg!(recipient, identity);
+
+    assert!(recipient.starts_with("age1"));
+    assert!(identity.trim_end().starts_with("AGE-SECRET-KEY-1"));
+}
+
+#[test]
+fn age_decrypt_file_from_age() {
+    // Encrypted with `rage -r <recipient> -o hello.age`.
+    let identity = format!("{FIXTURES_DIR}age_identity.txt");
+    let file = format!("{FIXTURES_DIR}hello.age");
+
+    let output = run(&["decrypt", "-k", &identity, "-f", &file]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Hello, World!");
+}
+
+#[test]
+fn age_decrypt_armored_file_from_age() {
+    // Encrypted with `rage -a -r <recipient> -o hello.age.asc`.
+    let identity = format!("{FIXTURES_DIR}age_identity.txt");
+    let file = format!("{FIXTURES_DIR}hello.age.asc");
+
+    let output = run(&["decrypt", "-k", &identity, "-f", &file]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "Hello, World!");
+}
+
+#[test]
+fn age_round_trip_file() {
+    let output = run(&["keygen", "-a", "age"]);
+    let (recipient, identity) = output.stdout.split_once('\n').unwrap();
+    let identity = identity.trim_end();
+
+    let file = get_text_file("age_round_trip_file.txt");
+    let encrypted = file.with_extension("age");
+    let decrypted = file.with_extension("dec");
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        recipient,
+        "-f",
+        file.to_str().unwrap(),
+        "-o",
+        encrypted.to_str().unwrap(),
+        "--raw",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        fs::read(&encrypted)
+            .unwrap()
+            .starts_with(b"age-encryption.org/v1\n")
+    );
+
+    let output = run(&[
+        "decrypt",
+        "-k",
+        identity,
+        "-f",
+        encrypted.to_str().unwrap(),
+        "-o",
+        decrypted.to_str().unwrap(),
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(fs::read(&decrypted).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn age_round_trip_password() {
+    let output = run_binary(
+        &[
+            "encrypt",
+            "-a",
+            "age",
+            "--password",
+            "-k",
+            "hunter2",
+            "--raw",
+        ],
+        b"hello, world",
+    );
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with(b"age-encryption.org/v1\n-> scrypt ")
+    );
+
+    let output = run_binary(
+        &["decrypt", "-a", "age", "--password", "-k", "hunter2"],
+        &output.stdout,
+    );
+    dbg!(&output.stderr);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, b"hello, world");
+}
@@ -4,7 +4,14 @@ use std::path::Path;
 
 use jolokia::traits::Base64Decode;
 
-use utils::{checksum, get_test_file, run};
+#[cfg(unix)]
+use utils::feed_fifo;
+#[cfg(target_os = "linux")]
+use utils::run_in_terminal;
+use utils::{
+    FIXTURES_DIR, checksum, get_test_dir, get_test_file, get_text_file, run,
+    run_with_closed_stdout, run_with_env, run_with_stdin, spawn,
+};
 
 #[test]
 fn chacha_keygen() {
@@ -13,11 +20,49 @@ fn chacha_keygen() {
     let key = output.stdout;
     dbg!(&key);
 
+    let (algorithm_id, key) = key.trim_end().split_once(':').unwrap();
+    assert_eq!(algorithm_id, "chacha20poly1305");
+
     let key = key.base64_decode().unwrap();
 
     assert_eq!(key.len(), 32);
 }
 
+#[test]
+fn chacha_key_for_another_algorithm_is_error() {
+    let output = run(&[
+        "encrypt",
+        "-a",
+        "chacha",
+        "-k",
+        "hpke:QfSivWNCgT8oeOoTuAWK4cat1PpSCU1GhxXwcfxjlFE",
+        "lorem ipsum dolor sit amet",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 7);
+    assert!(
+        output
+            .stderr
+            .contains("This key is for 'hpke', not 'chacha20poly1305'")
+    );
+}
+
+#[test]
+fn chacha_untagged_key_is_accepted() {
+    let output = run(&[
+        "encrypt",
+        "-a",
+        "chacha",
+        "-k",
+        "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
+        "lorem ipsum dolor sit amet",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+}
+
 #[test]
 fn chacha_encrypt() {
     let output = run(&[
@@ -44,7 +89,7 @@ fn chacha_decrypt() {
         "chacha",
         "-k",
         "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
-        "Q0gyMAE+uvjw+kK0AAAAKiWpFnhyfdVM5v6z0a2g5eEEVM2FaqguZxjjF7g2CYSncAcmpACrlLkCpQAAAAA",
+        "Q0gyMAJbwDxHkMvNAAAAKm0PhPArDNzaO4HR2B5hzdxncGyGd/D4Xj6WcXe5hVthUuNaGgYC6fC5HwAAAACGo25q1D/jfl3MXFt0KuynPqjS7kgDqPQjnnL1RQhvCg",
     ]);
 
     dbg!(&output);
@@ -53,6 +98,25 @@ fn chacha_decrypt() {
     assert_eq!(output.stdout, "lorem ipsum dolor sit amet");
 }
 
+#[test]
+fn chacha_decrypt_version_1_requires_legacy() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let ciphertext =
+        "Q0gyMAE+uvjw+kK0AAAAKiWpFnhyfdVM5v6z0a2g5eEEVM2FaqguZxjjF7g2CYSncAcmpACrlLkCpQAAAAA";
+
+    let output = run(&["decrypt", "-k", key, ciphertext]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 6);
+    assert!(output.stderr.contains("outdated format"));
+    assert!(output.stderr.contains("'--legacy'"));
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["decrypt", "-k", key, ciphertext, "--legacy"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum dolor sit amet");
+}
+
 #[test]
 fn chacha_regular_round_trip() {
     // Get initial file checksum.
@@ -147,6 +211,65 @@ fn chacha_raw_round_trip() {
     assert_eq!(checksum_decrypted, checksum_initial);
 }
 
+#[test]
+fn chacha_encoding_is_base64_for_files() {
+    let file = get_text_file("chacha_encoding_is_base64_for_files");
+    let file_path = file.to_string_lossy().to_string();
+    let encrypted = file.with_extension("enc");
+    let encrypted_path = encrypted.to_string_lossy().to_string();
+
+    let output = run(&["encrypt", "-f", &file_path, "-o", &encrypted_path]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert!(std::fs::read(&encrypted).unwrap().starts_with(b"Q0gy"));
+
+    let output = run(&["decrypt", "-f", &encrypted_path]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, std::fs::read_to_string(&file).unwrap());
+
+    // Raw is detected, no `--raw` needed to decrypt it.
+    let output = run(&[
+        "encrypt",
+        "-f",
+        &file_path,
+        "-o",
+        &encrypted_path,
+        "--raw",
+        "--force",
+    ]);
+    dbg!(&output);
+    assert!(std::fs::read(&encrypted).unwrap().starts_with(b"CH20"));
+
+    let output = run(&["decrypt", "-f", &encrypted_path]);
+    dbg!(&output);
+    assert_eq!(output.stdout, std::fs::read_to_string(&file).unwrap());
+}
+
+#[test]
+fn chacha_mmap_round_trip() {
+    let file = get_text_file("chacha_mmap_round_trip");
+    let file_path = file.to_string_lossy().to_string();
+    let encrypted = file.with_extension("enc");
+    let encrypted_path = encrypted.to_string_lossy().to_string();
+
+    let output = run(&["encrypt", "-f", &file_path, "--mmap", "-o", &encrypted_path]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["decrypt", "-f", &encrypted_path, "--mmap"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, std::fs::read_to_string(&file).unwrap());
+}
+
+#[test]
+fn chacha_encoding_is_base64_for_pipes() {
+    let output = run(&["encrypt", "hello"]);
+    dbg!(&output);
+    assert!(output.stdout.starts_with("Q0gy"));
+}
+
 #[test]
 fn chacha_implicit_in_place_round_trip() {
     // Get initial file checksum.
@@ -250,3 +373,1160 @@ fn chacha_default_key_round_trip() {
     dbg!(&checksum_decrypted);
     assert_eq!(checksum_decrypted, checksum_initial);
 }
+
+#[test]
+fn chacha_associated_data_round_trip() {
+    let file = get_text_file("chacha_associated_data_round_trip");
+    let file_path = file.to_string_lossy().to_string();
+    let file_path_encrypted = file_path.clone() + ".enc";
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    // Encrypt file with associated data.
+    let output = run(&[
+        "encrypt",
+        "invoice-2024",
+        "-k",
+        key,
+        "-f",
+        &file_path,
+        "-o",
+        &file_path_encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    // Decrypting with other associated data fails.
+    let output = run(&[
+        "decrypt",
+        "invoice-2025",
+        "-k",
+        key,
+        "-f",
+        &file_path_encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 4);
+    assert!(output.stdout.is_empty());
+
+    // Decrypting without associated data fails.
+    let output = run(&["decrypt", "-k", key, "-f", &file_path_encrypted]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 4);
+
+    // Decrypting with the same associated data succeeds.
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "-f",
+        &file_path_encrypted,
+        "invoice-2024",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, std::fs::read_to_string(&file).unwrap());
+}
+
+#[test]
+fn chacha_keygen_bits() {
+    let output = run(&["keygen", "-a", "chacha", "--bits", "256"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let key = output
+        .stdout
+        .trim_end()
+        .strip_prefix("chacha20poly1305:")
+        .unwrap();
+    assert_eq!(key.base64_decode().unwrap().len(), 32);
+
+    let output = run(&["keygen", "-a", "chacha", "--bits", "128"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("Key size must be 256 bits, not 128"));
+}
+
+#[test]
+fn chacha_framed_round_trip() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run_with_stdin(&["encrypt", "-k", key, "--framed"], b"foo\nbar\n\nbaz\n");
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    // One frame (line) per message.
+    let frames: Vec<&str> = output.stdout.lines().collect();
+    assert_eq!(frames.len(), 4);
+    assert!(frames.iter().all(|frame| frame.base64_decode().is_ok()));
+
+    let output = run_with_stdin(
+        &["decrypt", "-k", key, "--framed"],
+        output.stdout.as_bytes(),
+    );
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "foo\nbar\n\nbaz\n");
+}
+
+#[test]
+fn chacha_framed_continue_on_error() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run_with_stdin(&["encrypt", "-k", key, "--framed"], b"foo\nbar\n");
+    assert_eq!(output.exit_code, 0);
+    let frames: Vec<&str> = output.stdout.lines().collect();
+    let corrupted = format!("{}\nAAAA\n{}\n", frames[0], frames[1]);
+
+    // Stop at the first bad frame.
+    let output = run_with_stdin(&["decrypt", "-k", key, "--framed"], corrupted.as_bytes());
+    dbg!(&output);
+    assert_eq!(output.exit_code, 5);
+    assert_eq!(output.stdout, "foo\n");
+
+    // Skip bad frames.
+    let output = run_with_stdin(
+        &["decrypt", "-k", key, "--framed", "--continue-on-error"],
+        corrupted.as_bytes(),
+    );
+    dbg!(&output);
+    assert_eq!(output.exit_code, 3);
+    assert_eq!(output.stdout, "foo\nbar\n");
+    assert!(output.stderr.contains("Skipping frame 2"));
+    assert!(
+        output
+            .stderr
+            .contains("1 of 3 frames could not be decrypted")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_fifo_round_trip() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let lorem = std::fs::read(Path::new(FIXTURES_DIR).join("lorem.txt")).unwrap();
+
+    let (fifo, writer) = feed_fifo("chacha_fifo_round_trip_encrypt", lorem.clone());
+    let output = run(&["encrypt", "-k", key, "-f", &fifo.to_string_lossy()]);
+    writer.join().unwrap();
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let encrypted = output.stdout;
+
+    // Short reads do not make short chunks.
+    let output = run(&["inspect", encrypted.trim_end()]);
+    dbg!(&output);
+    let nb_chunks = lorem.len().div_ceil(4096);
+    assert!(
+        output
+            .stdout
+            .contains(&format!("chunks:       {nb_chunks} "))
+    );
+
+    let (fifo, writer) = feed_fifo("chacha_fifo_round_trip_decrypt", encrypted.into_bytes());
+    let output = run(&["decrypt", "-k", key, "-f", &fifo.to_string_lossy()]);
+    writer.join().unwrap();
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout.as_bytes(), lorem);
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_fifo_in_place_is_error() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let (fifo, writer) = feed_fifo("chacha_fifo_in_place_is_error", Vec::new());
+    let fifo = fifo.to_string_lossy().to_string();
+    let output = run(&["encrypt", "-k", key, "-f", &fifo, "-i", "-y"]);
+    dbg!(&output);
+
+    // Unblock the writer, which waits for a reader.
+    drop(std::fs::File::open(&fifo).unwrap());
+    writer.join().unwrap();
+
+    assert_ne!(output.exit_code, 0);
+    assert!(output.stderr.contains("it is not a regular file"));
+}
+
+#[test]
+fn chacha_in_place_failure_leaves_no_temporary_file() {
+    let dir = get_test_dir("chacha_in_place_failure_leaves_no_temporary_file");
+    let file = dir.join("lorem.txt");
+    std::fs::copy(Path::new(FIXTURES_DIR).join("lorem.txt"), &file).unwrap();
+    let checksum_initial = checksum(&file);
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    // Not a ciphertext.
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "-f",
+        &file.to_string_lossy(),
+        "-i",
+        "-y",
+    ]);
+    dbg!(&output);
+
+    assert_ne!(output.exit_code, 0);
+    assert_eq!(checksum(&file), checksum_initial);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+/// Kill an in-place encryption with `signal`, once its temporary file
+/// exists, and return the exit code.
+#[cfg(unix)]
+fn in_place_killed_exit_code(test_name: &str, signal: &str) -> Option<i32> {
+    let dir = get_test_dir(test_name);
+    let file = dir.join("lorem.txt");
+    std::fs::copy(Path::new(FIXTURES_DIR).join("lorem.txt"), &file).unwrap();
+    let checksum_initial = checksum(&file);
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    // The pre-filter stalls the encryption, after the temporary file is
+    // created.
+    let mut child = spawn(&[
+        "encrypt",
+        "-k",
+        key,
+        "-f",
+        &file.to_string_lossy(),
+        "-i",
+        "-y",
+        "--pre-filter",
+        "sleep 10; cat",
+    ]);
+    let started = std::time::Instant::now();
+    while std::fs::read_dir(&dir).unwrap().count() < 2 {
+        assert!(started.elapsed().as_secs() < 10, "no temporary file");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let status = std::process::Command::new("kill")
+        .args([&format!("-{signal}"), &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let status = child.wait().unwrap();
+
+    assert_eq!(checksum(&file), checksum_initial);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    status.code()
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_in_place_interrupted_leaves_no_temporary_file() {
+    let code = in_place_killed_exit_code(
+        "chacha_in_place_interrupted_leaves_no_temporary_file",
+        "INT",
+    );
+
+    assert_eq!(code, Some(130));
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_in_place_terminated_leaves_no_temporary_file() {
+    let code = in_place_killed_exit_code(
+        "chacha_in_place_terminated_leaves_no_temporary_file",
+        "TERM",
+    );
+
+    assert_eq!(code, Some(143));
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_output_keeps_permissions_and_times() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, SystemTime};
+
+    let dir = get_test_dir("chacha_output_keeps_permissions_and_times");
+    let file = dir.join("script.sh");
+    std::fs::write(&file, "#!/bin/sh\necho hello\n").unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o700)).unwrap();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    std::fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    let encrypted = dir.join("script.sh.enc");
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "-f",
+        &file.to_string_lossy(),
+        "-o",
+        &encrypted.to_string_lossy(),
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let metadata = std::fs::metadata(&encrypted).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+    assert_ne!(metadata.modified().unwrap(), modified);
+
+    std::fs::File::options()
+        .write(true)
+        .open(&encrypted)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "-f",
+        &encrypted.to_string_lossy(),
+        "-i",
+        "-y",
+        "--preserve-times",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let metadata = std::fs::metadata(&encrypted).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+    assert_eq!(metadata.modified().unwrap(), modified);
+    assert_eq!(
+        std::fs::read(&encrypted).unwrap(),
+        std::fs::read(&file).unwrap()
+    );
+}
+
+#[test]
+fn chacha_suffix_round_trip() {
+    let file = get_text_file("chacha_suffix_round_trip");
+    let encrypted = file.with_extension("txt.sealed");
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "-f",
+        &file.to_string_lossy(),
+        "--suffix",
+        ".sealed",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert!(encrypted.is_file());
+
+    std::fs::remove_file(&file).unwrap();
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "-f",
+        &encrypted.to_string_lossy(),
+        "--suffix",
+        ".sealed",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        std::fs::read(&file).unwrap(),
+        std::fs::read(Path::new(FIXTURES_DIR).join("lorem.txt")).unwrap()
+    );
+}
+
+#[test]
+fn chacha_shred_round_trip() {
+    let file = get_text_file("chacha_shred_round_trip");
+    let encrypted = file.with_extension("txt.enc");
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "-f",
+        &file.to_string_lossy(),
+        "--suffix",
+        ".enc",
+        "--shred",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("[W004]"));
+    assert!(!file.exists());
+
+    let output = run(&["decrypt", "-k", key, "-f", &encrypted.to_string_lossy()]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(
+        output.stdout,
+        std::fs::read_to_string(Path::new(FIXTURES_DIR).join("lorem.txt")).unwrap()
+    );
+}
+
+#[test]
+fn chacha_shred_failure_keeps_input() {
+    let file = get_text_file("chacha_shred_failure_keeps_input");
+    let file_path = file.to_string_lossy().to_string();
+
+    // The pre-filter fails, so does the encryption.
+    let output = run(&[
+        "encrypt",
+        "-f",
+        &file_path,
+        "--suffix",
+        ".enc",
+        "--shred",
+        "--pre-filter",
+        "false",
+    ]);
+    dbg!(&output);
+    assert_ne!(output.exit_code, 0);
+    assert!(file.exists());
+    assert_eq!(
+        checksum(&file),
+        checksum(&Path::new(FIXTURES_DIR).join("lorem.txt"))
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn chacha_shred_asks_first_unless_yes() {
+    let file = get_text_file("chacha_shred_asks_first_unless_yes");
+    let file_path = file.to_string_lossy().to_string();
+    let args = ["encrypt", "-f", &file_path, "--suffix", ".enc", "--shred"];
+
+    let output = run_in_terminal(&args, b"n\n");
+    dbg!(&output);
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("About to shred"));
+    assert!(file.exists());
+
+    let output = run_in_terminal(&[args.as_slice(), &["--force", "--yes"]].concat(), b"");
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stdout.contains("About to shred"));
+    assert!(!output.stdout.contains("overwrite it?"));
+    assert!(!file.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn chacha_force_asks_first_unless_yes() {
+    let file = get_text_file("chacha_force_asks_first_unless_yes");
+    let file_path = file.to_string_lossy().to_string();
+    let encrypted = file.with_extension("enc");
+    let encrypted_path = encrypted.to_string_lossy().to_string();
+    std::fs::write(&encrypted, "existing").unwrap();
+    let args = [
+        "encrypt",
+        "-f",
+        &file_path,
+        "-o",
+        &encrypted_path,
+        "--force",
+    ];
+
+    let output = run_in_terminal(&args, b"n\n");
+    dbg!(&output);
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("already exists, overwrite it?"));
+    assert_eq!(std::fs::read(&encrypted).unwrap(), b"existing");
+
+    let output = run_in_terminal(&[args.as_slice(), &["--yes"]].concat(), b"");
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stdout.contains("overwrite it?"));
+    assert_ne!(std::fs::read(&encrypted).unwrap(), b"existing");
+}
+
+#[test]
+fn chacha_check_intact_and_damaged() {
+    let file = get_test_file("chacha_check_intact_and_damaged");
+    let encrypted = file.with_extension("webp.enc");
+    let encrypted_path = encrypted.to_string_lossy().to_string();
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "-f",
+        &file.to_string_lossy(),
+        "-o",
+        &encrypted_path,
+    ]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["check", "-k", key, "-f", &encrypted_path]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "The ciphertext is intact.\n");
+
+    // Bit rot, in the middle.
+    let mut ciphertext = std::fs::read(&encrypted).unwrap();
+    let middle = ciphertext.len() / 2;
+    ciphertext[middle] ^= 0x01;
+    std::fs::write(&encrypted, &ciphertext).unwrap();
+    let output = run(&["check", "-k", key, "-f", &encrypted_path]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 4);
+    assert_eq!(output.stdout, "");
+
+    // Truncated, on a chunk boundary.
+    ciphertext[middle] ^= 0x01;
+    ciphertext.truncate(12 + 4116);
+    std::fs::write(&encrypted, &ciphertext).unwrap();
+    let output = run(&["check", "-k", key, "-f", &encrypted_path]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 5);
+}
+
+#[test]
+fn chacha_output_directory_round_trip() {
+    let file = get_text_file("chacha_output_directory_round_trip");
+    let file_path = file.to_string_lossy().to_string();
+    let tmp_dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let encrypted_dir = tmp_dir.join("chacha_output_directory_round_trip_encrypted");
+    let decrypted_dir = tmp_dir.join("chacha_output_directory_round_trip_decrypted");
+    for dir in [&encrypted_dir, &decrypted_dir] {
+        _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir(dir).unwrap();
+    }
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    // Trailing slash.
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "-f",
+        &file_path,
+        "-o",
+        &format!("{}/", encrypted_dir.display()),
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    let encrypted = encrypted_dir.join("chacha_output_directory_round_trip.txt.enc");
+    assert!(encrypted.is_file());
+
+    // No trailing slash.
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "-f",
+        &encrypted.to_string_lossy(),
+        "-o",
+        &decrypted_dir.to_string_lossy(),
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    let decrypted = decrypted_dir.join("chacha_output_directory_round_trip.txt");
+    assert_eq!(checksum(&decrypted), checksum(&file));
+}
+
+#[test]
+fn chacha_closed_output_is_not_an_error() {
+    let file = get_test_file("chacha_closed_output_is_not_an_error");
+    let file_path = file.to_string_lossy().to_string();
+    let file_path_encrypted = file_path.clone() + ".enc";
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run_with_closed_stdout(&["encrypt", "-k", key, "-f", &file_path]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 141);
+    assert_eq!(output.stderr, "");
+
+    let output = run(&[
+        "encrypt",
+        "-r",
+        "-k",
+        key,
+        "-f",
+        &file_path,
+        "-o",
+        &file_path_encrypted,
+    ]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run_with_closed_stdout(&["decrypt", "-r", "-k", key, "-f", &file_path_encrypted]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 141);
+    assert_eq!(output.stderr, "");
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_filters_round_trip() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&["encrypt", "-k", key, "--pre-filter", "tr a-z A-Z", "hello"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["decrypt", "-k", key, output.stdout.trim_end()]);
+    assert_eq!(output.stdout, "HELLO");
+
+    let output = run(&["encrypt", "-k", key, "hello"]);
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "--post-filter",
+        "rev",
+        output.stdout.trim_end(),
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "olleh");
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_filter_failure_is_error() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&["encrypt", "-k", key, "--pre-filter", "exit 3", "hello"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(
+        output
+            .stderr
+            .contains("Filter 'exit 3' failed (exit status: 3)")
+    );
+}
+
+#[test]
+fn chacha_framed_raw_round_trip() {
+    let file = get_text_file("chacha_framed_raw_round_trip");
+    let file_path = file.to_string_lossy().to_string();
+    let file_path_encrypted = file_path.clone() + ".enc";
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "--framed",
+        "-r",
+        "-f",
+        &file_path,
+        "-o",
+        &file_path_encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "--framed",
+        "-r",
+        "-f",
+        &file_path_encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, std::fs::read_to_string(&file).unwrap());
+}
+
+#[test]
+fn chacha_in_place_dry_run() {
+    let file = get_test_file("chacha_in_place_dry_run");
+    let file_path = file.to_string_lossy().to_string();
+    let checksum_initial = checksum(&file);
+
+    let output = run(&[
+        "encrypt",
+        "-a",
+        "chacha",
+        "-f",
+        &file_path,
+        "-i",
+        "--dry-run",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("command:   encrypt\n"));
+    assert!(output.stdout.contains("key:       default (not secure)\n"));
+    assert!(
+        output
+            .stdout
+            .contains(&format!("input:     '{file_path}'\n"))
+    );
+    assert!(output.stdout.contains("(in-place, through '"));
+    // Dry-runs don't warn, the plan already shows the default key.
+    assert!(output.stderr.is_empty());
+
+    // Ensure the file has not changed.
+    assert_eq!(checksum(&file), checksum_initial);
+}
+
+#[test]
+fn chacha_decrypt_peek() {
+    let file = get_test_file("chacha_decrypt_peek");
+    let file_path = file.to_string_lossy().to_string();
+    let file_path_encrypted = file_path.clone() + ".enc";
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "-r",
+        "-f",
+        &file_path,
+        "-o",
+        &file_path_encrypted,
+    ]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "-r",
+        "-f",
+        &file_path_encrypted,
+        "--peek",
+        "4",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    // WebP files start with `RIFF`.
+    assert_eq!(output.stdout, "RIFF\n");
+}
+
+#[test]
+fn chacha_decrypt_range() {
+    let file = get_test_file("chacha_decrypt_range");
+    let file_path = file.to_string_lossy().to_string();
+    let file_path_encrypted = file_path.clone() + ".enc";
+    let file_path_range = file_path.clone() + ".range";
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "-r",
+        "-f",
+        &file_path,
+        "-o",
+        &file_path_encrypted,
+    ]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&[
+        "decrypt",
+        "-k",
+        key,
+        "-f",
+        &file_path_encrypted,
+        "--range",
+        "100000:110000",
+        "-o",
+        &file_path_range,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    let plaintext = std::fs::read(&file).unwrap();
+    let range = std::fs::read(&file_path_range).unwrap();
+    assert_eq!(range, plaintext[100_000..110_000]);
+}
+
+#[test]
+fn chacha_decrypt_range_base64() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&["encrypt", "-k", key, "Hello, World!"]);
+    assert_eq!(output.exit_code, 0);
+
+    let output = run(&["decrypt", "-k", key, &output.stdout, "--range", "7:12"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "World");
+}
+
+#[test]
+fn chacha_decrypt_range_into_input_is_error() {
+    let file = get_test_file("chacha_decrypt_range_into_input_is_error");
+    let file_path = file.to_string_lossy().to_string();
+    let checksum_initial = checksum(&file);
+
+    let output = run(&[
+        "decrypt",
+        "-k",
+        "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
+        "-f",
+        &file_path,
+        "--range",
+        "0:10",
+        "-o",
+        &file_path,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stderr.contains("it is the input file"));
+    assert_eq!(checksum(&file), checksum_initial);
+}
+
+#[test]
+fn chacha_key_name_round_trip() {
+    let config = format!("{FIXTURES_DIR}config");
+    let env = [("XDG_CONFIG_HOME", config.as_str())];
+
+    let output = run_with_env(&["encrypt", "--key-name", "work", "lorem ipsum"], &env);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    // Same key as `work` in the keyring.
+    let output = run(&[
+        "decrypt",
+        "-k",
+        "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ",
+        &encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum");
+}
+
+#[test]
+fn chacha_password_round_trip() {
+    let env = [("JOLOKIA_PASSWORD", "correct horse battery staple")];
+
+    let output = run_with_env(&["encrypt", "--password", "lorem ipsum"], &env);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run_with_env(&["decrypt", "--password", &encrypted], &env);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum");
+
+    // The passphrase can also be given with `--key`.
+    let output = run(&[
+        "decrypt",
+        "--password",
+        "-k",
+        "correct horse battery staple",
+        &encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.stdout, "lorem ipsum");
+}
+
+#[test]
+fn chacha_password_wrong_passphrase_is_error() {
+    let output = run(&["encrypt", "--password", "-k", "hunter2", "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run(&["decrypt", "--password", "-k", "hunter3", &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 4);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn chacha_malformed_key_is_error() {
+    let output = run(&["encrypt", "-k", "not base64!", "lorem ipsum"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 7);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.contains("Malformed key"));
+}
+
+#[test]
+fn chacha_id_hint_wrong_key() {
+    let key = "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let other_key = "chacha20poly1305:uWdkP4kcRlAgiRJ3DGdk2DDPPdEEj/bn3Lq8brJbwZY";
+
+    let output = run(&["encrypt", "-k", key, "--id-hint", "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run(&["decrypt", "-k", key, &encrypted]);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum");
+
+    let output = run(&["decrypt", "-k", other_key, &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 4);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.contains("The message was encrypted for key "));
+}
+
+#[test]
+fn chacha_encoding_hex_round_trip() {
+    let output = run(&["keygen", "--encoding", "hex"]);
+    let key = output.stdout.trim_end().to_string();
+    assert!(key.starts_with("chacha20poly1305:"));
+    assert!(key[17..].bytes().all(|c| c.is_ascii_hexdigit()));
+
+    let output = run(&["encrypt", "-k", &key, "--encoding", "hex", "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+    assert!(encrypted.bytes().all(|c| c.is_ascii_hexdigit()));
+
+    let output = run(&["decrypt", "-k", &key, "--encoding", "hex", &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum");
+}
+
+#[test]
+fn chacha_encoding_base32_is_case_insensitive() {
+    let output = run(&["keygen", "--encoding", "base32"]);
+    let key = output.stdout.trim_end().to_string();
+
+    let output = run(&["encrypt", "-k", &key, "--encoding", "base32", "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_lowercase();
+
+    let output = run(&["decrypt", "-k", &key, "--encoding", "base32", &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum");
+}
+
+#[test]
+fn chacha_expire_not_expired() {
+    let key = "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&["encrypt", "-k", key, "--expire", "1h", "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run(&["decrypt", "-k", key, "--strict", &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn chacha_expire_expired_is_warning() {
+    let key = "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&["encrypt", "-k", key, "--expire", "0s", "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run(&["decrypt", "-k", key, &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "lorem ipsum");
+    assert!(
+        output
+            .stderr
+            .contains("warning[W003]: This message expired")
+    );
+}
+
+#[test]
+fn chacha_expire_expired_strict_is_error() {
+    let key = "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&["encrypt", "-k", key, "--expire", "0s", "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run(&["decrypt", "-k", key, "--strict", &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.contains("error: This message expired"));
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_entropy_device_round_trip() {
+    let output = run(&["keygen", "--entropy", "/dev/urandom"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let key = output.stdout.trim();
+
+    let output = run(&["encrypt", "-k", key, "--entropy", "/dev/urandom", "hello"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let ciphertext = output.stdout.trim();
+
+    let output = run(&["decrypt", "-k", key, ciphertext]);
+    dbg!(&output);
+    assert_eq!(output.stdout, "hello");
+}
+
+#[cfg(unix)]
+#[test]
+fn chacha_entropy_non_random_device_is_error() {
+    // Devices, but reads are always empty, or always zeros.
+    for device in ["/dev/null", "/dev/zero"] {
+        let output = run(&["keygen", "--entropy", device]);
+        dbg!(&output);
+
+        assert_eq!(output.exit_code, 2);
+        assert!(
+            output
+                .stderr
+                .contains(&format!("Invalid entropy source '{device}'"))
+        );
+    }
+}
+
+#[test]
+fn chacha_rekey_round_trip() {
+    let old_key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let new_key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ";
+
+    let output = run(&["encrypt", "-k", old_key, "hello, world"]);
+    dbg!(&output);
+    let encrypted = output.stdout.trim();
+
+    let output = run(&["rekey", "-k", old_key, "--new-key", new_key, encrypted]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let rekeyed = output.stdout.trim();
+
+    let output = run(&["decrypt", "-k", old_key, rekeyed]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 4);
+
+    let output = run(&["decrypt", "-k", new_key, rekeyed]);
+    dbg!(&output);
+    assert_eq!(output.stdout, "hello, world");
+}
+
+#[test]
+fn chacha_rekey_in_place_round_trip() {
+    let old_key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let new_key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ";
+
+    let file = get_test_file("chacha_rekey_in_place_round_trip");
+    let file_path = file.to_string_lossy().to_string();
+    let checksum_initial = checksum(&file);
+
+    let output = run(&["encrypt", "-k", old_key, "-f", &file_path, "-i"]);
+    dbg!(&output);
+    let checksum_encrypted = checksum(&file);
+
+    let output = run(&[
+        "rekey",
+        "-k",
+        old_key,
+        "--new-key",
+        new_key,
+        "-f",
+        &file_path,
+        "-i",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_ne!(checksum(&file), checksum_encrypted);
+
+    let output = run(&["decrypt", "-k", new_key, "-f", &file_path, "-i"]);
+    dbg!(&output);
+    assert_eq!(checksum(&file), checksum_initial);
+}
+
+#[test]
+fn chacha_migrate_directory_in_place() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let dir = get_test_dir("chacha_migrate_directory_in_place");
+    let (old, latest) = (dir.join("old.enc"), dir.join("latest.enc"));
+    let latest_path = latest.to_string_lossy().to_string();
+    let output = run(&[
+        "encrypt",
+        "-r",
+        "-k",
+        key,
+        "-o",
+        &latest_path,
+        "hello, world",
+    ]);
+    dbg!(&output);
+    // Version 1 (see `chacha_decrypt_version_1_requires_legacy()`).
+    let ciphertext =
+        "Q0gyMAE+uvjw+kK0AAAAKiWpFnhyfdVM5v6z0a2g5eEEVM2FaqguZxjjF7g2CYSncAcmpACrlLkCpQAAAAA"
+            .base64_decode()
+            .unwrap();
+    std::fs::write(&old, &ciphertext).unwrap();
+    let latest_before = std::fs::read(&latest).unwrap();
+
+    let old_path = old.to_string_lossy().to_string();
+    let output = run(&["inspect", "-f", &old_path]);
+    dbg!(&output);
+    assert!(
+        output
+            .stdout
+            .contains("format:       outdated (see 'migrate')")
+    );
+
+    let dir_path = dir.to_string_lossy().to_string();
+    let output = run(&["migrate", "-k", key, "-f", &dir_path, "-i"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    assert!(std::fs::read(&old).unwrap().starts_with(b"CH20\x02"));
+    let output = run(&["decrypt", "-k", key, "-f", &old_path]);
+    dbg!(&output);
+    assert_eq!(output.stdout, "lorem ipsum dolor sit amet");
+    // Already in the latest format, left untouched.
+    assert_eq!(std::fs::read(&latest).unwrap(), latest_before);
+}
+
+#[test]
+fn chacha_rekey_directory_in_place() {
+    let old_key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let new_key = "aZZfFANQlAtS5jxyyzHh0R8BWpHGDR2iqsBqROXzPkQ";
+
+    let dir = get_test_dir("chacha_rekey_directory_in_place");
+    std::fs::create_dir(dir.join("nested")).unwrap();
+    let files = [dir.join("a.enc"), dir.join("nested").join("b.enc")];
+    for file in &files {
+        let file = file.to_string_lossy().to_string();
+        let output = run(&["encrypt", "-k", old_key, "-o", &file, "hello, world"]);
+        dbg!(&output);
+    }
+    // Not a ciphertext, it can't be rekeyed.
+    std::fs::write(dir.join("notes.txt"), "hello, world").unwrap();
+
+    let dir_path = dir.to_string_lossy().to_string();
+    let output = run(&[
+        "rekey",
+        "-k",
+        old_key,
+        "--new-key",
+        new_key,
+        "-f",
+        &dir_path,
+        "-i",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 3);
+    assert!(output.stderr.contains("1 of 3 files could not be rekeyed"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("notes.txt")).unwrap(),
+        "hello, world"
+    );
+    for file in &files {
+        let file = file.to_string_lossy().to_string();
+        let output = run(&["decrypt", "-k", new_key, "-f", &file]);
+        dbg!(&output);
+        assert_eq!(output.stdout, "hello, world");
+    }
+    // No temporary files are left behind.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+}
@@ -0,0 +1,111 @@
+mod utils;
+
+use jolokia::traits::Base64Decode;
+
+use utils::{get_test_file, run};
+
+fn keygen() -> (String, String) {
+    let output = run(&["sign", "--keygen"]);
+    dbg!(&output);
+    let (pubkey, privkey) = output.stdout.split_once('\n').unwrap();
+    (pubkey.to_string(), privkey.trim_end().to_string())
+}
+
+#[test]
+fn ed25519_keygen() {
+    let (pubkey, privkey) = keygen();
+
+    let pubkey = pubkey.strip_prefix("ed25519:").unwrap();
+    let privkey = privkey.strip_prefix("ed25519:").unwrap();
+
+    assert_eq!(pubkey.base64_decode().unwrap().len(), 32);
+    assert_eq!(privkey.base64_decode().unwrap().len(), 32);
+}
+
+#[test]
+fn ed25519_sign_verify_round_trip() {
+    let file = get_test_file("ed25519_sign_verify_round_trip");
+    let file_path = file.to_string_lossy().to_string();
+    let signature_path = file_path.clone() + ".sig";
+    let (pubkey, privkey) = keygen();
+
+    // Sign file.
+    let output = run(&[
+        "sign",
+        "-k",
+        &privkey,
+        "-f",
+        &file_path,
+        "-o",
+        &signature_path,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    // Verify with the signature file.
+    let output = run(&[
+        "verify",
+        "-k",
+        &pubkey,
+        "--signature",
+        &signature_path,
+        "-f",
+        &file_path,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "The signature is valid.\n");
+
+    // Tamper with the file.
+    let mut content = std::fs::read(&file).unwrap();
+    content[100] ^= 1;
+    std::fs::write(&file, content).unwrap();
+
+    let output = run(&[
+        "verify",
+        "-k",
+        &pubkey,
+        "--signature",
+        &signature_path,
+        "-f",
+        &file_path,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 4);
+    assert!(output.stderr.contains("The signature is not valid."));
+}
+
+#[test]
+fn ed25519_verify_with_other_key_is_error() {
+    let (_, privkey) = keygen();
+    let (other_pubkey, _) = keygen();
+
+    let output = run(&["sign", "-k", &privkey, "hello"]);
+    dbg!(&output);
+    let signature = output.stdout.trim();
+
+    let output = run(&[
+        "verify",
+        "-k",
+        &other_pubkey,
+        "--signature",
+        signature,
+        "hello",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 4);
+    assert!(output.stderr.contains("The signature is not valid."));
+}
+
+#[test]
+fn ed25519_cipher_key_is_error() {
+    let output = run(&[
+        "sign",
+        "-k",
+        "chacha20poly1305:edLKPT4jYaabmMwuKzgQwklMC9HxTYmhVY7qln4yrJM",
+        "hello",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 7);
+    assert!(output.stderr.contains("not 'ed25519'"));
+}
@@ -4,7 +4,7 @@ use std::path::Path;
 
 use jolokia::traits::Base64Decode;
 
-use utils::{checksum, get_test_file, run};
+use utils::{checksum, get_test_file, run, run_with_env};
 
 #[test]
 fn hpke_keygen() {
@@ -13,6 +13,9 @@ fn hpke_keygen() {
     let (pubkey, privkey) = output.stdout.split_once('\n').unwrap();
     dbg!(pubkey, privkey);
 
+    let pubkey = pubkey.strip_prefix("hpke:").unwrap();
+    let privkey = privkey.trim_end().strip_prefix("hpke:").unwrap();
+
     let pubkey = pubkey.base64_decode().unwrap();
     let privkey = privkey.base64_decode().unwrap();
 
@@ -46,7 +49,7 @@ fn hpke_decrypt() {
         "hpke",
         "-k",
         "KkQpXGsXQTmGD0UI0Z8wejnmw8UAg+YRMgviV1x+abA",
-        "SFBLRQEAIF8k55ZEOO6wlScUZSntiWqT1dP1T/bMhywp3kEkxQB5Q0gyMAHU1a6oIPlVAAAAKlbKzpjJk0EHu/6gTn/7DllxCLS74Gvad+MojxSnkQFr/PlfB6iToal23AAAAAA",
+        "SFBLRQEAIBSPhRlFskKH05nV1o+e1jpMMi3mvYJMQI2yyQ31uL8lQ0gyMAL1meSDBwXoAAAAKhc1xuAYAbYTLGV2fv5cK3zysuFe2Uc8+rfS1l5PPReyAy0m5xHtBIY6hgAAAABUoemX+HOyp3Gmh9RdmDlld1l8tm9ytU9ItWdccnm+vQ",
     ]);
 
     dbg!(&output);
@@ -255,3 +258,141 @@ fn hpke_default_key_round_trip() {
     dbg!(&checksum_decrypted);
     assert_eq!(checksum_decrypted, checksum_initial);
 }
+
+#[test]
+fn hpke_pq_round_trip() {
+    let file = get_test_file("hpke_pq_round_trip");
+    let file_path = file.to_string_lossy().to_string();
+    let file_path_encrypted = file_path.clone() + ".enc";
+    let checksum_initial = checksum(&file);
+
+    // Generate keypair.
+    let output = run(&["keygen", "-a", "hpke-pq"]);
+    dbg!(&output);
+    let (pubkey, privkey) = output.stdout.split_once('\n').unwrap();
+    assert!(pubkey.starts_with("hpke-pq:"));
+
+    let output = run(&[
+        "encrypt",
+        "-a",
+        "hpke-pq",
+        "-k",
+        pubkey,
+        "-f",
+        &file_path,
+        "-o",
+        &file_path_encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    // The ciphertext says what it is, without the key.
+    let output = run(&["inspect", "-f", &file_path_encrypted]);
+    dbg!(&output);
+    assert!(output.stdout.contains("HPKE-PQ (X-Wing)"));
+
+    let output = run(&[
+        "decrypt",
+        "-a",
+        "hpke-pq",
+        "-k",
+        privkey.trim_end(),
+        "-f",
+        &file_path_encrypted,
+        "-o",
+        &file_path,
+        "--force",
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+
+    assert_eq!(checksum(&file), checksum_initial);
+}
+
+#[test]
+fn hpke_rekey_from_chacha() {
+    let old_key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+
+    let output = run(&["keygen", "-a", "hpke"]);
+    dbg!(&output);
+    let (pubkey, privkey) = output.stdout.split_once('\n').unwrap();
+    let privkey = privkey.trim_end();
+
+    let output = run(&["encrypt", "-a", "chacha", "-k", old_key, "hello, world"]);
+    dbg!(&output);
+    let encrypted = output.stdout.trim();
+
+    let output = run(&[
+        "rekey",
+        "-a",
+        "chacha",
+        "-k",
+        old_key,
+        "--new-algorithm",
+        "hpke",
+        "--new-key",
+        pubkey,
+        encrypted,
+    ]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let rekeyed = output.stdout.trim();
+
+    let output = run(&["decrypt", "-a", "hpke", "-k", privkey, rekeyed]);
+    dbg!(&output);
+    assert_eq!(output.stdout, "hello, world");
+}
+
+#[test]
+fn hpke_protected_key_round_trip() {
+    let env = [("JOLOKIA_PASSWORD", "correct horse battery staple")];
+
+    let output = run_with_env(&["keygen", "-a", "hpke", "--protect"], &env);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let (pubkey, privkey) = output.stdout.split_once('\n').unwrap();
+    let privkey = privkey.trim_end();
+    assert!(pubkey.starts_with("hpke:"));
+    assert!(privkey.starts_with("protected:hpke:"));
+
+    let output = run(&["encrypt", "-a", "hpke", "-k", pubkey, "hello, world"]);
+    dbg!(&output);
+    let encrypted = output.stdout.trim();
+
+    let output = run_with_env(&["decrypt", "-a", "hpke", "-k", privkey, encrypted], &env);
+    dbg!(&output);
+    assert_eq!(output.stdout, "hello, world");
+
+    let output = run_with_env(
+        &["decrypt", "-a", "hpke", "-k", privkey, encrypted],
+        &[("JOLOKIA_PASSWORD", "wrong")],
+    );
+    dbg!(&output);
+    assert_eq!(output.exit_code, 1);
+    assert!(
+        output
+            .stderr
+            .contains("Could not unlock the key, the passphrase is likely wrong")
+    );
+
+    // No passphrase, and no terminal to type it in.
+    let output = run(&["decrypt", "-a", "hpke", "-k", privkey, encrypted]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 2);
+}
+
+#[test]
+fn hpke_ssh_key_round_trip() {
+    let pubkey = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/id_ed25519.pub");
+    let privkey = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/id_ed25519");
+
+    // No `-a hpke`, SSH keys select HPKE.
+    let output = run(&["encrypt", "-k", pubkey, "hello, world"]);
+    dbg!(&output);
+    assert_eq!(output.exit_code, 0);
+    let encrypted = output.stdout.trim();
+
+    let output = run(&["decrypt", "-k", privkey, encrypted]);
+    dbg!(&output);
+    assert_eq!(output.stdout, "hello, world");
+}
@@ -1,6 +1,6 @@
 mod utils;
 
-use utils::run;
+use utils::{run, run_with_env, run_with_stdin};
 
 #[test]
 fn short_help() {
@@ -12,8 +12,12 @@ fn short_help() {
     assert!(output.stdout.contains(env!("CARGO_PKG_DESCRIPTION")));
 
     assert!(output.stdout.contains("keygen"));
+    assert!(output.stdout.contains("--bits <N>"));
+    assert!(output.stdout.contains("--params <K=V,...>"));
     assert!(output.stdout.contains("encrypt"));
     assert!(output.stdout.contains("decrypt"));
+    assert!(output.stdout.contains("doctor"));
+    assert!(output.stdout.contains("man"));
 
     assert!(output.stdout.contains("<MESSAGE>"));
     assert!(output.stdout.contains("-a, --algorithm <ALGO>"));
@@ -22,7 +26,9 @@ fn short_help() {
     assert!(output.stdout.contains("-r, --raw"));
     assert!(output.stdout.contains("-f, --file"));
     assert!(output.stdout.contains("-i, --in-place"));
+    assert!(output.stdout.contains("--max-stdin-size <N>"));
     assert!(output.stdout.contains("-o, --output"));
+    assert!(output.stdout.contains("--no-warn <ID,...>"));
 
     assert!(output.stdout.contains("-h, --help"));
     assert!(output.stdout.contains("-V, --version"));
@@ -68,3 +74,432 @@ fn version() {
     assert!(output.stdout.contains(env!("CARGO_PKG_NAME")));
     assert!(output.stdout.contains(env!("CARGO_PKG_VERSION")));
 }
+
+#[test]
+fn stdin_within_max_size() {
+    let output = run_with_stdin(
+        &["encrypt", "-a", "rot", "-k", "13", "--max-stdin-size", "5"],
+        b"hello",
+    );
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert_eq!(output.stdout, "uryyb");
+}
+
+#[test]
+fn stdin_exceeds_max_size() {
+    let output = run_with_stdin(
+        &["encrypt", "-a", "rot", "-k", "13", "--max-stdin-size", "5"],
+        b"hello, world",
+    );
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 5);
+    assert!(output.stdout.is_empty());
+    assert!(
+        output
+            .stderr
+            .contains("exceeds the maximum stdin size (5 bytes)")
+    );
+}
+
+#[test]
+fn warning_default_key() {
+    let output = run(&["encrypt", "foo"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stderr
+            .contains("warning[W001]: Using jolokia's default cipher key.")
+    );
+}
+
+#[test]
+fn warning_default_key_suppressed_by_option() {
+    let output = run(&["encrypt", "foo", "--no-warn", "W001"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn warning_default_key_suppressed_by_dedicated_env() {
+    let output = run_with_env(&["encrypt", "foo"], &[("JOLOKIA_NO_WARN_DEFAULT_KEY", "1")]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn warning_default_key_suppressed_by_quiet() {
+    let output = run(&["encrypt", "-q", "foo"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(!output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn verbose_shows_key_source() {
+    let output = run_with_env(
+        &["decrypt", "-v", "Q0gyMAI"],
+        &[(
+            "JOLOKIA_CIPHER_KEY",
+            "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
+        )],
+    );
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 5);
+    assert!(
+        output
+            .stderr
+            .contains("info: algorithm: ChaCha20-Poly1305\n")
+    );
+    assert!(output.stderr.contains("info: key: $JOLOKIA_CIPHER_KEY\n"));
+}
+
+#[test]
+fn verbose_shows_byte_counts() {
+    let output = run(&[
+        "encrypt",
+        "-v",
+        "-k",
+        "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
+        "hello",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("info: key: --key\n"));
+    // Header (5) + nonce prefix (7) + chunk (4 + 5 + 16) + EOF marker
+    // (4) + trailer (32).
+    assert!(
+        output
+            .stderr
+            .contains("info: 5 bytes in, 73 bytes out (1 chunks)\n")
+    );
+}
+
+#[test]
+fn warning_insecure_algorithm() {
+    let output = run(&["encrypt", "foo", "-a", "rot"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("warning[W002]:"));
+}
+
+#[test]
+fn warning_suppression_is_specific() {
+    let output = run_with_env(
+        &["encrypt", "foo", "-a", "rot"],
+        &[("JOLOKIA_NO_WARN", "W001")],
+    );
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.contains("warning[W002]:"));
+
+    let output = run_with_env(
+        &["encrypt", "foo", "-a", "rot"],
+        &[("JOLOKIA_NO_WARN", "insecure-algorithm")],
+    );
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn doctor_valid_key() {
+    let output = run_with_env(
+        &["doctor"],
+        &[(
+            "JOLOKIA_CIPHER_KEY",
+            "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A",
+        )],
+    );
+    dbg!(&output);
+
+    assert!(
+        output
+            .stdout
+            .contains("ok: `JOLOKIA_CIPHER_KEY` contains a valid key.")
+    );
+}
+
+#[test]
+fn doctor_key_file_does_not_exist() {
+    let output = run_with_env(
+        &["doctor"],
+        &[("JOLOKIA_CIPHER_KEY", "/does/not/exist.key")],
+    );
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.contains("looks like a path"));
+    assert!(output.stdout.contains("fix:"));
+}
+
+#[test]
+fn bench_one_algorithm() {
+    let output = run(&["bench", "-a", "chacha", "--size", "65536"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with("ChaCha20-Poly1305 "));
+    assert!(output.stdout.trim_end().ends_with(" MB/s"));
+    assert_eq!(output.stdout.lines().count(), 1);
+}
+
+#[test]
+fn man_page() {
+    let output = run(&["man"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with(".TH JOLOKIA 1"));
+    assert!(output.stdout.contains(".SH COMMANDS"));
+    assert!(output.stdout.contains(".B \\-k, \\-\\-key <KEY>"));
+}
+
+#[test]
+fn long_help_no_pager() {
+    let output = run_with_env(&["--help", "--no-pager"], &[("PAGER", "/does/not/exist")]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.contains("What does jolokia do?"));
+    assert!(output.stderr.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn plugin_is_dispatched() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("plugins");
+    std::fs::create_dir_all(&dir).unwrap();
+    let plugin = dir.join("jolokia-hello");
+    std::fs::write(
+        &plugin,
+        "#!/bin/sh\necho \"args: $*\"\necho \"exe: $JOLOKIA\"\nexit 7\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+    let output = run_with_env(&["hello", "--world", "42"], &[("PATH", &path)]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 7);
+    assert!(output.stdout.contains("args: --world 42\n"));
+    assert!(
+        output
+            .stdout
+            .contains(&format!("exe: {}\n", env!("CARGO_BIN_EXE_jolokia")))
+    );
+}
+
+#[test]
+fn plugin_not_found_is_unknown_argument() {
+    let output = run(&["no-such-plugin"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 2);
+    assert!(output.stderr.contains("'no-such-plugin'"));
+}
+
+#[test]
+fn keygen_confirm_then_clear_requires_terminal() {
+    let output = run(&["keygen", "--confirm-then-clear"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 2);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.contains("requires a terminal"));
+}
+
+#[cfg(unix)]
+#[test]
+fn keygen_output_is_private_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("keygen-output");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("jolokia.key");
+    _ = std::fs::remove_file(&file);
+
+    let output = run(&["keygen", "-o", file.to_str().unwrap()]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.is_empty());
+    let key = std::fs::read_to_string(&file).unwrap();
+    assert!(key.starts_with("chacha20poly1305:"));
+    assert!(key.ends_with('\n'));
+    let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn keygen_public_output_prints_nothing() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("keygen-public-output");
+    std::fs::create_dir_all(&dir).unwrap();
+    let private = dir.join("private.key");
+    let public = dir.join("public.key");
+
+    let output = run(&[
+        "keygen",
+        "-a",
+        "hpke",
+        "-o",
+        private.to_str().unwrap(),
+        "--public-output",
+        public.to_str().unwrap(),
+        "--force",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+    assert!(
+        std::fs::read_to_string(&public)
+            .unwrap()
+            .starts_with("hpke:")
+    );
+    assert!(
+        std::fs::read_to_string(&private)
+            .unwrap()
+            .starts_with("hpke:")
+    );
+}
+
+#[test]
+fn keygen_output_prints_public_key() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("keygen-output-public");
+    std::fs::create_dir_all(&dir).unwrap();
+    let private = dir.join("private.key");
+
+    let output = run(&[
+        "keygen",
+        "-a",
+        "hpke",
+        "-o",
+        private.to_str().unwrap(),
+        "--force",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with("hpke:"));
+    assert_eq!(output.stdout.lines().count(), 1);
+    assert!(output.stderr.is_empty());
+    let key = std::fs::read_to_string(&private).unwrap();
+    assert_ne!(key, output.stdout);
+}
+
+#[test]
+fn keygen_qr_is_printed() {
+    let output = run(&["keygen", "--qr"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.starts_with('█'));
+    assert!(!output.stdout.contains("chacha20poly1305"));
+    let width = output.stdout.lines().next().unwrap().chars().count();
+    assert!(
+        output
+            .stdout
+            .lines()
+            .all(|line| line.chars().count() == width)
+    );
+}
+
+#[test]
+fn encrypt_qr_png() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("encrypt-qr");
+    std::fs::create_dir_all(&dir).unwrap();
+    let image = dir.join("message.png");
+
+    let key = "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let output = run(&[
+        "encrypt",
+        "-k",
+        key,
+        "--qr",
+        "-o",
+        image.to_str().unwrap(),
+        "--force",
+        "hello",
+    ]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(output.stdout.is_empty());
+    assert!(
+        std::fs::read(&image)
+            .unwrap()
+            .starts_with(b"\x89PNG\r\n\x1a\n")
+    );
+}
+
+#[test]
+fn encrypt_qr_too_long() {
+    let key = "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let output = run_with_stdin(&["encrypt", "-k", key, "--qr"], &[b'a'; 3000]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 1);
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.contains("Too long for a QR code"));
+}
+
+#[test]
+fn inspect_does_not_need_key() {
+    let key = "chacha20poly1305:9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let output = run(&["encrypt", "-k", key, "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run(&["inspect", &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 0);
+    assert!(
+        output
+            .stdout
+            .starts_with("algorithm:    ChaCha20-Poly1305\n")
+    );
+    assert!(output.stdout.contains("plaintext:    11 bytes\n"));
+    assert!(output.stdout.ends_with("end:          ok\n"));
+}
+
+#[test]
+fn exit_code_missing_file() {
+    let output = run(&["decrypt", "-f", "/this/file/does/not/exist"]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 5);
+    assert!(output.stderr.contains("Could not read"));
+}
+
+#[test]
+fn exit_code_unsupported_algorithm() {
+    let key = "9zcb2kyrHdHG0w7yGUs7dcYK7YWKAuatm77FgoLoO2A";
+    let output = run(&["encrypt", "-k", key, "lorem ipsum"]);
+    let encrypted = output.stdout.trim_end().to_string();
+
+    let output = run(&["decrypt", "-a", "hpke", "-k", key, &encrypted]);
+    dbg!(&output);
+
+    assert_eq!(output.exit_code, 6);
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,230 @@
+//! Round trips through the binary, for every combination of algorithm,
+//! encoding, input, and output.
+//!
+//! The combinations are generated, not written by hand. A new option
+//! only needs to be added to its list to be tested against all others.
+
+mod utils;
+
+use std::fs;
+use std::path::Path;
+
+use jolokia::armor::is_armored;
+use jolokia::inspect::is_raw;
+
+use utils::{FIXTURES_DIR, get_text_file, run, run_binary};
+
+const ENCODINGS: [Encoding; 4] = [
+    Encoding::Auto,
+    Encoding::Base64,
+    Encoding::Raw,
+    Encoding::Armor,
+];
+const INPUTS: [Input; 3] = [Input::Argument, Input::File, Input::Stdin];
+const OUTPUTS: [Output; 3] = [Output::Stdout, Output::File, Output::InPlace];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Encoding {
+    Auto,
+    Base64,
+    Raw,
+    Armor,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Input {
+    Argument,
+    File,
+    Stdin,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Output {
+    Stdout,
+    File,
+    InPlace,
+}
+
+/// Keys to encrypt and decrypt with (the same, if symmetric).
+struct Keys {
+    encrypt: Option<String>,
+    decrypt: Option<String>,
+}
+
+impl Keys {
+    fn generate(algorithm: &str) -> Self {
+        match algorithm {
+            "rot" => Self {
+                encrypt: Some("5".to_string()),
+                decrypt: Some("5".to_string()),
+            },
+            "brainfuck" => Self {
+                encrypt: None,
+                decrypt: None,
+            },
+            _ => {
+                let output = run(&["keygen", "-a", algorithm]);
+                assert_eq!(output.exit_code, 0, "{output:?}");
+                let mut lines = output.stdout.lines().map(ToString::to_string);
+                let encrypt = lines.next();
+                // Symmetric keys have a single line.
+                let decrypt = lines.next().or_else(|| encrypt.clone());
+                Self { encrypt, decrypt }
+            }
+        }
+    }
+}
+
+fn round_trip_all(algorithm: &str) {
+    let keys = Keys::generate(algorithm);
+    for encoding in ENCODINGS {
+        for input in INPUTS {
+            for output in OUTPUTS {
+                round_trip(algorithm, &keys, encoding, input, output);
+            }
+        }
+    }
+}
+
+fn round_trip(algorithm: &str, keys: &Keys, encoding: Encoding, input: Input, output: Output) {
+    let name = format!("matrix_{algorithm}_{encoding:?}_{input:?}_{output:?}").to_lowercase();
+    let combination = format!("{algorithm}, {encoding:?}, {input:?}, {output:?}");
+    let plaintext = fs::read(Path::new(FIXTURES_DIR).join("lorem.txt")).unwrap();
+    let file = get_text_file(&name);
+
+    let mut flags = vec!["-a", algorithm];
+    match encoding {
+        // Armor is detected on the way back.
+        Encoding::Auto | Encoding::Armor => (),
+        Encoding::Base64 => flags.push("--base64"),
+        Encoding::Raw => flags.push("--raw"),
+    }
+
+    let mut encrypt_flags = flags.clone();
+    if encoding == Encoding::Armor {
+        encrypt_flags.push("--armor");
+    }
+    if let Some(ref key) = keys.encrypt {
+        encrypt_flags.extend(["-k", key]);
+    }
+    let (exit_code, ciphertext) = step("encrypt", &encrypt_flags, &plaintext, input, output, &file);
+
+    // In-place needs a file to replace.
+    if output == Output::InPlace && input != Input::File {
+        assert_eq!(exit_code, 2, "{combination}");
+        return;
+    }
+    assert_eq!(exit_code, 0, "{combination}");
+    assert_ne!(ciphertext, plaintext, "{combination}");
+
+    // ROT-n and Brainfuck have no header to tell raw from base64.
+    if !matches!(algorithm, "rot" | "brainfuck") {
+        let expect_raw = match encoding {
+            Encoding::Auto | Encoding::Base64 | Encoding::Armor => false,
+            Encoding::Raw => true,
+        };
+        assert_eq!(is_raw(&ciphertext), expect_raw, "{combination}");
+    }
+    if encoding == Encoding::Armor {
+        assert!(is_armored(&ciphertext), "{combination}");
+    }
+
+    // Binary can't be passed as an argument.
+    let decrypt_input = match input {
+        Input::Argument if std::str::from_utf8(&ciphertext).is_err() || ciphertext.contains(&0) => {
+            Input::Stdin
+        }
+        input => input,
+    };
+
+    let mut decrypt_flags = flags;
+    if let Some(ref key) = keys.decrypt {
+        decrypt_flags.extend(["-k", key]);
+    }
+    let (exit_code, decrypted) = step(
+        "decrypt",
+        &decrypt_flags,
+        &ciphertext,
+        decrypt_input,
+        output,
+        &file,
+    );
+
+    assert_eq!(exit_code, 0, "{combination}");
+    assert_eq!(decrypted, plaintext, "{combination}");
+}
+
+/// Run `command` on `data`, given as `input`, written to `output`.
+///
+/// Returns the exit code, and what was written.
+fn step(
+    command: &str,
+    flags: &[&str],
+    data: &[u8],
+    input: Input,
+    output: Output,
+    file: &Path,
+) -> (i32, Vec<u8>) {
+    let file_path = file.to_string_lossy().to_string();
+    let output_path = file
+        .with_extension(format!("{command}.out"))
+        .to_string_lossy()
+        .to_string();
+    let message = String::from_utf8_lossy(data).to_string();
+
+    let mut args = vec![command];
+    args.extend(flags);
+    let mut stdin: &[u8] = &[];
+    match input {
+        // After the options, the message could look like one.
+        Input::Argument => (),
+        Input::File => {
+            fs::write(file, data).unwrap();
+            args.extend(["-f", &file_path]);
+        }
+        Input::Stdin => stdin = data,
+    }
+    match output {
+        Output::Stdout => (),
+        Output::File => args.extend(["-o", &output_path]),
+        Output::InPlace => args.push("-i"),
+    }
+    if input == Input::Argument {
+        args.extend(["--", &message]);
+    }
+
+    let result = run_binary(&args, stdin);
+
+    let written = match output {
+        _ if result.exit_code != 0 => Vec::new(),
+        Output::Stdout => result.stdout,
+        Output::File => fs::read(&output_path).unwrap(),
+        Output::InPlace => fs::read(file).unwrap(),
+    };
+    (result.exit_code, written)
+}
+
+#[test]
+fn matrix_chacha() {
+    round_trip_all("chacha");
+}
+
+#[test]
+fn matrix_hpke() {
+    round_trip_all("hpke");
+}
+
+#[test]
+fn matrix_hpke_pq() {
+    round_trip_all("hpke-pq");
+}
+
+#[test]
+fn matrix_rotn() {
+    round_trip_all("rot");
+}
+
+#[test]
+fn matrix_brainfuck() {
+    round_trip_all("brainfuck");
+}
@@ -9,7 +9,7 @@ fn rotn_keygen() {
     let output = run(&["keygen", "-a", "rotn"]);
     dbg!(&output);
 
-    assert_eq!(output.exit_code, 1);
+    assert_eq!(output.exit_code, 2);
 }
 
 #[test]
@@ -1,8 +1,9 @@
 #![allow(dead_code)]
 
 use std::env;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 const JOLOKIA: &str = env!("CARGO_BIN_EXE_jolokia");
 const TMP_DIR: &str = env!("CARGO_TARGET_TMPDIR");
@@ -15,33 +16,194 @@ pub struct Output {
     pub stderr: String,
 }
 
-pub fn get_test_file(file_name: &str) -> PathBuf {
-    let cat_source = Path::new(FIXTURES_DIR).join("chat.webp");
-    let cat_dest = Path::new(TMP_DIR)
+/// A copy of a fixture, alone in a fresh directory.
+///
+/// Outputs written next to it (e.g., `file.with_extension("enc")`) are
+/// gone on the next run, so they can't be overwritten by accident.
+fn get_fixture_copy(file_name: &str, fixture: &str) -> PathBuf {
+    let source = Path::new(FIXTURES_DIR).join(fixture);
+    let dir = Path::new(TMP_DIR).join("files").join(file_name);
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let dest = dir
         .join(file_name)
-        .with_extension(cat_source.extension().unwrap());
-    std::fs::copy(cat_source, &cat_dest).unwrap();
-    cat_dest
+        .with_extension(source.extension().unwrap());
+    std::fs::copy(source, &dest).unwrap();
+    dest
+}
+
+pub fn get_test_file(file_name: &str) -> PathBuf {
+    get_fixture_copy(file_name, "chat.webp")
 }
 
 pub fn get_text_file(file_name: &str) -> PathBuf {
-    let lorem_source = Path::new(FIXTURES_DIR).join("lorem.txt");
-    let lorem_dest = Path::new(TMP_DIR)
-        .join(file_name)
-        .with_extension(lorem_source.extension().unwrap());
-    std::fs::copy(lorem_source, &lorem_dest).unwrap();
-    lorem_dest
+    get_fixture_copy(file_name, "lorem.txt")
+}
+
+/// An empty directory, to fill with test files.
+pub fn get_test_dir(dir_name: &str) -> PathBuf {
+    let dir = Path::new(TMP_DIR).join(dir_name);
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Create a named pipe (FIFO), and feed it `data` in small pieces from
+/// a background thread, once a reader opens it.
+#[cfg(unix)]
+pub fn feed_fifo(file_name: &str, data: Vec<u8>) -> (PathBuf, std::thread::JoinHandle<()>) {
+    let fifo = Path::new(TMP_DIR).join(file_name).with_extension("fifo");
+    _ = std::fs::remove_file(&fifo);
+    let status = Command::new("mkfifo").arg(&fifo).status().unwrap();
+    assert!(status.success());
+
+    let path = fifo.clone();
+    let writer = std::thread::spawn(move || {
+        // Blocks until the reader opens the FIFO.
+        let mut f = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        // Small writes, with pauses, make the reader see short reads.
+        for piece in data.chunks(100) {
+            f.write_all(piece).unwrap();
+            f.flush().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    });
+
+    (fifo, writer)
 }
 
 pub fn run(args: &[&str]) -> Output {
-    let mut command = Command::new(JOLOKIA);
-    command.env("NO_COLOR", "1");
-    command.env_remove("PAGER");
+    let output = command(args).output().unwrap();
 
-    for arg in args {
-        command.arg(arg);
+    Output {
+        exit_code: output.status.code().unwrap(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    }
+}
+
+pub fn run_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = command(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // If the process exits early (e.g., on error), writing may fail
+    // with a broken pipe. That's fine, we only care about the output.
+    _ = child.stdin.take().unwrap().write_all(stdin);
+
+    let output = child.wait_with_output().unwrap();
+
+    Output {
+        exit_code: output.status.code().unwrap(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    }
+}
+
+/// Like [`run_with_stdin()`], but in a (pseudo-)terminal, with `script`.
+///
+/// Everything the terminal shows ends up in `stdout` (prompts and input
+/// echo included), `stderr` is empty.
+#[cfg(target_os = "linux")]
+pub fn run_in_terminal(args: &[&str], stdin: &[u8]) -> Output {
+    let quote = |arg: &str| format!("'{}'", arg.replace('\'', r"'\''"));
+    let line = std::iter::once(JOLOKIA)
+        .chain(args.iter().copied())
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut script = Command::new("script");
+    script.args(["--quiet", "--return", "--command", &line, "/dev/null"]);
+    for (key, value) in command(&[]).get_envs() {
+        match value {
+            Some(value) => script.env(key, value),
+            None => script.env_remove(key),
+        };
+    }
+    let mut child = script
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    _ = child.stdin.take().unwrap().write_all(stdin);
+
+    let output = child.wait_with_output().unwrap();
+
+    Output {
+        exit_code: output.status.code().unwrap(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    }
+}
+
+/// Like [`Output`], but `stdout` is kept as-is (e.g., raw ciphertext).
+#[derive(Debug)]
+pub struct BinaryOutput {
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+}
+
+/// Like [`run_with_stdin()`], but `stdout` is not decoded.
+pub fn run_binary(args: &[&str], stdin: &[u8]) -> BinaryOutput {
+    let mut child = command(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    _ = child.stdin.take().unwrap().write_all(stdin);
+
+    let output = child.wait_with_output().unwrap();
+
+    BinaryOutput {
+        exit_code: output.status.code().unwrap(),
+        stdout: output.stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
     }
+}
+
+/// Like [`run()`], but `stdout` is closed right away, like `| head`
+/// would eventually do.
+pub fn run_with_closed_stdout(args: &[&str]) -> Output {
+    let mut child = command(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    drop(child.stdout.take());
+
+    let output = child.wait_with_output().unwrap();
 
+    Output {
+        exit_code: output.status.code().unwrap(),
+        stdout: String::new(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    }
+}
+
+/// Start `jolokia` in the background, without waiting for it.
+pub fn spawn(args: &[&str]) -> std::process::Child {
+    command(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+pub fn run_with_env(args: &[&str], env: &[(&str, &str)]) -> Output {
+    let mut command = command(args);
+    for (key, value) in env {
+        command.env(key, value);
+    }
     let output = command.output().unwrap();
 
     Output {
@@ -51,6 +213,24 @@ pub fn run(args: &[&str]) -> Output {
     }
 }
 
+fn command(args: &[&str]) -> Command {
+    let mut command = Command::new(JOLOKIA);
+    command.env("NO_COLOR", "1");
+    command.env_remove("PAGER");
+    command.env_remove("JOLOKIA_CIPHER_KEY");
+    command.env_remove("JOLOKIA_PASSWORD");
+    command.env_remove("JOLOKIA_NO_WARN");
+    command.env_remove("JOLOKIA_NO_WARN_DEFAULT_KEY");
+    command.env_remove("JOLOKIA_NO_WARN_INSECURE_ALGORITHM");
+    command.env_remove("JOLOKIA_NO_WARN_EXPIRED");
+
+    for arg in args {
+        command.arg(arg);
+    }
+
+    command
+}
+
 pub fn checksum(path: &Path) -> String {
     let payload = std::fs::read(path).unwrap();
     let hash = blake3::hash(&payload);
@@ -0,0 +1,264 @@
+//! JavaScript interface to jolokia, for the browser.
+//!
+//! Built with `wasm-pack build --target web wasm/`, this gives a web
+//! page the same algorithms, keys, and ciphertext format as the CLI: a
+//! message encrypted with `jolokia encrypt` can be decrypted in the
+//! browser, and the other way around.
+//!
+//! ```js
+//! import init, { keygen, encrypt, decrypt } from "./pkg/jolokia_wasm.js";
+//!
+//! await init();
+//! const keys = keygen("hpke");
+//! const ciphertext = encrypt("hpke", keys.publicKey, new TextEncoder().encode("Hello"));
+//! const plaintext = decrypt("hpke", keys.secretKey, ciphertext);
+//! ```
+//!
+//! # Conventions
+//!
+//! - Algorithms are given by ID (`"chacha20poly1305"`, `"hpke"`,
+//!   `"hpke-pq"`, `"age"`). `undefined` means the default,
+//!   ChaCha20-Poly1305.
+//! - Keys are strings, exactly as printed by `jolokia keygen`.
+//! - [`encrypt()`] returns base64 (like `jolokia encrypt`), and
+//!   [`decrypt()`] takes base64 or armor. [`encrypt_bytes()`] and
+//!   [`decrypt_bytes()`] work on raw ciphertexts (like `--raw`).
+//! - Errors are thrown, as `Error`s with the same messages as the CLI.
+//!
+//! Randomness comes from `crypto.getRandomValues()`.
+//!
+//! Secrets handed to JavaScript (generated keys, plaintexts) are copied
+//! out of WebAssembly memory, and can't be zeroed from here.
+
+// `wasm-bindgen` can't borrow optional strings (`Option<&str>`).
+#![allow(clippy::needless_pass_by_value)]
+
+use std::io::Read;
+
+use wasm_bindgen::prelude::*;
+
+use jolokia::armor::{self, ArmorSource};
+use jolokia::cipher;
+use jolokia::key;
+use jolokia::traits::{
+    self, Base64Decode, Base64Encode, Cipher, Error, GeneratedKey, KeygenOptions,
+};
+
+/// Keys generated by [`keygen()`].
+#[wasm_bindgen]
+pub struct Keys {
+    secret_key: String,
+    public_key: Option<String>,
+}
+
+#[wasm_bindgen]
+impl Keys {
+    /// The private key, or the key of symmetric algorithms.
+    #[must_use]
+    #[wasm_bindgen(getter, js_name = secretKey)]
+    pub fn secret_key(&self) -> String {
+        self.secret_key.clone()
+    }
+
+    /// The public key, `undefined` for symmetric algorithms.
+    #[must_use]
+    #[wasm_bindgen(getter, js_name = publicKey)]
+    pub fn public_key(&self) -> Option<String> {
+        self.public_key.clone()
+    }
+}
+
+/// Generate a key for `algorithm`.
+///
+/// # Errors
+///
+/// Throws if the algorithm is unknown, or if there is no randomness.
+#[wasm_bindgen]
+pub fn keygen(algorithm: Option<String>) -> Result<Keys, JsError> {
+    generate_keys(algorithm.as_deref()).map_err(to_js_error)
+}
+
+/// Encrypt `plaintext` with `key`, into base64.
+///
+/// # Errors
+///
+/// Throws if the algorithm is unknown, or if the key is invalid.
+#[wasm_bindgen]
+pub fn encrypt(algorithm: Option<String>, key: &str, plaintext: &[u8]) -> Result<String, JsError> {
+    encrypt_raw(algorithm.as_deref(), key, plaintext)
+        .map(|ciphertext| ciphertext.base64_encode())
+        .map_err(to_js_error)
+}
+
+/// Decrypt base64 or armored `ciphertext` with `key`.
+///
+/// # Errors
+///
+/// Throws if the algorithm is unknown, if the key is invalid, or if
+/// decryption fails.
+#[wasm_bindgen]
+pub fn decrypt(algorithm: Option<String>, key: &str, ciphertext: &str) -> Result<Vec<u8>, JsError> {
+    decode_text(ciphertext)
+        .and_then(|ciphertext| decrypt_raw(algorithm.as_deref(), key, &ciphertext))
+        .map_err(to_js_error)
+}
+
+/// Encrypt `plaintext` with `key`, into raw bytes.
+///
+/// # Errors
+///
+/// Throws if the algorithm is unknown, or if the key is invalid.
+#[wasm_bindgen(js_name = encryptBytes)]
+pub fn encrypt_bytes(
+    algorithm: Option<String>,
+    key: &str,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, JsError> {
+    encrypt_raw(algorithm.as_deref(), key, plaintext).map_err(to_js_error)
+}
+
+/// Decrypt raw `ciphertext` with `key`.
+///
+/// # Errors
+///
+/// Throws if the algorithm is unknown, if the key is invalid, or if
+/// decryption fails.
+#[wasm_bindgen(js_name = decryptBytes)]
+pub fn decrypt_bytes(
+    algorithm: Option<String>,
+    key: &str,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, JsError> {
+    decrypt_raw(algorithm.as_deref(), key, ciphertext).map_err(to_js_error)
+}
+
+fn generate_keys(algorithm: Option<&str>) -> traits::Result<Keys> {
+    let cipher = cipher_from_id(algorithm)?;
+    let (secret, public) = match cipher.generate_key(&KeygenOptions::default())? {
+        GeneratedKey::Symmetric(key) => (key, None),
+        GeneratedKey::Asymmetric { private, public } => (private, Some(public)),
+        GeneratedKey::None => return Err(Error::Key),
+    };
+    let (secret, public) = key::encode(cipher.algorithm_id(), &secret, public.as_ref())?;
+    Ok(Keys {
+        secret_key: secret.to_string(),
+        public_key: public,
+    })
+}
+
+fn encrypt_raw(algorithm: Option<&str>, key: &str, plaintext: &[u8]) -> traits::Result<Vec<u8>> {
+    let cipher = cipher_from_id(algorithm)?;
+    let key = key::decode(cipher.algorithm_id(), key)?;
+    cipher.encrypt(&key, plaintext)
+}
+
+fn decrypt_raw(algorithm: Option<&str>, key: &str, ciphertext: &[u8]) -> traits::Result<Vec<u8>> {
+    let cipher = cipher_from_id(algorithm)?;
+    let key = key::decode(cipher.algorithm_id(), key)?;
+    cipher.decrypt(&key, ciphertext)
+}
+
+fn cipher_from_id(algorithm: Option<&str>) -> traits::Result<Box<dyn Cipher>> {
+    let Some(algorithm) = algorithm else {
+        return Ok(Box::new(cipher::ChaCha20Poly1305));
+    };
+    cipher::from_algorithm_id(algorithm)
+        .ok_or_else(|| Error::Parameter(format!("Unknown algorithm '{algorithm}'")))
+}
+
+/// Base64, or armored base64.
+fn decode_text(text: &str) -> traits::Result<Vec<u8>> {
+    let text = text.trim();
+    if !armor::is_armored(text.as_bytes()) {
+        return text.base64_decode();
+    }
+    let mut base64 = String::new();
+    ArmorSource::new(text.as_bytes())?
+        .read_to_string(&mut base64)
+        .map_err(|e| Error::Base64Decode(e.to_string()))?;
+    base64.base64_decode()
+}
+
+fn to_js_error(error: Error) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+// The exported functions only work in WebAssembly (`JsError` calls into
+// JavaScript), the tests go through their implementations.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keygen_encrypt_decrypt_round_trip() {
+        let keys = generate_keys(Some("hpke")).unwrap();
+
+        let ciphertext = encrypt_raw(Some("hpke"), &keys.public_key.unwrap(), b"Hello").unwrap();
+        let plaintext = decrypt_raw(Some("hpke"), &keys.secret_key, &ciphertext).unwrap();
+
+        assert_eq!(plaintext, b"Hello");
+    }
+
+    #[test]
+    fn keygen_symmetric_has_no_public_key() {
+        let keys = generate_keys(None).unwrap();
+
+        assert!(keys.secret_key.starts_with("chacha20poly1305:"));
+        assert!(keys.public_key.is_none());
+    }
+
+    #[test]
+    fn keygen_age() {
+        let keys = generate_keys(Some("age")).unwrap();
+
+        assert!(keys.secret_key.starts_with("AGE-SECRET-KEY-1"));
+        assert!(keys.public_key.unwrap().starts_with("age1"));
+    }
+
+    #[test]
+    fn unknown_algorithm_is_error() {
+        assert!(matches!(
+            generate_keys(Some("rot13")),
+            Err(Error::Parameter(_))
+        ));
+    }
+
+    #[test]
+    fn key_for_other_algorithm_is_error() {
+        let keys = generate_keys(None).unwrap();
+
+        assert!(matches!(
+            encrypt_raw(Some("hpke"), &keys.secret_key, b"Hello"),
+            Err(Error::Key)
+        ));
+    }
+
+    #[test]
+    fn decode_text_base64() {
+        assert_eq!(decode_text(" SGVsbG8\n").unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn decode_text_armored() {
+        let armored = format!(
+            "{}\nAlgorithm: hpke\n\nSGVs\nbG8\n{}\n",
+            armor::BEGIN,
+            armor::END
+        );
+
+        assert_eq!(decode_text(&armored).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn decrypt_cli_ciphertext() {
+        // `jolokia encrypt "Hello" --key <key>`
+        let key = "chacha20poly1305:KHc7gBIeg3C/WsYAEMCXQFp8C1hl7eBY0VWRHav6XgE";
+        let ciphertext = "Q0gyMAEUnn2N3trwAAAAFcZg+W2FwitJZWPLXrjwbFL09+iD/AAAAAA";
+
+        let plaintext = decode_text(ciphertext)
+            .and_then(|ciphertext| decrypt_raw(None, key, &ciphertext))
+            .unwrap();
+
+        assert_eq!(plaintext, b"Hello");
+    }
+}